@@ -0,0 +1,331 @@
+//! Throughput of the enrichment hot path (lookup resolution, document
+//! cloning, and nesting) over synthetic in-memory lookup tables, so
+//! refactors of the joins can be measured without a database.
+//!
+//! Run with `cargo bench`; `cargo bench -- enrich_file` runs one group.
+
+use bson::{doc, DateTime, Document};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::collections::HashMap;
+
+use materialize::cli::Options;
+use materialize::coerce::Unparseable;
+use materialize::enrich::{enrich_collection, enrich_file, Misses, Trace};
+use materialize::intern::InternedMap;
+use materialize::lookup::{LookupContext, LookupMap, MultiMap, ANATOMY_TABLE, DISEASE_TABLE};
+use materialize::redact::Redactions;
+
+const SUBMISSION: &str = "bench";
+const NAMESPACE: &str = "tag:bench.example.org,2024:";
+
+/// Terms per vocabulary table.
+const TERMS: usize = 50;
+
+/// Shape of the synthetic submission: each file is in `collections_per_file`
+/// collections, each holding `biosamples_per_collection` biosamples from
+/// distinct subjects.
+struct Shape {
+    collections_per_file: usize,
+    biosamples_per_collection: usize,
+}
+
+fn key(id: impl Into<String>) -> (String, String) {
+    (NAMESPACE.to_string(), id.into())
+}
+
+fn term_id(table: &str, i: usize) -> String {
+    format!("{}:{:07}", table, i % TERMS)
+}
+
+fn vocabulary(table: &str) -> LookupMap {
+    let terms = (0..TERMS)
+        .map(|i| {
+            let id = term_id(table, i);
+            let term = doc! {
+                "id": &id,
+                "name": format!("{} term {}", table, i),
+                "description": "A synthetic vocabulary term",
+                "submission": SUBMISSION,
+                "table": table,
+            };
+            ((SUBMISSION.to_string(), id), term)
+        })
+        .collect();
+    LookupMap::Memory(terms)
+}
+
+/// Lookup tables for `collections` collections of the given shape.
+fn context<'a>(opts: &'a Options, collections: usize, shape: &Shape) -> LookupContext<'a> {
+    let mut terms: HashMap<String, LookupMap> = opts
+        .config
+        .enrichment
+        .tables()
+        .into_iter()
+        .map(|table| (table.to_string(), vocabulary(table)))
+        .collect();
+    for table in [ANATOMY_TABLE, DISEASE_TABLE] {
+        terms.insert(table.to_string(), vocabulary(table));
+    }
+
+    let mut collection_rows = HashMap::new();
+    let mut biosample_in_collection = HashMap::new();
+    let mut collection_anatomy = HashMap::new();
+    let mut collection_defined_by_project = HashMap::new();
+    let mut biosamples = HashMap::new();
+    let mut biosample_from_subject = HashMap::new();
+    let mut subjects = HashMap::new();
+    let mut subject_race = HashMap::new();
+    for c in 0..collections {
+        let coll_id = format!("collection-{}", c);
+        collection_rows.insert(
+            key(&coll_id),
+            doc! {
+                "id_namespace": NAMESPACE,
+                "local_id": &coll_id,
+                "name": format!("Collection {}", c),
+                "creation_time": "2024-01-01T00:00:00Z",
+                "has_time_series_data": "false",
+            },
+        );
+        collection_anatomy.insert(
+            key(&coll_id),
+            vec![doc! { "anatomy": term_id(ANATOMY_TABLE, c) }],
+        );
+        collection_defined_by_project.insert(
+            key(&coll_id),
+            vec![doc! { "project_id_namespace": NAMESPACE, "project_local_id": "root" }],
+        );
+        let mut members = Vec::new();
+        for b in 0..shape.biosamples_per_collection {
+            let bio_id = format!("biosample-{}-{}", c, b);
+            let subject_id = format!("subject-{}-{}", c, b);
+            members.push(doc! {
+                "biosample_id_namespace": NAMESPACE,
+                "biosample_local_id": &bio_id,
+            });
+            biosamples.insert(
+                key(&bio_id),
+                doc! {
+                    "id_namespace": NAMESPACE,
+                    "local_id": &bio_id,
+                    "anatomy": term_id("anatomy", b),
+                    "creation_time": "2024-01-01T00:00:00Z",
+                },
+            );
+            biosample_from_subject.insert(
+                key(&bio_id),
+                vec![doc! { "subject_id_namespace": NAMESPACE, "subject_local_id": &subject_id }],
+            );
+            subjects.insert(
+                key(&subject_id),
+                doc! {
+                    "id_namespace": NAMESPACE,
+                    "local_id": &subject_id,
+                    "granularity": term_id("subject_granularity", b),
+                    "sex": term_id("subject_sex", b),
+                    "ethnicity": term_id("subject_ethnicity", b),
+                },
+            );
+            subject_race.insert(
+                key(&subject_id),
+                vec![doc! { "race": term_id("subject_race_CV", b) }],
+            );
+        }
+        biosample_in_collection.insert(key(&coll_id), members);
+    }
+    let projects = HashMap::from([(
+        key("root"),
+        doc! { "id_namespace": NAMESPACE, "local_id": "root", "name": "Root project" },
+    )]);
+    let dccs = HashMap::from([(
+        SUBMISSION.to_string(),
+        doc! {
+            "id": "cfde_registry_dcc:bench",
+            "dcc_name": "Benchmark DCC",
+            "dcc_abbreviation": "BENCH",
+            "submission": SUBMISSION,
+        },
+    )]);
+
+    LookupContext {
+        opts,
+        dccs,
+        terms,
+        obi: None,
+        uberon: None,
+        collections: LookupMap::Memory(collection_rows.into_iter().collect()),
+        biosamples: LookupMap::Memory(biosamples.into_iter().collect()),
+        file_in_collection: MultiMap::Memory(InternedMap::default()),
+        biosample_in_collection: MultiMap::Memory(biosample_in_collection.into_iter().collect()),
+        collection_anatomy: MultiMap::Memory(collection_anatomy.into_iter().collect()),
+        collection_disease: MultiMap::Memory(InternedMap::default()),
+        collection_defined_by_project: MultiMap::Memory(
+            collection_defined_by_project.into_iter().collect(),
+        ),
+        projects: LookupMap::Memory(projects.into_iter().collect()),
+        subjects: LookupMap::Memory(subjects.into_iter().collect()),
+        biosample_from_subject: MultiMap::Memory(biosample_from_subject.into_iter().collect()),
+        subject_race: MultiMap::Memory(subject_race.into_iter().collect()),
+        subject_races: vocabulary("subject_race_CV"),
+        biosample_substance: MultiMap::Memory(InternedMap::default()),
+        substances: vocabulary("substance"),
+        biosample_gene: MultiMap::Memory(InternedMap::default()),
+        genes: vocabulary("gene"),
+        derivations: Vec::new(),
+        file_names: LookupMap::Memory(InternedMap::default()),
+        load_ms: Document::new(),
+        materialized_at: DateTime::now(),
+        duplicates: Document::new(),
+        misses: Misses::default(),
+        unparseable: Unparseable::default(),
+        redactions: Redactions::default(),
+        enrichers: Vec::new(),
+    }
+}
+
+/// `files` raw file rows, file `i` in collections `i..i + per_file`.
+fn files(
+    ctx: &mut LookupContext,
+    files: usize,
+    collections: usize,
+    per_file: usize,
+) -> Vec<Document> {
+    let mut file_in_collection = HashMap::new();
+    let rows = (0..files)
+        .map(|i| {
+            let local_id = format!("file-{}", i);
+            let memberships = (0..per_file)
+                .map(|c| {
+                    doc! {
+                        "collection_id_namespace": NAMESPACE,
+                        "collection_local_id": format!("collection-{}", (i + c) % collections),
+                    }
+                })
+                .collect();
+            file_in_collection.insert(key(&local_id), memberships);
+            doc! {
+                "id_namespace": NAMESPACE,
+                "local_id": &local_id,
+                "submission": SUBMISSION,
+                "filename": format!("{}.fastq.gz", local_id),
+                "size_in_bytes": "1048576",
+                "creation_time": "2024-03-01 12:00:00",
+                "sha256": "0".repeat(64),
+                "file_format": term_id("file_format", i),
+                "compression_format": term_id("file_format", i + 1),
+                "data_type": term_id("data_type", i),
+                "assay_type": term_id("assay_type", i),
+                "mime_type": "",
+            }
+        })
+        .collect();
+    ctx.file_in_collection = MultiMap::Memory(file_in_collection.into_iter().collect());
+    rows
+}
+
+fn options() -> Options {
+    Options::from_args(vec!["materialize".to_string()]).expect("default options")
+}
+
+fn bench_enrich_file(c: &mut Criterion) {
+    let opts = options();
+    let mut group = c.benchmark_group("enrich_file");
+    for (collections_per_file, biosamples_per_collection) in [(1, 1), (1, 10), (4, 10), (16, 10)] {
+        let shape = Shape {
+            collections_per_file,
+            biosamples_per_collection,
+        };
+        let mut ctx = context(&opts, 64, &shape);
+        let rows = files(&mut ctx, 256, 64, shape.collections_per_file);
+        group.throughput(Throughput::Elements(rows.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!(
+                "{}x{}",
+                shape.collections_per_file, shape.biosamples_per_collection
+            )),
+            &rows,
+            |b, rows| {
+                b.iter(|| {
+                    for row in rows {
+                        black_box(enrich_file(row.clone(), &ctx, &mut Trace::disabled()));
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_lookup(c: &mut Criterion) {
+    let table = vocabulary("file_format");
+    let hit = term_id("file_format", 7);
+    let mut group = c.benchmark_group("lookup");
+    group.bench_function("hit", |b| {
+        b.iter(|| black_box(table.get(black_box(SUBMISSION), black_box(&hit))))
+    });
+    group.bench_function("miss", |b| {
+        b.iter(|| black_box(table.get(black_box(SUBMISSION), black_box("format:missing"))))
+    });
+    group.finish();
+}
+
+fn bench_clone(c: &mut Criterion) {
+    let opts = options();
+    let shape = Shape {
+        collections_per_file: 4,
+        biosamples_per_collection: 10,
+    };
+    let mut ctx = context(&opts, 64, &shape);
+    let rows = files(&mut ctx, 1, 64, shape.collections_per_file);
+    let enriched = enrich_file(rows[0].clone(), &ctx, &mut Trace::disabled());
+    let mut group = c.benchmark_group("clone");
+    group.bench_function("raw_file", |b| b.iter(|| black_box(rows[0].clone())));
+    group.bench_function("enriched_file", |b| b.iter(|| black_box(enriched.clone())));
+    group.finish();
+}
+
+fn bench_nesting(c: &mut Criterion) {
+    let opts = options();
+    let mut group = c.benchmark_group("enrich_collection");
+    for biosamples_per_collection in [1, 10, 50] {
+        let shape = Shape {
+            collections_per_file: 1,
+            biosamples_per_collection,
+        };
+        let ctx = context(&opts, 1, &shape);
+        let collection = ctx
+            .collections
+            .get(NAMESPACE, "collection-0")
+            .unwrap()
+            .into_owned();
+        group.throughput(Throughput::Elements(biosamples_per_collection as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(biosamples_per_collection),
+            &collection,
+            |b, collection| {
+                b.iter(|| {
+                    let mut coll = collection.clone();
+                    enrich_collection(
+                        &mut coll,
+                        NAMESPACE,
+                        "collection-0",
+                        SUBMISSION,
+                        &ctx,
+                        &mut Trace::disabled(),
+                    );
+                    black_box(coll)
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_enrich_file,
+    bench_lookup,
+    bench_clone,
+    bench_nesting
+);
+criterion_main!(benches);
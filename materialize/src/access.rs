@@ -0,0 +1,87 @@
+use bson::{doc, Bson, Document};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// C2M2 `data_access_level` values, least to most restrictive.
+pub const LEVELS: [&str; 4] = ["open", "registered", "controlled", "protected"];
+
+/// DCC-level access policies, keyed by DCC abbreviation (or submission for
+/// files without a dcc). A policy's `level` is a floor: a file keeps its own
+/// `data_access_level` only when that is at least as restrictive.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessConfig {
+    pub dcc: HashMap<String, AccessPolicy>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessPolicy {
+    pub level: Option<String>,
+    pub embargo_until: Option<String>,
+    pub dbgap_study_id: Option<String>,
+}
+
+impl AccessConfig {
+    /// The normalized `access` subdocument for an enriched file: `level`,
+    /// plus `embargo_until` (the latest of the DCC policy's and any embedded
+    /// collection's), `dbgap_study_id`, and `url` when known.
+    pub fn resolve(&self, file: &Document) -> Document {
+        let dcc = file
+            .get_document("dcc")
+            .and_then(|dcc| dcc.get_str("dcc_abbreviation"))
+            .or_else(|_| file.get_str("submission"))
+            .unwrap_or_default();
+        let policy = self.dcc.get(dcc);
+
+        let own = non_empty(file, "data_access_level").map(str::to_ascii_lowercase);
+        let floor = policy
+            .and_then(|p| p.level.as_deref())
+            .map(str::to_ascii_lowercase);
+        let level = match (own, floor) {
+            (Some(own), Some(floor)) if rank(&floor) > rank(&own) => floor,
+            (Some(own), _) => own,
+            (None, Some(floor)) => floor,
+            (None, None) => "open".to_string(),
+        };
+
+        let mut embargo = policy.and_then(|p| p.embargo_until.clone());
+        if let Ok(collections) = file.get_array("collections") {
+            for coll in collections.iter().filter_map(Bson::as_document) {
+                if let Some(until) = non_empty(coll, "embargo_until") {
+                    // ISO 8601 dates order lexicographically
+                    if embargo.as_deref().is_none_or(|latest| until > latest) {
+                        embargo = Some(until.to_string());
+                    }
+                }
+            }
+        }
+
+        let mut access = doc! { "level": level };
+        if let Some(until) = embargo {
+            access.insert("embargo_until", until);
+        }
+        let study = non_empty(file, "dbgap_study_id")
+            .map(str::to_string)
+            .or_else(|| policy.and_then(|p| p.dbgap_study_id.clone()));
+        if let Some(study) = study {
+            access.insert("dbgap_study_id", study);
+        }
+        if let Some(url) = non_empty(file, "access_url").or_else(|| non_empty(file, "drs_uri")) {
+            access.insert("url", url);
+        }
+        access
+    }
+}
+
+/// Position in [`LEVELS`]; unrecognized levels rank as most restrictive.
+fn rank(level: &str) -> usize {
+    LEVELS
+        .iter()
+        .position(|known| *known == level)
+        .unwrap_or(LEVELS.len())
+}
+
+fn non_empty<'d>(doc: &'d Document, field: &str) -> Option<&'d str> {
+    doc.get_str(field).ok().filter(|value| !value.is_empty())
+}
@@ -0,0 +1,224 @@
+use anyhow::{Context, Result};
+use md5::{Digest, Md5};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::time::{Duration, SystemTime};
+
+use crate::cli::{flag, value};
+use crate::ingest::hex;
+use crate::search::{atlas_definition, AtlasSearchConfig};
+use crate::spec::EnrichmentSpec;
+
+const ADMIN_HOST: &str = "https://cloud.mongodb.com";
+const ADMIN_PATH: &str = "/api/atlas/v2";
+
+/// Versioned media type the Admin API answers with.
+const ADMIN_MEDIA_TYPE: &str = "application/vnd.atlas.2024-05-30+json";
+
+/// The Atlas Admin API, authenticated with a programmatic API key by HTTP
+/// digest.
+struct AdminApi {
+    agent: ureq::Agent,
+    public_key: String,
+    private_key: String,
+}
+
+impl AdminApi {
+    fn from_env() -> Result<Self> {
+        Ok(AdminApi {
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .build(),
+            public_key: env::var("ATLAS_PUBLIC_KEY")
+                .context("ATLAS_PUBLIC_KEY must be set to an Atlas API public key")?,
+            private_key: env::var("ATLAS_PRIVATE_KEY")
+                .context("ATLAS_PRIVATE_KEY must be set to an Atlas API private key")?,
+        })
+    }
+
+    /// Send a request to `path` under the v2 API and return the JSON body.
+    /// The first attempt is unauthenticated to obtain the digest challenge.
+    fn request(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Value> {
+        let uri = format!("{}{}", ADMIN_PATH, path);
+        let url = format!("{}{}", ADMIN_HOST, uri);
+        let send = |authorization: Option<&str>| {
+            let mut request = self
+                .agent
+                .request(method, &url)
+                .set("Accept", ADMIN_MEDIA_TYPE);
+            if let Some(authorization) = authorization {
+                request = request.set("Authorization", authorization);
+            }
+            let response = match body {
+                Some(body) => request
+                    .set("Content-Type", "application/json")
+                    .send_string(&body.to_string()),
+                None => request.call(),
+            };
+            // Boxed: ureq's error carries the whole response
+            response.map_err(Box::new)
+        };
+
+        let challenge = match send(None).map_err(|e| *e) {
+            Err(ureq::Error::Status(401, response)) => response
+                .header("WWW-Authenticate")
+                .unwrap_or_default()
+                .to_string(),
+            other => return json_body(other),
+        };
+        let authorization = digest_authorization(
+            &self.public_key,
+            &self.private_key,
+            method,
+            &uri,
+            &challenge,
+        )?;
+        json_body(send(Some(&authorization)).map_err(|e| *e))
+    }
+}
+
+fn json_body(response: Result<ureq::Response, ureq::Error>) -> Result<Value> {
+    match response {
+        Ok(response) => {
+            let text = response.into_string()?;
+            if text.trim().is_empty() {
+                Ok(Value::Null)
+            } else {
+                Ok(serde_json::from_str(&text)?)
+            }
+        }
+        Err(ureq::Error::Status(status, response)) => anyhow::bail!(
+            "Atlas Admin API returned {}: {}",
+            status,
+            response.into_string().unwrap_or_default()
+        ),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Answer an RFC 7616 `Digest` challenge (MD5, `qop=auth`) for one request.
+fn digest_authorization(
+    username: &str,
+    password: &str,
+    method: &str,
+    uri: &str,
+    challenge: &str,
+) -> Result<String> {
+    let params: HashMap<&str, &str> = challenge
+        .trim()
+        .trim_start_matches("Digest")
+        .split(',')
+        .filter_map(|param| {
+            let (key, value) = param.trim().split_once('=')?;
+            Some((key, value.trim_matches('"')))
+        })
+        .collect();
+    let realm = params
+        .get("realm")
+        .context("Atlas Admin API sent no digest challenge")?;
+    let nonce = params
+        .get("nonce")
+        .context("Atlas Admin API sent no digest nonce")?;
+
+    let md5 = |text: String| hex(&Md5::digest(text.as_bytes()));
+    let cnonce = md5(format!("{:?}", SystemTime::now()));
+    let nc = "00000001";
+    let ha1 = md5(format!("{}:{}:{}", username, realm, password));
+    let ha2 = md5(format!("{}:{}", method, uri));
+    let response = md5(format!("{}:{}:{}:{}:auth:{}", ha1, nonce, nc, cnonce, ha2));
+    Ok(format!(
+        "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", qop=auth, nc={}, \
+         cnonce=\"{}\", response=\"{}\", algorithm=MD5",
+        username, realm, nonce, uri, nc, cnonce, response
+    ))
+}
+
+/// `search-index apply [--project ID] [--cluster NAME] [--name INDEX]
+/// [--dry-run]` creates the Atlas Search index over the materialized files,
+/// or updates its definition if it exists. Flags override `[atlas_search]`.
+pub fn command(
+    database: &str,
+    config: &AtlasSearchConfig,
+    spec: &EnrichmentSpec,
+    args: &[String],
+) -> Result<()> {
+    match args.first().map(String::as_str) {
+        Some("apply") => {
+            let args = &args[1..];
+            let config = AtlasSearchConfig {
+                project_id: value(args, "--project").or_else(|| config.project_id.clone()),
+                cluster: value(args, "--cluster").or_else(|| config.cluster.clone()),
+                index_name: value(args, "--name").unwrap_or_else(|| config.index_name.clone()),
+                collection: config.collection.clone(),
+            };
+            if flag(args, "--dry-run") {
+                println!("{}", serde_json::to_string_pretty(&atlas_definition(spec))?);
+                return Ok(());
+            }
+            apply(database, &config, spec)
+        }
+        Some(other) => anyhow::bail!("Unknown search-index command: {}", other),
+        None => anyhow::bail!("Usage: search-index apply [--project ID] [--cluster NAME]"),
+    }
+}
+
+/// Create or update the configured Atlas Search index with the current
+/// definition. Atlas builds it in the background; queries keep using the
+/// previous definition until the new one is ready.
+pub fn apply(database: &str, config: &AtlasSearchConfig, spec: &EnrichmentSpec) -> Result<()> {
+    let project = config
+        .project_id
+        .as_deref()
+        .context("No Atlas project; set [atlas_search] project_id or pass --project")?;
+    let cluster = config
+        .cluster
+        .as_deref()
+        .context("No Atlas cluster; set [atlas_search] cluster or pass --cluster")?;
+    let api = AdminApi::from_env()?;
+    let indexes = format!("/groups/{}/clusters/{}/search/indexes", project, cluster);
+    let definition = atlas_definition(spec);
+
+    let existing = api.request(
+        "GET",
+        &format!("{}/{}/{}", indexes, database, config.collection),
+        None,
+    )?;
+    let index_id = existing
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|index| index["name"] == config.index_name.as_str())
+        .and_then(|index| index["indexID"].as_str());
+    match index_id {
+        Some(id) => {
+            api.request(
+                "PATCH",
+                &format!("{}/{}", indexes, id),
+                Some(&json!({ "definition": definition })),
+            )?;
+            println!(
+                "Updated Atlas Search index {} on {}.{}",
+                config.index_name, database, config.collection
+            );
+        }
+        None => {
+            api.request(
+                "POST",
+                &indexes,
+                Some(&json!({
+                    "database": database,
+                    "collectionName": config.collection,
+                    "name": config.index_name,
+                    "type": "search",
+                    "definition": definition,
+                })),
+            )?;
+            println!(
+                "Created Atlas Search index {} on {}.{}",
+                config.index_name, database, config.collection
+            );
+        }
+    }
+    Ok(())
+}
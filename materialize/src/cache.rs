@@ -0,0 +1,118 @@
+use anyhow::{Context, Result};
+use bson::{doc, Bson, Document};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind};
+use std::path::PathBuf;
+
+/// zstd level for snapshots; favours load speed over the last few percent.
+const LEVEL: i32 = 3;
+
+/// Zstandard-compressed snapshots of in-memory lookup maps.
+///
+/// Each (table, submission) pair is one file under `dir`, holding a header
+/// with the fingerprint of the rows it was loaded from followed by one BSON
+/// document per map entry. A later run whose table has the same fingerprint
+/// reads the snapshot instead of querying MongoDB.
+pub struct LookupCache {
+    dir: PathBuf,
+}
+
+impl LookupCache {
+    pub fn open(dir: &str) -> Result<Self> {
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+        Ok(LookupCache { dir })
+    }
+
+    fn path(&self, table: &str, submission: &Option<String>) -> PathBuf {
+        let submission = submission.as_deref().unwrap_or("_all");
+        let safe: String = submission
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir.join(format!("{}.{}.bson.zst", table, safe))
+    }
+
+    /// The snapshot's entries as `(a, b, value)`, if one exists for
+    /// `fingerprint`. An unreadable snapshot counts as missing.
+    pub fn load(
+        &self,
+        table: &str,
+        submission: &Option<String>,
+        fingerprint: &str,
+    ) -> Result<Option<Vec<(String, String, Bson)>>> {
+        let path = self.path(table, submission);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        match read(BufReader::new(zstd::Decoder::new(file)?), fingerprint) {
+            Ok(entries) => Ok(entries),
+            Err(e) => {
+                println!(
+                    "  Warning: ignoring unreadable cache {}: {}",
+                    path.display(),
+                    e
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Replace the snapshot for (`table`, `submission`). Written to a
+    /// temporary file and renamed, so an interrupted run never leaves a
+    /// truncated snapshot behind.
+    pub fn store(
+        &self,
+        table: &str,
+        submission: &Option<String>,
+        fingerprint: &str,
+        entries: impl ExactSizeIterator<Item = Document>,
+    ) -> Result<()> {
+        let path = self.path(table, submission);
+        let partial = path.with_extension("zst.partial");
+        let mut encoder = zstd::Encoder::new(BufWriter::new(File::create(&partial)?), LEVEL)?;
+        doc! { "fingerprint": fingerprint, "entries": entries.len() as i64 }
+            .to_writer(&mut encoder)?;
+        for entry in entries {
+            entry.to_writer(&mut encoder)?;
+        }
+        encoder.finish()?.into_inner()?.sync_all()?;
+        fs::rename(&partial, &path)?;
+        Ok(())
+    }
+}
+
+fn read(
+    mut reader: impl std::io::Read,
+    fingerprint: &str,
+) -> Result<Option<Vec<(String, String, Bson)>>> {
+    let header = Document::from_reader(&mut reader)?;
+    if header.get_str("fingerprint") != Ok(fingerprint) {
+        return Ok(None);
+    }
+    let count = header.get_i64("entries")? as usize;
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut entry = Document::from_reader(&mut reader)?;
+        let value = entry.remove("v").unwrap_or(Bson::Null);
+        entries.push((
+            entry.get_str("a")?.to_string(),
+            entry.get_str("b")?.to_string(),
+            value,
+        ));
+    }
+    Ok(Some(entries))
+}
+
+/// One snapshot entry.
+pub fn entry(a: &str, b: &str, value: impl Into<Bson>) -> Document {
+    doc! { "a": a, "b": b, "v": value.into() }
+}
@@ -0,0 +1,79 @@
+use anyhow::Result;
+use bson::{doc, Document};
+use mongodb::sync::Collection;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use crate::ingest::hex;
+
+/// Field holding the hash of an enriched file's content.
+pub const HASH_FIELD: &str = "content_hash";
+
+/// Fields that differ between runs without the file changing.
+const VOLATILE_FIELDS: [&str; 4] = ["_id", "materialized_at", "migrated_at", HASH_FIELD];
+
+type FileKey = (String, String);
+
+/// SHA-256 of an enriched file without its volatile fields. Enrichment is
+/// deterministic, so an unchanged source row hashes the same on every run.
+pub fn content_hash(file: &Document) -> String {
+    let mut content = file.clone();
+    for field in VOLATILE_FIELDS {
+        content.remove(field);
+    }
+    let bytes = bson::to_vec(&content).unwrap_or_default();
+    hex(&Sha256::digest(&bytes))
+}
+
+pub fn file_key(file: &Document) -> FileKey {
+    (
+        file.get_str("id_namespace").unwrap_or_default().to_string(),
+        file.get_str("local_id").unwrap_or_default().to_string(),
+    )
+}
+
+/// The content hash of every file in `files` matching `query`, by key.
+/// Files written before hashes were stamped map to an empty hash, so they
+/// are rewritten once.
+pub fn existing_hashes(
+    files: &Collection<Document>,
+    query: &Document,
+) -> Result<HashMap<FileKey, String>> {
+    let mut hashes = HashMap::new();
+    for doc in files
+        .find(query.clone())
+        .projection(doc! { "id_namespace": 1, "local_id": 1, HASH_FIELD: 1 })
+        .run()?
+    {
+        let doc = doc?;
+        let hash = doc.get_str(HASH_FIELD).unwrap_or_default().to_string();
+        hashes.insert(file_key(&doc), hash);
+    }
+    Ok(hashes)
+}
+
+/// Delete the given files and their relations, before their new content is
+/// inserted or because they left the source.
+pub fn remove_files(
+    files: &Collection<Document>,
+    relations: &Collection<Document>,
+    keys: &[FileKey],
+) -> Result<u64> {
+    if keys.is_empty() {
+        return Ok(0);
+    }
+    let (file_keys, relation_keys): (Vec<Document>, Vec<Document>) = keys
+        .iter()
+        .map(|(ns, id)| {
+            (
+                doc! { "id_namespace": ns, "local_id": id },
+                doc! { "file_id_namespace": ns, "file_local_id": id },
+            )
+        })
+        .unzip();
+    relations.delete_many(doc! { "$or": relation_keys }).run()?;
+    Ok(files
+        .delete_many(doc! { "$or": file_keys })
+        .run()?
+        .deleted_count)
+}
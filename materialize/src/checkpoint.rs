@@ -0,0 +1,59 @@
+use anyhow::Result;
+use bson::{doc, Bson, DateTime, Document};
+use mongodb::options::ReplaceOptions;
+use mongodb::sync::{Collection, Database};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const CHECKPOINTS_COLLECTION: &str = "materialize_checkpoints";
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+/// Trap SIGINT/SIGTERM so the write loop can stop at a batch boundary. A
+/// second signal exits immediately.
+pub fn install_handler() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if SHUTDOWN.swap(true, Ordering::SeqCst) {
+            eprintln!("\nForced exit");
+            std::process::exit(130);
+        }
+        eprintln!("\nShutdown requested; finishing the in-flight batch...");
+    })?;
+    Ok(())
+}
+
+/// Whether a shutdown signal has been received.
+pub fn requested() -> bool {
+    SHUTDOWN.load(Ordering::SeqCst)
+}
+
+fn key(submission: &Option<String>) -> Bson {
+    submission.as_deref().map_or(Bson::Null, Bson::from)
+}
+
+/// Record how far an interrupted run got writing `files`.
+pub fn save(db: &Database, submission: &Option<String>, written: u64) -> Result<()> {
+    let coll: Collection<Document> = db.collection(CHECKPOINTS_COLLECTION);
+    coll.replace_one(
+        doc! { "_id": key(submission) },
+        doc! {
+            "_id": key(submission),
+            "written": written as i64,
+            "interrupted_at": DateTime::now(),
+        },
+    )
+    .with_options(ReplaceOptions::builder().upsert(true).build())
+    .run()?;
+    Ok(())
+}
+
+/// The checkpoint left by an interrupted run, if any.
+pub fn load(db: &Database, submission: &Option<String>) -> Result<Option<Document>> {
+    let coll: Collection<Document> = db.collection(CHECKPOINTS_COLLECTION);
+    Ok(coll.find_one(doc! { "_id": key(submission) }).run()?)
+}
+
+pub fn clear(db: &Database, submission: &Option<String>) -> Result<()> {
+    let coll: Collection<Document> = db.collection(CHECKPOINTS_COLLECTION);
+    coll.delete_one(doc! { "_id": key(submission) }).run()?;
+    Ok(())
+}
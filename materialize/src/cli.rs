@@ -0,0 +1,323 @@
+use anyhow::Result;
+use std::env;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::config::Config;
+use crate::enrich::{EmbedDcc, MissingDcc, OnMiss};
+use crate::latency::DEFAULT_SLOW_BATCH_MS;
+use crate::lookup::VocabScope;
+#[cfg(feature = "notify")]
+use crate::notify::NotifyFormat;
+use crate::output::Output;
+use crate::publish::DEFAULT_RETAIN_HOURS;
+use crate::sample::Sample;
+use crate::sharding::ShardKey;
+use crate::size_policy::parse_size;
+use crate::views::View;
+use crate::workers::Workers;
+
+/// Default `--batch-size`.
+pub const DEFAULT_BATCH_SIZE: usize = 10000;
+
+/// Default `--find-batch-size`.
+pub const DEFAULT_FIND_BATCH_SIZE: u32 = 50000;
+
+/// Command-line options for a materialization run.
+pub struct Options {
+    /// Subcommand (e.g. `runs`) given as the first argument, if any
+    pub command: Option<String>,
+    /// Arguments following the subcommand
+    pub command_args: Vec<String>,
+    /// Only materialize files for this submission
+    pub submission: Option<String>,
+    /// Materialize every submission owned by this DCC abbreviation
+    pub dcc: Option<String>,
+    /// Materialize the submissions listed in this file, one per line
+    pub submissions_file: Option<String>,
+    /// Submissions left out of the run, from repeated `--exclude-submission`
+    pub exclude_submissions: Vec<String>,
+    /// Materialize only this worker's share of the selected submissions
+    pub workers: Option<Workers>,
+    /// Back lookup maps with an on-disk store at this path
+    pub lookup_dir: Option<String>,
+    /// Snapshot in-memory lookup maps to compressed files under this path
+    pub cache_dir: Option<String>,
+    /// Reload every on-disk or cached lookup map instead of reusing unchanged ones
+    pub refresh_lookups: bool,
+    /// UBERON ontology used for anatomy ancestors
+    pub uberon: Option<String>,
+    /// OBI ontology used for assay type ancestors
+    pub obi: Option<String>,
+    /// Trace the enrichment of a single file instead of materializing
+    pub explain: Option<String>,
+    /// Write files ordered by (id_namespace, local_id)
+    pub sort: bool,
+    /// Drop files with a duplicate (id_namespace, local_id), keeping the first
+    pub dedupe: bool,
+    /// Sort/dedupe with an external merge that spills runs under this directory
+    pub spill_dir: Option<String>,
+    /// Documents larger than this many bytes switch to the sidecar strategy
+    pub max_doc_size: Option<usize>,
+    /// Capture a CPU profile of the enrichment phase under this directory
+    #[cfg(feature = "profiling")]
+    pub profile_cpu: Option<String>,
+    /// Post the run summary to this URL when the run ends
+    #[cfg(feature = "notify")]
+    pub notify_webhook: Option<String>,
+    /// Shape of the `--notify-webhook` payload
+    #[cfg(feature = "notify")]
+    pub notify_format: NotifyFormat,
+    /// Create or update the `[atlas_search]` index after the run
+    #[cfg(feature = "atlas")]
+    pub apply_search_index: bool,
+    /// Warn when writing one batch takes longer than this
+    pub slow_batch_ms: u64,
+    /// Documents per insert batch
+    pub batch_size: usize,
+    /// Documents per cursor batch when reading `file`
+    pub find_batch_size: u32,
+    /// Read at most this many `file` documents a second
+    pub max_read_ops_per_sec: Option<u64>,
+    /// Write at most this many documents a second
+    pub max_write_ops_per_sec: Option<u64>,
+    /// Size of the enrichment thread pool (all cores if unset)
+    pub threads: Option<usize>,
+    /// Append each run's JSON report to this file (`-` for stdout)
+    pub report: Option<String>,
+    /// Continue the interrupted run recorded in the checkpoint
+    pub resume: bool,
+    /// Fail the run when a lookup table has duplicate keys
+    pub strict: bool,
+    /// Whether vocabulary references resolve within their submission or
+    /// against every submission's terms
+    pub vocab_scope: VocabScope,
+    /// Replace a submission outside a transaction even on a replica set
+    pub no_transaction: bool,
+    /// Policy for files whose submission has no `dcc` document
+    pub on_missing_dcc: Option<MissingDcc>,
+    /// How much of its DCC each file embeds
+    pub embed_dcc: EmbedDcc,
+    /// What to do with vocabulary ids that don't resolve
+    pub on_miss: OnMiss,
+    /// Embed each file's biosamples once, in a top-level `biosamples` array
+    pub hoist_biosamples: bool,
+    /// Derive `mime_type_inferred` for files without a `mime_type`
+    pub infer_mime_type: bool,
+    /// Record on each embedded row the source table and submission it came
+    /// from and when it was loaded, in `_provenance`
+    pub provenance: bool,
+    /// Publish `files` as a new generation behind `files_current` after the run
+    pub publish: bool,
+    /// Hours superseded generations are kept when publishing
+    pub retain_hours: u64,
+    /// Hide progress bars
+    pub quiet: bool,
+    /// Enrich only a deterministic sample of files into `files_sample`
+    pub sample: Option<Sample>,
+    /// Where enriched files are written
+    pub output: Output,
+    /// Collections to materialize from the loaded lookup tables
+    pub views: Vec<View>,
+    /// Shard the output collection on these fields before writing
+    pub shard_key: Option<ShardKey>,
+    /// Compare each file's content hash with the existing output and only
+    /// write the files that changed
+    pub skip_unchanged: bool,
+    /// Write `files_controlled` and a redacted `files_public` instead of
+    /// `files`
+    pub tiers: bool,
+    /// Read the raw C2M2 tables from this deployment instead of `DATABASE_URL`
+    pub source_uri: Option<String>,
+    /// Write `files` and the derived collections to this deployment instead
+    /// of `DATABASE_URL`
+    pub target_uri: Option<String>,
+    /// CA certificate(s) the servers' TLS certificates are checked against
+    pub tls_ca_file: Option<String>,
+    /// PEM file with the client certificate and private key for mutual TLS
+    pub tls_cert_key_file: Option<String>,
+    /// Authentication mechanism, e.g. `SCRAM-SHA-256` or `MONGODB-X509`
+    pub auth_mechanism: Option<String>,
+    /// Database the credentials are defined in
+    pub auth_source: Option<String>,
+    /// Settings from the `--config` file
+    pub config: Config,
+}
+
+impl Options {
+    pub fn parse() -> Result<Self> {
+        Self::from_args(env::args().collect())
+    }
+
+    /// Parse options from a full argument list, program name first.
+    pub fn from_args(args: Vec<String>) -> Result<Self> {
+        let command = args.get(1).filter(|a| !a.starts_with("--")).cloned();
+        let command_args = match command {
+            Some(_) => args[2..].to_vec(),
+            None => Vec::new(),
+        };
+        let sample = Sample::parse(number(&args, "--sample")?, number(&args, "--sample-frac")?)?;
+        if sample.is_some() && flag(&args, "--resume") {
+            anyhow::bail!("--resume cannot be combined with --sample");
+        }
+        let selectors = ["--submission", "--dcc", "--submissions-file"];
+        if selectors.iter().filter(|s| flag(&args, s)).count() > 1 {
+            anyhow::bail!("Only one of --submission, --dcc, and --submissions-file may be given");
+        }
+        if cfg!(not(feature = "profiling")) && flag(&args, "--profile-cpu") {
+            anyhow::bail!("--profile-cpu requires building with `--features profiling`");
+        }
+        if cfg!(not(feature = "notify")) && flag(&args, "--notify-webhook") {
+            anyhow::bail!("--notify-webhook requires building with `--features notify`");
+        }
+        if cfg!(not(feature = "atlas")) && flag(&args, "--apply-search-index") {
+            anyhow::bail!("--apply-search-index requires building with `--features atlas`");
+        }
+        let options = Options {
+            command,
+            command_args,
+            submission: value(&args, "--submission"),
+            dcc: value(&args, "--dcc"),
+            submissions_file: value(&args, "--submissions-file"),
+            exclude_submissions: values(&args, "--exclude-submission"),
+            workers: Workers::parse(number(&args, "--workers")?, number(&args, "--worker-id")?)?,
+            lookup_dir: value(&args, "--lookup-dir"),
+            cache_dir: value(&args, "--cache-dir"),
+            refresh_lookups: flag(&args, "--refresh-lookups"),
+            uberon: value(&args, "--uberon"),
+            obi: value(&args, "--obi"),
+            explain: value(&args, "--explain"),
+            sort: flag(&args, "--sort"),
+            dedupe: flag(&args, "--dedupe"),
+            spill_dir: value(&args, "--spill-dir"),
+            max_doc_size: value(&args, "--max-doc-size")
+                .map(|size| parse_size(&size))
+                .transpose()?,
+            #[cfg(feature = "profiling")]
+            profile_cpu: value(&args, "--profile-cpu"),
+            #[cfg(feature = "notify")]
+            notify_webhook: value(&args, "--notify-webhook"),
+            #[cfg(feature = "notify")]
+            notify_format: NotifyFormat::parse(
+                value(&args, "--notify-format").as_deref().unwrap_or("json"),
+            )?,
+            #[cfg(feature = "atlas")]
+            apply_search_index: flag(&args, "--apply-search-index"),
+            slow_batch_ms: number(&args, "--slow-batch-ms")?.unwrap_or(DEFAULT_SLOW_BATCH_MS),
+            batch_size: number(&args, "--batch-size")?.unwrap_or(DEFAULT_BATCH_SIZE),
+            find_batch_size: number(&args, "--find-batch-size")?.unwrap_or(DEFAULT_FIND_BATCH_SIZE),
+            max_read_ops_per_sec: number(&args, "--max-read-ops-per-sec")?,
+            max_write_ops_per_sec: number(&args, "--max-write-ops-per-sec")?,
+            threads: number(&args, "--threads")?,
+            report: value(&args, "--report"),
+            resume: flag(&args, "--resume"),
+            strict: flag(&args, "--strict"),
+            vocab_scope: value(&args, "--vocab-scope")
+                .map(|scope| VocabScope::parse(&scope))
+                .transpose()?
+                .unwrap_or_default(),
+            no_transaction: flag(&args, "--no-transaction"),
+            on_missing_dcc: value(&args, "--on-missing-dcc")
+                .map(|policy| MissingDcc::parse(&policy))
+                .transpose()?,
+            embed_dcc: value(&args, "--embed-dcc")
+                .map(|mode| EmbedDcc::parse(&mode))
+                .transpose()?
+                .unwrap_or_default(),
+            on_miss: value(&args, "--on-miss")
+                .map(|policy| OnMiss::parse(&policy))
+                .transpose()?
+                .unwrap_or_default(),
+            hoist_biosamples: flag(&args, "--hoist-biosamples"),
+            infer_mime_type: flag(&args, "--infer-mime-type"),
+            provenance: flag(&args, "--provenance"),
+            publish: flag(&args, "--publish"),
+            retain_hours: number(&args, "--retain-hours")?.unwrap_or(DEFAULT_RETAIN_HOURS),
+            quiet: flag(&args, "--quiet"),
+            sample,
+            output: Output::parse(
+                value(&args, "--output")
+                    .or_else(|| value(&args, "--sink"))
+                    .as_deref(),
+                value(&args, "--uri").as_deref(),
+            )?,
+            views: View::parse_list(value(&args, "--views").as_deref())?,
+            shard_key: value(&args, "--shard-key")
+                .map(|key| ShardKey::parse(&key))
+                .transpose()?,
+            skip_unchanged: flag(&args, "--skip-unchanged"),
+            tiers: flag(&args, "--tiers"),
+            source_uri: value(&args, "--source-uri"),
+            target_uri: value(&args, "--target-uri"),
+            tls_ca_file: value(&args, "--tls-ca-file"),
+            tls_cert_key_file: value(&args, "--tls-cert-key-file"),
+            auth_mechanism: value(&args, "--auth-mechanism"),
+            auth_source: value(&args, "--auth-source"),
+            config: Config::load(value(&args, "--config").as_deref())?,
+        };
+        if options.publish && (options.sample.is_some() || !matches!(options.output, Output::Mongo))
+        {
+            anyhow::bail!("--publish needs a run that writes the files collection");
+        }
+        if options.shard_key.is_some() && !matches!(options.output, Output::Mongo) {
+            anyhow::bail!("--shard-key needs a run that writes to MongoDB");
+        }
+        if options.skip_unchanged
+            && (options.sample.is_some()
+                || options.resume
+                || options.tiers
+                || !matches!(options.output, Output::Mongo))
+        {
+            anyhow::bail!("--skip-unchanged needs a full MongoDB run without --resume or --tiers");
+        }
+        if options.workers.is_some() && (options.sample.is_some() || options.publish) {
+            anyhow::bail!("--workers cannot be combined with --sample or --publish");
+        }
+        if options.tiers {
+            if options.sample.is_some()
+                || options.publish
+                || !matches!(options.output, Output::Mongo)
+            {
+                anyhow::bail!("--tiers needs a full run written to MongoDB without --publish");
+            }
+            if options.config.redact.is_empty() {
+                anyhow::bail!("--tiers needs [[redact.fields]] in the config file");
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// The argument following `flag`, if the flag was given.
+pub fn value(args: &[String], flag: &str) -> Option<String> {
+    args.iter()
+        .position(|a| a == flag)
+        .and_then(|i| args.get(i + 1).cloned())
+}
+
+/// The arguments following every occurrence of a repeatable `flag`.
+pub fn values(args: &[String], flag: &str) -> Vec<String> {
+    args.windows(2)
+        .filter(|pair| pair[0] == flag)
+        .map(|pair| pair[1].clone())
+        .collect()
+}
+
+/// The argument following `flag` parsed as a number, if the flag was given.
+pub fn number<T>(args: &[String], flag: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value(args, flag)
+        .map(|v| {
+            v.parse()
+                .map_err(|e| anyhow::anyhow!("{} must be a number: {}", flag, e))
+        })
+        .transpose()
+}
+
+/// Whether the boolean `flag` was given.
+pub fn flag(args: &[String], flag: &str) -> bool {
+    args.iter().any(|a| a == flag)
+}
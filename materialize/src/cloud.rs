@@ -0,0 +1,85 @@
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use object_store::aws::AmazonS3Builder;
+use object_store::gcp::GoogleCloudStorageBuilder;
+use object_store::path::Path as ObjectPath;
+use object_store::ObjectStore;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// A bucket prefix on S3 (`s3://bucket/prefix/`) or Google Cloud Storage
+/// (`gs://bucket/prefix/`). Credentials come from the provider's standard
+/// chain: for S3 the `AWS_*` environment variables, web identity tokens, or
+/// the instance/task role; for GCS `GOOGLE_SERVICE_ACCOUNT` /
+/// `GOOGLE_APPLICATION_CREDENTIALS`, or gcloud's application default
+/// credentials.
+pub struct Bucket {
+    store: Box<dyn ObjectStore>,
+    prefix: String,
+    url: String,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl Bucket {
+    pub fn open(url: &str) -> Result<Self> {
+        let (scheme, rest) = url
+            .split_once("://")
+            .with_context(|| format!("{} is not an s3:// or gs:// URL", url))?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        let store: Box<dyn ObjectStore> = match scheme {
+            "s3" => Box::new(
+                AmazonS3Builder::from_env()
+                    .with_bucket_name(bucket)
+                    .build()?,
+            ),
+            "gs" => Box::new(
+                GoogleCloudStorageBuilder::from_env()
+                    .with_bucket_name(bucket)
+                    .build()?,
+            ),
+            other => anyhow::bail!("Unsupported object store scheme {}://", other),
+        };
+        Ok(Bucket {
+            store,
+            prefix: prefix.to_string(),
+            url: url.to_string(),
+            runtime: tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?,
+        })
+    }
+
+    /// The object key the URL names, when it names a single object.
+    pub fn key(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Keys of every object under the prefix.
+    pub fn list(&self) -> Result<Vec<String>> {
+        let prefix = (!self.prefix.is_empty()).then(|| ObjectPath::from(self.prefix.as_str()));
+        self.runtime.block_on(async {
+            let mut keys = Vec::new();
+            let mut objects = self.store.list(prefix.as_ref());
+            while let Some(object) = objects.next().await {
+                keys.push(object?.location.to_string());
+            }
+            Ok(keys)
+        })
+    }
+
+    /// Stream the object at `key` into a local file.
+    pub fn download(&self, key: &str, dest: &Path) -> Result<()> {
+        self.runtime
+            .block_on(async {
+                let mut out = File::create(dest)?;
+                let mut chunks = self.store.get(&ObjectPath::from(key)).await?.into_stream();
+                while let Some(chunk) = chunks.next().await {
+                    out.write_all(&chunk?)?;
+                }
+                out.flush()?;
+                Ok::<_, anyhow::Error>(())
+            })
+            .with_context(|| format!("downloading {} from {}", key, self.url))
+    }
+}
@@ -0,0 +1,170 @@
+use bson::{doc, Bson, Document};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// File fields stored as Int64 so range queries and the size index see one
+/// type. TSV ingestion loads every column as a string.
+pub const NUMERIC_FIELDS: [&str; 2] = ["size_in_bytes", "uncompressed_size_in_bytes"];
+
+/// File field stored as a BSON DateTime. A value that doesn't parse moves
+/// to `creation_time_raw` so `creation_time` holds a single type.
+pub const DATE_FIELD: &str = "creation_time";
+const DATE_RAW_FIELD: &str = "creation_time_raw";
+
+/// Timestamp layouts seen in submissions besides RFC 3339; times without an
+/// offset are taken as UTC.
+const DATETIME_FORMATS: [&str; 5] = [
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+    "%Y/%m/%d %H:%M:%S",
+];
+const OFFSET_FORMATS: [&str; 2] = ["%Y-%m-%dT%H:%M:%S%.f%#z", "%Y-%m-%d %H:%M:%S%.f%#z"];
+const DATE_FORMATS: [&str; 3] = ["%Y-%m-%d", "%Y/%m/%d", "%Y%m%d"];
+
+/// Unparseable values kept per field for the run report.
+const EXAMPLES_KEPT: usize = 5;
+
+/// Values the typing pass couldn't coerce, counted per field with a few
+/// examples. Shared by the enrichment workers.
+#[derive(Default)]
+pub struct Unparseable {
+    fields: Mutex<BTreeMap<String, (u64, Vec<String>)>>,
+}
+
+impl Unparseable {
+    fn record(&self, field: &str, value: &Bson) {
+        let mut fields = self.fields.lock().unwrap();
+        let (count, examples) = fields.entry(field.to_string()).or_default();
+        *count += 1;
+        if examples.len() < EXAMPLES_KEPT {
+            examples.push(match value {
+                Bson::String(s) => s.clone(),
+                other => other.to_string(),
+            });
+        }
+    }
+
+    /// `{ field: { count, examples } }` for every field with a bad value.
+    pub fn report(&self) -> Document {
+        self.fields
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(field, (count, examples))| {
+                (
+                    field.clone(),
+                    Bson::Document(doc! { "count": *count as i64, "examples": examples }),
+                )
+            })
+            .collect()
+    }
+
+    pub fn print(&self) {
+        for (field, (count, examples)) in self.fields.lock().unwrap().iter() {
+            println!(
+                "  Warning: {} unparseable {} values (e.g. {})",
+                count,
+                field,
+                examples.join(", ")
+            );
+        }
+    }
+}
+
+/// Coerce the numeric fields of a file to Int64. Values that aren't whole,
+/// non-negative numbers are left as submitted and recorded in `unparseable`.
+/// Returns the number of fields changed.
+pub fn coerce_numeric(file: &mut Document, unparseable: &Unparseable) -> usize {
+    let mut coerced = 0;
+    for field in NUMERIC_FIELDS {
+        let Some(value) = file.get(field) else {
+            continue;
+        };
+        if matches!(value, Bson::Int64(_) | Bson::Null) {
+            continue;
+        }
+        match to_int(value) {
+            Some(n) => {
+                file.insert(field, n);
+                coerced += 1;
+            }
+            None => unparseable.record(field, value),
+        }
+    }
+    coerced
+}
+
+fn to_int(value: &Bson) -> Option<i64> {
+    let n = match value {
+        Bson::Int32(n) => *n as i64,
+        Bson::Int64(n) => *n,
+        Bson::Double(f) => whole(*f)?,
+        Bson::String(s) => {
+            let s = s.trim();
+            match s.parse::<i64>() {
+                Ok(n) => n,
+                // Spreadsheet exports write sizes like "1.5E+9" or "1024.0"
+                Err(_) => whole(s.parse::<f64>().ok()?)?,
+            }
+        }
+        _ => return None,
+    };
+    (n >= 0).then_some(n)
+}
+
+fn whole(f: f64) -> Option<i64> {
+    (f.is_finite() && f.fract() == 0.0 && f.abs() < i64::MAX as f64).then_some(f as i64)
+}
+
+/// Store the file's `creation_time` as a BSON DateTime. Values that don't
+/// parse are moved to `creation_time_raw` and recorded in `unparseable`.
+/// Returns whether the field was converted.
+pub fn coerce_date(file: &mut Document, unparseable: &Unparseable) -> bool {
+    let Some(Bson::String(raw)) = file.get(DATE_FIELD) else {
+        return false;
+    };
+    if raw.trim().is_empty() {
+        return false;
+    }
+    match parse_date(raw) {
+        Some(time) => {
+            file.insert(DATE_FIELD, bson::DateTime::from_chrono(time));
+            true
+        }
+        None => {
+            let raw = file.remove(DATE_FIELD).unwrap_or(Bson::Null);
+            unparseable.record(DATE_FIELD, &raw);
+            file.insert(DATE_RAW_FIELD, raw);
+            false
+        }
+    }
+}
+
+/// Parse an ISO-ish timestamp or date.
+pub fn parse_date(text: &str) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.with_timezone(&Utc));
+    }
+    if let Some(time) = OFFSET_FORMATS
+        .iter()
+        .find_map(|format| DateTime::parse_from_str(text, format).ok())
+    {
+        return Some(time.with_timezone(&Utc));
+    }
+    let naive = text.strip_suffix('Z').unwrap_or(text);
+    if let Some(time) = DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(naive, format).ok())
+    {
+        return Some(time.and_utc());
+    }
+    DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(text, format).ok())
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|time| time.and_utc())
+}
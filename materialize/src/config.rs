@@ -0,0 +1,96 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::fs;
+
+use crate::access::AccessConfig;
+use crate::connection::MongoConfig;
+use crate::drs::DrsConfig;
+use crate::enrichers::EnricherConfig;
+use crate::error::MaterializeError;
+use crate::indexes::{IndexConfig, MAX_INDEXES};
+use crate::normalize::NormalizeConfig;
+use crate::overrides::OverrideConfig;
+use crate::preview::PreviewConfig;
+use crate::projection::ProjectionConfig;
+use crate::redact::RedactConfig;
+use crate::scrub::ScrubConfig;
+use crate::search::AtlasSearchConfig;
+use crate::smoke::SmokeQuery;
+use crate::spec::EnrichmentSpec;
+
+/// Settings read from the `--config` TOML file. Every section is optional and
+/// falls back to built-in defaults.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub preview: PreviewConfig,
+    pub projections: ProjectionConfig,
+    pub drs: DrsConfig,
+    pub access: AccessConfig,
+    pub normalize: NormalizeConfig,
+    pub overrides: OverrideConfig,
+    pub scrub: ScrubConfig,
+    pub redact: RedactConfig,
+    pub enrichment: EnrichmentSpec,
+    pub enrichers: EnricherConfig,
+    pub smoke: Vec<SmokeQuery>,
+    pub mongo: MongoConfig,
+    pub atlas_search: AtlasSearchConfig,
+    pub indexes: IndexConfig,
+}
+
+impl Config {
+    pub fn load(path: Option<&str>) -> Result<Self, MaterializeError> {
+        match path {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .with_context(|| format!("reading {}", path))
+                    .map_err(MaterializeError::Config)?;
+                let config: Config = toml::from_str(&text)
+                    .with_context(|| format!("parsing {}", path))
+                    .map_err(MaterializeError::Config)?;
+                config.validate().map_err(MaterializeError::Config)?;
+                Ok(config)
+            }
+            None => Ok(Config::default()),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.redact.validate()?;
+        // MongoDB refuses the indexes past its limit only once a run has
+        // written every file, so the count is checked before anything runs
+        let count = crate::file_indexes(self, false).len() + 1;
+        if count > MAX_INDEXES {
+            bail!(
+                "files would need {} indexes counting _id, more than MongoDB's {}; \
+                 drop [[indexes.extra]] entries",
+                count,
+                MAX_INDEXES
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_fit_the_index_limit() {
+        let mut config = Config::default();
+        config.indexes.case_insensitive = true;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn too_many_indexes_fail_validation() {
+        let extra: String = (0..40)
+            .map(|i| format!("[[indexes.extra]]\nkeys = {{ field_{} = 1 }}\n", i))
+            .collect();
+        let config: Config = toml::from_str(&extra).unwrap();
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("more than MongoDB's 64"), "{}", error);
+    }
+}
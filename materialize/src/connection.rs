@@ -0,0 +1,234 @@
+use anyhow::{bail, Context, Result};
+use mongodb::options::{
+    Acknowledgment, AuthMechanism, ClientOptions, Compressor, Credential, ReadPreference,
+    SelectionCriteria, Tls, TlsOptions, WriteConcern,
+};
+use mongodb::sync::{Client, Database};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::cli::Options;
+use crate::error::MaterializeError;
+
+/// Database holding the C2M2 tables and everything materialized from them.
+pub const DATABASE: &str = "cfdb";
+
+/// The deployments a run reads the raw C2M2 tables from and writes `files`,
+/// the derived collections, and its bookkeeping to. Both are `DATABASE_URL`
+/// unless `--source-uri`/`--target-uri` split them.
+pub struct Connections {
+    /// Options the source client was built with, for the pipeline's async
+    /// reader
+    pub source_options: ClientOptions,
+    pub source: Database,
+    pub target_uri: String,
+    pub target_client: Client,
+    pub target: Database,
+}
+
+/// Client tuning from the `[mongo]` config section. Settings given here take
+/// precedence over the same options in a connection string.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MongoConfig {
+    /// Where reads of the raw C2M2 tables go; the target keeps the connection
+    /// string's
+    pub read_preference: Option<ReadMode>,
+    /// Nodes that must acknowledge each write: a count or `"majority"`
+    pub w: Option<Nodes>,
+    /// Wait for writes to reach the on-disk journal
+    pub journal: Option<bool>,
+    /// Give up waiting for write acknowledgement after this long
+    pub w_timeout_ms: Option<u64>,
+    pub connect_timeout_ms: Option<u64>,
+    pub server_selection_timeout_ms: Option<u64>,
+    /// Close pooled connections idle for this long
+    pub max_idle_time_ms: Option<u64>,
+    /// Wire compression to negotiate, in order of preference
+    pub compressors: Vec<Compression>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReadMode {
+    Primary,
+    PrimaryPreferred,
+    Secondary,
+    SecondaryPreferred,
+    Nearest,
+}
+
+impl ReadMode {
+    fn read_preference(self) -> ReadPreference {
+        match self {
+            ReadMode::Primary => ReadPreference::Primary,
+            ReadMode::PrimaryPreferred => ReadPreference::PrimaryPreferred { options: None },
+            ReadMode::Secondary => ReadPreference::Secondary { options: None },
+            ReadMode::SecondaryPreferred => ReadPreference::SecondaryPreferred { options: None },
+            ReadMode::Nearest => ReadPreference::Nearest { options: None },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Nodes {
+    Count(u32),
+    Tag(String),
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Zstd,
+    Snappy,
+    Zlib,
+}
+
+impl MongoConfig {
+    /// Apply everything but the read preference to `options`.
+    fn apply(&self, options: &mut ClientOptions) {
+        let ms = |ms: Option<u64>| ms.map(Duration::from_millis);
+        if self.w.is_some() || self.journal.is_some() || self.w_timeout_ms.is_some() {
+            let concern = options
+                .write_concern
+                .get_or_insert_with(WriteConcern::default);
+            if let Some(ref w) = self.w {
+                concern.w = Some(match w {
+                    Nodes::Count(n) => Acknowledgment::Nodes(*n),
+                    Nodes::Tag(tag) if tag == "majority" => Acknowledgment::Majority,
+                    Nodes::Tag(tag) => Acknowledgment::Custom(tag.clone()),
+                });
+            }
+            concern.journal = self.journal.or(concern.journal);
+            concern.w_timeout = ms(self.w_timeout_ms).or(concern.w_timeout);
+        }
+        options.connect_timeout = ms(self.connect_timeout_ms).or(options.connect_timeout);
+        options.server_selection_timeout =
+            ms(self.server_selection_timeout_ms).or(options.server_selection_timeout);
+        options.max_idle_time = ms(self.max_idle_time_ms).or(options.max_idle_time);
+        if !self.compressors.is_empty() {
+            options.compressors = Some(
+                self.compressors
+                    .iter()
+                    .map(|compression| match compression {
+                        Compression::Zstd => Compressor::Zstd { level: None },
+                        Compression::Snappy => Compressor::Snappy,
+                        Compression::Zlib => Compressor::Zlib { level: None },
+                    })
+                    .collect(),
+            );
+        }
+    }
+}
+
+impl Connections {
+    /// Connect to the target and, when it is a different deployment, the
+    /// source, tuned by the `[mongo]` config section. A separate source
+    /// reads from secondaries when it can (`secondaryPreferred`) unless its
+    /// URI or the config sets a read preference, so a run doesn't load a
+    /// production primary.
+    pub fn open(opts: &Options) -> Result<Self, MaterializeError> {
+        let config = &opts.config.mongo;
+        let default_uri =
+            env::var("DATABASE_URL").unwrap_or_else(|_| "mongodb://localhost:27017".to_string());
+        let target_uri = opts.target_uri.clone().unwrap_or(default_uri);
+        let mut target_options = ClientOptions::parse(&target_uri).run()?;
+        config.apply(&mut target_options);
+        secure(opts, &mut target_options).map_err(MaterializeError::Config)?;
+        let target_client = Client::with_options(target_options.clone())?;
+
+        let (source_options, source_client) = match opts.source_uri {
+            Some(ref uri) if *uri != target_uri => {
+                let mut options = ClientOptions::parse(uri).run()?;
+                config.apply(&mut options);
+                secure(opts, &mut options).map_err(MaterializeError::Config)?;
+                let mode = config
+                    .read_preference
+                    .unwrap_or(ReadMode::SecondaryPreferred);
+                if config.read_preference.is_some() || options.selection_criteria.is_none() {
+                    options.selection_criteria =
+                        Some(SelectionCriteria::ReadPreference(mode.read_preference()));
+                }
+                let client = Client::with_options(options.clone())?;
+                (options, client)
+            }
+            _ => match config.read_preference {
+                // One deployment: only the source's reads leave the primary
+                Some(mode) => {
+                    let mut options = target_options;
+                    options.selection_criteria =
+                        Some(SelectionCriteria::ReadPreference(mode.read_preference()));
+                    let client = Client::with_options(options.clone())?;
+                    (options, client)
+                }
+                None => (target_options, target_client.clone()),
+            },
+        };
+
+        Ok(Connections {
+            source_options,
+            source: source_client.database(DATABASE),
+            target_uri,
+            target: target_client.database(DATABASE),
+            target_client,
+        })
+    }
+}
+
+/// Apply the TLS and credential settings that don't belong in a connection
+/// string: the `--tls-ca-file`/`--tls-cert-key-file` certificates, and a
+/// username and password from `MONGODB_USERNAME` and `MONGODB_PASSWORD` (or
+/// the file named by `MONGODB_PASSWORD_FILE`, e.g. a mounted secret). Files
+/// are read on every run, so rotated certificates and passwords are picked
+/// up without a config change. `MONGODB-AWS` takes no password: the driver
+/// finds the AWS credentials itself (see the README).
+fn secure(opts: &Options, options: &mut ClientOptions) -> Result<()> {
+    if opts.tls_ca_file.is_some() || opts.tls_cert_key_file.is_some() {
+        let mut tls = match options.tls.take() {
+            Some(Tls::Enabled(tls)) => tls,
+            _ => TlsOptions::default(),
+        };
+        if let Some(ref path) = opts.tls_ca_file {
+            tls.ca_file_path = Some(PathBuf::from(path));
+        }
+        if let Some(ref path) = opts.tls_cert_key_file {
+            tls.cert_key_file_path = Some(PathBuf::from(path));
+        }
+        options.tls = Some(Tls::Enabled(tls));
+    }
+
+    let username = env::var("MONGODB_USERNAME").ok();
+    let password = match env::var("MONGODB_PASSWORD_FILE") {
+        Ok(path) => Some(
+            fs::read_to_string(&path)
+                .with_context(|| format!("reading MONGODB_PASSWORD_FILE {}", path))?
+                .trim_end()
+                .to_string(),
+        ),
+        Err(_) => env::var("MONGODB_PASSWORD").ok(),
+    };
+    // The driver only knows MONGODB-AWS with its `aws-auth` feature, which
+    // this crate's feature of the same name turns on
+    if !cfg!(feature = "aws-auth") && opts.auth_mechanism.as_deref() == Some("MONGODB-AWS") {
+        bail!("--auth-mechanism MONGODB-AWS requires building with --features aws-auth");
+    }
+    let mechanism = opts
+        .auth_mechanism
+        .as_deref()
+        .map(|name| name.parse::<AuthMechanism>())
+        .transpose()?;
+    if username.is_none() && password.is_none() && mechanism.is_none() && opts.auth_source.is_none()
+    {
+        return Ok(());
+    }
+    let credential = options.credential.get_or_insert_with(Credential::default);
+    credential.username = username.or(credential.username.take());
+    credential.password = password.or(credential.password.take());
+    credential.mechanism = mechanism.or(credential.mechanism.take());
+    credential.source = opts.auth_source.clone().or(credential.source.take());
+    Ok(())
+}
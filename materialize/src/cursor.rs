@@ -0,0 +1,104 @@
+use bson::{doc, Bson, Document};
+use mongodb::error::{Error, ErrorKind};
+use mongodb::sync::{Collection, Cursor};
+
+/// Server error codes for a cursor that timed out or was killed between
+/// batches (CursorNotFound, CursorKilled).
+const CURSOR_GONE: [i32; 2] = [43, 237];
+
+/// Times a scan reopens its cursor before giving up.
+pub const MAX_REOPENS: u32 = 10;
+
+/// Whether `error` means the server dropped the cursor, typically because
+/// enrichment or writing held it idle past the cursor timeout.
+pub fn is_cursor_gone(error: &Error) -> bool {
+    matches!(*error.kind, ErrorKind::Command(ref e) if CURSOR_GONE.contains(&e.code))
+}
+
+/// `query` narrowed to the documents after `last_id`, for reopening a scan
+/// ordered by `_id` where it stopped.
+pub fn resume_query(query: &Document, last_id: Option<&Bson>) -> Document {
+    match last_id {
+        Some(id) => doc! { "$and": [query.clone(), { "_id": { "$gt": id.clone() } }] },
+        None => query.clone(),
+    }
+}
+
+pub fn report_reopen(collection: &str, reopens: u32) {
+    println!(
+        "  {}: cursor timed out, reopening after the last document read ({} of {})",
+        collection, reopens, MAX_REOPENS
+    );
+}
+
+/// A scan of `coll` in `_id` order that survives its cursor timing out:
+/// when the server reports the cursor gone, it is reopened after the last
+/// document returned, up to `MAX_REOPENS` times. Other errors, and the
+/// last one, end the scan by yielding the error.
+pub struct ResumableCursor {
+    coll: Collection<Document>,
+    query: Document,
+    batch_size: u32,
+    cursor: Option<Cursor<Document>>,
+    last_id: Option<Bson>,
+    reopens: u32,
+    failed: bool,
+}
+
+impl ResumableCursor {
+    pub fn new(coll: Collection<Document>, query: Document, batch_size: u32) -> Self {
+        ResumableCursor {
+            coll,
+            query,
+            batch_size,
+            cursor: None,
+            last_id: None,
+            reopens: 0,
+            failed: false,
+        }
+    }
+
+    fn open(&self) -> mongodb::error::Result<Cursor<Document>> {
+        self.coll
+            .find(resume_query(&self.query, self.last_id.as_ref()))
+            .sort(doc! { "_id": 1 })
+            .batch_size(self.batch_size)
+            .run()
+    }
+}
+
+impl Iterator for ResumableCursor {
+    type Item = mongodb::error::Result<Document>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.failed {
+                return None;
+            }
+            if self.cursor.is_none() {
+                match self.open() {
+                    Ok(cursor) => self.cursor = Some(cursor),
+                    Err(e) => {
+                        self.failed = true;
+                        return Some(Err(e));
+                    }
+                }
+            }
+            match self.cursor.as_mut()?.next()? {
+                Ok(doc) => {
+                    self.last_id = doc.get("_id").cloned();
+                    return Some(Ok(doc));
+                }
+                Err(e) if is_cursor_gone(&e) && self.reopens < MAX_REOPENS => {
+                    self.reopens += 1;
+                    self.cursor = None;
+                    report_reopen(self.coll.name(), self.reopens);
+                }
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::fs;
+use std::time::Duration;
+
+use crate::enrich::Misses;
+use crate::status::StatusReporter;
+
+/// Files enriched between refreshes of the status message.
+const STATUS_EVERY: u64 = 1000;
+
+/// Progress display for a run: one bar per phase, stacked under an overall
+/// submissions bar when a pattern expands to several submissions. Phase bars
+/// carry the process's resident memory and the lookup misses seen so far.
+/// A quiet dashboard draws nothing, for cron jobs and log files. With a
+/// status reporter, the same progress is also upserted to MongoDB.
+pub struct Dashboard {
+    multi: MultiProgress,
+    submissions: Option<ProgressBar>,
+    status: Option<StatusReporter>,
+}
+
+impl Dashboard {
+    pub fn new(submissions: usize, quiet: bool) -> Self {
+        let multi = MultiProgress::new();
+        if quiet {
+            multi.set_draw_target(ProgressDrawTarget::hidden());
+        }
+        let submissions = (submissions > 1).then(|| {
+            let pb = multi.add(ProgressBar::new(submissions as u64));
+            pb.set_style(
+                ProgressStyle::default_bar()
+                    .template("{prefix:>8} [{elapsed_precise}] [{bar:40.green}] {pos}/{len} {msg}")
+                    .unwrap()
+                    .progress_chars("=> "),
+            );
+            pb.set_prefix("runs");
+            pb.enable_steady_tick(Duration::from_secs(1));
+            pb
+        });
+        Dashboard {
+            multi,
+            submissions,
+            status: None,
+        }
+    }
+
+    /// Also report progress to the `materialize_status` collection.
+    pub fn report_status(mut self, status: StatusReporter) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Show `submission` as the one in progress.
+    pub fn start_submission(&self, submission: &Option<String>) {
+        if let Some(ref pb) = self.submissions {
+            pb.set_message(submission.clone().unwrap_or_default());
+        }
+        if let Some(ref status) = self.status {
+            status.submission(submission);
+        }
+    }
+
+    pub fn finish_submission(&self) {
+        if let Some(ref pb) = self.submissions {
+            pb.inc(1);
+        }
+        if let Some(ref status) = self.status {
+            status.finish_submission();
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Some(ref pb) = self.submissions {
+            pb.finish_with_message("done");
+        }
+        if let Some(ref status) = self.status {
+            status.finish(None);
+        }
+    }
+
+    pub fn fail(&self, error: &impl std::fmt::Display) {
+        if let Some(ref status) = self.status {
+            status.finish(Some(format!("{:#}", error)));
+        }
+    }
+
+    /// Name the phase under way when it has no bar of its own.
+    pub fn stage(&self, name: &str) {
+        if let Some(ref status) = self.status {
+            status.phase(name, None);
+        }
+    }
+
+    /// Add a bar for a phase processing `len` documents.
+    pub fn phase(&self, name: &str, len: u64) -> ProgressBar {
+        let pb = self.multi.add(ProgressBar::new(len));
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template(
+                    "{prefix:>8} {spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({per_sec}) {msg}",
+                )
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+        pb.set_prefix(name.to_string());
+        if let Some(ref status) = self.status {
+            status.phase(name, Some(pb.clone()));
+        }
+        pb
+    }
+}
+
+/// Advance `pb` by `n` documents, refreshing the status message each time
+/// the position crosses a multiple of `STATUS_EVERY`.
+pub fn advance(pb: &ProgressBar, n: u64, misses: &Misses) {
+    let before = pb.position();
+    pb.inc(n);
+    if (before + n) / STATUS_EVERY != before / STATUS_EVERY {
+        pb.set_message(status(misses));
+    }
+}
+
+/// `mem <rss> | misses <counts>` for a phase bar's message.
+pub fn status(misses: &Misses) -> String {
+    let memory = resident_memory()
+        .map(|bytes| format!("{:.1} GiB", bytes as f64 / (1u64 << 30) as f64))
+        .unwrap_or_else(|| "?".to_string());
+    format!("mem {} | misses: {}", memory, misses.summary())
+}
+
+/// Resident set size from `/proc/self/status`; `None` off Linux.
+fn resident_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
@@ -0,0 +1,79 @@
+use anyhow::Result;
+use bson::{doc, Document};
+use mongodb::sync::{ClientSession, Collection, Database};
+use mongodb::IndexModel;
+use std::collections::{BTreeSet, HashMap};
+
+pub const DCCS_COLLECTION: &str = "dccs";
+
+/// Fields a file keeps of its DCC with `--embed-dcc ref`: enough to filter,
+/// facet, and label by DCC, and to look the rest up in `dccs`.
+pub const DCC_REF_FIELDS: [&str; 3] = ["id", "dcc_name", "dcc_abbreviation"];
+
+/// Upsert the full document of each loaded DCC (only `submission`'s, when
+/// given) into `dccs`, one per DCC `id` with the `submissions` it covers.
+/// Returns the number of DCCs written.
+pub fn write(
+    db: &Database,
+    dccs: &HashMap<String, Document>,
+    submission_filter: &Option<String>,
+) -> Result<usize> {
+    let coll: Collection<Document> = db.collection(DCCS_COLLECTION);
+    let mut submissions: Vec<&String> = dccs
+        .keys()
+        .filter(|sub| submission_filter.as_ref().is_none_or(|only| only == *sub))
+        .collect();
+    submissions.sort();
+
+    let mut written = BTreeSet::new();
+    for submission in submissions {
+        let mut dcc = dccs[submission].clone();
+        dcc.remove("_id");
+        dcc.remove("submission");
+        let Ok(id) = dcc.get_str("id").map(str::to_string) else {
+            continue;
+        };
+        coll.update_one(
+            doc! { "id": &id },
+            doc! { "$set": dcc, "$addToSet": { "submissions": submission } },
+        )
+        .upsert(true)
+        .run()?;
+        written.insert(id);
+    }
+
+    coll.create_indexes(
+        [doc! { "id": 1 }, doc! { "submissions": 1 }]
+            .into_iter()
+            .map(|keys| IndexModel::builder().keys(keys).build()),
+    )
+    .run()?;
+    Ok(written.len())
+}
+
+/// Drop `submission` from every DCC's `submissions`, then delete the DCCs
+/// no remaining submission describes. Runs in `session`'s transaction when
+/// given one.
+pub fn retract(
+    db: &Database,
+    submission: &str,
+    session: Option<&mut ClientSession>,
+) -> mongodb::error::Result<()> {
+    let coll: Collection<Document> = db.collection(DCCS_COLLECTION);
+    let pull = coll.update_many(
+        doc! { "submissions": submission },
+        doc! { "$pull": { "submissions": submission } },
+    );
+    let orphaned = doc! { "submissions": { "$size": 0 } };
+    match session {
+        Some(session) => {
+            pull.session(&mut *session).run()?;
+            coll.delete_many(orphaned).session(session).run()?;
+        }
+        None => {
+            pull.run()?;
+            coll.delete_many(orphaned).run()?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use bson::Document;
+use serde::Deserialize;
+
+/// Builds a GA4GH DRS URI for each file so the portal can hand off to DRS
+/// clients. Templates are tried in order; the first whose placeholders are
+/// all present on the file wins. Placeholders are `{persistent_id}`,
+/// `{id_namespace}`, `{local_id}`, `{sha256}` and `{md5}`; `{id_namespace}`
+/// and `{local_id}` are percent-encoded since they are often URIs themselves.
+/// Without templates no `drs_uri` is written.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DrsConfig {
+    pub templates: Vec<String>,
+}
+
+const PLACEHOLDERS: [&str; 5] = ["persistent_id", "id_namespace", "local_id", "sha256", "md5"];
+
+impl DrsConfig {
+    /// The file's DRS URI. A `persistent_id` that is already a `drs://` URI
+    /// is used as-is.
+    pub fn uri(&self, file: &Document) -> Option<String> {
+        if self.templates.is_empty() {
+            return None;
+        }
+        if let Ok(pid) = file.get_str("persistent_id") {
+            if pid.starts_with("drs://") {
+                return Some(pid.to_string());
+            }
+        }
+        self.templates
+            .iter()
+            .find_map(|template| render(template, file))
+    }
+}
+
+fn render(template: &str, file: &Document) -> Option<String> {
+    let mut uri = template.to_string();
+    for name in PLACEHOLDERS {
+        let placeholder = format!("{{{}}}", name);
+        if !uri.contains(&placeholder) {
+            continue;
+        }
+        let value = file.get_str(name).ok().filter(|v| !v.is_empty())?;
+        let value = match name {
+            "id_namespace" | "local_id" => percent_encode(value),
+            _ => value.to_string(),
+        };
+        uri = uri.replace(&placeholder, &value);
+    }
+    Some(uri)
+}
+
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
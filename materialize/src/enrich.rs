@@ -0,0 +1,943 @@
+use bson::{doc, Bson, Document};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::change::{content_hash, HASH_FIELD};
+use crate::coerce::{coerce_date, coerce_numeric};
+use crate::dccs::DCC_REF_FIELDS;
+use crate::lookup::{
+    LookupContext, LookupMap, ANATOMY_TABLE, DERIVATION_TABLES, DISEASE_TABLE, GENE_TABLE,
+    SUBJECT_RACE_TABLE, SUBSTANCE_TABLE,
+};
+use crate::migrate;
+use crate::mime;
+use crate::ontology::Ontology;
+use crate::search;
+use crate::spec::Entity;
+
+/// Step-by-step record of the lookups made while enriching a file.
+///
+/// Disabled traces are free: messages are built lazily and dropped.
+#[derive(Default)]
+pub struct Trace {
+    lines: Option<Vec<String>>,
+    depth: usize,
+}
+
+impl Trace {
+    pub fn disabled() -> Self {
+        Trace::default()
+    }
+
+    pub fn enabled() -> Self {
+        Trace {
+            lines: Some(Vec::new()),
+            depth: 0,
+        }
+    }
+
+    fn step(&mut self, message: impl FnOnce() -> String) {
+        if let Some(ref mut lines) = self.lines {
+            lines.push(format!("{}{}", "  ".repeat(self.depth), message()));
+        }
+    }
+
+    fn indent(&mut self) {
+        self.depth += 1;
+    }
+
+    fn dedent(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    pub fn lines(&self) -> &[String] {
+        self.lines.as_deref().unwrap_or_default()
+    }
+}
+
+/// Lookup misses seen while enriching, shown live on the progress display.
+#[derive(Default)]
+pub struct Misses {
+    dcc: AtomicU64,
+    terms: AtomicU64,
+    entities: AtomicU64,
+}
+
+impl Misses {
+    fn bump(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "dcc {}, terms {}, entities {}",
+            self.dcc.load(Ordering::Relaxed),
+            self.terms.load(Ordering::Relaxed),
+            self.entities.load(Ordering::Relaxed)
+        )
+    }
+
+    /// Vocabulary ids that didn't resolve so far.
+    pub fn terms(&self) -> u64 {
+        self.terms.load(Ordering::Relaxed)
+    }
+}
+
+/// Resolve `field` against a (submission, id) vocabulary table and embed the
+/// matching term. Empty strings are removed; a miss is handled per
+/// `on_miss`. When an ontology is given, the term also carries its
+/// `ancestors`, and with `provenance` (its table's name) its `_provenance`.
+/// Returns whether the id was set but not found.
+#[allow(clippy::too_many_arguments)]
+fn embed_term(
+    file: &mut Document,
+    field: &str,
+    table: &LookupMap,
+    ontology: Option<&Ontology>,
+    submission: &str,
+    on_miss: OnMiss,
+    provenance: Option<&str>,
+    trace: &mut Trace,
+) -> bool {
+    let Ok(term_id) = file.get_str(field) else {
+        trace.step(|| format!("{}: not set", field));
+        return false;
+    };
+    if term_id.is_empty() {
+        trace.step(|| format!("{}: empty string -> removed", field));
+        file.remove(field);
+        return false;
+    }
+    match table.get(submission, term_id) {
+        Some(term) => {
+            let mut term_copy = term.into_owned();
+            if let Some(table) = provenance {
+                annotate(&mut term_copy, table, submission);
+            }
+            term_copy.remove("_id");
+            if let Some(ontology) = ontology {
+                let ancestors = ontology.ancestors(term_id);
+                trace.step(|| {
+                    format!(
+                        "{}: {} ancestors",
+                        field,
+                        ancestors.as_array().map_or(0, |a| a.len())
+                    )
+                });
+                term_copy.insert("ancestors", ancestors);
+            }
+            trace.step(|| {
+                format!(
+                    "{}: lookup ({}, {}) -> hit {:?}, embedded",
+                    field,
+                    submission,
+                    term_id,
+                    term_copy.get_str("name").unwrap_or_default()
+                )
+            });
+            file.insert(field, term_copy);
+            false
+        }
+        None => {
+            let term_id = term_id.to_string();
+            let outcome = match on_miss {
+                OnMiss::KeepId | OnMiss::Fail => "raw id kept",
+                OnMiss::Drop => {
+                    file.remove(field);
+                    "dropped"
+                }
+                OnMiss::Stub => {
+                    file.insert(field, doc! { "id": &term_id, "unresolved": true });
+                    "stub embedded"
+                }
+            };
+            trace.step(|| {
+                format!(
+                    "{}: lookup ({}, {}) -> miss, {}",
+                    field, submission, term_id, outcome
+                )
+            });
+            true
+        }
+    }
+}
+
+/// Embed every term the enrichment spec lists for `entity`.
+fn embed_terms(
+    doc: &mut Document,
+    entity: Entity,
+    submission: &str,
+    ctx: &LookupContext,
+    trace: &mut Trace,
+) {
+    let submission = ctx.vocab_submission(submission);
+    for term in ctx.opts.config.enrichment.terms_for(entity) {
+        let missed = embed_term(
+            doc,
+            &term.field,
+            ctx.term_table(term),
+            ctx.ontology(term),
+            submission,
+            ctx.opts.on_miss,
+            ctx.opts.provenance.then_some(term.table.as_str()),
+            trace,
+        );
+        if missed {
+            Misses::bump(&ctx.misses.terms);
+        }
+    }
+}
+
+/// What to do with files whose submission has no `dcc` document
+/// (`--on-missing-dcc`). Without a policy they are written without `dcc`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MissingDcc {
+    /// Fail the run before anything is written
+    Fail,
+    /// Leave those submissions' files out of the run
+    Skip,
+    /// Embed a stand-in `dcc` named after the submission
+    Placeholder,
+}
+
+impl MissingDcc {
+    pub fn parse(policy: &str) -> anyhow::Result<Self> {
+        match policy {
+            "fail" => Ok(MissingDcc::Fail),
+            "skip" => Ok(MissingDcc::Skip),
+            "placeholder" => Ok(MissingDcc::Placeholder),
+            other => anyhow::bail!(
+                "Unknown --on-missing-dcc policy {:?}; expected fail, skip, or placeholder",
+                other
+            ),
+        }
+    }
+}
+
+/// How much of its DCC each file embeds (`--embed-dcc`).
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmbedDcc {
+    /// The whole `dcc` document
+    #[default]
+    Full,
+    /// Only `id`, `dcc_name`, and `dcc_abbreviation`; the full documents
+    /// go to the `dccs` collection
+    Ref,
+}
+
+impl EmbedDcc {
+    pub fn parse(mode: &str) -> anyhow::Result<Self> {
+        match mode {
+            "full" => Ok(EmbedDcc::Full),
+            "ref" => Ok(EmbedDcc::Ref),
+            other => anyhow::bail!("Unknown --embed-dcc mode {:?}; expected full or ref", other),
+        }
+    }
+}
+
+/// What to do with a vocabulary id that doesn't resolve (`--on-miss`).
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnMiss {
+    /// Leave the raw id string in place
+    #[default]
+    KeepId,
+    /// Remove the field, or the id from a term list
+    Drop,
+    /// Embed `{ id, unresolved: true }` so consumers can tell an
+    /// unresolved term from an absent one
+    Stub,
+    /// Fail the run before writing the first batch with a miss
+    Fail,
+}
+
+impl OnMiss {
+    pub fn parse(policy: &str) -> anyhow::Result<Self> {
+        match policy {
+            "keep-id" => Ok(OnMiss::KeepId),
+            "drop" => Ok(OnMiss::Drop),
+            "stub" => Ok(OnMiss::Stub),
+            "fail" => Ok(OnMiss::Fail),
+            other => anyhow::bail!(
+                "Unknown --on-miss policy {:?}; expected keep-id, drop, stub, or fail",
+                other
+            ),
+        }
+    }
+}
+
+/// Where an embedded row came from, with `--provenance`.
+pub const PROVENANCE_FIELD: &str = "_provenance";
+
+/// `row`'s source table and submission, from its own `table` and
+/// `submission` when it has them, and when it was loaded, from its `_id`.
+fn provenance(row: &Document, table: &str, submission: &str) -> Document {
+    let mut provenance = doc! {
+        "table": row.get_str("table").unwrap_or(table),
+    };
+    let submission = row.get_str("submission").unwrap_or(submission);
+    if !submission.is_empty() {
+        provenance.insert("submission", submission);
+    }
+    if let Ok(id) = row.get_object_id("_id") {
+        provenance.insert("loaded_at", id.timestamp());
+    }
+    provenance
+}
+
+/// Record where the embedded `row` came from before its `_id` is dropped.
+fn annotate(row: &mut Document, table: &str, submission: &str) {
+    let provenance = provenance(row, table, submission);
+    row.insert(PROVENANCE_FIELD, provenance);
+}
+
+/// Embed the DCC responsible for `submission`.
+pub fn embed_dcc(doc: &mut Document, submission: &str, ctx: &LookupContext, trace: &mut Trace) {
+    match ctx.dccs.get(submission) {
+        Some(dcc) if ctx.opts.embed_dcc == EmbedDcc::Ref => {
+            let mut dcc_ref: Document = dcc
+                .iter()
+                .filter(|(key, _)| DCC_REF_FIELDS.contains(&key.as_str()))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+            if ctx.opts.provenance {
+                dcc_ref.insert(PROVENANCE_FIELD, provenance(dcc, "dcc", submission));
+            }
+            trace.step(|| format!("dcc: lookup {} -> hit, reference embedded", submission));
+            doc.insert("dcc", dcc_ref);
+        }
+        Some(dcc) => {
+            let mut dcc_copy = dcc.clone();
+            if ctx.opts.provenance {
+                annotate(&mut dcc_copy, "dcc", submission);
+            }
+            dcc_copy.remove("_id");
+            trace.step(|| format!("dcc: lookup {} -> hit, embedded", submission));
+            doc.insert("dcc", dcc_copy);
+        }
+        None if ctx.opts.on_missing_dcc == Some(MissingDcc::Placeholder) => {
+            Misses::bump(&ctx.misses.dcc);
+            trace.step(|| format!("dcc: lookup {} -> miss, placeholder", submission));
+            doc.insert(
+                "dcc",
+                doc! {
+                    "dcc_name": submission,
+                    "dcc_abbreviation": submission,
+                    "placeholder": true,
+                },
+            );
+        }
+        None => {
+            Misses::bump(&ctx.misses.dcc);
+            trace.step(|| format!("dcc: lookup {} -> miss", submission));
+        }
+    }
+}
+
+/// Join a raw `file` document against the lookup tables, embedding its DCC,
+/// vocabulary terms, and collections with nested biosamples.
+pub fn enrich_file(mut file: Document, ctx: &LookupContext, trace: &mut Trace) -> Document {
+    ctx.opts.config.normalize.apply(&mut file);
+    let coerced = coerce_numeric(&mut file, &ctx.unparseable);
+    if coerced > 0 {
+        trace.step(|| format!("coerced {} numeric fields to int64", coerced));
+    }
+    if coerce_date(&mut file, &ctx.unparseable) {
+        trace.step(|| "creation_time: parsed as a date".to_string());
+    }
+    let submission = file.get_str("submission").unwrap_or_default().to_string();
+    let id_namespace = file.get_str("id_namespace").unwrap_or_default().to_string();
+    let local_id = file.get_str("local_id").unwrap_or_default().to_string();
+    trace.step(|| {
+        format!(
+            "file ({}, {}) in submission {}",
+            id_namespace, local_id, submission
+        )
+    });
+    trace.indent();
+
+    embed_dcc(&mut file, &submission, ctx, trace);
+    embed_terms(&mut file, Entity::File, &submission, ctx, trace);
+
+    // Build collections array with nested biosamples
+    let mut enriched_collections: Vec<Document> = Vec::new();
+
+    match ctx.file_in_collection.get(&id_namespace, &local_id) {
+        Some(file_colls) => {
+            trace.step(|| {
+                format!(
+                    "file_in_collection: lookup ({}, {}) -> {} entries",
+                    id_namespace,
+                    local_id,
+                    file_colls.len()
+                )
+            });
+            trace.indent();
+            for fc in file_colls.iter() {
+                let coll_ns = fc.get_str("collection_id_namespace").unwrap_or_default();
+                let coll_id = fc.get_str("collection_local_id").unwrap_or_default();
+
+                let Some(coll) = ctx.collections.get(coll_ns, coll_id) else {
+                    Misses::bump(&ctx.misses.entities);
+                    trace.step(|| {
+                        format!(
+                            "collection: lookup ({}, {}) -> miss, skipped",
+                            coll_ns, coll_id
+                        )
+                    });
+                    continue;
+                };
+                let mut coll_copy = coll.into_owned();
+                if ctx.opts.provenance {
+                    annotate(&mut coll_copy, "collection", &submission);
+                }
+                coll_copy.remove("_id");
+                trace.step(|| {
+                    format!(
+                        "collection: lookup ({}, {}) -> hit {:?}, embedded",
+                        coll_ns,
+                        coll_id,
+                        coll_copy.get_str("name").unwrap_or_default()
+                    )
+                });
+                trace.indent();
+                enrich_collection(&mut coll_copy, coll_ns, coll_id, &submission, ctx, trace);
+                enriched_collections.push(coll_copy);
+                trace.dedent();
+            }
+            trace.dedent();
+        }
+        None => trace.step(|| {
+            format!(
+                "file_in_collection: lookup ({}, {}) -> miss, no collections",
+                id_namespace, local_id
+            )
+        }),
+    }
+
+    let parents = derived_from(&id_namespace, &local_id, ctx, trace);
+    if !parents.is_empty() {
+        file.insert("derived_from", parents);
+    }
+
+    if let Some((min, max)) = age_range(&enriched_collections) {
+        trace.step(|| format!("age_at_sampling: {} to {}", min, max));
+        file.insert(MIN_AGE_FIELD, min);
+        file.insert(MAX_AGE_FIELD, max);
+    }
+
+    if ctx.opts.hoist_biosamples {
+        let biosamples = hoist_biosamples(&mut enriched_collections);
+        trace.step(|| format!("biosamples: {} hoisted", biosamples.len()));
+        file.insert("biosamples", biosamples);
+    }
+    file.insert("collections", enriched_collections);
+
+    let scrubbed = ctx.opts.config.scrub.apply(&mut file);
+    if scrubbed > 0 {
+        trace.step(|| format!("scrub: {} empty-string fields", scrubbed));
+    }
+
+    if ctx.opts.infer_mime_type && file.get_str("mime_type").map_or(true, str::is_empty) {
+        let inferred = mime::infer(&file);
+        trace.step(|| format!("mime_type_inferred: {:?}", inferred));
+        if let Some(mime_type) = inferred {
+            file.insert("mime_type_inferred", mime_type);
+        }
+    }
+
+    let preview = ctx.opts.config.preview.classify(&file).to_string();
+    trace.step(|| format!("preview: {}", preview));
+    file.insert("preview", preview);
+
+    if let Some(drs_uri) = ctx.opts.config.drs.uri(&file) {
+        trace.step(|| format!("drs_uri: {}", drs_uri));
+        file.insert("drs_uri", drs_uri);
+    }
+
+    let access = ctx.opts.config.access.resolve(&file);
+    trace.step(|| format!("access: {}", access));
+    file.insert("access", access);
+
+    for enricher in &ctx.enrichers {
+        enricher.enrich(&mut file, ctx);
+        trace.step(|| format!("enricher: {}", enricher.name()));
+    }
+
+    // After the enrichers, so nothing they add escapes it, and before
+    // search_text, so redacted values aren't searchable. A tiered run keeps
+    // everything here and redacts the public copy as it is written
+    if !ctx.opts.tiers {
+        let redacted = ctx.opts.config.redact.apply(&mut file, &ctx.redactions);
+        if redacted > 0 {
+            trace.step(|| format!("redact: {} protected fields", redacted));
+        }
+    }
+
+    let search_text = search::search_text(&file, &ctx.opts.config.enrichment);
+    trace.step(|| format!("search_text: {} chars", search_text.len()));
+    file.insert("search_text", search_text);
+
+    migrate::stamp(&mut file, ctx.materialized_at);
+    file.insert(HASH_FIELD, content_hash(&file));
+    trace.dedent();
+    file
+}
+
+/// The files a file was derived or produced from, per the derivation
+/// tables, as `{id_namespace, local_id, filename}` stubs, each once. A
+/// parent outside the run's files keeps only its key.
+fn derived_from(
+    id_namespace: &str,
+    local_id: &str,
+    ctx: &LookupContext,
+    trace: &mut Trace,
+) -> Vec<Document> {
+    let mut seen: HashSet<(String, String)> = HashSet::new();
+    let mut parents = Vec::new();
+    for ((table, ns_field, id_field), edges) in DERIVATION_TABLES.iter().zip(&ctx.derivations) {
+        let Some(edges) = edges.get(id_namespace, local_id) else {
+            continue;
+        };
+        trace.step(|| {
+            format!(
+                "{}: lookup ({}, {}) -> {} entries",
+                table,
+                id_namespace,
+                local_id,
+                edges.len()
+            )
+        });
+        for edge in edges.iter() {
+            let (Ok(parent_ns), Ok(parent_id)) = (edge.get_str(ns_field), edge.get_str(id_field))
+            else {
+                continue;
+            };
+            if !seen.insert((parent_ns.to_string(), parent_id.to_string())) {
+                continue;
+            }
+            let mut parent = doc! { "id_namespace": parent_ns, "local_id": parent_id };
+            let filename = ctx
+                .file_names
+                .get(parent_ns, parent_id)
+                .and_then(|file| file.get_str("filename").ok().map(str::to_string));
+            if let Some(filename) = filename {
+                parent.insert("filename", filename);
+            }
+            parents.push(parent);
+        }
+    }
+    parents
+}
+
+/// The youngest and oldest `age_at_sampling` among the biosamples of
+/// `collections`, before they are hoisted.
+fn age_range(collections: &[Document]) -> Option<(f64, f64)> {
+    collections
+        .iter()
+        .filter_map(|coll| coll.get_array("biosamples").ok())
+        .flatten()
+        .filter_map(Bson::as_document)
+        .filter_map(|biosample| biosample.get_f64(AGE_FIELD).ok())
+        .fold(None, |range, age| match range {
+            Some((min, max)) => Some((age.min(min), age.max(max))),
+            None => Some((age, age)),
+        })
+}
+
+/// Move the biosamples embedded in `collections` to one list without
+/// duplicates, leaving each collection `biosample_refs` keys into it. A
+/// biosample in several of a file's collections is then embedded once.
+fn hoist_biosamples(collections: &mut [Document]) -> Vec<Document> {
+    let mut seen: HashSet<(String, String)> = HashSet::new();
+    let mut hoisted = Vec::new();
+    for coll in collections.iter_mut() {
+        let biosamples = match coll.remove("biosamples") {
+            Some(Bson::Array(biosamples)) => biosamples,
+            _ => Vec::new(),
+        };
+        let mut refs = Vec::with_capacity(biosamples.len());
+        for biosample in biosamples {
+            let Bson::Document(biosample) = biosample else {
+                continue;
+            };
+            let key = (
+                biosample
+                    .get_str("id_namespace")
+                    .unwrap_or_default()
+                    .to_string(),
+                biosample
+                    .get_str("local_id")
+                    .unwrap_or_default()
+                    .to_string(),
+            );
+            refs.push(doc! { "id_namespace": &key.0, "local_id": &key.1 });
+            if seen.insert(key) {
+                hoisted.push(biosample);
+            }
+        }
+        coll.insert("biosample_refs", refs);
+    }
+    hoisted
+}
+
+/// Embed a collection's terms and its biosamples.
+pub fn enrich_collection(
+    coll: &mut Document,
+    coll_ns: &str,
+    coll_id: &str,
+    submission: &str,
+    ctx: &LookupContext,
+    trace: &mut Trace,
+) {
+    embed_terms(coll, Entity::Collection, submission, ctx, trace);
+
+    // Anatomy and disease rollups recorded for the collection as a whole
+    let anatomies = match ctx.collection_anatomy.get(coll_ns, coll_id) {
+        Some(rows) => embed_term_list(
+            &rows,
+            "anatomy",
+            &ctx.terms[ANATOMY_TABLE],
+            ANATOMY_TABLE,
+            ctx.uberon.as_ref(),
+            submission,
+            ctx,
+            trace,
+        ),
+        None => Vec::new(),
+    };
+    coll.insert("anatomies", anatomies);
+    let diseases = match ctx.collection_disease.get(coll_ns, coll_id) {
+        Some(rows) => embed_term_list(
+            &rows,
+            "disease",
+            &ctx.terms[DISEASE_TABLE],
+            DISEASE_TABLE,
+            None,
+            submission,
+            ctx,
+            trace,
+        ),
+        None => Vec::new(),
+    };
+    coll.insert("diseases", diseases);
+
+    let mut projects = Vec::new();
+    for row in ctx
+        .collection_defined_by_project
+        .get(coll_ns, coll_id)
+        .unwrap_or_default()
+        .iter()
+    {
+        let project_ns = row.get_str("project_id_namespace").unwrap_or_default();
+        let project_id = row.get_str("project_local_id").unwrap_or_default();
+        match ctx.projects.get(project_ns, project_id) {
+            Some(project) => {
+                let mut project = project.into_owned();
+                if ctx.opts.provenance {
+                    annotate(&mut project, "project", submission);
+                }
+                project.remove("_id");
+                trace.step(|| format!("project: lookup ({}, {}) -> hit", project_ns, project_id));
+                projects.push(project);
+            }
+            None => {
+                Misses::bump(&ctx.misses.entities);
+                trace.step(|| {
+                    format!(
+                        "project: lookup ({}, {}) -> miss, skipped",
+                        project_ns, project_id
+                    )
+                });
+            }
+        }
+    }
+    coll.insert("defined_by_project", projects);
+
+    let biosamples = enrich_biosamples(coll_ns, coll_id, submission, ctx, trace);
+    coll.insert("biosamples", biosamples);
+}
+
+fn enrich_biosamples(
+    coll_ns: &str,
+    coll_id: &str,
+    submission: &str,
+    ctx: &LookupContext,
+    trace: &mut Trace,
+) -> Vec<Document> {
+    let mut enriched_biosamples: Vec<Document> = Vec::new();
+
+    let Some(bios_in_coll) = ctx.biosample_in_collection.get(coll_ns, coll_id) else {
+        trace.step(|| {
+            format!(
+                "biosample_in_collection: lookup ({}, {}) -> miss, no biosamples",
+                coll_ns, coll_id
+            )
+        });
+        return enriched_biosamples;
+    };
+    trace.step(|| {
+        format!(
+            "biosample_in_collection: lookup ({}, {}) -> {} entries",
+            coll_ns,
+            coll_id,
+            bios_in_coll.len()
+        )
+    });
+    trace.indent();
+
+    for bc in bios_in_coll.iter() {
+        let bio_ns = bc.get_str("biosample_id_namespace").unwrap_or_default();
+        let bio_id = bc.get_str("biosample_local_id").unwrap_or_default();
+
+        let Some(biosample) = ctx.biosamples.get(bio_ns, bio_id) else {
+            Misses::bump(&ctx.misses.entities);
+            trace.step(|| {
+                format!(
+                    "biosample: lookup ({}, {}) -> miss, skipped",
+                    bio_ns, bio_id
+                )
+            });
+            continue;
+        };
+        let mut bio_copy = biosample.into_owned();
+        if ctx.opts.provenance {
+            annotate(&mut bio_copy, "biosample", submission);
+        }
+        bio_copy.remove("_id");
+        trace.step(|| {
+            format!(
+                "biosample: lookup ({}, {}) -> hit, embedded",
+                bio_ns, bio_id
+            )
+        });
+        trace.indent();
+        enrich_biosample(&mut bio_copy, bio_ns, bio_id, submission, ctx, trace);
+        enriched_biosamples.push(bio_copy);
+        trace.dedent();
+    }
+
+    trace.dedent();
+    enriched_biosamples
+}
+
+/// Embed a biosample's terms, its substances and genes, and the subjects it
+/// was taken from.
+pub fn enrich_biosample(
+    biosample: &mut Document,
+    bio_ns: &str,
+    bio_id: &str,
+    submission: &str,
+    ctx: &LookupContext,
+    trace: &mut Trace,
+) {
+    embed_terms(biosample, Entity::Biosample, submission, ctx, trace);
+
+    // Substances and genes the biosample is associated with
+    let substances = match ctx.biosample_substance.get(bio_ns, bio_id) {
+        Some(rows) => embed_term_list(
+            &rows,
+            "substance",
+            &ctx.substances,
+            SUBSTANCE_TABLE,
+            None,
+            submission,
+            ctx,
+            trace,
+        ),
+        None => Vec::new(),
+    };
+    biosample.insert("substances", substances);
+    let genes = match ctx.biosample_gene.get(bio_ns, bio_id) {
+        Some(rows) => embed_term_list(
+            &rows, "gene", &ctx.genes, GENE_TABLE, None, submission, ctx, trace,
+        ),
+        None => Vec::new(),
+    };
+    biosample.insert("genes", genes);
+
+    // A biosample taken from several subjects at different ages is as young
+    // as the youngest of them
+    let age = ctx
+        .biosample_from_subject
+        .get(bio_ns, bio_id)
+        .and_then(|links| {
+            links
+                .iter()
+                .filter_map(age_at_sampling)
+                .min_by(f64::total_cmp)
+        });
+    if let Some(age) = age {
+        trace.step(|| format!("age_at_sampling: {}", age));
+        biosample.insert(AGE_FIELD, age);
+    }
+
+    let subjects = enrich_subjects(bio_ns, bio_id, submission, ctx, trace);
+    biosample.insert("subjects", subjects);
+}
+
+/// `biosample_from_subject` column with the subject's age, in years, when
+/// the biosample was taken. Copied as a double onto the embedded biosample
+/// and subject, and summarized on the file as `min_age_at_sampling` and
+/// `max_age_at_sampling` for age-range queries.
+pub const AGE_FIELD: &str = "age_at_sampling";
+pub const MIN_AGE_FIELD: &str = "min_age_at_sampling";
+pub const MAX_AGE_FIELD: &str = "max_age_at_sampling";
+
+/// A link's `age_at_sampling` as a number; TSV ingestion loads it as a
+/// string, and blank or unparseable values count as unknown.
+fn age_at_sampling(link: &Document) -> Option<f64> {
+    let age = match link.get(AGE_FIELD)? {
+        Bson::Double(age) => *age,
+        Bson::Int32(age) => *age as f64,
+        Bson::Int64(age) => *age as f64,
+        Bson::String(age) => age.trim().parse().ok()?,
+        _ => return None,
+    };
+    (age.is_finite() && age >= 0.0).then_some(age)
+}
+
+/// Resolve the subjects a biosample was taken from, with granularity, sex,
+/// ethnicity, and race embedded from the subject CV tables, and the age
+/// each was sampled at.
+fn enrich_subjects(
+    bio_ns: &str,
+    bio_id: &str,
+    submission: &str,
+    ctx: &LookupContext,
+    trace: &mut Trace,
+) -> Vec<Document> {
+    let mut enriched_subjects: Vec<Document> = Vec::new();
+
+    let Some(links) = ctx.biosample_from_subject.get(bio_ns, bio_id) else {
+        trace.step(|| {
+            format!(
+                "biosample_from_subject: lookup ({}, {}) -> miss, no subjects",
+                bio_ns, bio_id
+            )
+        });
+        return enriched_subjects;
+    };
+    trace.step(|| {
+        format!(
+            "biosample_from_subject: lookup ({}, {}) -> {} entries",
+            bio_ns,
+            bio_id,
+            links.len()
+        )
+    });
+    trace.indent();
+
+    for link in links.iter() {
+        let subject_ns = link.get_str("subject_id_namespace").unwrap_or_default();
+        let subject_id = link.get_str("subject_local_id").unwrap_or_default();
+
+        let Some(subject) = ctx.subjects.get(subject_ns, subject_id) else {
+            Misses::bump(&ctx.misses.entities);
+            trace.step(|| {
+                format!(
+                    "subject: lookup ({}, {}) -> miss, skipped",
+                    subject_ns, subject_id
+                )
+            });
+            continue;
+        };
+        let mut subject_copy = subject.into_owned();
+        if ctx.opts.provenance {
+            annotate(&mut subject_copy, "subject", submission);
+        }
+        subject_copy.remove("_id");
+        trace.step(|| {
+            format!(
+                "subject: lookup ({}, {}) -> hit, embedded",
+                subject_ns, subject_id
+            )
+        });
+        trace.indent();
+
+        if let Some(age) = age_at_sampling(link) {
+            subject_copy.insert(AGE_FIELD, age);
+        }
+        enrich_subject(
+            &mut subject_copy,
+            subject_ns,
+            subject_id,
+            submission,
+            ctx,
+            trace,
+        );
+        enriched_subjects.push(subject_copy);
+        trace.dedent();
+    }
+
+    trace.dedent();
+    enriched_subjects
+}
+
+/// Embed a subject's terms, plus every race it reports.
+pub fn enrich_subject(
+    subject: &mut Document,
+    subject_ns: &str,
+    subject_id: &str,
+    submission: &str,
+    ctx: &LookupContext,
+    trace: &mut Trace,
+) {
+    embed_terms(subject, Entity::Subject, submission, ctx, trace);
+
+    // A subject may report several races
+    let races = match ctx.subject_race.get(subject_ns, subject_id) {
+        Some(rows) => embed_term_list(
+            &rows,
+            "race",
+            &ctx.subject_races,
+            SUBJECT_RACE_TABLE,
+            None,
+            submission,
+            ctx,
+            trace,
+        ),
+        None => Vec::new(),
+    };
+    subject.insert("race", races);
+}
+
+/// Resolve the `field` term of each association row (e.g. `subject_race`)
+/// against `table`, the vocabulary table `table_name`, returning the
+/// embedded terms; a missed id is kept as `{id}`.
+#[allow(clippy::too_many_arguments)]
+fn embed_term_list(
+    rows: &[Document],
+    field: &str,
+    table: &LookupMap,
+    table_name: &str,
+    ontology: Option<&Ontology>,
+    submission: &str,
+    ctx: &LookupContext,
+    trace: &mut Trace,
+) -> Vec<Document> {
+    let submission = ctx.vocab_submission(submission);
+    let mut terms = Vec::new();
+    for row in rows {
+        let mut term = doc! { field: row.get_str(field).unwrap_or_default() };
+        let missed = embed_term(
+            &mut term,
+            field,
+            table,
+            ontology,
+            submission,
+            ctx.opts.on_miss,
+            ctx.opts.provenance.then_some(table_name),
+            trace,
+        );
+        if missed {
+            Misses::bump(&ctx.misses.terms);
+        }
+        if let Some(term) = term.remove(field) {
+            terms.push(match term {
+                Bson::Document(term) => term,
+                id => doc! { "id": id },
+            });
+        }
+    }
+    terms
+}
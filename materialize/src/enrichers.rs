@@ -0,0 +1,181 @@
+use bson::Document;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::sync::Mutex;
+
+use crate::flatten::term_value;
+use crate::lookup::LookupContext;
+
+/// A site-specific enrichment step, run on every file after the built-in
+/// joins and derived fields, so deployments can add their own fields
+/// (billing tags, cohort flags) without touching the join code.
+///
+/// Enrichers run on the enrichment thread pool and must not block on I/O
+/// per file; load what they need up front.
+pub trait Enricher: Send + Sync {
+    /// Shown in `--explain` traces and the run's startup output
+    fn name(&self) -> &str;
+    fn enrich(&self, file: &mut Document, ctx: &LookupContext);
+}
+
+/// Builds an enricher from the run's configuration.
+pub type Factory = fn(&EnricherConfig) -> Option<Box<dyn Enricher>>;
+
+/// Enrichers compiled into the binary, in the order they run.
+static REGISTERED: Mutex<Vec<Factory>> = Mutex::new(Vec::new());
+
+/// Register an enricher to run after those registered before it. Call from
+/// `main` before the lookup tables are loaded; the factory may return `None`
+/// to stay off for a run.
+pub fn register(factory: Factory) {
+    REGISTERED.lock().unwrap().push(factory);
+}
+
+/// Settings for the built-in enrichers, from the `[enrichers]` section of
+/// the config file.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnricherConfig {
+    pub tags: Vec<TagRule>,
+    pub categories: Vec<CategoryRule>,
+}
+
+/// Fields set on every file of a DCC or submission (or on every file when
+/// neither is given).
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TagRule {
+    pub dcc: Option<String>,
+    pub submission: Option<String>,
+    pub set: Document,
+}
+
+/// A curated `category` label (e.g. "raw sequencing", "imaging") given to
+/// the files matching every criterion the rule sets. Term criteria list
+/// ids, matched whether the term was embedded or left as its raw id;
+/// `filename` is a regular expression. A rule with no criteria matches
+/// every file.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CategoryRule {
+    pub category: String,
+    #[serde(default)]
+    pub file_formats: Vec<String>,
+    #[serde(default)]
+    pub data_types: Vec<String>,
+    #[serde(default)]
+    pub assay_types: Vec<String>,
+    #[serde(default, deserialize_with = "pattern")]
+    pub filename: Option<Regex>,
+}
+
+/// Compile a rule's regular expression while the config is parsed, so a bad
+/// pattern is reported before anything runs.
+fn pattern<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Regex>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(pattern) => Regex::new(&pattern)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+impl CategoryRule {
+    fn matches(&self, file: &Document) -> bool {
+        let term = |field: &str, ids: &[String]| {
+            ids.is_empty()
+                || term_value(file, field, "id").is_some_and(|id| ids.iter().any(|i| i == id))
+        };
+        term("file_format", &self.file_formats)
+            && term("data_type", &self.data_types)
+            && term("assay_type", &self.assay_types)
+            && self.filename.as_ref().is_none_or(|filename| {
+                file.get_str("filename")
+                    .is_ok_and(|name| filename.is_match(name))
+            })
+    }
+}
+
+/// The registered enrichers that are on for a run.
+pub fn active(config: &EnricherConfig) -> Vec<Box<dyn Enricher>> {
+    REGISTERED
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|factory| factory(config))
+        .collect()
+}
+
+/// Applies the `[[enrichers.tags]]` rules.
+pub struct Tags(Vec<(Option<String>, Option<String>, Document)>);
+
+impl Tags {
+    pub fn build(config: &EnricherConfig) -> Option<Box<dyn Enricher>> {
+        if config.tags.is_empty() {
+            return None;
+        }
+        let rules = config
+            .tags
+            .iter()
+            .map(|rule| (rule.dcc.clone(), rule.submission.clone(), rule.set.clone()))
+            .collect();
+        Some(Box::new(Tags(rules)))
+    }
+}
+
+impl Enricher for Tags {
+    fn name(&self) -> &str {
+        "tags"
+    }
+
+    fn enrich(&self, file: &mut Document, _ctx: &LookupContext) {
+        let dcc = file
+            .get_document("dcc")
+            .and_then(|dcc| dcc.get_str("dcc_abbreviation"))
+            .unwrap_or_default()
+            .to_string();
+        let submission = file.get_str("submission").unwrap_or_default().to_string();
+        for (rule_dcc, rule_submission, set) in &self.0 {
+            let matches = rule_dcc.as_deref().is_none_or(|d| d == dcc)
+                && rule_submission.as_deref().is_none_or(|s| s == submission);
+            if matches {
+                for (key, value) in set {
+                    file.insert(key, value.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Applies the `[[enrichers.categories]]` rules, setting `category` to the
+/// labels of every rule a file matches, in rule order, each once. Files no
+/// rule matches get an empty list, so the portal can facet on it.
+pub struct Categories(Vec<CategoryRule>);
+
+impl Categories {
+    pub fn build(config: &EnricherConfig) -> Option<Box<dyn Enricher>> {
+        if config.categories.is_empty() {
+            return None;
+        }
+        Some(Box::new(Categories(config.categories.clone())))
+    }
+}
+
+impl Enricher for Categories {
+    fn name(&self) -> &str {
+        "categories"
+    }
+
+    fn enrich(&self, file: &mut Document, _ctx: &LookupContext) {
+        let mut categories: Vec<&str> = Vec::new();
+        for rule in self.0.iter().filter(|rule| rule.matches(file)) {
+            if !categories.contains(&rule.category.as_str()) {
+                categories.push(&rule.category);
+            }
+        }
+        file.insert(CATEGORY_FIELD, categories);
+    }
+}
+
+/// Field the `categories` enricher sets.
+pub const CATEGORY_FIELD: &str = "category";
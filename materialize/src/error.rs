@@ -0,0 +1,147 @@
+use mongodb::error::ErrorKind;
+use std::error::Error;
+use std::fmt;
+
+/// Process exit codes, so wrappers such as Kubernetes CronJobs and Argo
+/// workflows can branch on how a run ended.
+pub const EXIT_FAILURE: u8 = 1;
+pub const EXIT_CONFIG: u8 = 2;
+pub const EXIT_CONNECTION: u8 = 3;
+pub const EXIT_VALIDATION: u8 = 4;
+/// Some files were written but not all: a failed write, an interrupted run,
+/// or files the output rejected
+pub const EXIT_PARTIAL: u8 = 5;
+
+/// Why a materialization failed, for callers that react differently to each
+/// failure mode. The library's entry points return it:
+/// [`Config::load`](crate::config::Config::load),
+/// [`Connections::open`](crate::connection::Connections::open),
+/// [`LookupContext::load`](crate::lookup::LookupContext::load), and the
+/// [`FileSink`](crate::output::FileSink) writes. Internals still use
+/// `anyhow`; errors are classified when they leave an entry point, and code
+/// that knows the failure mode raises the matching variant directly so it
+/// survives the trip.
+#[derive(Debug)]
+pub enum MaterializeError {
+    /// MongoDB could not be reached or refused the credentials
+    Connection(mongodb::error::Error),
+    /// The config file or command-line options are invalid
+    Config(anyhow::Error),
+    /// A lookup the run depends on is missing or ambiguous
+    MissingLookup(String),
+    /// The written files failed the smoke queries
+    Validation(String),
+    /// Writing enriched files to the output failed
+    WriteFailure(anyhow::Error),
+    /// A shutdown signal stopped the run
+    Interrupted(String),
+    Other(anyhow::Error),
+}
+
+impl fmt::Display for MaterializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // `{:#}` carries through to wrapped errors to print their causes
+        match self {
+            MaterializeError::Connection(e) => write!(f, "Cannot reach MongoDB: {}", e),
+            MaterializeError::Config(e) if f.alternate() => {
+                write!(f, "Invalid configuration: {:#}", e)
+            }
+            MaterializeError::Config(e) => write!(f, "Invalid configuration: {}", e),
+            MaterializeError::MissingLookup(message) | MaterializeError::Validation(message) => {
+                write!(f, "{}", message)
+            }
+            MaterializeError::WriteFailure(e) if f.alternate() => {
+                write!(f, "Write failed: {:#}", e)
+            }
+            MaterializeError::WriteFailure(e) => write!(f, "Write failed: {}", e),
+            MaterializeError::Interrupted(message) => write!(f, "{}", message),
+            MaterializeError::Other(e) if f.alternate() => write!(f, "{:#}", e),
+            MaterializeError::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for MaterializeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MaterializeError::Connection(e) => Some(e),
+            MaterializeError::Config(e)
+            | MaterializeError::WriteFailure(e)
+            | MaterializeError::Other(e) => e.source(),
+            MaterializeError::MissingLookup(_)
+            | MaterializeError::Validation(_)
+            | MaterializeError::Interrupted(_) => None,
+        }
+    }
+}
+
+impl MaterializeError {
+    /// Classify a failure to parse the options as `Config`, unless it
+    /// already carries a variant.
+    pub fn config(error: anyhow::Error) -> Self {
+        match error.downcast::<MaterializeError>() {
+            Ok(error) => error,
+            Err(error) => MaterializeError::Config(error),
+        }
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            MaterializeError::Config(_) => EXIT_CONFIG,
+            MaterializeError::Connection(_) => EXIT_CONNECTION,
+            MaterializeError::MissingLookup(_) | MaterializeError::Validation(_) => EXIT_VALIDATION,
+            MaterializeError::WriteFailure(_) | MaterializeError::Interrupted(_) => EXIT_PARTIAL,
+            MaterializeError::Other(_) => EXIT_FAILURE,
+        }
+    }
+
+    /// Short name of the failure mode for machine-readable summaries.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MaterializeError::Connection(_) => "connection",
+            MaterializeError::Config(_) => "config",
+            MaterializeError::MissingLookup(_) => "missing_lookup",
+            MaterializeError::Validation(_) => "validation",
+            MaterializeError::WriteFailure(_) => "write_failure",
+            MaterializeError::Interrupted(_) => "interrupted",
+            MaterializeError::Other(_) => "other",
+        }
+    }
+}
+
+impl From<anyhow::Error> for MaterializeError {
+    /// Recover a variant raised further down, or recognize a MongoDB
+    /// connection failure; anything else is `Other`.
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<MaterializeError>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        match error.downcast::<mongodb::error::Error>() {
+            Ok(e) => e.into(),
+            Err(error) => MaterializeError::Other(error),
+        }
+    }
+}
+
+impl From<mongodb::error::Error> for MaterializeError {
+    /// A connection failure is `Connection`; anything else is `Other`.
+    fn from(error: mongodb::error::Error) -> Self {
+        if is_connection(&error) {
+            MaterializeError::Connection(error)
+        } else {
+            MaterializeError::Other(error.into())
+        }
+    }
+}
+
+fn is_connection(error: &mongodb::error::Error) -> bool {
+    matches!(
+        *error.kind,
+        ErrorKind::ServerSelection { .. }
+            | ErrorKind::Io(_)
+            | ErrorKind::DnsResolve { .. }
+            | ErrorKind::Authentication { .. }
+            | ErrorKind::ConnectionPoolCleared { .. }
+    )
+}
@@ -0,0 +1,30 @@
+use anyhow::{Context, Result};
+use mongodb::sync::Database;
+
+use crate::cli::value;
+
+/// `export sqlite <path> [--submission X]` writes the materialized files to
+/// a portable catalog.
+pub fn command(db: &Database, args: &[String]) -> Result<()> {
+    match args.first().map(String::as_str) {
+        Some("sqlite") => {
+            let path = args
+                .get(1)
+                .filter(|a| !a.starts_with("--"))
+                .context("usage: export sqlite <path> [--submission X]")?;
+            sqlite(db, path, &value(args, "--submission"))
+        }
+        Some(other) => anyhow::bail!("Unknown export format: {}", other),
+        None => anyhow::bail!("usage: export sqlite <path> [--submission X]"),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite(db: &Database, path: &str, submission: &Option<String>) -> Result<()> {
+    crate::sqlite::export(db, std::path::Path::new(path), submission)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sqlite(_db: &Database, _path: &str, _submission: &Option<String>) -> Result<()> {
+    anyhow::bail!("export sqlite requires building with `--features sqlite`")
+}
@@ -0,0 +1,604 @@
+use anyhow::{Context, Result};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::cli::{number, value};
+use crate::ingest::hex;
+
+const DEFAULT_FILES: usize = 1000;
+const DEFAULT_COLLECTIONS: usize = 10;
+const DEFAULT_BIOSAMPLES: usize = 100;
+
+/// Formats files are drawn from: EDAM format, filename extension, EDAM data
+/// type, and MIME type.
+const FORMATS: [(&str, &str, &str, &str); 6] = [
+    ("format:1930", "fastq.gz", "data:2044", "text/x-fastq"),
+    ("format:2572", "bam", "data:1916", "application/x-bam"),
+    ("format:3016", "vcf.gz", "data:3498", "text/x-vcf"),
+    ("format:3003", "bed", "data:3498", "text/x-bed"),
+    (
+        "format:3475",
+        "tsv",
+        "data:3917",
+        "text/tab-separated-values",
+    ),
+    ("format:3752", "csv", "data:3917", "text/csv"),
+];
+
+/// EDAM gzip, the `compression_format` of the `.gz` files.
+const GZIP_FORMAT: &str = "format:3989";
+
+const FILE_FORMAT_TERMS: [(&str, &str); 7] = [
+    ("format:1930", "FASTQ"),
+    ("format:2572", "BAM"),
+    ("format:3016", "VCF"),
+    ("format:3003", "BED"),
+    ("format:3475", "TSV"),
+    ("format:3752", "CSV"),
+    ("format:3989", "GZIP format"),
+];
+
+const DATA_TYPE_TERMS: [(&str, &str); 4] = [
+    ("data:2044", "Sequence"),
+    ("data:1916", "Alignment"),
+    ("data:3498", "Sequence variations"),
+    ("data:3917", "Count matrix"),
+];
+
+const ASSAY_TYPE_TERMS: [(&str, &str); 5] = [
+    ("OBI:0002117", "whole genome sequencing assay"),
+    ("OBI:0001271", "RNA-seq assay"),
+    ("OBI:0002631", "single-cell RNA sequencing assay"),
+    ("OBI:0000716", "ChIP-seq assay"),
+    (
+        "OBI:0002039",
+        "assay for transposase-accessible chromatin using sequencing",
+    ),
+];
+
+const ANALYSIS_TYPE_TERMS: [(&str, &str); 2] = [
+    ("OBI:0200000", "data transformation"),
+    ("OBI:0200111", "data visualization"),
+];
+
+const ANATOMY_TERMS: [(&str, &str); 7] = [
+    ("UBERON:0000955", "brain"),
+    ("UBERON:0002107", "liver"),
+    ("UBERON:0000948", "heart"),
+    ("UBERON:0002048", "lung"),
+    ("UBERON:0002113", "kidney"),
+    ("UBERON:0000178", "blood"),
+    ("UBERON:0001155", "colon"),
+];
+
+const DISEASE_TERMS: [(&str, &str); 5] = [
+    ("DOID:162", "cancer"),
+    ("DOID:1612", "breast cancer"),
+    ("DOID:9352", "type 2 diabetes mellitus"),
+    ("DOID:10652", "Alzheimer's disease"),
+    ("DOID:2841", "asthma"),
+];
+
+const GRANULARITY_TERMS: [(&str, &str); 2] = [
+    ("cfde_subject_granularity:0", "single organism"),
+    ("cfde_subject_granularity:1", "cell line"),
+];
+
+const SEX_TERMS: [(&str, &str); 3] = [
+    ("cfde_subject_sex:0", "Indeterminate"),
+    ("cfde_subject_sex:1", "Female"),
+    ("cfde_subject_sex:2", "Male"),
+];
+
+const ETHNICITY_TERMS: [(&str, &str); 2] = [
+    ("cfde_subject_ethnicity:0", "Hispanic or Latino"),
+    ("cfde_subject_ethnicity:1", "Not Hispanic or Latino"),
+];
+
+const RACE_TERMS: [(&str, &str); 5] = [
+    ("cfde_subject_race:0", "American Indian or Alaska Native"),
+    ("cfde_subject_race:2", "Black or African American"),
+    ("cfde_subject_race:3", "White"),
+    ("cfde_subject_race:5", "Asian"),
+    (
+        "cfde_subject_race:6",
+        "Native Hawaiian or Other Pacific Islander",
+    ),
+];
+
+/// `generate-fixtures <out.zip> [--files N] [--collections M]
+/// [--biosamples K] [--subjects S] [--dcc ABBR] [--seed X]` writes a
+/// synthetic C2M2 datapackage, zipped as a bdbag with a sha256 manifest, that
+/// `ingest` loads as-is. Every row is schema-valid and references real EDAM,
+/// OBI, UBERON, DOID, and CFDE terms, which the package's CV tables define.
+/// The same seed always produces the same package.
+pub fn command(args: &[String]) -> Result<()> {
+    let Some(out) = args.first().filter(|a| !a.starts_with("--")) else {
+        anyhow::bail!(
+            "Usage: generate-fixtures <out.zip> [--files N] [--collections M] \
+             [--biosamples K] [--subjects S] [--dcc ABBR] [--seed X]"
+        );
+    };
+    let files = number(args, "--files")?.unwrap_or(DEFAULT_FILES);
+    let collections = number(args, "--collections")?.unwrap_or(DEFAULT_COLLECTIONS);
+    let biosamples = number(args, "--biosamples")?.unwrap_or(DEFAULT_BIOSAMPLES);
+    let subjects = number(args, "--subjects")?.unwrap_or((biosamples / 2).max(1));
+    if collections == 0 || subjects == 0 {
+        anyhow::bail!("--collections and --subjects must be at least 1");
+    }
+    let abbreviation = value(args, "--dcc").unwrap_or_else(|| "DEMO".to_string());
+    let seed = number(args, "--seed")?.unwrap_or(1);
+
+    let fixture = Fixture {
+        abbreviation,
+        files,
+        collections,
+        biosamples,
+        subjects,
+    };
+    let tables = fixture.tables(&mut Rng(seed));
+    write_bag(Path::new(out), &tables)?;
+    println!(
+        "Wrote {} ({} files, {} collections, {} biosamples, {} subjects); \
+         load it with `materialize ingest {} --submission <id>`",
+        out, files, collections, biosamples, subjects, out
+    );
+    Ok(())
+}
+
+/// A small deterministic generator (SplitMix64), so fixtures are
+/// reproducible without pulling in `rand`.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// One generated TSV table.
+struct Table {
+    name: &'static str,
+    header: &'static [&'static str],
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn new(name: &'static str, header: &'static [&'static str]) -> Self {
+        Table {
+            name,
+            header,
+            rows: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    fn vocabulary(name: &'static str, terms: &[(&str, &str)]) -> Self {
+        let mut table = Table::new(name, &["id", "name", "description"]);
+        for (id, term) in terms {
+            table.push(vec![id.to_string(), term.to_string(), String::new()]);
+        }
+        table
+    }
+
+    fn to_tsv(&self) -> Result<Vec<u8>> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(b'\t')
+            .from_writer(Vec::new());
+        writer.write_record(self.header)?;
+        for row in &self.rows {
+            writer.write_record(row)?;
+        }
+        Ok(writer.into_inner()?)
+    }
+}
+
+struct Fixture {
+    abbreviation: String,
+    files: usize,
+    collections: usize,
+    biosamples: usize,
+    subjects: usize,
+}
+
+impl Fixture {
+    fn tables(&self, rng: &mut Rng) -> Vec<Table> {
+        let lower = self.abbreviation.to_ascii_lowercase();
+        let namespace = format!("tag:{}.fixtures.example.org,2024:", lower);
+        let project = format!("{}-root", lower);
+        let timestamp = |rng: &mut Rng| {
+            format!(
+                "2024-{:02}-{:02}T{:02}:{:02}:00+00:00",
+                1 + rng.below(12),
+                1 + rng.below(28),
+                rng.below(24),
+                rng.below(60)
+            )
+        };
+
+        let mut dcc = Table::new(
+            "dcc",
+            &[
+                "id",
+                "dcc_name",
+                "dcc_abbreviation",
+                "dcc_description",
+                "contact_email",
+                "contact_name",
+                "dcc_url",
+                "project_id_namespace",
+                "project_local_id",
+            ],
+        );
+        dcc.push(vec![
+            format!("cfde_registry_dcc:{}", lower),
+            format!("{} Data Coordinating Center", self.abbreviation),
+            self.abbreviation.clone(),
+            "Synthetic submission generated for tests and demos".to_string(),
+            format!("help@{}.fixtures.example.org", lower),
+            "Fixture Contact".to_string(),
+            format!("https://{}.fixtures.example.org", lower),
+            namespace.clone(),
+            project.clone(),
+        ]);
+
+        let mut id_namespace = Table::new(
+            "id_namespace",
+            &["id", "abbreviation", "name", "description"],
+        );
+        id_namespace.push(vec![
+            namespace.clone(),
+            self.abbreviation.clone(),
+            format!("{} fixtures", self.abbreviation),
+            String::new(),
+        ]);
+
+        let mut projects = Table::new(
+            "project",
+            &[
+                "id_namespace",
+                "local_id",
+                "persistent_id",
+                "creation_time",
+                "abbreviation",
+                "name",
+                "description",
+            ],
+        );
+        projects.push(vec![
+            namespace.clone(),
+            project.clone(),
+            String::new(),
+            timestamp(rng),
+            self.abbreviation.clone(),
+            format!("{} root project", self.abbreviation),
+            String::new(),
+        ]);
+
+        let mut collections = Table::new(
+            "collection",
+            &[
+                "id_namespace",
+                "local_id",
+                "persistent_id",
+                "creation_time",
+                "abbreviation",
+                "name",
+                "description",
+                "has_time_series_data",
+            ],
+        );
+        let mut collection_anatomy = Table::new(
+            "collection_anatomy",
+            &["collection_id_namespace", "collection_local_id", "anatomy"],
+        );
+        let mut collection_disease = Table::new(
+            "collection_disease",
+            &["collection_id_namespace", "collection_local_id", "disease"],
+        );
+        let mut collection_defined_by_project = Table::new(
+            "collection_defined_by_project",
+            &[
+                "collection_id_namespace",
+                "collection_local_id",
+                "project_id_namespace",
+                "project_local_id",
+            ],
+        );
+        let collection_id = |i: usize| format!("collection-{:05}", i);
+        for i in 0..self.collections {
+            let id = collection_id(i);
+            collections.push(vec![
+                namespace.clone(),
+                id.clone(),
+                String::new(),
+                timestamp(rng),
+                format!("C{}", i),
+                format!("Collection {}", i),
+                String::new(),
+                if i % 5 == 0 { "true" } else { "false" }.to_string(),
+            ]);
+            collection_anatomy.push(vec![
+                namespace.clone(),
+                id.clone(),
+                rng.pick(&ANATOMY_TERMS).0.to_string(),
+            ]);
+            if i % 2 == 0 {
+                collection_disease.push(vec![
+                    namespace.clone(),
+                    id.clone(),
+                    rng.pick(&DISEASE_TERMS).0.to_string(),
+                ]);
+            }
+            collection_defined_by_project.push(vec![
+                namespace.clone(),
+                id,
+                namespace.clone(),
+                project.clone(),
+            ]);
+        }
+
+        let mut subjects = Table::new(
+            "subject",
+            &[
+                "id_namespace",
+                "local_id",
+                "project_id_namespace",
+                "project_local_id",
+                "persistent_id",
+                "creation_time",
+                "granularity",
+                "sex",
+                "ethnicity",
+                "age_at_enrollment",
+            ],
+        );
+        let mut subject_race = Table::new(
+            "subject_race",
+            &["subject_id_namespace", "subject_local_id", "race"],
+        );
+        let subject_id = |i: usize| format!("subject-{:05}", i);
+        for i in 0..self.subjects {
+            let id = subject_id(i);
+            subjects.push(vec![
+                namespace.clone(),
+                id.clone(),
+                namespace.clone(),
+                project.clone(),
+                String::new(),
+                timestamp(rng),
+                GRANULARITY_TERMS[0].0.to_string(),
+                rng.pick(&SEX_TERMS).0.to_string(),
+                rng.pick(&ETHNICITY_TERMS).0.to_string(),
+                format!("{}", 18 + rng.below(70)),
+            ]);
+            subject_race.push(vec![
+                namespace.clone(),
+                id,
+                rng.pick(&RACE_TERMS).0.to_string(),
+            ]);
+        }
+
+        let mut biosamples = Table::new(
+            "biosample",
+            &[
+                "id_namespace",
+                "local_id",
+                "project_id_namespace",
+                "project_local_id",
+                "persistent_id",
+                "creation_time",
+                "sample_prep_method",
+                "anatomy",
+            ],
+        );
+        let mut biosample_in_collection = Table::new(
+            "biosample_in_collection",
+            &[
+                "biosample_id_namespace",
+                "biosample_local_id",
+                "collection_id_namespace",
+                "collection_local_id",
+            ],
+        );
+        let mut biosample_from_subject = Table::new(
+            "biosample_from_subject",
+            &[
+                "biosample_id_namespace",
+                "biosample_local_id",
+                "subject_id_namespace",
+                "subject_local_id",
+                "age_at_sampling",
+            ],
+        );
+        for i in 0..self.biosamples {
+            let id = format!("biosample-{:06}", i);
+            biosamples.push(vec![
+                namespace.clone(),
+                id.clone(),
+                namespace.clone(),
+                project.clone(),
+                String::new(),
+                timestamp(rng),
+                String::new(),
+                rng.pick(&ANATOMY_TERMS).0.to_string(),
+            ]);
+            biosample_in_collection.push(vec![
+                namespace.clone(),
+                id.clone(),
+                namespace.clone(),
+                collection_id(i % self.collections),
+            ]);
+            biosample_from_subject.push(vec![
+                namespace.clone(),
+                id,
+                namespace.clone(),
+                subject_id(i % self.subjects),
+                String::new(),
+            ]);
+        }
+
+        let mut files = Table::new(
+            "file",
+            &[
+                "id_namespace",
+                "local_id",
+                "project_id_namespace",
+                "project_local_id",
+                "persistent_id",
+                "creation_time",
+                "size_in_bytes",
+                "uncompressed_size_in_bytes",
+                "sha256",
+                "md5",
+                "filename",
+                "file_format",
+                "compression_format",
+                "data_type",
+                "assay_type",
+                "analysis_type",
+                "mime_type",
+                "bundle_collection_id_namespace",
+                "bundle_collection_local_id",
+                "dbgap_study_id",
+            ],
+        );
+        let mut file_in_collection = Table::new(
+            "file_in_collection",
+            &[
+                "file_id_namespace",
+                "file_local_id",
+                "collection_id_namespace",
+                "collection_local_id",
+            ],
+        );
+        for i in 0..self.files {
+            let id = format!("file-{:07}", i);
+            let (format, extension, data_type, mime_type) = *rng.pick(&FORMATS);
+            let compressed = extension.ends_with(".gz");
+            let size = 1024 + rng.next() % 10_000_000_000;
+            let analysis_type = if rng.below(4) == 0 {
+                rng.pick(&ANALYSIS_TYPE_TERMS).0
+            } else {
+                ""
+            };
+            let filename = format!("{}.{}", id, extension);
+            files.push(vec![
+                namespace.clone(),
+                id.clone(),
+                namespace.clone(),
+                project.clone(),
+                format!("https://{}.fixtures.example.org/files/{}", lower, filename),
+                timestamp(rng),
+                size.to_string(),
+                if compressed {
+                    (size * 4).to_string()
+                } else {
+                    String::new()
+                },
+                hex(&Sha256::digest(filename.as_bytes())),
+                hex(&Md5::digest(filename.as_bytes())),
+                filename,
+                format.to_string(),
+                if compressed { GZIP_FORMAT } else { "" }.to_string(),
+                data_type.to_string(),
+                rng.pick(&ASSAY_TYPE_TERMS).0.to_string(),
+                analysis_type.to_string(),
+                mime_type.to_string(),
+                String::new(),
+                String::new(),
+                String::new(),
+            ]);
+            let home = i % self.collections;
+            file_in_collection.push(vec![
+                namespace.clone(),
+                id.clone(),
+                namespace.clone(),
+                collection_id(home),
+            ]);
+            // Some files belong to a second collection too
+            if self.collections > 1 && rng.below(10) == 0 {
+                file_in_collection.push(vec![
+                    namespace.clone(),
+                    id,
+                    namespace.clone(),
+                    collection_id((home + 1) % self.collections),
+                ]);
+            }
+        }
+
+        vec![
+            dcc,
+            id_namespace,
+            projects,
+            collections,
+            collection_anatomy,
+            collection_disease,
+            collection_defined_by_project,
+            subjects,
+            subject_race,
+            biosamples,
+            biosample_in_collection,
+            biosample_from_subject,
+            files,
+            file_in_collection,
+            Table::vocabulary("file_format", &FILE_FORMAT_TERMS),
+            Table::vocabulary("data_type", &DATA_TYPE_TERMS),
+            Table::vocabulary("assay_type", &ASSAY_TYPE_TERMS),
+            Table::vocabulary("analysis_type", &ANALYSIS_TYPE_TERMS),
+            Table::vocabulary("anatomy", &ANATOMY_TERMS),
+            Table::vocabulary("disease", &DISEASE_TERMS),
+            Table::vocabulary("subject_granularity", &GRANULARITY_TERMS),
+            Table::vocabulary("subject_sex", &SEX_TERMS),
+            Table::vocabulary("subject_ethnicity", &ETHNICITY_TERMS),
+            Table::vocabulary("subject_race_CV", &RACE_TERMS),
+        ]
+    }
+}
+
+/// Zip the tables as a bdbag: `<stem>/data/<table>.tsv` plus `bagit.txt`
+/// and a `manifest-sha256.txt` covering every payload file.
+fn write_bag(path: &Path, tables: &[Table]) -> Result<()> {
+    let stem = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_suffix(".zip"))
+        .with_context(|| format!("{} must end in .zip", path.display()))?;
+    let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default();
+    let mut manifest = String::new();
+    for table in tables {
+        let tsv = table.to_tsv()?;
+        let name = format!("data/{}.tsv", table.name);
+        manifest.push_str(&format!("{}  {}\n", hex(&Sha256::digest(&tsv)), name));
+        zip.start_file(format!("{}/{}", stem, name), options)?;
+        zip.write_all(&tsv)?;
+    }
+    zip.start_file(format!("{}/bagit.txt", stem), options)?;
+    zip.write_all(b"BagIt-Version: 0.97\nTag-File-Character-Encoding: UTF-8\n")?;
+    zip.start_file(format!("{}/manifest-sha256.txt", stem), options)?;
+    zip.write_all(manifest.as_bytes())?;
+    zip.finish()?;
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use bson::{Bson, Document};
+
+// Flat, columnar view of an enriched file shared by the tabular exports
+// (Parquet, SQLite) and the `schema` command and
+// the categories enricher, so it is built without the
+// export features.
+
+/// Top-level string fields copied as-is.
+pub const STRING_COLUMNS: [&str; 19] = [
+    "submission",
+    "id_namespace",
+    "local_id",
+    "project_id_namespace",
+    "project_local_id",
+    "persistent_id",
+    "creation_time",
+    "sha256",
+    "md5",
+    "filename",
+    "mime_type",
+    "bundle_collection_id_namespace",
+    "bundle_collection_local_id",
+    "dbgap_study_id",
+    "access_url",
+    "status",
+    "data_access_level",
+    "preview",
+    "drs_uri",
+];
+
+pub const INTEGER_COLUMNS: [&str; 2] = ["size_in_bytes", "uncompressed_size_in_bytes"];
+
+/// (column, embedded field, key) for flattened embedded documents. A term
+/// whose lookup missed still fills its `_id` column from the raw id.
+pub const TERM_COLUMNS: [(&str, &str, &str); 13] = [
+    ("dcc_id", "dcc", "id"),
+    ("dcc_name", "dcc", "dcc_name"),
+    ("dcc_abbreviation", "dcc", "dcc_abbreviation"),
+    ("file_format_id", "file_format", "id"),
+    ("file_format_name", "file_format", "name"),
+    ("compression_format_id", "compression_format", "id"),
+    ("compression_format_name", "compression_format", "name"),
+    ("data_type_id", "data_type", "id"),
+    ("data_type_name", "data_type", "name"),
+    ("assay_type_id", "assay_type", "id"),
+    ("assay_type_name", "assay_type", "name"),
+    ("analysis_type_id", "analysis_type", "id"),
+    ("analysis_type_name", "analysis_type", "name"),
+];
+
+/// List columns gathered from the embedded collections and their biosamples.
+pub const LIST_COLUMNS: [&str; 5] = [
+    "collection_local_ids",
+    "collection_names",
+    "biosample_local_ids",
+    "anatomy_ids",
+    "anatomy_names",
+];
+
+/// A [`STRING_COLUMNS`] value; `creation_time` is stored as a date and
+/// written out as RFC 3339.
+pub fn string_value(file: &Document, name: &str) -> Option<String> {
+    match file.get(name)? {
+        Bson::String(s) => Some(s.clone()),
+        Bson::DateTime(time) => time.try_to_rfc3339_string().ok(),
+        _ => None,
+    }
+}
+
+/// The `key` of the embedded document under `field`, or the raw id when the
+/// lookup missed and `key` is `id`.
+pub fn term_value<'a>(file: &'a Document, field: &str, key: &str) -> Option<&'a str> {
+    match file.get(field)? {
+        Bson::Document(term) => term.get_str(key).ok(),
+        Bson::String(id) if key == "id" => Some(id),
+        _ => None,
+    }
+}
+
+/// Sorted, distinct values of a [`LIST_COLUMNS`] column.
+pub fn list_values<'a>(file: &'a Document, column: &str) -> Vec<&'a str> {
+    let collections = file
+        .get_array("collections")
+        .map(|c| c.iter().filter_map(Bson::as_document).collect::<Vec<_>>())
+        .unwrap_or_default();
+    // Biosamples are nested in their collections, or hoisted to the file
+    // with `--hoist-biosamples`
+    let biosamples = || {
+        collections
+            .iter()
+            .filter_map(|c| c.get_array("biosamples").ok())
+            .chain(file.get_array("biosamples").ok())
+            .flatten()
+            .filter_map(Bson::as_document)
+    };
+    let mut values: Vec<&str> = match column {
+        "collection_local_ids" => collections
+            .iter()
+            .filter_map(|c| c.get_str("local_id").ok())
+            .collect(),
+        "collection_names" => collections
+            .iter()
+            .filter_map(|c| c.get_str("name").ok())
+            .collect(),
+        "biosample_local_ids" => biosamples()
+            .filter_map(|b| b.get_str("local_id").ok())
+            .collect(),
+        "anatomy_ids" => biosamples()
+            .filter_map(|b| term_value(b, "anatomy", "id"))
+            .collect(),
+        "anatomy_names" => biosamples()
+            .filter_map(|b| term_value(b, "anatomy", "name"))
+            .collect(),
+        _ => Vec::new(),
+    };
+    values.sort_unstable();
+    values.dedup();
+    values
+}
@@ -0,0 +1,141 @@
+use anyhow::Result;
+use bson::{doc, Bson, DateTime, Document};
+use mongodb::sync::{Collection, Database};
+use mongodb::IndexModel;
+
+use crate::cli::flag;
+use crate::projects::integer_field;
+
+pub const IDENTITY_COLLECTION: &str = "file_identity";
+
+/// Fields identifying one copy of a file in `file_identity` and the links.
+fn copy_of(file: &Document) -> Document {
+    let mut copy = Document::new();
+    for field in ["id_namespace", "local_id", "submission"] {
+        if let Some(value) = file.get(field) {
+            copy.insert(field, value.clone());
+        }
+    }
+    copy
+}
+
+/// `dedupe [--link]` groups the materialized `files` by `sha256` and writes
+/// one `file_identity` document per checksum held by more than one file,
+/// listing every copy; the first copy by (submission, id_namespace,
+/// local_id) is the canonical one. With `--link`, each copy also gets
+/// `also_in` (the other copies) and every copy but the canonical one gets
+/// `duplicate_of`. Rematerializing a submission drops its links, so rerun
+/// after materializing.
+pub fn command(db: &Database, args: &[String]) -> Result<()> {
+    let link = flag(args, "--link");
+    let files: Collection<Document> = db.collection("files");
+
+    let pipeline = vec![
+        doc! { "$match": { "sha256": { "$type": "string", "$ne": "" } } },
+        doc! { "$sort": { "submission": 1, "id_namespace": 1, "local_id": 1 } },
+        doc! { "$group": {
+            "_id": "$sha256",
+            "copies": { "$push": {
+                "id_namespace": "$id_namespace",
+                "local_id": "$local_id",
+                "submission": "$submission",
+            } },
+            "size_in_bytes": { "$first": "$size_in_bytes" },
+            "count": { "$sum": 1 },
+        } },
+        doc! { "$match": { "count": { "$gt": 1 } } },
+    ];
+
+    if link {
+        let cleared = files
+            .update_many(
+                doc! { "$or": [
+                    { "also_in": { "$exists": true } },
+                    { "duplicate_of": { "$exists": true } },
+                ] },
+                doc! { "$unset": { "also_in": "", "duplicate_of": "" } },
+            )
+            .run()?;
+        println!("Cleared links from {} files", cleared.modified_count);
+    }
+
+    let computed_at = DateTime::now();
+    let mut identities = Vec::new();
+    let mut duplicates: i64 = 0;
+    for group in files.aggregate(pipeline).allow_disk_use(true).run()? {
+        let group = group?;
+        let Ok(sha256) = group.get_str("_id") else {
+            continue;
+        };
+        let copies: Vec<Document> = group
+            .get_array("copies")
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(Bson::as_document)
+            .map(copy_of)
+            .collect();
+        let Some(canonical) = copies.first().cloned() else {
+            continue;
+        };
+        duplicates += copies.len() as i64 - 1;
+
+        if link {
+            for (i, copy) in copies.iter().enumerate() {
+                let others: Vec<Document> = copies
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, other)| other.clone())
+                    .collect();
+                let mut set = doc! { "also_in": others };
+                if i > 0 {
+                    set.insert("duplicate_of", canonical.clone());
+                }
+                let mut filter = copy.clone();
+                filter.remove("submission");
+                files.update_one(filter, doc! { "$set": set }).run()?;
+            }
+        }
+
+        // Copies are sorted by submission, so repeats are adjacent
+        let mut submissions: Vec<String> = copies
+            .iter()
+            .filter_map(|copy| copy.get_str("submission").ok())
+            .map(str::to_string)
+            .collect();
+        submissions.dedup();
+        identities.push(doc! {
+            "sha256": sha256,
+            "size_in_bytes": integer_field(&group, "size_in_bytes"),
+            "count": copies.len() as i64,
+            "submissions": submissions,
+            "canonical": canonical,
+            "copies": copies,
+            "computed_at": computed_at,
+        });
+    }
+
+    println!(
+        "{} checksums shared by several files; {} files duplicate another",
+        identities.len(),
+        duplicates
+    );
+
+    let coll: Collection<Document> = db.collection(IDENTITY_COLLECTION);
+    coll.drop().run()?;
+    if !identities.is_empty() {
+        coll.insert_many(&identities).run()?;
+    }
+    coll.create_indexes(
+        [
+            doc! { "sha256": 1 },
+            doc! { "submissions": 1 },
+            doc! { "copies.id_namespace": 1, "copies.local_id": 1 },
+        ]
+        .into_iter()
+        .map(|keys| IndexModel::builder().keys(keys).build()),
+    )
+    .run()?;
+    Ok(())
+}
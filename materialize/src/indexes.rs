@@ -0,0 +1,102 @@
+use bson::{doc, Document};
+use mongodb::options::{Collation, CollationStrength, IndexOptions};
+use serde::Deserialize;
+
+use crate::spec::EnrichmentSpec;
+
+/// Most indexes MongoDB allows on one collection, `_id` included.
+pub const MAX_INDEXES: usize = 64;
+
+/// Text-like `files` fields the case-insensitive preset covers, besides the
+/// names of the spec's terms and their ancestors.
+const TEXT_LIKE_FIELDS: [&str; 9] = [
+    "filename",
+    "dcc.dcc_name",
+    "dcc.dcc_abbreviation",
+    "collections.name",
+    "collections.anatomies.name",
+    "collections.diseases.name",
+    "collections.biosamples.substances.name",
+    "collections.biosamples.genes.name",
+    "collections.biosamples.subjects.race.name",
+];
+
+/// Indexes built on `files` beyond the built-in ones (`[indexes]`).
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IndexConfig {
+    /// Index every text-like field case-insensitively, in an index named
+    /// `<field>_ci` that takes the place of any plain one on the field, so
+    /// name searches that use the same collation are indexed
+    pub case_insensitive: bool,
+    /// Locale of the case-insensitive preset's collation
+    pub locale: String,
+    pub extra: Vec<IndexDefinition>,
+}
+
+/// One extra index. An index on the same keys as a built-in one but with a
+/// collation needs its own `name`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IndexDefinition {
+    pub keys: Document,
+    pub name: Option<String>,
+    /// MongoDB collation document, e.g. `{ locale = "en", strength = 2 }`
+    pub collation: Option<Collation>,
+    #[serde(default)]
+    pub unique: bool,
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        IndexConfig {
+            case_insensitive: false,
+            locale: "en".to_string(),
+            extra: Vec::new(),
+        }
+    }
+}
+
+impl IndexConfig {
+    /// Fields the case-insensitive preset indexes, none when it is off.
+    pub fn case_insensitive_fields(&self, spec: &EnrichmentSpec) -> Vec<String> {
+        if !self.case_insensitive {
+            return Vec::new();
+        }
+        let term_names = spec
+            .index_fields()
+            .into_iter()
+            .filter(|field| field.ends_with(".name"));
+        TEXT_LIKE_FIELDS
+            .iter()
+            .map(|f| f.to_string())
+            .chain(term_names)
+            .collect()
+    }
+
+    /// Keys and options of the configured indexes: the case-insensitive
+    /// preset when enabled, then the extra definitions.
+    pub fn definitions(&self, spec: &EnrichmentSpec) -> Vec<(Document, IndexOptions)> {
+        let mut definitions = Vec::new();
+        for field in self.case_insensitive_fields(spec) {
+            let collation = Collation::builder()
+                .locale(self.locale.clone())
+                .strength(CollationStrength::Secondary)
+                .build();
+            let options = IndexOptions::builder()
+                .name(format!("{}_ci", field))
+                .collation(collation)
+                .build();
+            definitions.push((doc! { field: 1 }, options));
+        }
+        for index in &self.extra {
+            let options = IndexOptions::builder()
+                .name(index.name.clone())
+                .collation(index.collation.clone())
+                .unique(index.unique.then_some(true))
+                .build();
+            definitions.push((index.keys.clone(), options));
+        }
+        definitions
+    }
+}
@@ -0,0 +1,496 @@
+use anyhow::{Context, Result};
+use bson::{doc, Document};
+use flate2::read::GzDecoder;
+use md5::Md5;
+use mongodb::sync::{Collection, Database};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::cli::{flag, number, value};
+#[cfg(feature = "cloud")]
+use crate::cloud::Bucket;
+use crate::mapping::{Mapping, TableMapping};
+use crate::submissions::source_collections;
+
+/// Rows per `insert_many`, matching the sync service's loader.
+const INSERT_BATCH: usize = 1000;
+
+/// Packages ingested at once from a directory without `--workers`.
+const DEFAULT_WORKERS: usize = 4;
+
+/// `ingest <archive> --submission X [--no-verify]` loads a zipped or
+/// tar.gz C2M2 datapackage (bdbag) into the raw collections, one collection
+/// per TSV/CSV table, tagging every row with its `submission` and `table`
+/// like the sync service does. Tables are streamed out of the archive
+/// without unpacking it. Unless `--no-verify` is given, every payload file
+/// is first checked against the bag's `manifest-sha256.txt` or
+/// `manifest-md5.txt`, and nothing is loaded on a mismatch. The
+/// submission's existing rows are replaced.
+///
+/// `ingest <directory> [--workers N] [--no-verify]` ingests every archive in
+/// the directory as the submission named by its file stem (`hubmap.zip` ->
+/// `hubmap`), N at a time (default 4). A package that fails is reported and
+/// the rest still load; the command fails at the end if any did.
+///
+/// With `--features cloud`, the archive or directory may be an object
+/// storage URL: `s3://bucket/key.zip` (with `--submission`) or
+/// `s3://bucket/prefix/` for every package under the prefix, likewise with
+/// `gs://`. Packages are downloaded to the temp directory one per worker.
+///
+/// `--mapping mapping.toml` (or `mapping.yaml`) loads partner packages that
+/// aren't C2M2: the payload files it names are renamed and derived into
+/// C2M2 tables as they load (see `Mapping`).
+pub fn command(db: &Database, args: &[String]) -> Result<()> {
+    let Some(path) = args.first().filter(|a| !a.starts_with("--")) else {
+        anyhow::bail!(
+            "Usage: ingest <archive.zip|archive.tar.gz> --submission X [--no-verify] \
+             | ingest <directory|s3://bucket/prefix/|gs://bucket/prefix/> [--workers N] [--no-verify] \
+             [--mapping mapping.toml|mapping.yaml]"
+        );
+    };
+    let verify = !flag(args, "--no-verify");
+    let mapping = value(args, "--mapping")
+        .map(|path| Mapping::load(&path))
+        .transpose()?;
+    let mapping = mapping.as_ref();
+    if path.starts_with("s3://") || path.starts_with("gs://") {
+        return ingest_remote(db, path, args, verify, mapping);
+    }
+    let path = Path::new(path);
+    if path.is_dir() {
+        return ingest_directory(db, path, number(args, "--workers")?, verify, mapping);
+    }
+    let Some(submission) = value(args, "--submission") else {
+        anyhow::bail!("ingest needs --submission");
+    };
+    ingest(db, path, &submission, verify, mapping)
+}
+
+fn ingest(
+    db: &Database,
+    path: &Path,
+    submission: &str,
+    verify_checksums: bool,
+    mapping: Option<&Mapping>,
+) -> Result<()> {
+    if verify_checksums {
+        verify(path, submission)?;
+    }
+    let tables = load(db, path, submission, mapping)?;
+    println!(
+        "Ingested {} rows into {} tables for {}",
+        tables.values().sum::<u64>(),
+        tables.len(),
+        submission
+    );
+    Ok(())
+}
+
+fn ingest_directory(
+    db: &Database,
+    dir: &Path,
+    workers: Option<usize>,
+    verify_checksums: bool,
+    mapping: Option<&Mapping>,
+) -> Result<()> {
+    let mut packages: Vec<(String, PathBuf)> = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if let Some(stem) = package_stem(&name) {
+            packages.push((stem.to_string(), path.clone()));
+        }
+    }
+    if packages.is_empty() {
+        anyhow::bail!("No .zip, .tar.gz, or .tgz packages in {}", dir.display());
+    }
+    println!(
+        "Ingesting {} packages from {}",
+        packages.len(),
+        dir.display()
+    );
+    ingest_all(packages, workers, |submission, path| {
+        ingest(db, path, submission, verify_checksums, mapping)
+    })
+}
+
+/// The submission a package file name stands for (`hubmap.zip` ->
+/// `hubmap`), if it is a package.
+fn package_stem(name: &str) -> Option<&str> {
+    let name = name.rsplit('/').next().unwrap_or(name);
+    [".zip", ".tar.gz", ".tgz"]
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .filter(|stem| !stem.is_empty())
+}
+
+/// Ingest `packages` (submission, source) `workers` at a time, reporting
+/// each failure and failing at the end if any package did.
+fn ingest_all<T: Sync>(
+    mut packages: Vec<(String, T)>,
+    workers: Option<usize>,
+    ingest_one: impl Fn(&str, &T) -> Result<()> + Sync,
+) -> Result<()> {
+    packages.sort_by(|a, b| a.0.cmp(&b.0));
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(workers.unwrap_or(DEFAULT_WORKERS))
+        .build()?;
+    let outcomes: Vec<Result<()>> = pool.install(|| {
+        packages
+            .par_iter()
+            .map(|(submission, source)| ingest_one(submission, source))
+            .collect()
+    });
+
+    let mut failed = Vec::new();
+    for ((submission, _), outcome) in packages.iter().zip(outcomes) {
+        if let Err(e) = outcome {
+            println!("  {}: failed: {:#}", submission, e);
+            failed.push(submission.as_str());
+        }
+    }
+    if !failed.is_empty() {
+        anyhow::bail!(
+            "{} of {} packages failed to ingest: {}",
+            failed.len(),
+            packages.len(),
+            failed.join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "cloud")]
+fn ingest_remote(
+    db: &Database,
+    url: &str,
+    args: &[String],
+    verify_checksums: bool,
+    mapping: Option<&Mapping>,
+) -> Result<()> {
+    let bucket = Bucket::open(url)?;
+    // Packages are downloaded next to each other under the temp directory
+    // and removed once loaded, whatever the outcome
+    let fetch_and_ingest = |submission: &str, key: &String| {
+        let file_name = key.rsplit('/').next().unwrap_or(key);
+        let local =
+            std::env::temp_dir().join(format!("materialize-{}-{}", std::process::id(), file_name));
+        println!("  {}: downloading {}", submission, key);
+        let outcome = bucket
+            .download(key, &local)
+            .and_then(|()| ingest(db, &local, submission, verify_checksums, mapping));
+        let _ = fs::remove_file(&local);
+        outcome
+    };
+
+    if !url.ends_with('/') && package_stem(bucket.key()).is_some() {
+        let Some(submission) = value(args, "--submission") else {
+            anyhow::bail!("ingest needs --submission");
+        };
+        return fetch_and_ingest(&submission, &bucket.key().to_string());
+    }
+    let packages: Vec<(String, String)> = bucket
+        .list()?
+        .into_iter()
+        .filter_map(|key| Some((package_stem(&key)?.to_string(), key)))
+        .collect();
+    if packages.is_empty() {
+        anyhow::bail!("No .zip, .tar.gz, or .tgz packages under {}", url);
+    }
+    println!("Ingesting {} packages from {}", packages.len(), url);
+    ingest_all(packages, number(args, "--workers")?, fetch_and_ingest)
+}
+
+#[cfg(not(feature = "cloud"))]
+fn ingest_remote(_: &Database, _: &str, _: &[String], _: bool, _: Option<&Mapping>) -> Result<()> {
+    anyhow::bail!("ingest from object storage requires building with `--features cloud`")
+}
+
+/// Call `visit` with the name and contents of every regular file in a
+/// `.zip`, `.tar.gz`, or `.tgz` archive, in archive order.
+fn for_each_entry(
+    path: &Path,
+    mut visit: impl FnMut(&str, &mut dyn Read) -> Result<()>,
+) -> Result<()> {
+    let name = path.to_string_lossy();
+    let file = File::open(path).with_context(|| format!("opening {}", name))?;
+    if name.ends_with(".zip") {
+        let mut archive = zip::ZipArchive::new(file)?;
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i)?;
+            if entry.is_file() {
+                let entry_name = entry.name().to_string();
+                visit(&entry_name, &mut entry)?;
+            }
+        }
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        let mut archive = tar::Archive::new(GzDecoder::new(file));
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if entry.header().entry_type().is_file() {
+                let entry_name = entry.path()?.to_string_lossy().into_owned();
+                visit(&entry_name, &mut entry)?;
+            }
+        }
+    } else {
+        anyhow::bail!("{} is not a .zip, .tar.gz, or .tgz archive", name);
+    }
+    Ok(())
+}
+
+/// Check every file listed in the bag manifests against its checksum.
+fn verify(path: &Path, submission: &str) -> Result<()> {
+    // Entries are hashed as they stream past because a tar's manifest may
+    // come after the payload it describes
+    let mut sha256: HashMap<String, String> = HashMap::new();
+    let mut md5: HashMap<String, String> = HashMap::new();
+    let mut manifests: Vec<(String, String, String)> = Vec::new();
+    for_each_entry(path, |name, reader| {
+        let file_name = name.rsplit('/').next().unwrap_or(name);
+        if let Some(algorithm) = file_name
+            .strip_prefix("manifest-")
+            .and_then(|rest| rest.strip_suffix(".txt"))
+        {
+            let mut text = String::new();
+            reader.read_to_string(&mut text)?;
+            let root = &name[..name.len() - file_name.len()];
+            manifests.push((root.to_string(), algorithm.to_string(), text));
+            return Ok(());
+        }
+        let mut sha = Sha256::new();
+        let mut md = Md5::new();
+        let mut buffer = [0u8; 64 * 1024];
+        loop {
+            let n = reader.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            sha.update(&buffer[..n]);
+            md.update(&buffer[..n]);
+        }
+        sha256.insert(name.to_string(), hex(&sha.finalize()));
+        md5.insert(name.to_string(), hex(&md.finalize()));
+        Ok(())
+    })?;
+
+    if manifests.is_empty() {
+        println!(
+            "  {}: warning: no bag manifest in the archive; checksums not verified",
+            submission
+        );
+        return Ok(());
+    }
+    let mut checked = 0;
+    let mut problems = Vec::new();
+    for (root, algorithm, text) in &manifests {
+        let actual = match algorithm.as_str() {
+            "sha256" => &sha256,
+            "md5" => &md5,
+            other => {
+                println!(
+                    "  {}: warning: skipping manifest-{}.txt (unsupported)",
+                    submission, other
+                );
+                continue;
+            }
+        };
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let Some((expected, file)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let name = format!("{}{}", root, file.trim());
+            checked += 1;
+            match actual.get(&name) {
+                None => problems.push(format!("{}: listed in the manifest but missing", name)),
+                Some(sum) if !sum.eq_ignore_ascii_case(expected) => {
+                    problems.push(format!("{}: {} {} != {}", name, algorithm, sum, expected))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    for problem in &problems {
+        println!("  {}: {}", submission, problem);
+    }
+    if !problems.is_empty() {
+        anyhow::bail!(
+            "{} of {} manifest entries fail verification; nothing was loaded",
+            problems.len(),
+            checked
+        );
+    }
+    println!(
+        "  {}: verified {} files against the bag manifest",
+        submission, checked
+    );
+    Ok(())
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The table a payload file loads into (its stem), for TSV/CSV files that
+/// aren't archive metadata; with its field delimiter.
+fn table_for(name: &str) -> Option<(String, u8)> {
+    if name.contains("__MACOSX/") {
+        return None;
+    }
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    if file_name.starts_with("._") {
+        return None;
+    }
+    if let Some(stem) = file_name.strip_suffix(".tsv") {
+        Some((stem.to_string(), b'\t'))
+    } else {
+        file_name
+            .strip_suffix(".csv")
+            .map(|stem| (stem.to_string(), b','))
+    }
+}
+
+/// Replace the submission's raw rows with the archive's tables, or with the
+/// tables `mapping` derives from its files. Returns the rows loaded per
+/// table.
+fn load(
+    db: &Database,
+    path: &Path,
+    submission: &str,
+    mapping: Option<&Mapping>,
+) -> Result<BTreeMap<String, u64>> {
+    let mut tables: BTreeSet<String> = source_collections(db)?.into_iter().collect();
+    tables.extend(
+        mapping
+            .into_iter()
+            .flat_map(Mapping::tables)
+            .map(str::to_string),
+    );
+    for_each_entry(path, |name, _| {
+        if let Some((table, _)) = table_for(name) {
+            tables.insert(table);
+        }
+        Ok(())
+    })?;
+    for table in &tables {
+        db.collection::<Document>(table)
+            .delete_many(doc! { "submission": submission })
+            .run()?;
+    }
+
+    let mut loaded = BTreeMap::new();
+    for_each_entry(path, |name, reader| {
+        let Some((table, delimiter)) = table_for(name) else {
+            return Ok(());
+        };
+        let mapped = mapping.map(|m| m.for_file(name)).unwrap_or_default();
+        if !mapped.is_empty() {
+            let counts = load_mapped(db, reader, delimiter, submission, &mapped)
+                .with_context(|| format!("loading {}", name))?;
+            for (table, count) in counts {
+                println!(
+                    "  {}: loaded {} records from {} into {}",
+                    submission, count, name, table
+                );
+                *loaded.entry(table).or_default() += count;
+            }
+            return Ok(());
+        }
+        let coll: Collection<Document> = db.collection(&table);
+        let count = load_table(&coll, reader, delimiter, submission, &table)
+            .with_context(|| format!("loading {}", name))?;
+        println!("  {}: loaded {} records into {}", submission, count, table);
+        *loaded.entry(table).or_default() += count;
+        Ok(())
+    })?;
+    Ok(loaded)
+}
+
+fn load_table(
+    coll: &Collection<Document>,
+    reader: &mut dyn Read,
+    delimiter: u8,
+    submission: &str,
+    table: &str,
+) -> Result<u64> {
+    let mut csv = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(io::BufReader::new(reader));
+    let headers = csv.headers()?.clone();
+    let mut batch: Vec<Document> = Vec::with_capacity(INSERT_BATCH);
+    let mut count = 0;
+    for record in csv.records() {
+        let record = record?;
+        let mut row = Document::new();
+        for (field, value) in headers.iter().zip(record.iter()) {
+            row.insert(field, value);
+        }
+        row.insert("submission", submission);
+        row.insert("table", table);
+        batch.push(row);
+        count += 1;
+        if batch.len() >= INSERT_BATCH {
+            coll.insert_many(&batch).run()?;
+            batch.clear();
+        }
+    }
+    if !batch.is_empty() {
+        coll.insert_many(&batch).run()?;
+    }
+    Ok(count)
+}
+
+/// Load one partner file into every table `mappings` derive from it,
+/// reading it once. Returns the rows loaded per table.
+fn load_mapped(
+    db: &Database,
+    reader: &mut dyn Read,
+    delimiter: u8,
+    submission: &str,
+    mappings: &[&TableMapping],
+) -> Result<Vec<(String, u64)>> {
+    let mut csv = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(io::BufReader::new(reader));
+    let headers = csv.headers()?.clone();
+    let colls: Vec<Collection<Document>> =
+        mappings.iter().map(|m| db.collection(&m.table)).collect();
+    let mut batches: Vec<Vec<Document>> = vec![Vec::with_capacity(INSERT_BATCH); mappings.len()];
+    let mut counts = vec![0u64; mappings.len()];
+    for record in csv.records() {
+        let record = record?;
+        let mut source = Document::new();
+        for (field, value) in headers.iter().zip(record.iter()) {
+            source.insert(field, value);
+        }
+        for (i, mapping) in mappings.iter().enumerate() {
+            let mut row = mapping.apply(&source);
+            row.insert("submission", submission);
+            row.insert("table", &mapping.table);
+            batches[i].push(row);
+            counts[i] += 1;
+            if batches[i].len() >= INSERT_BATCH {
+                colls[i].insert_many(&batches[i]).run()?;
+                batches[i].clear();
+            }
+        }
+    }
+    for (coll, batch) in colls.iter().zip(&batches) {
+        if !batch.is_empty() {
+            coll.insert_many(batch).run()?;
+        }
+    }
+    Ok(mappings
+        .iter()
+        .map(|m| m.table.clone())
+        .zip(counts)
+        .collect())
+}
@@ -0,0 +1,89 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::lookup::KeyPair;
+
+/// Hands out one shared `Arc<str>` per distinct string. Namespaces,
+/// submissions, and ontology ids repeat across every row of a table, so
+/// interning them stores each once instead of once per row.
+#[derive(Default)]
+pub struct Interner(HashSet<Arc<str>>);
+
+impl Interner {
+    pub fn intern(&mut self, s: &str) -> Arc<str> {
+        match self.0.get(s) {
+            Some(shared) => shared.clone(),
+            None => {
+                let shared: Arc<str> = Arc::from(s);
+                self.0.insert(shared.clone());
+                shared
+            }
+        }
+    }
+}
+
+/// A map keyed by `(a, b)` string pairs whose key parts are interned, so
+/// e.g. the `id_namespace` shared by a million `file_in_collection` keys is
+/// stored once.
+pub struct InternedMap<V> {
+    map: HashMap<(Arc<str>, Arc<str>), V>,
+    strings: Interner,
+}
+
+impl<V> Default for InternedMap<V> {
+    fn default() -> Self {
+        InternedMap {
+            map: HashMap::new(),
+            strings: Interner::default(),
+        }
+    }
+}
+
+impl<V> InternedMap<V> {
+    fn key(&mut self, a: &str, b: &str) -> (Arc<str>, Arc<str>) {
+        (self.strings.intern(a), self.strings.intern(b))
+    }
+
+    /// Insert `value`, returning the value it replaced, if any.
+    pub fn insert(&mut self, a: &str, b: &str, value: V) -> Option<V> {
+        let key = self.key(a, b);
+        self.map.insert(key, value)
+    }
+
+    pub fn get(&self, a: &str, b: &str) -> Option<&V> {
+        self.map.get(&(a, b) as &dyn KeyPair)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (&str, &str, &V)> {
+        self.map.iter().map(|((a, b), v)| (&**a, &**b, v))
+    }
+}
+
+impl<V: Default> InternedMap<V> {
+    /// The value for `(a, b)`, inserting `V::default()` first if absent.
+    pub fn get_or_default(&mut self, a: &str, b: &str) -> &mut V {
+        if !self.map.contains_key(&(a, b) as &dyn KeyPair) {
+            let key = self.key(a, b);
+            self.map.insert(key, V::default());
+        }
+        self.map.get_mut(&(a, b) as &dyn KeyPair).unwrap()
+    }
+}
+
+impl<A: AsRef<str>, B: AsRef<str>, V> FromIterator<((A, B), V)> for InternedMap<V> {
+    fn from_iter<I: IntoIterator<Item = ((A, B), V)>>(entries: I) -> Self {
+        let mut map = InternedMap::default();
+        for ((a, b), value) in entries {
+            map.insert(a.as_ref(), b.as_ref(), value);
+        }
+        map
+    }
+}
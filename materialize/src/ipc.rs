@@ -0,0 +1,51 @@
+use anyhow::{Context, Result};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::SchemaRef;
+use bson::Document;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::record_batch::{record_batch, schema};
+
+/// Writes enriched files as one Arrow IPC stream (the flattened schema of
+/// the Parquet output), a record batch per written batch. The stream format
+/// needs no seeking, so `path` can be a named pipe read by a consumer such
+/// as `pyarrow.ipc.open_stream` or `polars.read_ipc_stream` while the run
+/// is still going.
+pub struct ArrowIpcExport {
+    path: PathBuf,
+    schema: SchemaRef,
+    writer: StreamWriter<std::io::BufWriter<File>>,
+    rows: usize,
+}
+
+impl ArrowIpcExport {
+    pub fn create(path: &Path) -> Result<Self> {
+        let schema = Arc::new(schema());
+        let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+        Ok(ArrowIpcExport {
+            path: path.to_path_buf(),
+            writer: StreamWriter::try_new_buffered(file, &schema)?,
+            schema,
+            rows: 0,
+        })
+    }
+
+    pub fn write(&mut self, batch: &[Document]) -> Result<()> {
+        let files: Vec<&Document> = batch.iter().collect();
+        self.writer.write(&record_batch(&self.schema, &files)?)?;
+        self.rows += files.len();
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.writer.finish()?;
+        println!(
+            "  Wrote {} rows to the Arrow IPC stream {}",
+            self.rows,
+            self.path.display()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use bson::{doc, Document};
+use indicatif::ProgressBar;
+use std::time::Duration;
+
+/// Upper bounds (ms) of the latency histogram buckets; slower batches fall
+/// into a final `+inf` bucket.
+const BUCKETS_MS: [u64; 8] = [10, 50, 100, 250, 500, 1000, 2500, 5000];
+
+/// Default `--slow-batch-ms`.
+pub const DEFAULT_SLOW_BATCH_MS: u64 = 5000;
+
+/// Latency of each batch write, bucketed into a histogram for the run
+/// record. Batches slower than the threshold are reported as they happen,
+/// since they usually point at index pressure or a struggling cluster.
+pub struct WriteLatency {
+    slow_threshold: Duration,
+    counts: [u64; BUCKETS_MS.len() + 1],
+    batches: u64,
+    slow: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl WriteLatency {
+    pub fn new(slow_threshold_ms: u64) -> Self {
+        WriteLatency {
+            slow_threshold: Duration::from_millis(slow_threshold_ms),
+            counts: [0; BUCKETS_MS.len() + 1],
+            batches: 0,
+            slow: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+
+    pub fn observe(&mut self, elapsed: Duration, docs: usize, pb: &ProgressBar) {
+        let ms = elapsed.as_millis() as u64;
+        let bucket = BUCKETS_MS
+            .iter()
+            .position(|&bound| ms <= bound)
+            .unwrap_or(BUCKETS_MS.len());
+        self.counts[bucket] += 1;
+        self.batches += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+        if elapsed > self.slow_threshold {
+            self.slow += 1;
+            let warning = format!(
+                "  Warning: batch {} ({} documents) took {} ms (threshold {} ms)",
+                self.batches,
+                docs,
+                ms,
+                self.slow_threshold.as_millis()
+            );
+            // A hidden (--quiet) bar drops println output
+            if pb.is_hidden() {
+                println!("{}", warning);
+            } else {
+                pb.println(warning);
+            }
+        }
+    }
+
+    /// `{batches, slow_batches, mean_ms, max_ms, histogram: {"<=10ms": n, ...}}`
+    pub fn report(&self) -> Document {
+        let mut histogram = Document::new();
+        for (i, count) in self.counts.iter().enumerate() {
+            let label = match BUCKETS_MS.get(i) {
+                Some(bound) => format!("<={}ms", bound),
+                None => format!(">{}ms", BUCKETS_MS[BUCKETS_MS.len() - 1]),
+            };
+            histogram.insert(label, *count as i64);
+        }
+        let mean_ms = if self.batches > 0 {
+            self.total.as_secs_f64() * 1000.0 / self.batches as f64
+        } else {
+            0.0
+        };
+        doc! {
+            "batches": self.batches as i64,
+            "slow_batches": self.slow as i64,
+            "slow_threshold_ms": self.slow_threshold.as_millis() as i64,
+            "mean_ms": mean_ms,
+            "max_ms": self.max.as_millis() as i64,
+            "histogram": histogram,
+        }
+    }
+
+    pub fn print_summary(&self) {
+        println!(
+            "  Write latency: {} batches, mean {:.0} ms, max {} ms, {} slow",
+            self.batches,
+            self.report().get_f64("mean_ms").unwrap_or_default(),
+            self.max.as_millis(),
+            self.slow
+        );
+    }
+}
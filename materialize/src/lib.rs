@@ -0,0 +1,419 @@
+//! The materializer's modules, built as a library so the benchmarks can
+//! drive the enrichment without a database.
+
+use anyhow::Result;
+use bson::{doc, Bson, Document};
+use mongodb::error::ErrorKind;
+use mongodb::options::IndexOptions;
+use mongodb::sync::{Client, Collection};
+use mongodb::IndexModel;
+use std::collections::HashMap;
+
+pub mod access;
+#[cfg(feature = "atlas")]
+pub mod atlas;
+pub mod cache;
+pub mod change;
+pub mod checkpoint;
+pub mod cli;
+#[cfg(feature = "cloud")]
+pub mod cloud;
+pub mod coerce;
+pub mod config;
+pub mod connection;
+pub mod cursor;
+pub mod dashboard;
+pub mod dccs;
+pub mod drs;
+pub mod enrich;
+pub mod enrichers;
+pub mod error;
+pub mod export;
+pub mod fixtures;
+pub mod flatten;
+pub mod identity;
+pub mod indexes;
+pub mod ingest;
+pub mod intern;
+#[cfg(feature = "arrow")]
+pub mod ipc;
+pub mod latency;
+pub mod lookup;
+pub mod manifest;
+pub mod mapping;
+pub mod migrate;
+pub mod mime;
+pub mod normalize;
+#[cfg(feature = "notify")]
+pub mod notify;
+pub mod ontology;
+pub mod openapi;
+pub mod output;
+pub mod overrides;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod pipeline;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod preview;
+#[cfg(feature = "profiling")]
+pub mod profile;
+pub mod projection;
+pub mod projects;
+pub mod publish;
+#[cfg(any(feature = "parquet", feature = "arrow"))]
+pub mod record_batch;
+pub mod redact;
+pub mod refresh;
+pub mod retract;
+pub mod runs;
+pub mod sample;
+pub mod scrub;
+pub mod search;
+#[cfg(feature = "serve")]
+pub mod serve;
+pub mod sharding;
+pub mod size_policy;
+pub mod smoke;
+pub mod spec;
+pub mod spill;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stats;
+pub mod status;
+pub mod submission_stats;
+pub mod submissions;
+pub mod summary;
+pub mod throttle;
+pub mod timing;
+pub mod transactions;
+pub mod validate;
+#[cfg(feature = "verify")]
+pub mod verify;
+pub mod views;
+pub mod vocab;
+pub mod workers;
+
+use config::Config;
+
+/// Server error code for a collection that doesn't exist.
+const NAMESPACE_NOT_FOUND: i32 = 26;
+
+/// Name of the wildcard index over the embedded terms.
+const TERMS_INDEX: &str = "terms";
+
+/// Term fields the built-in enrichment embeds whatever the spec says.
+const BUILT_IN_TERM_FIELDS: [&str; 6] = [
+    "collections.anatomies.id",
+    "collections.anatomies.ancestors.id",
+    "collections.diseases.id",
+    "collections.biosamples.substances.id",
+    "collections.biosamples.genes.id",
+    "collections.biosamples.subjects.race.id",
+];
+
+/// The `files` indexes, plus those configured under `[indexes]`. With
+/// `hoisted`, biosample indexes are built on the top-level `biosamples`
+/// array that `--hoist-biosamples` writes instead of on
+/// `collections.biosamples`. A field the case-insensitive preset covers
+/// gets only its collated index.
+///
+/// MongoDB caps a collection at 64 indexes, `_id` included, and every one
+/// left free is one `[[indexes.extra]]` or the case-insensitive preset can
+/// use. So none is built that another already serves (`id_namespace` alone
+/// uses the compound key's prefix, `access.level` is the normalized
+/// `data_access_level`), `category` and `drs_uri` are indexed only when
+/// the config fills them in, and the embedded terms' ids and names share
+/// one wildcard index instead of taking one each.
+pub fn file_indexes(config: &Config, hoisted: bool) -> Vec<IndexModel> {
+    let mut indexes = vec![
+        doc! { "local_id": 1 },
+        doc! { "id_namespace": 1, "local_id": 1 },
+        doc! { "persistent_id": 1 },
+        doc! { "filename": 1 },
+        doc! { "size_in_bytes": 1 },
+        doc! { "creation_time": 1 },
+        doc! { "min_age_at_sampling": 1 },
+        doc! { "max_age_at_sampling": 1 },
+        doc! { "sha256": 1 },
+        doc! { "md5": 1 },
+        doc! { "mime_type": 1 },
+        doc! { "mime_type_inferred": 1 },
+        doc! { "dcc.id": 1 },
+        doc! { "dcc.dcc_name": 1 },
+        doc! { "dcc.dcc_abbreviation": 1 },
+        doc! { "collections.id_namespace": 1 },
+        doc! { "collections.local_id": 1 },
+        doc! { "collections.name": 1 },
+        doc! { "collections.defined_by_project.local_id": 1 },
+        doc! { "collections.biosamples.id_namespace": 1 },
+        doc! { "collections.biosamples.local_id": 1 },
+        doc! { "collections.biosamples.subjects.local_id": 1 },
+        doc! { "access.level": 1 },
+        doc! { "access.embargo_until": 1 },
+        doc! { "preview": 1 },
+        doc! { "size_policy.strategy": 1 },
+        doc! { "truncated": 1 },
+        doc! { "submission": 1 },
+        doc! { "duplicate_of.local_id": 1 },
+        doc! { "derived_from.id_namespace": 1, "derived_from.local_id": 1 },
+        doc! { "search_text": "text" },
+    ];
+    if !config.enrichers.categories.is_empty() {
+        indexes.push(doc! { "category": 1 });
+    }
+    if !config.drs.templates.is_empty() {
+        indexes.push(doc! { "drs_uri": 1 });
+    }
+    let collated = config.indexes.case_insensitive_fields(&config.enrichment);
+    indexes.retain(|keys| {
+        keys.len() > 1 || !keys.keys().next().is_some_and(|key| collated.contains(key))
+    });
+    let hoist = |key: String| -> String {
+        match key.strip_prefix("collections.biosamples.") {
+            Some(rest) if hoisted => format!("biosamples.{}", rest),
+            _ => key,
+        }
+    };
+    let hoist_keys =
+        |keys: Document| -> Document { keys.into_iter().map(|(k, v)| (hoist(k), v)).collect() };
+
+    let mut models: Vec<IndexModel> = indexes
+        .into_iter()
+        .map(|keys| IndexModel::builder().keys(hoist_keys(keys)).build())
+        .collect();
+    let terms: Document = BUILT_IN_TERM_FIELDS
+        .iter()
+        .map(|field| field.to_string())
+        .chain(config.enrichment.index_fields())
+        .filter(|field| !collated.contains(field))
+        .map(|field| (hoist(field), Bson::Int32(1)))
+        .collect();
+    models.push(
+        IndexModel::builder()
+            .keys(doc! { "$**": 1 })
+            .options(
+                IndexOptions::builder()
+                    .name(TERMS_INDEX.to_string())
+                    .wildcard_projection(terms)
+                    .build(),
+            )
+            .build(),
+    );
+    for (keys, options) in config.indexes.definitions(&config.enrichment) {
+        models.push(
+            IndexModel::builder()
+                .keys(hoist_keys(keys))
+                .options(options)
+                .build(),
+        );
+    }
+    models
+}
+
+/// Build the `files` indexes (see [`file_indexes`]).
+pub fn create_indexes(coll: &Collection<Document>, config: &Config, hoisted: bool) -> Result<()> {
+    let models = file_indexes(config, hoisted);
+    let count = models.len();
+
+    coll.create_indexes(models).run()?;
+    println!("  Created {} indexes", count);
+    Ok(())
+}
+
+/// Drop the indexes on `coll` that [`file_indexes`] no longer builds as
+/// they are: those an older build made, that the config stopped asking for,
+/// or whose definition changed under the same name, such as the terms'
+/// wildcard index after the spec's terms change. A run that
+/// keeps existing files calls this before writing, since MongoDB refuses
+/// indexes past its limit only when [`create_indexes`] runs after every file
+/// is written. `_id` and the shard key's index are kept.
+pub fn drop_stale_indexes(
+    client: &Client,
+    coll: &Collection<Document>,
+    config: &Config,
+    hoisted: bool,
+) -> Result<()> {
+    let managed: HashMap<String, IndexModel> = file_indexes(config, hoisted)
+        .into_iter()
+        .map(|model| {
+            let name = match model.options.as_ref().and_then(|o| o.name.clone()) {
+                Some(name) => name,
+                None => index_name(&model.keys),
+            };
+            (name, model)
+        })
+        .collect();
+    let shard_key = sharding::shard_key(client, &coll.namespace().to_string())?;
+
+    let indexes = match coll.list_indexes().run() {
+        Ok(indexes) => indexes,
+        // A collection not created yet has nothing to drop
+        Err(e) if matches!(*e.kind, ErrorKind::Command(ref c) if c.code == NAMESPACE_NOT_FOUND) => {
+            return Ok(())
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let mut dropped = Vec::new();
+    for index in indexes {
+        let index = index?;
+        let Some(name) = index.options.as_ref().and_then(|o| o.name.clone()) else {
+            continue;
+        };
+        let current = managed
+            .get(&name)
+            .is_some_and(|model| same_definition(model, &index));
+        if name == "_id_" || current || shard_key.as_ref() == Some(&index.keys) {
+            continue;
+        }
+        coll.drop_index(name.as_str()).run()?;
+        dropped.push(name);
+    }
+    if !dropped.is_empty() {
+        println!(
+            "  Dropped {} indexes no longer built: {}",
+            dropped.len(),
+            dropped.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Whether the live index `index` has the keys, uniqueness, and wildcard
+/// fields `model` asks for.
+fn same_definition(model: &IndexModel, index: &IndexModel) -> bool {
+    let unique = |m: &IndexModel| m.options.as_ref().and_then(|o| o.unique) == Some(true);
+    let wildcard = |m: &IndexModel| -> Vec<String> {
+        let mut fields: Vec<String> = m
+            .options
+            .as_ref()
+            .and_then(|o| o.wildcard_projection.as_ref())
+            .map(|p| p.keys().cloned().collect())
+            .unwrap_or_default();
+        fields.sort();
+        fields
+    };
+    model.keys == index.keys && unique(model) == unique(index) && wildcard(model) == wildcard(index)
+}
+
+/// The name MongoDB gives an index created without one, e.g.
+/// `id_namespace_1_local_id_1`.
+fn index_name(keys: &Document) -> String {
+    keys.iter()
+        .map(|(key, order)| match order {
+            Bson::String(kind) => format!("{}_{}", key, kind),
+            order => format!("{}_{}", key, order),
+        })
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+pub fn create_relation_indexes(coll: &Collection<Document>) -> Result<()> {
+    let indexes = vec![
+        doc! { "file_id_namespace": 1, "file_local_id": 1 },
+        doc! { "collection.id_namespace": 1, "collection.local_id": 1 },
+        doc! { "submission": 1 },
+    ];
+    coll.create_indexes(
+        indexes
+            .into_iter()
+            .map(|keys| IndexModel::builder().keys(keys).build()),
+    )
+    .run()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_names_match_the_servers_defaults() {
+        assert_eq!(
+            index_name(&doc! { "id_namespace": 1, "local_id": 1 }),
+            "id_namespace_1_local_id_1"
+        );
+        assert_eq!(
+            index_name(&doc! { "search_text": "text" }),
+            "search_text_text"
+        );
+        assert_eq!(
+            index_name(&doc! { "size_in_bytes": -1 }),
+            "size_in_bytes_-1"
+        );
+    }
+
+    #[test]
+    fn hoisting_moves_biosample_indexes() {
+        let config = Config::default();
+        let hoisted = file_indexes(&config, true);
+        let nested = |key: &str| key.starts_with("collections.biosamples.");
+        assert!(hoisted
+            .iter()
+            .any(|model| model.keys.contains_key("biosamples.local_id")));
+        assert!(!hoisted
+            .iter()
+            .any(|model| model.keys.keys().any(|key| nested(key))));
+        let terms = hoisted
+            .iter()
+            .find_map(|model| model.options.as_ref()?.wildcard_projection.as_ref())
+            .unwrap();
+        assert!(terms.contains_key("biosamples.genes.id"));
+        assert!(!terms.keys().any(|key| nested(key)));
+    }
+
+    #[test]
+    fn terms_share_one_wildcard_index() {
+        let mut config = Config::default();
+        let indexes = file_indexes(&config, false);
+        let terms = indexes
+            .iter()
+            .filter_map(|model| model.options.as_ref()?.wildcard_projection.as_ref())
+            .collect::<Vec<_>>();
+        assert_eq!(terms.len(), 1);
+        assert!(terms[0].contains_key("file_format.name"));
+        assert!(terms[0].contains_key("collections.anatomies.ancestors.id"));
+        assert!(!indexes
+            .iter()
+            .any(|m| m.keys.contains_key("file_format.id")));
+        assert!(!indexes.iter().any(|m| m.keys.contains_key("drs_uri")));
+
+        config.drs.templates = vec!["drs://example.org/{local_id}".to_string()];
+        let indexes = file_indexes(&config, false);
+        assert!(indexes.iter().any(|m| m.keys.contains_key("drs_uri")));
+    }
+
+    #[test]
+    fn case_insensitive_preset_replaces_plain_indexes() {
+        let mut config = Config::default();
+        config.indexes.case_insensitive = true;
+        let indexes = file_indexes(&config, false);
+        let name = |m: &IndexModel| m.options.as_ref().and_then(|o| o.name.clone());
+        let on = |field: &str| {
+            indexes
+                .iter()
+                .filter(|m| m.keys.len() == 1 && m.keys.contains_key(field))
+                .map(name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(on("filename"), vec![Some("filename_ci".to_string())]);
+        assert_eq!(
+            on("dcc.dcc_name"),
+            vec![Some("dcc.dcc_name_ci".to_string())]
+        );
+        assert_eq!(
+            on("file_format.name"),
+            vec![Some("file_format.name_ci".to_string())]
+        );
+        let terms = indexes
+            .iter()
+            .find_map(|m| m.options.as_ref()?.wildcard_projection.as_ref())
+            .unwrap();
+        assert!(terms.contains_key("file_format.id"));
+        assert!(!terms.contains_key("file_format.name"));
+        // The compound key keeps its plain index
+        assert!(indexes
+            .iter()
+            .any(|m| m.keys == doc! { "id_namespace": 1, "local_id": 1 }));
+    }
+}
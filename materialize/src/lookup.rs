@@ -0,0 +1,1355 @@
+use anyhow::Result;
+use bson::{doc, Bson, DateTime, Document};
+use mongodb::options::FindOptions;
+use mongodb::sync::{Collection, Database};
+use std::borrow::{Borrow, Cow};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::cache::{self, LookupCache};
+use crate::cli::Options;
+use crate::coerce::Unparseable;
+use crate::enrich::Misses;
+use crate::enrichers::{self, Enricher};
+use crate::error::MaterializeError;
+use crate::intern::InternedMap;
+use crate::normalize::NormalizeConfig;
+use crate::ontology::Ontology;
+use crate::overrides::{OverrideConfig, TermOverrides};
+use crate::projection::{find_projection, strip_keys};
+use crate::redact::Redactions;
+use crate::spec::{OntologyName, TermSpec};
+use crate::timing::lap;
+
+/// Where lookup maps keep their documents.
+///
+/// `Memory` is the default and fastest option. `Disk` backs every map with a
+/// sled tree so joins over the largest DCCs fit on modest hardware. Disk trees
+/// persist between runs and are reused when their source table is unchanged.
+/// `Cached` keeps maps in memory but snapshots each one to a compressed file,
+/// read back instead of MongoDB while the source table is unchanged.
+pub enum LookupBackend {
+    Memory,
+    Disk { db: sled::Db, refresh: bool },
+    Cached { cache: LookupCache, refresh: bool },
+}
+
+/// Tree recording the fingerprint each disk tree was loaded from.
+const FINGERPRINTS_TREE: &str = "__fingerprints";
+
+impl LookupBackend {
+    /// Open the backend; `refresh` ignores maps cached by previous runs.
+    pub fn open(dir: Option<&str>, cache_dir: Option<&str>, refresh: bool) -> Result<Self> {
+        match (dir, cache_dir) {
+            (Some(_), Some(_)) => {
+                anyhow::bail!("--lookup-dir and --cache-dir cannot be used together")
+            }
+            (Some(dir), None) => {
+                let db = sled::Config::new()
+                    .path(Path::new(dir))
+                    .cache_capacity(256 * 1024 * 1024)
+                    .open()?;
+                Ok(LookupBackend::Disk { db, refresh })
+            }
+            (None, Some(dir)) => Ok(LookupBackend::Cached {
+                cache: LookupCache::open(dir)?,
+                refresh,
+            }),
+            (None, None) => Ok(LookupBackend::Memory),
+        }
+    }
+
+    /// Cheap fingerprint of the rows a loader will read: the query, the
+    /// projection, the row count, and the largest `_id`. Ingestion replaces
+    /// rows rather than updating them, so any change moves the count or the
+    /// max `_id`. Only computed for the disk and cached backends.
+    fn fingerprint(
+        &self,
+        coll: &Collection<Document>,
+        query: &Document,
+        fields: Option<&[String]>,
+    ) -> Result<Option<String>> {
+        if let LookupBackend::Memory = self {
+            return Ok(None);
+        }
+        let count = coll.count_documents(query.clone()).run()?;
+        let max_id = coll
+            .find_one(query.clone())
+            .sort(doc! { "_id": -1 })
+            .projection(doc! { "_id": 1 })
+            .run()?
+            .and_then(|d| d.get("_id").map(ToString::to_string))
+            .unwrap_or_default();
+        let fields = fields.map(|f| f.join(",")).unwrap_or_default();
+        Ok(Some(format!("{}|{}|{}|{}", query, fields, count, max_id)))
+    }
+
+    /// Open the tree for `name`. If it was fully loaded by an earlier run with
+    /// the same fingerprint, it is kept and its recorded metadata returned;
+    /// otherwise it is cleared for a fresh load.
+    fn tree(
+        &self,
+        name: &str,
+        fingerprint: Option<&str>,
+    ) -> Result<Option<(sled::Tree, Option<Document>)>> {
+        match self {
+            LookupBackend::Memory | LookupBackend::Cached { .. } => Ok(None),
+            LookupBackend::Disk { db, refresh } => {
+                let tree = db.open_tree(name)?;
+                let fingerprints = db.open_tree(FINGERPRINTS_TREE)?;
+                if let (false, Some(fingerprint), Some(bytes)) =
+                    (*refresh, fingerprint, fingerprints.get(name)?)
+                {
+                    let meta: Document = bson::from_slice(&bytes)?;
+                    if meta.get_str("fingerprint") == Ok(fingerprint) {
+                        return Ok(Some((tree, Some(meta))));
+                    }
+                }
+                // Forget the old fingerprint first so an interrupted load is
+                // never mistaken for a complete one
+                fingerprints.remove(name)?;
+                tree.clear()?;
+                Ok(Some((tree, None)))
+            }
+        }
+    }
+
+    /// Entries of the snapshot cached for `fingerprint`, if any.
+    fn snapshot(
+        &self,
+        name: &str,
+        submission: &Option<String>,
+        fingerprint: Option<&str>,
+    ) -> Result<Option<Vec<(String, String, Bson)>>> {
+        match (self, fingerprint) {
+            (
+                LookupBackend::Cached {
+                    cache,
+                    refresh: false,
+                },
+                Some(fingerprint),
+            ) => cache.load(name, submission, fingerprint),
+            _ => Ok(None),
+        }
+    }
+
+    /// Snapshot a freshly loaded map when caching.
+    fn save_snapshot(
+        &self,
+        name: &str,
+        submission: &Option<String>,
+        fingerprint: Option<&str>,
+        entries: impl ExactSizeIterator<Item = Document>,
+    ) -> Result<()> {
+        if let (LookupBackend::Cached { cache, .. }, Some(fingerprint)) = (self, fingerprint) {
+            cache.store(name, submission, fingerprint, entries)?;
+        }
+        Ok(())
+    }
+
+    /// Record that the tree for `name` is fully loaded for `fingerprint`.
+    fn record(&self, name: &str, fingerprint: Option<&str>, mut meta: Document) -> Result<()> {
+        if let (LookupBackend::Disk { db, .. }, Some(fingerprint)) = (self, fingerprint) {
+            meta.insert("fingerprint", fingerprint);
+            db.open_tree(FINGERPRINTS_TREE)?
+                .insert(name, bson::to_vec(&meta)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// A two-part map key viewed as a pair of `&str`, so maps keyed by
+/// `(String, String)` can be probed with borrowed strings. Lookups run
+/// several times per file, and building an owned key for each one was
+/// two allocations per lookup.
+pub trait KeyPair {
+    fn a(&self) -> &str;
+    fn b(&self) -> &str;
+}
+
+impl KeyPair for (String, String) {
+    fn a(&self) -> &str {
+        &self.0
+    }
+
+    fn b(&self) -> &str {
+        &self.1
+    }
+}
+
+impl KeyPair for (Arc<str>, Arc<str>) {
+    fn a(&self) -> &str {
+        &self.0
+    }
+
+    fn b(&self) -> &str {
+        &self.1
+    }
+}
+
+impl KeyPair for (&str, &str) {
+    fn a(&self) -> &str {
+        self.0
+    }
+
+    fn b(&self) -> &str {
+        self.1
+    }
+}
+
+impl<'a> Borrow<dyn KeyPair + 'a> for (String, String) {
+    fn borrow(&self) -> &(dyn KeyPair + 'a) {
+        self
+    }
+}
+
+impl<'a> Borrow<dyn KeyPair + 'a> for (Arc<str>, Arc<str>) {
+    fn borrow(&self) -> &(dyn KeyPair + 'a) {
+        self
+    }
+}
+
+// Must hash like the `(String, String)` tuple: each element in order
+impl Hash for dyn KeyPair + '_ {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.a().hash(state);
+        self.b().hash(state);
+    }
+}
+
+impl PartialEq for dyn KeyPair + '_ {
+    fn eq(&self, other: &Self) -> bool {
+        self.a() == other.a() && self.b() == other.b()
+    }
+}
+
+impl Eq for dyn KeyPair + '_ {}
+
+/// (submission, id) -> doc, or (id_namespace, local_id) -> doc
+pub enum LookupMap {
+    Memory(InternedMap<Document>),
+    Disk(sled::Tree),
+}
+
+impl LookupMap {
+    /// Returns the map and whether it was reused from an earlier run.
+    fn new(backend: &LookupBackend, name: &str, fingerprint: Option<&str>) -> Result<(Self, bool)> {
+        Ok(match backend.tree(name, fingerprint)? {
+            Some((tree, meta)) => (LookupMap::Disk(tree), meta.is_some()),
+            None => (LookupMap::Memory(InternedMap::default()), false),
+        })
+    }
+
+    /// Insert `doc`, returning the document it replaced, if any.
+    fn insert(&mut self, a: String, b: String, doc: Document) -> Result<Option<Document>> {
+        Ok(match self {
+            LookupMap::Memory(map) => map.insert(&a, &b, doc),
+            LookupMap::Disk(tree) => match tree.insert(encode_key(&a, &b), bson::to_vec(&doc)?)? {
+                Some(bytes) => Some(bson::from_slice(&bytes)?),
+                None => None,
+            },
+        })
+    }
+
+    fn from_snapshot(entries: Vec<(String, String, Bson)>) -> Self {
+        LookupMap::Memory(
+            entries
+                .into_iter()
+                .filter_map(|(a, b, value)| match value {
+                    Bson::Document(doc) => Some(((a, b), doc)),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    /// Entries to snapshot; empty for disk maps, which persist themselves.
+    fn snapshot(&self) -> Box<dyn ExactSizeIterator<Item = Document> + '_> {
+        match self {
+            LookupMap::Memory(map) => Box::new(
+                map.iter()
+                    .map(|(a, b, doc)| cache::entry(a, b, doc.clone())),
+            ),
+            LookupMap::Disk(_) => Box::new(std::iter::empty()),
+        }
+    }
+
+    pub fn get(&self, a: &str, b: &str) -> Option<Cow<'_, Document>> {
+        match self {
+            LookupMap::Memory(map) => map.get(a, b).map(Cow::Borrowed),
+            LookupMap::Disk(tree) => {
+                let bytes = tree.get(encode_key(a, b)).ok()??;
+                bson::from_slice(&bytes).ok().map(Cow::Owned)
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            LookupMap::Memory(map) => map.len(),
+            LookupMap::Disk(tree) => tree.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every entry as (a, b, doc).
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (String, String, Cow<'_, Document>)> + '_> {
+        match self {
+            LookupMap::Memory(map) => Box::new(
+                map.iter()
+                    .map(|(a, b, doc)| (a.to_string(), b.to_string(), Cow::Borrowed(doc))),
+            ),
+            LookupMap::Disk(tree) => Box::new(tree.iter().filter_map(|entry| {
+                let (key, bytes) = entry.ok()?;
+                let (a, b) = decode_key(&key)?;
+                Some((a, b, Cow::Owned(bson::from_slice(&bytes).ok()?)))
+            })),
+        }
+    }
+}
+
+/// (namespace, local_id) -> [docs]
+pub enum MultiMap {
+    Memory(InternedMap<Vec<Document>>),
+    Disk {
+        tree: sled::Tree,
+        seq: u64,
+        keys: usize,
+    },
+}
+
+impl MultiMap {
+    /// Returns the map and whether it was reused from an earlier run.
+    fn new(backend: &LookupBackend, name: &str, fingerprint: Option<&str>) -> Result<(Self, bool)> {
+        Ok(match backend.tree(name, fingerprint)? {
+            Some((tree, meta)) => {
+                let counter = |field| {
+                    meta.as_ref()
+                        .and_then(|m| m.get_i64(field).ok())
+                        .unwrap_or(0)
+                };
+                let map = MultiMap::Disk {
+                    tree,
+                    seq: counter("seq") as u64,
+                    keys: counter("keys") as usize,
+                };
+                (map, meta.is_some())
+            }
+            None => (MultiMap::Memory(InternedMap::default()), false),
+        })
+    }
+
+    /// Counters to restore when the map is reused by a later run.
+    fn meta(&self) -> Document {
+        match self {
+            MultiMap::Memory(_) => doc! {},
+            MultiMap::Disk { seq, keys, .. } => doc! { "seq": *seq as i64, "keys": *keys as i64 },
+        }
+    }
+
+    fn from_snapshot(entries: Vec<(String, String, Bson)>) -> Self {
+        MultiMap::Memory(
+            entries
+                .into_iter()
+                .filter_map(|(a, b, value)| match value {
+                    Bson::Array(docs) => Some((
+                        (a, b),
+                        docs.into_iter()
+                            .filter_map(|doc| match doc {
+                                Bson::Document(doc) => Some(doc),
+                                _ => None,
+                            })
+                            .collect(),
+                    )),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    /// Entries to snapshot; empty for disk maps, which persist themselves.
+    fn snapshot(&self) -> Box<dyn ExactSizeIterator<Item = Document> + '_> {
+        match self {
+            MultiMap::Memory(map) => Box::new(
+                map.iter()
+                    .map(|(a, b, docs)| cache::entry(a, b, docs.clone())),
+            ),
+            MultiMap::Disk { .. } => Box::new(std::iter::empty()),
+        }
+    }
+
+    fn push(&mut self, a: String, b: String, doc: Document) -> Result<()> {
+        match self {
+            MultiMap::Memory(map) => map.get_or_default(&a, &b).push(doc),
+            MultiMap::Disk { tree, seq, keys } => {
+                let prefix = encode_key(&a, &b);
+                if tree.scan_prefix(&prefix).next().is_none() {
+                    *keys += 1;
+                }
+                // Entries sharing a key are stored under a monotonically
+                // increasing suffix so a prefix scan returns them in load order
+                let mut key = prefix;
+                key.extend_from_slice(&seq.to_be_bytes());
+                *seq += 1;
+                tree.insert(key, bson::to_vec(&doc)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, a: &str, b: &str) -> Option<Cow<'_, [Document]>> {
+        match self {
+            MultiMap::Memory(map) => map.get(a, b).map(|docs| Cow::Borrowed(docs.as_slice())),
+            MultiMap::Disk { tree, .. } => {
+                let docs: Vec<Document> = tree
+                    .scan_prefix(encode_key(a, b))
+                    .filter_map(|r| r.ok())
+                    .filter_map(|(_, bytes)| bson::from_slice(&bytes).ok())
+                    .collect();
+                if docs.is_empty() {
+                    None
+                } else {
+                    Some(Cow::Owned(docs))
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            MultiMap::Memory(map) => map.len(),
+            MultiMap::Disk { keys, .. } => *keys,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Encode a composite key as `a \0 b \0` so prefix scans can't match a
+/// longer `b` sharing the same leading bytes.
+fn encode_key(a: &str, b: &str) -> Vec<u8> {
+    let mut key = Vec::with_capacity(a.len() + b.len() + 2);
+    key.extend_from_slice(a.as_bytes());
+    key.push(0);
+    key.extend_from_slice(b.as_bytes());
+    key.push(0);
+    key
+}
+
+fn decode_key(key: &[u8]) -> Option<(String, String)> {
+    let mut parts = key.split(|&byte| byte == 0);
+    let a = String::from_utf8(parts.next()?.to_vec()).ok()?;
+    let b = String::from_utf8(parts.next()?.to_vec()).ok()?;
+    Some((a, b))
+}
+
+/// Load DCCs keyed by submission.
+fn load_dccs(
+    coll: &Collection<Document>,
+    fields: Option<&[String]>,
+    normalize: &NormalizeConfig,
+) -> HashMap<String, Document> {
+    const KEYS: [&str; 1] = ["submission"];
+    coll.find(doc! {})
+        .with_options(
+            FindOptions::builder()
+                .projection(fields.map(|fields| find_projection(fields, &KEYS)))
+                .build(),
+        )
+        .run()
+        .unwrap()
+        .filter_map(|r| r.ok())
+        .filter_map(|mut d| {
+            normalize.apply(&mut d);
+            let submission = d.get_str("submission").ok()?.to_string();
+            if let Some(fields) = fields {
+                strip_keys(&mut d, fields, &KEYS);
+            }
+            Some((submission, d))
+        })
+        .collect()
+}
+
+fn submission_query(submission: &Option<String>) -> Document {
+    match submission {
+        Some(sub) => doc! { "submission": sub },
+        None => doc! {},
+    }
+}
+
+fn for_each_filtered(
+    coll: &Collection<Document>,
+    submission: &Option<String>,
+    normalize: &NormalizeConfig,
+    projection: Option<Document>,
+    mut f: impl FnMut(Document) -> Result<()>,
+) -> Result<()> {
+    let query = submission_query(submission);
+    for mut doc in coll
+        .find(query)
+        .with_options(FindOptions::builder().projection(projection).build())
+        .run()?
+        .filter_map(|r| r.ok())
+    {
+        normalize.apply(&mut doc);
+        f(doc)?;
+    }
+    Ok(())
+}
+
+/// Duplicate keys printed per table before the rest are only counted.
+const DUPLICATES_SHOWN: u64 = 10;
+
+/// Load a table keyed by the two string fields in `keys`, keeping only the
+/// whitelisted `fields` (plus the keys) when a projection is configured.
+///
+/// A later row with the same key replaces the earlier one; each collision is
+/// logged and the table's count recorded in `duplicates`. Tables reused from
+/// the disk cache were checked when they were loaded.
+#[allow(clippy::too_many_arguments)]
+fn load_keyed_table(
+    backend: &LookupBackend,
+    coll: &Collection<Document>,
+    submission: &Option<String>,
+    normalize: &NormalizeConfig,
+    fields: Option<&[String]>,
+    keys: [&str; 2],
+    overrides: Option<&TermOverrides>,
+    duplicates: &mut Document,
+) -> Result<LookupMap> {
+    let mut fingerprint = backend.fingerprint(coll, &submission_query(submission), fields)?;
+    if let Some(overrides) = overrides {
+        fingerprint = overrides.fingerprint(fingerprint);
+    }
+    if let Some(entries) = backend.snapshot(coll.name(), submission, fingerprint.as_deref())? {
+        println!("  {}: unchanged, reusing cached lookup map", coll.name());
+        return Ok(LookupMap::from_snapshot(entries));
+    }
+    let (mut map, reused) = LookupMap::new(backend, coll.name(), fingerprint.as_deref())?;
+    if reused {
+        println!("  {}: unchanged, reusing cached lookup map", coll.name());
+        return Ok(map);
+    }
+    let projection = fields.map(|fields| find_projection(fields, &keys));
+    let mut collisions: u64 = 0;
+    let mut overridden: u64 = 0;
+    for_each_filtered(coll, submission, normalize, projection, |mut d| {
+        if let (Ok(a), Ok(b)) = (d.get_str(keys[0]), d.get_str(keys[1])) {
+            let (a, b) = (a.to_string(), b.to_string());
+            if overrides.is_some_and(|o| o.apply(&b, &mut d)) {
+                overridden += 1;
+            }
+            if let Some(fields) = fields {
+                strip_keys(&mut d, fields, &keys);
+            }
+            let id = d.get("_id").map(ToString::to_string).unwrap_or_default();
+            if let Some(replaced) = map.insert(a.clone(), b.clone(), d)? {
+                collisions += 1;
+                if collisions <= DUPLICATES_SHOWN {
+                    println!(
+                        "  Warning: {}: duplicate key ({}, {}): _id {} replaced _id {}",
+                        coll.name(),
+                        a,
+                        b,
+                        id,
+                        replaced
+                            .get("_id")
+                            .map(ToString::to_string)
+                            .unwrap_or_default()
+                    );
+                }
+            }
+        }
+        Ok(())
+    })?;
+    if collisions > 0 {
+        println!(
+            "  Warning: {}: {} duplicate keys; the last row for each key wins",
+            coll.name(),
+            collisions
+        );
+        duplicates.insert(coll.name(), collisions as i64);
+    }
+    if let Some(overrides) = overrides {
+        print_overridden(coll, overrides, overridden);
+    }
+    backend.record(coll.name(), fingerprint.as_deref(), doc! {})?;
+    backend.save_snapshot(
+        coll.name(),
+        submission,
+        fingerprint.as_deref(),
+        map.snapshot(),
+    )?;
+    Ok(map)
+}
+
+fn load_lookup_table(
+    backend: &LookupBackend,
+    coll: &Collection<Document>,
+    submission: &Option<String>,
+    normalize: &NormalizeConfig,
+    fields: Option<&[String]>,
+    overrides: Option<&TermOverrides>,
+    duplicates: &mut Document,
+) -> Result<LookupMap> {
+    load_keyed_table(
+        backend,
+        coll,
+        submission,
+        normalize,
+        fields,
+        ["submission", "id"],
+        overrides,
+        duplicates,
+    )
+}
+
+fn print_overridden(coll: &Collection<Document>, overrides: &TermOverrides, overridden: u64) {
+    println!(
+        "  {}: {} rows overridden from {} ({} terms)",
+        coll.name(),
+        overridden,
+        overrides.path,
+        overrides.len()
+    );
+}
+
+/// Which submissions' rows a vocabulary reference resolves against
+/// (`--vocab-scope`).
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum VocabScope {
+    /// Only the rows of the referencing file's own submission
+    #[default]
+    Submission,
+    /// Every submission's rows, merged by id; the most recently ingested
+    /// definition of an id wins
+    Global,
+}
+
+impl VocabScope {
+    pub fn parse(scope: &str) -> Result<Self> {
+        match scope {
+            "submission" => Ok(VocabScope::Submission),
+            "global" => Ok(VocabScope::Global),
+            other => anyhow::bail!(
+                "Unknown --vocab-scope {:?}; expected submission or global",
+                other
+            ),
+        }
+    }
+
+    /// The submission vocabulary lookups for `submission`'s entities are
+    /// keyed by: its own, or the merged terms' key with a global scope.
+    pub fn key(self, submission: &str) -> &str {
+        match self {
+            VocabScope::Submission => submission,
+            VocabScope::Global => GLOBAL_VOCAB,
+        }
+    }
+}
+
+/// Submission key merged terms are stored under with `VocabScope::Global`.
+const GLOBAL_VOCAB: &str = "";
+
+/// Load a vocabulary table keyed by (submission, id), or with
+/// `VocabScope::Global` by id alone across every submission. Merged rows
+/// are read in `_id` order, so the most recently ingested definition of a
+/// term replaces earlier ones; that is expected, not counted as a duplicate.
+/// Terms in the table's override file take precedence over either.
+#[allow(clippy::too_many_arguments)]
+fn load_vocab_table(
+    backend: &LookupBackend,
+    coll: &Collection<Document>,
+    submission: &Option<String>,
+    normalize: &NormalizeConfig,
+    fields: Option<&[String]>,
+    scope: VocabScope,
+    overrides: &OverrideConfig,
+    duplicates: &mut Document,
+) -> Result<LookupMap> {
+    let overrides = overrides.load(coll.name())?;
+    if scope == VocabScope::Submission {
+        return load_lookup_table(
+            backend,
+            coll,
+            submission,
+            normalize,
+            fields,
+            overrides.as_ref(),
+            duplicates,
+        );
+    }
+    // Cached under its own name so it never stands in for a per-submission map
+    let name = format!("{}.global", coll.name());
+    let mut fingerprint = backend.fingerprint(coll, &doc! {}, fields)?;
+    if let Some(ref overrides) = overrides {
+        fingerprint = overrides.fingerprint(fingerprint);
+    }
+    if let Some(entries) = backend.snapshot(&name, &None, fingerprint.as_deref())? {
+        println!("  {}: unchanged, reusing cached lookup map", coll.name());
+        return Ok(LookupMap::from_snapshot(entries));
+    }
+    let (mut map, reused) = LookupMap::new(backend, &name, fingerprint.as_deref())?;
+    if reused {
+        println!("  {}: unchanged, reusing cached lookup map", coll.name());
+        return Ok(map);
+    }
+    const KEYS: [&str; 2] = ["submission", "id"];
+    let projection = fields.map(|fields| find_projection(fields, &KEYS));
+    let mut merged: u64 = 0;
+    let mut overridden: u64 = 0;
+    for mut d in coll
+        .find(doc! {})
+        .with_options(
+            FindOptions::builder()
+                .projection(projection)
+                .sort(doc! { "_id": 1 })
+                .build(),
+        )
+        .run()?
+        .filter_map(|r| r.ok())
+    {
+        normalize.apply(&mut d);
+        let Ok(id) = d.get_str("id") else {
+            continue;
+        };
+        let id = id.to_string();
+        if overrides.as_ref().is_some_and(|o| o.apply(&id, &mut d)) {
+            overridden += 1;
+        }
+        if let Some(fields) = fields {
+            strip_keys(&mut d, fields, &KEYS);
+        }
+        if map.insert(GLOBAL_VOCAB.to_string(), id, d)?.is_some() {
+            merged += 1;
+        }
+    }
+    if merged > 0 {
+        println!(
+            "  {}: {} definitions merged into terms defined by another submission",
+            coll.name(),
+            merged
+        );
+    }
+    if let Some(ref overrides) = overrides {
+        print_overridden(coll, overrides, overridden);
+    }
+    backend.record(&name, fingerprint.as_deref(), doc! {})?;
+    backend.save_snapshot(&name, &None, fingerprint.as_deref(), map.snapshot())?;
+    Ok(map)
+}
+
+/// Load one vocabulary table the way a run does, for commands that need
+/// the vocabularies without the rest of the lookup context.
+pub fn load_vocabulary(
+    db: &Database,
+    backend: &LookupBackend,
+    table: &str,
+    submission: &Option<String>,
+    opts: &Options,
+) -> Result<LookupMap> {
+    load_vocab_table(
+        backend,
+        &db.collection(table),
+        submission,
+        &opts.config.normalize,
+        opts.config.projections.for_table(table),
+        opts.vocab_scope,
+        &opts.config.overrides,
+        &mut Document::new(),
+    )
+}
+
+fn load_entity_table(
+    backend: &LookupBackend,
+    coll: &Collection<Document>,
+    submission: &Option<String>,
+    normalize: &NormalizeConfig,
+    fields: Option<&[String]>,
+    duplicates: &mut Document,
+) -> Result<LookupMap> {
+    load_keyed_table(
+        backend,
+        coll,
+        submission,
+        normalize,
+        fields,
+        ["id_namespace", "local_id"],
+        None,
+        duplicates,
+    )
+}
+
+/// Group a junction table by the (namespace, local_id) pair stored under
+/// `ns_field`/`id_field`.
+fn load_junction_table(
+    backend: &LookupBackend,
+    coll: &Collection<Document>,
+    submission: &Option<String>,
+    normalize: &NormalizeConfig,
+    ns_field: &str,
+    id_field: &str,
+) -> Result<MultiMap> {
+    let fingerprint = backend.fingerprint(coll, &submission_query(submission), None)?;
+    if let Some(entries) = backend.snapshot(coll.name(), submission, fingerprint.as_deref())? {
+        println!("  {}: unchanged, reusing cached lookup map", coll.name());
+        return Ok(MultiMap::from_snapshot(entries));
+    }
+    let (mut map, reused) = MultiMap::new(backend, coll.name(), fingerprint.as_deref())?;
+    if reused {
+        println!("  {}: unchanged, reusing cached lookup map", coll.name());
+        return Ok(map);
+    }
+    for_each_filtered(coll, submission, normalize, None, |d| {
+        if let (Ok(ns), Ok(id)) = (d.get_str(ns_field), d.get_str(id_field)) {
+            let (ns, id) = (ns.to_string(), id.to_string());
+            map.push(ns, id, d)?;
+        }
+        Ok(())
+    })?;
+    backend.record(coll.name(), fingerprint.as_deref(), map.meta())?;
+    backend.save_snapshot(
+        coll.name(),
+        submission,
+        fingerprint.as_deref(),
+        map.snapshot(),
+    )?;
+    Ok(map)
+}
+
+/// Load a table of file rows (`file_in_collection`, the derivation tables)
+/// grouped by file.
+fn load_file_junction(
+    backend: &LookupBackend,
+    coll: &Collection<Document>,
+    submission: &Option<String>,
+    normalize: &NormalizeConfig,
+) -> Result<MultiMap> {
+    load_junction_table(
+        backend,
+        coll,
+        submission,
+        normalize,
+        "file_id_namespace",
+        "file_local_id",
+    )
+}
+
+fn load_biosample_in_collection(
+    backend: &LookupBackend,
+    coll: &Collection<Document>,
+    submission: &Option<String>,
+    normalize: &NormalizeConfig,
+) -> Result<MultiMap> {
+    load_junction_table(
+        backend,
+        coll,
+        submission,
+        normalize,
+        "collection_id_namespace",
+        "collection_local_id",
+    )
+}
+
+/// Load a collection association table (`collection_anatomy`,
+/// `collection_disease`, `collection_defined_by_project`) grouped by
+/// collection.
+fn load_collection_junction(
+    backend: &LookupBackend,
+    coll: &Collection<Document>,
+    submission: &Option<String>,
+    normalize: &NormalizeConfig,
+) -> Result<MultiMap> {
+    load_junction_table(
+        backend,
+        coll,
+        submission,
+        normalize,
+        "collection_id_namespace",
+        "collection_local_id",
+    )
+}
+
+/// Load a biosample association table (`biosample_from_subject`,
+/// `biosample_substance`, `biosample_gene`) grouped by biosample.
+fn load_biosample_junction(
+    backend: &LookupBackend,
+    coll: &Collection<Document>,
+    submission: &Option<String>,
+    normalize: &NormalizeConfig,
+) -> Result<MultiMap> {
+    load_junction_table(
+        backend,
+        coll,
+        submission,
+        normalize,
+        "biosample_id_namespace",
+        "biosample_local_id",
+    )
+}
+
+fn load_subject_race(
+    backend: &LookupBackend,
+    coll: &Collection<Document>,
+    submission: &Option<String>,
+    normalize: &NormalizeConfig,
+) -> Result<MultiMap> {
+    load_junction_table(
+        backend,
+        coll,
+        submission,
+        normalize,
+        "subject_id_namespace",
+        "subject_local_id",
+    )
+}
+
+/// File derivation tables, each with the columns holding the parent file's
+/// key. Rows are grouped by the derived file's `file_id_namespace` and
+/// `file_local_id`; deployments without them load nothing.
+pub const DERIVATION_TABLES: [(&str, &str, &str); 2] = [
+    (
+        "file_derived_from",
+        "derived_from_file_id_namespace",
+        "derived_from_file_local_id",
+    ),
+    (
+        "file_produced_from",
+        "produced_from_file_id_namespace",
+        "produced_from_file_local_id",
+    ),
+];
+
+/// Vocabulary tables collection rollups resolve against, loaded even when
+/// the enrichment spec doesn't name them.
+pub const ANATOMY_TABLE: &str = "anatomy";
+pub const DISEASE_TABLE: &str = "disease";
+
+/// Vocabulary table subject races resolve against.
+pub const SUBJECT_RACE_TABLE: &str = "subject_race_CV";
+
+/// Vocabulary tables biosample substances and genes resolve against.
+pub const SUBSTANCE_TABLE: &str = "substance";
+pub const GENE_TABLE: &str = "gene";
+
+/// Every table the enrichment joins against, loaded once per run, plus the
+/// run's options.
+pub struct LookupContext<'a> {
+    pub opts: &'a Options,
+    pub dccs: HashMap<String, Document>,
+    /// Vocabulary tables named by the enrichment spec, keyed by table
+    pub terms: HashMap<String, LookupMap>,
+    pub obi: Option<Ontology>,
+    pub uberon: Option<Ontology>,
+    pub collections: LookupMap,
+    pub biosamples: LookupMap,
+    pub file_in_collection: MultiMap,
+    pub biosample_in_collection: MultiMap,
+    pub collection_anatomy: MultiMap,
+    pub collection_disease: MultiMap,
+    pub collection_defined_by_project: MultiMap,
+    pub projects: LookupMap,
+    pub subjects: LookupMap,
+    pub biosample_from_subject: MultiMap,
+    pub subject_race: MultiMap,
+    pub subject_races: LookupMap,
+    pub biosample_substance: MultiMap,
+    pub substances: LookupMap,
+    pub biosample_gene: MultiMap,
+    pub genes: LookupMap,
+    /// Edges of each of the `DERIVATION_TABLES`, in order
+    pub derivations: Vec<MultiMap>,
+    /// `filename` of the run's files keyed by (id_namespace, local_id), for
+    /// the parents of derived files; empty without derivation edges
+    pub file_names: LookupMap,
+    /// Milliseconds spent loading each table
+    pub load_ms: Document,
+    /// When the run started, stamped on every document it writes
+    pub materialized_at: DateTime,
+    /// Colliding keys found in each table that had any
+    pub duplicates: Document,
+    /// Lookup misses seen by the enrichment so far
+    pub misses: Misses,
+    /// Values the typing pass couldn't coerce
+    pub unparseable: Unparseable,
+    /// Protected fields removed or hashed so far
+    pub redactions: Redactions,
+    /// Site-specific enrichers run after the built-in joins
+    pub enrichers: Vec<Box<dyn Enricher>>,
+}
+
+impl<'a> LookupContext<'a> {
+    /// Load the DCCs, vocabularies, ontologies, and relation tables the
+    /// run's files are joined against.
+    pub fn load(
+        db: &Database,
+        backend: &LookupBackend,
+        submission: &Option<String>,
+        opts: &'a Options,
+    ) -> Result<Self, MaterializeError> {
+        Self::load_tables(db, backend, submission, opts).map_err(MaterializeError::from)
+    }
+
+    fn load_tables(
+        db: &Database,
+        backend: &LookupBackend,
+        submission: &Option<String>,
+        opts: &'a Options,
+    ) -> Result<Self> {
+        println!("\nLoading lookup tables...");
+        let projections = &opts.config.projections;
+        let mut load_ms = Document::new();
+        let mut duplicates = Document::new();
+        let mut started = Instant::now();
+
+        // Load DCCs keyed by submission
+        let dccs = load_dccs(
+            &db.collection("dcc"),
+            projections.for_table("dcc"),
+            &opts.config.normalize,
+        );
+        println!("  dcc: {} entries", dccs.len());
+        lap(&mut load_ms, "dcc", &mut started);
+
+        // Load the spec's vocabulary tables keyed by (submission, id), or
+        // merged by id with `--vocab-scope global`
+        let mut terms = HashMap::new();
+        for table in opts.config.enrichment.tables() {
+            let map = load_vocab_table(
+                backend,
+                &db.collection(table),
+                submission,
+                &opts.config.normalize,
+                projections.for_table(table),
+                opts.vocab_scope,
+                &opts.config.overrides,
+                &mut duplicates,
+            )?;
+            println!("  {}: {} entries", table, map.len());
+            lap(&mut load_ms, table, &mut started);
+            terms.insert(table.to_string(), map);
+        }
+        for table in [ANATOMY_TABLE, DISEASE_TABLE] {
+            if terms.contains_key(table) {
+                continue;
+            }
+            let map = load_vocab_table(
+                backend,
+                &db.collection(table),
+                submission,
+                &opts.config.normalize,
+                projections.for_table(table),
+                opts.vocab_scope,
+                &opts.config.overrides,
+                &mut duplicates,
+            )?;
+            println!("  {}: {} entries", table, map.len());
+            lap(&mut load_ms, table, &mut started);
+            terms.insert(table.to_string(), map);
+        }
+
+        let obi = opts.obi.as_deref().map(Ontology::load).transpose()?;
+        if let Some(ref obi) = obi {
+            println!("  obi: {} terms", obi.len());
+            lap(&mut load_ms, "obi", &mut started);
+        }
+
+        let uberon = opts.uberon.as_deref().map(Ontology::load).transpose()?;
+        if let Some(ref uberon) = uberon {
+            println!("  uberon: {} terms", uberon.len());
+            lap(&mut load_ms, "uberon", &mut started);
+        }
+
+        // Load collections keyed by (id_namespace, local_id)
+        let collections = load_entity_table(
+            backend,
+            &db.collection("collection"),
+            submission,
+            &opts.config.normalize,
+            projections.for_table("collection"),
+            &mut duplicates,
+        )?;
+        println!("  collection: {} entries", collections.len());
+        lap(&mut load_ms, "collection", &mut started);
+
+        // Load biosamples keyed by (id_namespace, local_id)
+        let biosamples = load_entity_table(
+            backend,
+            &db.collection("biosample"),
+            submission,
+            &opts.config.normalize,
+            projections.for_table("biosample"),
+            &mut duplicates,
+        )?;
+        println!("  biosample: {} entries", biosamples.len());
+        lap(&mut load_ms, "biosample", &mut started);
+
+        // Load junction tables as multi-maps
+        let file_in_collection = load_file_junction(
+            backend,
+            &db.collection("file_in_collection"),
+            submission,
+            &opts.config.normalize,
+        )?;
+        println!("  file_in_collection: {} entries", file_in_collection.len());
+        lap(&mut load_ms, "file_in_collection", &mut started);
+
+        let biosample_in_collection = load_biosample_in_collection(
+            backend,
+            &db.collection("biosample_in_collection"),
+            submission,
+            &opts.config.normalize,
+        )?;
+        println!(
+            "  biosample_in_collection: {} entries",
+            biosample_in_collection.len()
+        );
+        lap(&mut load_ms, "biosample_in_collection", &mut started);
+
+        // Collection rollups: anatomy, disease, and defining projects
+        let collection_anatomy = load_collection_junction(
+            backend,
+            &db.collection("collection_anatomy"),
+            submission,
+            &opts.config.normalize,
+        )?;
+        println!("  collection_anatomy: {} entries", collection_anatomy.len());
+        lap(&mut load_ms, "collection_anatomy", &mut started);
+
+        let collection_disease = load_collection_junction(
+            backend,
+            &db.collection("collection_disease"),
+            submission,
+            &opts.config.normalize,
+        )?;
+        println!("  collection_disease: {} entries", collection_disease.len());
+        lap(&mut load_ms, "collection_disease", &mut started);
+
+        let collection_defined_by_project = load_collection_junction(
+            backend,
+            &db.collection("collection_defined_by_project"),
+            submission,
+            &opts.config.normalize,
+        )?;
+        println!(
+            "  collection_defined_by_project: {} entries",
+            collection_defined_by_project.len()
+        );
+        lap(&mut load_ms, "collection_defined_by_project", &mut started);
+
+        let projects = load_entity_table(
+            backend,
+            &db.collection("project"),
+            submission,
+            &opts.config.normalize,
+            projections.for_table("project"),
+            &mut duplicates,
+        )?;
+        println!("  project: {} entries", projects.len());
+        lap(&mut load_ms, "project", &mut started);
+
+        // Load subjects, their biosample/race associations, and subject CVs
+        let subjects = load_entity_table(
+            backend,
+            &db.collection("subject"),
+            submission,
+            &opts.config.normalize,
+            projections.for_table("subject"),
+            &mut duplicates,
+        )?;
+        println!("  subject: {} entries", subjects.len());
+        lap(&mut load_ms, "subject", &mut started);
+
+        let biosample_from_subject = load_biosample_junction(
+            backend,
+            &db.collection("biosample_from_subject"),
+            submission,
+            &opts.config.normalize,
+        )?;
+        println!(
+            "  biosample_from_subject: {} entries",
+            biosample_from_subject.len()
+        );
+        lap(&mut load_ms, "biosample_from_subject", &mut started);
+
+        let subject_race = load_subject_race(
+            backend,
+            &db.collection("subject_race"),
+            submission,
+            &opts.config.normalize,
+        )?;
+        println!("  subject_race: {} entries", subject_race.len());
+        lap(&mut load_ms, "subject_race", &mut started);
+
+        let subject_races = load_vocab_table(
+            backend,
+            &db.collection(SUBJECT_RACE_TABLE),
+            submission,
+            &opts.config.normalize,
+            None,
+            opts.vocab_scope,
+            &opts.config.overrides,
+            &mut duplicates,
+        )?;
+        println!("  {}: {} entries", SUBJECT_RACE_TABLE, subject_races.len());
+        lap(&mut load_ms, SUBJECT_RACE_TABLE, &mut started);
+
+        // Load the substances and genes biosamples are associated with
+        let biosample_substance = load_biosample_junction(
+            backend,
+            &db.collection("biosample_substance"),
+            submission,
+            &opts.config.normalize,
+        )?;
+        println!(
+            "  biosample_substance: {} entries",
+            biosample_substance.len()
+        );
+        lap(&mut load_ms, "biosample_substance", &mut started);
+
+        let substances = load_vocab_table(
+            backend,
+            &db.collection(SUBSTANCE_TABLE),
+            submission,
+            &opts.config.normalize,
+            None,
+            opts.vocab_scope,
+            &opts.config.overrides,
+            &mut duplicates,
+        )?;
+        println!("  {}: {} entries", SUBSTANCE_TABLE, substances.len());
+        lap(&mut load_ms, SUBSTANCE_TABLE, &mut started);
+
+        let biosample_gene = load_biosample_junction(
+            backend,
+            &db.collection("biosample_gene"),
+            submission,
+            &opts.config.normalize,
+        )?;
+        println!("  biosample_gene: {} entries", biosample_gene.len());
+        lap(&mut load_ms, "biosample_gene", &mut started);
+
+        let genes = load_vocab_table(
+            backend,
+            &db.collection(GENE_TABLE),
+            submission,
+            &opts.config.normalize,
+            None,
+            opts.vocab_scope,
+            &opts.config.overrides,
+            &mut duplicates,
+        )?;
+        println!("  {}: {} entries", GENE_TABLE, genes.len());
+        lap(&mut load_ms, GENE_TABLE, &mut started);
+
+        // File derivation edges, and the filenames of the files they point at
+        let mut derivations = Vec::new();
+        for (table, _, _) in DERIVATION_TABLES {
+            let edges = load_file_junction(
+                backend,
+                &db.collection(table),
+                submission,
+                &opts.config.normalize,
+            )?;
+            if !edges.is_empty() {
+                println!("  {}: {} entries", table, edges.len());
+            }
+            lap(&mut load_ms, table, &mut started);
+            derivations.push(edges);
+        }
+        let file_names = if derivations.iter().all(MultiMap::is_empty) {
+            LookupMap::Memory(InternedMap::default())
+        } else {
+            // Duplicate files are `dedupe`'s concern, not a lookup failure
+            let file_names = load_entity_table(
+                backend,
+                &db.collection("file"),
+                submission,
+                &opts.config.normalize,
+                Some(&["filename".to_string()]),
+                &mut Document::new(),
+            )?;
+            println!("  file names: {} entries", file_names.len());
+            lap(&mut load_ms, "file", &mut started);
+            file_names
+        };
+
+        if opts.strict && !duplicates.is_empty() {
+            let tables: Vec<String> = duplicates
+                .iter()
+                .map(|(table, count)| format!("{} ({})", table, count))
+                .collect();
+            return Err(MaterializeError::MissingLookup(format!(
+                "Duplicate lookup keys with --strict: {}",
+                tables.join(", ")
+            ))
+            .into());
+        }
+
+        let enrichers = enrichers::active(&opts.config.enrichers);
+        if !enrichers.is_empty() {
+            let names: Vec<&str> = enrichers.iter().map(|e| e.name()).collect();
+            println!("  enrichers: {}", names.join(", "));
+        }
+
+        Ok(LookupContext {
+            opts,
+            dccs,
+            terms,
+            obi,
+            uberon,
+            collections,
+            biosamples,
+            file_in_collection,
+            biosample_in_collection,
+            collection_anatomy,
+            collection_disease,
+            collection_defined_by_project,
+            projects,
+            subjects,
+            biosample_from_subject,
+            subject_race,
+            subject_races,
+            biosample_substance,
+            substances,
+            biosample_gene,
+            genes,
+            derivations,
+            file_names,
+            load_ms,
+            materialized_at: DateTime::now(),
+            duplicates,
+            misses: Misses::default(),
+            unparseable: Unparseable::default(),
+            redactions: Redactions::default(),
+            enrichers,
+        })
+    }
+
+    /// The submission vocabulary lookups for `submission`'s entities are
+    /// keyed by under the run's `--vocab-scope`.
+    pub fn vocab_submission<'s>(&self, submission: &'s str) -> &'s str {
+        self.opts.vocab_scope.key(submission)
+    }
+
+    /// The loaded vocabulary table for a spec term.
+    pub fn term_table(&self, term: &TermSpec) -> &LookupMap {
+        &self.terms[&term.table]
+    }
+
+    /// The ontology a spec term carries ancestors from, if it was loaded.
+    pub fn ontology(&self, term: &TermSpec) -> Option<&Ontology> {
+        match term.ontology? {
+            OntologyName::Obi => self.obi.as_ref(),
+            OntologyName::Uberon => self.uberon.as_ref(),
+        }
+    }
+}
@@ -1,384 +1,1030 @@
 use anyhow::Result;
 use bson::{doc, Document};
-use indicatif::{ProgressBar, ProgressStyle};
-use mongodb::sync::{Client, Collection};
+use mongodb::sync::{Collection, Database};
 use rayon::prelude::*;
-use std::collections::HashMap;
-use std::env;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Instant;
 
-const BATCH_SIZE: usize = 10000;
+#[cfg(feature = "atlas")]
+use materialize::atlas;
+#[cfg(feature = "arrow")]
+use materialize::ipc;
+#[cfg(feature = "parquet")]
+use materialize::parquet;
+#[cfg(feature = "postgres")]
+use materialize::postgres;
+#[cfg(feature = "profiling")]
+use materialize::profile;
+#[cfg(feature = "serve")]
+use materialize::serve;
+#[cfg(feature = "verify")]
+use materialize::verify;
+use materialize::{
+    change, checkpoint, create_indexes, create_relation_indexes, dashboard, dccs,
+    drop_stale_indexes, enrichers, export, fixtures, identity, ingest, manifest, migrate, openapi,
+    pipeline, publish, refresh, retract, runs, sharding, smoke, spill, submission_stats,
+    submissions, transactions, validate, views, vocab,
+};
 
-type LookupMap = HashMap<(String, String), Document>; // (submission, id) -> doc
-type MultiMap = HashMap<(String, String), Vec<Document>>; // (namespace, local_id) -> [docs]
+use materialize::cli::Options;
+use materialize::connection::Connections;
+use materialize::cursor::ResumableCursor;
+use materialize::dashboard::Dashboard;
+use materialize::enrich::{enrich_file, EmbedDcc, MissingDcc, OnMiss, Trace};
+use materialize::error::{MaterializeError, EXIT_PARTIAL};
+use materialize::latency::WriteLatency;
+use materialize::lookup::{LookupBackend, LookupContext};
+use materialize::manifest::Manifest;
+use materialize::output::{FileSink, Output};
+use materialize::projects::ProjectAggregator;
+use materialize::redact::{public_copy, CONTROLLED_COLLECTION, PUBLIC_COLLECTION};
+use materialize::runs::RunRecord;
+use materialize::sample::{self, SAMPLE_COLLECTION, SAMPLE_RELATIONS_COLLECTION};
+use materialize::size_policy::{SizePolicy, RELATIONS_COLLECTION};
+use materialize::spill::{ExternalSorter, SPILL_RUN_SIZE};
+use materialize::stats::FieldStats;
+use materialize::status::StatusReporter;
+use materialize::summary::RunSummary;
+use materialize::throttle::{self, RateLimit};
+use materialize::timing::PhaseTimings;
+use materialize::views::View;
 
-fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
+fn main() -> ExitCode {
+    let mut summary = RunSummary::start();
+    let error = run(&mut summary).err().map(MaterializeError::from);
+    let exit_code = match error {
+        Some(ref e) => e.exit_code(),
+        None if summary.partial() => EXIT_PARTIAL,
+        None => 0,
+    };
+    if let Some(ref e) = error {
+        eprintln!("Error: {:#}", e);
+    }
+    summary.finish(exit_code, error.as_ref());
+    ExitCode::from(exit_code)
+}
 
-    // Parse --submission flag
-    let submission_filter: Option<String> = args
-        .iter()
-        .position(|a| a == "--submission")
-        .and_then(|i| args.get(i + 1).cloned());
+fn run(summary: &mut RunSummary) -> Result<()> {
+    // Leaked so the lookup tables can be shared with the pipeline's threads
+    let opts: &'static Options = Box::leak(Box::new(
+        Options::parse().map_err(MaterializeError::config)?,
+    ));
+    #[cfg(feature = "notify")]
+    if let Some(ref url) = opts.notify_webhook {
+        summary.notify(url, opts.notify_format);
+    }
 
-    let uri = env::var("DATABASE_URL").unwrap_or_else(|_| "mongodb://localhost:27017".to_string());
-    let client = Client::with_uri_str(&uri)?;
-    let db = client.database("cfdb");
+    // Enrichers compiled into this build, run on every file in this order
+    enrichers::register(enrichers::Categories::build);
+    enrichers::register(enrichers::Tags::build);
 
-    if let Some(ref sub) = submission_filter {
-        println!("Materializing files for submission: {}", sub);
+    let conns = Connections::open(opts)?;
+    let (source, db) = (&conns.source, &conns.target);
+
+    if let Some(ref command) = opts.command {
+        summary.disable();
+        return match command.as_str() {
+            "runs" => runs::command(db, &opts.command_args),
+            "stats" => submission_stats::command(db, &opts.command_args),
+            "export" => export::command(db, &opts.command_args),
+            "submissions" => submissions::command(db, &opts.command_args),
+            "ingest" => ingest::command(source, &opts.command_args),
+            "generate-fixtures" => fixtures::command(&opts.command_args),
+            "schema" => openapi::command(&opts.config.enrichment, &opts.command_args),
+            "retract" => retract::command(&conns.target_client, db, &opts.command_args),
+            "publish" => publish::command(db, &opts.command_args, &opts.config),
+            "validate" => validate::command(source, &opts.command_args),
+            "migrate" => migrate::command(db, &opts.command_args),
+            "dedupe" => identity::command(db, &opts.command_args),
+            "refresh-vocab" => refresh::command(source, db, opts, &opts.command_args),
+            "vocab" => vocab::command(source, &opts.config, &opts.command_args),
+            #[cfg(feature = "atlas")]
+            "search-index" => atlas::command(
+                db.name(),
+                &opts.config.atlas_search,
+                &opts.config.enrichment,
+                &opts.command_args,
+            ),
+            #[cfg(not(feature = "atlas"))]
+            "search-index" => {
+                anyhow::bail!("search-index requires building with `--features atlas`")
+            }
+            #[cfg(feature = "serve")]
+            "serve" => serve::command(&conns.target_uri, &opts.command_args),
+            #[cfg(not(feature = "serve"))]
+            "serve" => anyhow::bail!("serve requires building with `--features serve`"),
+            #[cfg(feature = "verify")]
+            "verify" => verify::command(db, &opts.command_args),
+            #[cfg(not(feature = "verify"))]
+            "verify" => anyhow::bail!("verify requires building with `--features verify`"),
+            other => anyhow::bail!("Unknown command: {}", other),
+        };
+    }
+
+    let backend = LookupBackend::open(
+        opts.lookup_dir.as_deref(),
+        opts.cache_dir.as_deref(),
+        opts.refresh_lookups,
+    )?;
+    if let Some(ref dir) = opts.lookup_dir {
+        println!("Using on-disk lookup store at {}", dir);
+    }
+    if let Some(ref dir) = opts.cache_dir {
+        println!("Caching lookup tables under {}", dir);
+    }
+    if opts.source_uri.is_some() {
+        println!("Reading the C2M2 tables from the --source-uri deployment");
+    }
+
+    // A submission pattern, DCC, or list expands to one run per submission
+    let selected = if let Some(ref spec) = opts.submission {
+        Some(submissions::expand(source, spec)?)
+    } else if let Some(ref dcc) = opts.dcc {
+        Some(submissions::for_dcc(source, dcc)?)
+    } else if let Some(ref path) = opts.submissions_file {
+        Some(submissions::read_list(path)?)
     } else {
-        println!("Materializing all files");
+        None
+    };
+    // Workers split every submission between them when none are selected
+    let selected = match (selected, opts.workers) {
+        (None, Some(_)) => Some(submissions::expand(source, "*")?),
+        (selected, _) => selected,
+    };
+    let submissions: Vec<Option<String>> = match selected {
+        Some(selected) => {
+            let mut selected = submissions::exclude(selected, &opts.exclude_submissions)?;
+            if let Some(workers) = opts.workers {
+                selected = workers.assign(source, selected)?;
+            }
+            selected.into_iter().map(Some).collect()
+        }
+        None => vec![None],
+    };
+    if submissions.is_empty() {
+        println!("No submissions left for this worker");
+        return Ok(());
+    }
+    // Each run opens its own sink, so a second one would replace the stream
+    #[cfg(feature = "arrow")]
+    if submissions.len() > 1 && matches!(opts.output, Output::ArrowIpc(_)) {
+        return Err(MaterializeError::Config(anyhow::anyhow!(
+            "--output arrow-ipc writes one stream; select a single submission, or none for all files"
+        ))
+        .into());
     }
 
-    println!("\nLoading lookup tables...");
+    if let Some(ref key) = opts.explain {
+        summary.disable();
+        return explain(source, &backend, key, &submissions, opts);
+    }
 
-    // Load DCCs keyed by submission
-    let dccs: HashMap<String, Document> = load_collection(&db.collection("dcc"))
-        .into_iter()
-        .filter_map(|d| {
-            let submission = d.get_str("submission").ok()?.to_string();
-            Some((submission, d))
-        })
-        .collect();
-    println!("  dcc: {} entries", dccs.len());
+    if let Some(threads) = opts.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()?;
+    }
+
+    checkpoint::install_handler()?;
+    let dashboard = Dashboard::new(submissions.len(), opts.quiet)
+        .report_status(StatusReporter::start(db, submissions.len()));
+    let mut manifest = Manifest::default();
+    for submission in &submissions {
+        dashboard.start_submission(submission);
+        let run = RunRecord::start(db, submission)?;
+        match materialize(&conns, &backend, opts, submission, &dashboard) {
+            Ok(report) => {
+                if let Some(ref path) = opts.report {
+                    write_report(path, submission, &report)?;
+                }
+                summary.succeeded(submission, &report);
+                manifest.observe(run.id(), &report);
+                run.finish(report)?;
+                dashboard.finish_submission();
+            }
+            Err(e) => {
+                if let Err(record_error) = run.fail(&e) {
+                    eprintln!("Failed to record run outcome: {}", record_error);
+                }
+                summary.failed(submission, &e);
+                dashboard.fail(&e);
+                // Earlier submissions' changes are live; still announce them
+                if matches!(opts.output, Output::Mongo) && !manifest.is_empty() {
+                    if let Err(manifest_error) = manifest.write(db, None) {
+                        eprintln!("Failed to write manifest: {}", manifest_error);
+                    }
+                }
+                return Err(e.into());
+            }
+        }
+    }
+    dashboard.finish();
+    let mut published = None;
+    if opts.publish {
+        published = Some(publish::publish(db, &opts.config, opts.retain_hours)?);
+    }
+    if matches!(opts.output, Output::Mongo) {
+        let generation = manifest.write(db, published.as_deref())?;
+        println!(
+            "Manifest generation {} written to {}",
+            generation,
+            manifest::MANIFEST_COLLECTION
+        );
+    }
+    #[cfg(feature = "atlas")]
+    if opts.apply_search_index {
+        atlas::apply(
+            db.name(),
+            &opts.config.atlas_search,
+            &opts.config.enrichment,
+        )?;
+    }
+    println!("Done!");
+    Ok(())
+}
 
-    // Load ontology lookups keyed by (submission, id)
-    let file_formats = load_lookup_table(&db.collection("file_format"), &submission_filter);
-    println!("  file_format: {} entries", file_formats.len());
+/// Enrich the selected files, replace them in the `files` collection, and
+/// write the derived collections, then run the smoke queries. Returns the
+/// run's report (`counts`, `write_latency`, `timings`, `smoke`), or why the
+/// run failed.
+fn materialize(
+    conns: &Connections,
+    backend: &LookupBackend,
+    opts: &'static Options,
+    submission_filter: &Option<String>,
+    dashboard: &Dashboard,
+) -> Result<Document, MaterializeError> {
+    materialize_submission(conns, backend, opts, submission_filter, dashboard)
+        .map_err(MaterializeError::from)
+}
 
-    let data_types = load_lookup_table(&db.collection("data_type"), &submission_filter);
-    println!("  data_type: {} entries", data_types.len());
+/// Rejected files listed in the run record; the rest are only counted.
+const REJECTED_RECORDED: usize = 100;
 
-    let assay_types = load_lookup_table(&db.collection("assay_type"), &submission_filter);
-    println!("  assay_type: {} entries", assay_types.len());
+/// Batches enriched ahead of the writer thread before enrichment waits.
+const WRITE_DEPTH: usize = 2;
 
-    let anatomies = load_lookup_table(&db.collection("anatomy"), &submission_filter);
-    println!("  anatomy: {} entries", anatomies.len());
+/// One batch for the writer thread: the files, their public copies, the
+/// sidecar edges of the oversized ones, and the changed files to remove
+/// before they are written again.
+#[derive(Default)]
+struct WriteBatch {
+    files: Vec<Document>,
+    public: Vec<Document>,
+    edges: Vec<Document>,
+    replaced: Vec<(String, String)>,
+}
+
+impl WriteBatch {
+    fn with_capacity(capacity: usize) -> Self {
+        WriteBatch {
+            files: Vec::with_capacity(capacity),
+            ..WriteBatch::default()
+        }
+    }
+}
 
-    // Load collections keyed by (id_namespace, local_id)
-    let collections = load_entity_table(&db.collection("collection"), &submission_filter);
-    println!("  collection: {} entries", collections.len());
+fn materialize_submission(
+    conns: &Connections,
+    backend: &LookupBackend,
+    opts: &'static Options,
+    submission_filter: &Option<String>,
+    dashboard: &Dashboard,
+) -> Result<Document> {
+    // Raw tables are read from the source; everything the run writes,
+    // including its checkpoint, goes to the target
+    let (source, db) = (&conns.source, &conns.target);
+    if let Some(ref sub) = submission_filter {
+        println!("Materializing files for submission: {}", sub);
+    } else {
+        println!("Materializing all files");
+    }
 
-    // Load biosamples keyed by (id_namespace, local_id)
-    let biosamples = load_entity_table(&db.collection("biosample"), &submission_filter);
-    println!("  biosample: {} entries", biosamples.len());
+    let resume_from = if opts.resume {
+        match checkpoint::load(db, submission_filter)? {
+            Some(checkpoint) => Some(checkpoint.get_i64("written").unwrap_or_default()),
+            None => {
+                println!("  No checkpoint to resume from; running from the start");
+                None
+            }
+        }
+    } else {
+        None
+    };
 
-    // Load junction tables as multi-maps
-    let file_in_collection = load_file_in_collection(&db.collection("file_in_collection"), &submission_filter);
-    println!("  file_in_collection: {} entries", file_in_collection.len());
+    let mut timings = PhaseTimings::default();
+    let started = Instant::now();
+    let ctx = Arc::new(LookupContext::load(
+        source,
+        backend,
+        submission_filter,
+        opts,
+    )?);
+    timings.record("lookup_load", started, None);
 
-    let biosample_in_collection =
-        load_biosample_in_collection(&db.collection("biosample_in_collection"), &submission_filter);
-    println!(
-        "  biosample_in_collection: {} entries",
-        biosample_in_collection.len()
-    );
+    // Entity views are written from the same loaded tables as `files`
+    let mut view_counts = Document::new();
+    for view in opts.views.iter().filter(|view| **view != View::Files) {
+        println!("\nWriting {} view...", view.collection());
+        let started = Instant::now();
+        let count = views::write(db, &ctx, *view, submission_filter)?;
+        println!("  Wrote {} {} documents", count, view.collection());
+        timings.record(view.collection(), started, Some(count));
+        view_counts.insert(view.collection(), count as i64);
+    }
+    if !opts.views.contains(&View::Files) {
+        timings.insert("lookup_tables_ms", ctx.load_ms.clone());
+        return Ok(doc! {
+            "counts": {
+                "views": view_counts,
+                "duplicate_keys": ctx.duplicates.clone(),
+            },
+            "timings": timings.report(),
+        });
+    }
 
     // Build file query filter
-    let file_query = match &submission_filter {
+    let mut file_query = match &submission_filter {
         Some(sub) => doc! { "submission": sub },
+        None if !opts.exclude_submissions.is_empty() => {
+            println!(
+                "  Excluding submissions: {}",
+                opts.exclude_submissions.join(", ")
+            );
+            doc! { "submission": { "$nin": &opts.exclude_submissions } }
+        }
         None => doc! {},
     };
 
-    // Count files
-    let file_count = db.collection::<Document>("file").count_documents(file_query.clone()).run()?;
-    println!("\nProcessing {} files...", file_count);
+    let missing_dcc = missing_dcc_submissions(source, &ctx, &file_query)?;
+    if !missing_dcc.is_empty() {
+        println!(
+            "\n  Warning: no dcc document for submissions: {}",
+            missing_dcc.join(", ")
+        );
+        match opts.on_missing_dcc {
+            Some(MissingDcc::Fail) => {
+                return Err(MaterializeError::MissingLookup(format!(
+                    "No dcc document for submissions: {}",
+                    missing_dcc.join(", ")
+                ))
+                .into())
+            }
+            Some(MissingDcc::Skip) => {
+                println!("  Skipping their files");
+                file_query =
+                    doc! { "$and": [file_query, { "submission": { "$nin": &missing_dcc } }] };
+            }
+            Some(MissingDcc::Placeholder) => println!("  Embedding a placeholder dcc"),
+            None => println!("  Their files are written without a dcc"),
+        }
+    }
 
-    // Load files into memory
-    let files: Vec<Document> = db
-        .collection("file")
-        .find(file_query)
-        .batch_size(50000)
-        .run()?
-        .filter_map(|r| r.ok())
-        .collect();
+    // A sample reads its chosen files by `_id`, in batches so no query
+    // carries more ids than fit in a command
+    let (file_queries, file_count) = match opts.sample {
+        Some(sample) => {
+            let ids = sample.select(source, &file_query)?;
+            (sample::batches(&file_query, &ids), ids.len() as u64)
+        }
+        None => {
+            let count = source
+                .collection::<Document>("file")
+                .count_documents(file_query.clone())
+                .run()?;
+            (vec![file_query.clone()], count)
+        }
+    };
+    println!("\nProcessing {} files...", file_count);
 
-    let pb = ProgressBar::new(files.len() as u64);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({per_sec})")
-            .unwrap()
-            .progress_chars("#>-"),
-    );
-
-    // Process files in parallel
-    let enriched: Vec<Document> = files
-        .into_par_iter()
-        .map(|mut file| {
-            let submission = file.get_str("submission").unwrap_or_default().to_string();
-            let id_namespace = file.get_str("id_namespace").unwrap_or_default().to_string();
-            let local_id = file.get_str("local_id").unwrap_or_default().to_string();
-
-            // Lookup DCC
-            if let Some(dcc) = dccs.get(&submission) {
-                let mut dcc_copy = dcc.clone();
-                dcc_copy.remove("_id");
-                file.insert("dcc", dcc_copy);
-            }
+    let pb = dashboard.phase("enrich", file_count);
 
-            // Lookup file_format (skip empty strings)
-            if let Some(format_id) = file.get_str("file_format").ok() {
-                if !format_id.is_empty() {
-                    if let Some(format) =
-                        file_formats.get(&(submission.clone(), format_id.to_string()))
-                    {
-                        let mut format_copy = format.clone();
-                        format_copy.remove("_id");
-                        file.insert("file_format", format_copy);
-                    }
-                } else {
-                    file.remove("file_format");
+    // Reads of `file` are paced with --max-read-ops-per-sec
+    let read_limit = opts
+        .max_read_ops_per_sec
+        .map(|n| Arc::new(RateLimit::new(n)));
+    let find_batch_size = throttle::read_batch_size(opts.find_batch_size, read_limit.as_deref());
+    let limit = read_limit.as_deref();
+    let files = || {
+        file_queries
+            .iter()
+            .flat_map(|query| {
+                ResumableCursor::new(source.collection("file"), query.clone(), find_batch_size)
+            })
+            .inspect(move |_| {
+                if let Some(limit) = limit {
+                    limit.acquire(1);
                 }
-            }
+            })
+    };
 
-            // Lookup data_type (skip empty strings)
-            if let Some(type_id) = file.get_str("data_type").ok() {
-                if !type_id.is_empty() {
-                    if let Some(dtype) = data_types.get(&(submission.clone(), type_id.to_string()))
-                    {
-                        let mut dtype_copy = dtype.clone();
-                        dtype_copy.remove("_id");
-                        file.insert("data_type", dtype_copy);
-                    }
-                } else {
-                    file.remove("data_type");
+    #[cfg(feature = "profiling")]
+    let mut profiler = opts
+        .profile_cpu
+        .as_deref()
+        .map(profile::CpuProfiler::start)
+        .transpose()?;
+
+    let started = Instant::now();
+
+    // Unordered runs stream files through enrichment while earlier ones are
+    // written. Sorting or deduping needs every file first: either enrich
+    // everything in memory, or (with a spill directory) enrich in runs that
+    // are sorted and spilled to disk
+    let ordered = opts.sort || opts.dedupe;
+    let (enriched_count, enriched): (Option<usize>, Box<dyn Iterator<Item = Result<Document>>>) =
+        match opts.spill_dir {
+            _ if !ordered => {
+                let stream = pipeline::stream(
+                    conns.source_options.clone(),
+                    file_queries.clone(),
+                    find_batch_size,
+                    opts.batch_size,
+                    read_limit.clone(),
+                    Arc::clone(&ctx),
+                    pb.clone(),
+                )?;
+                (None, stream)
+            }
+            Some(ref dir) => {
+                let mut sorter = ExternalSorter::new(Path::new(dir), opts.dedupe)?;
+                let mut files = files().peekable();
+                while files.peek().is_some() {
+                    let run: Vec<Document> = files
+                        .by_ref()
+                        .take(SPILL_RUN_SIZE)
+                        .collect::<Result<_, _>>()?;
+                    sorter.push_run(pipeline::enrich_all(run, &ctx, &pb))?;
                 }
+                pb.finish_with_message("Processing complete");
+                println!("  Spilled {} sorted runs to {}", sorter.run_count(), dir);
+                (None, Box::new(sorter.merge()?))
             }
-
-            // Lookup assay_type (skip empty strings)
-            if let Some(assay_id) = file.get_str("assay_type").ok() {
-                if !assay_id.is_empty() {
-                    if let Some(assay) =
-                        assay_types.get(&(submission.clone(), assay_id.to_string()))
-                    {
-                        let mut assay_copy = assay.clone();
-                        assay_copy.remove("_id");
-                        file.insert("assay_type", assay_copy);
-                    }
-                } else {
-                    file.remove("assay_type");
+            _ => {
+                // Load files into memory and process them in parallel
+                let files: Vec<Document> = files().collect::<Result<_, _>>()?;
+                let mut enriched = pipeline::enrich_all(files, &ctx, &pb);
+                pb.finish_with_message("Processing complete");
+                enriched.par_sort_by(spill::compare);
+                if opts.dedupe {
+                    enriched.dedup_by(|a, b| spill::sort_key(a) == spill::sort_key(b));
                 }
+                (Some(enriched.len()), Box::new(enriched.into_iter().map(Ok)))
             }
+        };
 
-            // Build collections array with nested biosamples
-            let file_key = (id_namespace.clone(), local_id.clone());
-            let mut enriched_collections: Vec<Document> = Vec::new();
-
-            if let Some(file_colls) = file_in_collection.get(&file_key) {
-                for fc in file_colls {
-                    let coll_ns = fc
-                        .get_str("collection_id_namespace")
-                        .unwrap_or_default()
-                        .to_string();
-                    let coll_id = fc
-                        .get_str("collection_local_id")
-                        .unwrap_or_default()
-                        .to_string();
-                    let coll_key = (coll_ns.clone(), coll_id.clone());
-
-                    if let Some(coll) = collections.get(&coll_key) {
-                        let mut coll_copy = coll.clone();
-                        coll_copy.remove("_id");
-
-                        // Build biosamples array for this collection
-                        let mut enriched_biosamples: Vec<Document> = Vec::new();
-
-                        if let Some(bios_in_coll) = biosample_in_collection.get(&coll_key) {
-                            for bc in bios_in_coll {
-                                let bio_ns = bc
-                                    .get_str("biosample_id_namespace")
-                                    .unwrap_or_default()
-                                    .to_string();
-                                let bio_id = bc
-                                    .get_str("biosample_local_id")
-                                    .unwrap_or_default()
-                                    .to_string();
-                                let bio_key = (bio_ns, bio_id);
-
-                                if let Some(biosample) = biosamples.get(&bio_key) {
-                                    let mut bio_copy = biosample.clone();
-                                    bio_copy.remove("_id");
-
-                                    // Lookup anatomy for biosample
-                                    if let Some(anatomy_id) = biosample.get_str("anatomy").ok() {
-                                        if let Some(anatomy) = anatomies
-                                            .get(&(submission.clone(), anatomy_id.to_string()))
-                                        {
-                                            let mut anatomy_copy = anatomy.clone();
-                                            anatomy_copy.remove("_id");
-                                            bio_copy.insert("anatomy", anatomy_copy);
-                                        }
-                                    }
-
-                                    enriched_biosamples.push(bio_copy);
-                                }
-                            }
-                        }
+    // A streamed run enriches while it writes, so its enrichment is timed
+    // and profiled together with the write phase
+    if ordered {
+        timings.record(
+            "enrich",
+            started,
+            Some(enriched_count.map_or(file_count, |c| c as u64)),
+        );
+        #[cfg(feature = "profiling")]
+        if let Some(profiler) = profiler.take() {
+            profiler.finish()?;
+        }
+    }
 
-                        coll_copy.insert("biosamples", enriched_biosamples);
-                        enriched_collections.push(coll_copy);
-                    }
-                }
+    if checkpoint::requested() {
+        return Err(MaterializeError::Interrupted(
+            "Interrupted during enrichment; nothing was written".to_string(),
+        )
+        .into());
+    }
+
+    // Write results
+    let started = Instant::now();
+    match enriched_count {
+        Some(count) => println!("\nWriting {} enriched documents...", count),
+        None => println!("\nWriting enriched documents..."),
+    }
+    // A sample goes to its own collections so it never replaces real files
+    let (output, relations): (Collection<Document>, Collection<Document>) = match opts.sample {
+        Some(_) => (
+            db.collection(SAMPLE_COLLECTION),
+            db.collection(SAMPLE_RELATIONS_COLLECTION),
+        ),
+        None if opts.tiers => (
+            db.collection(CONTROLLED_COLLECTION),
+            db.collection(RELATIONS_COLLECTION),
+        ),
+        None => (db.collection("files"), db.collection(RELATIONS_COLLECTION)),
+    };
+    // A tiered run writes a redacted copy of every file alongside
+    let public_output: Option<Collection<Document>> =
+        opts.tiers.then(|| db.collection(PUBLIC_COLLECTION));
+
+    // Replacing one submission on a replica set happens in a transaction, so
+    // readers see either the old files or the new ones. A tiered run writes
+    // two collections, and a --skip-unchanged run only touches what changed,
+    // so neither does. The transaction must commit within the server's
+    // lifetime limit, so the submission is enriched into memory before it
+    // starts and it spans only the delete and the inserts; a submission
+    // over TRANSACTION_MAX_BYTES is replaced without one
+    let mut enriched = enriched;
+    let session = match (&opts.output, submission_filter) {
+        (Output::Mongo, Some(_))
+            if resume_from.is_none()
+                && !opts.no_transaction
+                && !opts.tiers
+                && !opts.skip_unchanged
+                && transactions::supported(db)? =>
+        {
+            let (buffered, complete) =
+                transactions::buffer(&mut enriched, transactions::TRANSACTION_MAX_BYTES)?;
+            let rest = std::mem::replace(&mut enriched, Box::new(std::iter::empty()));
+            enriched = Box::new(buffered.into_iter().map(Ok).chain(rest));
+            if complete {
+                let mut session = conns.target_client.start_session().run()?;
+                session.start_transaction().run()?;
+                println!("  Replacing the submission in a transaction");
+                Some(session)
+            } else {
+                println!(
+                    "  Warning: the submission is over {}MB enriched, too large to replace \
+                     within a transaction's time limit; replacing it without one",
+                    transactions::TRANSACTION_MAX_BYTES / (1024 * 1024)
+                );
+                None
             }
+        }
+        _ => None,
+    };
 
-            file.insert("collections", enriched_collections);
-            pb.inc(1);
-            file
-        })
-        .collect();
+    let mut sink = match opts.output {
+        Output::Mongo => FileSink::Mongo {
+            files: &output,
+            relations: &relations,
+            session: session.map(Box::new),
+        },
+        #[cfg(feature = "parquet")]
+        Output::Parquet(ref dir) => FileSink::Parquet(parquet::ParquetExport::create(dir)?),
+        #[cfg(feature = "arrow")]
+        Output::ArrowIpc(ref path) => FileSink::ArrowIpc(ipc::ArrowIpcExport::create(path)?),
+        #[cfg(feature = "postgres")]
+        Output::Postgres(ref uri) => FileSink::Postgres(Box::new(postgres::PostgresSink::create(
+            uri,
+            submission_filter,
+        )?)),
+    };
 
-    pb.finish_with_message("Processing complete");
+    // The public tier never carries relations: a redacted copy is written
+    // whole, so nothing it dropped comes back through `file_relations`
+    let mut public_sink = public_output.as_ref().map(|files| FileSink::Mongo {
+        files,
+        relations: &relations,
+        session: None,
+    });
 
-    // Write results
-    println!("\nWriting {} enriched documents...", enriched.len());
-    let output: Collection<Document> = db.collection("files");
+    // Per-submission document counts before and after, for the manifest
+    let submission_query = match submission_filter {
+        Some(sub) => doc! { "submission": sub },
+        None => doc! {},
+    };
+    let mut counts_before = Default::default();
+    if matches!(sink, FileSink::Mongo { .. }) {
+        counts_before = manifest::submission_counts(&output, &submission_query)?;
+    }
 
-    // Delete existing documents (either all or just for this submission)
+    // A resumed run keeps what the interrupted run wrote and skips those
+    // files; a --skip-unchanged run keeps the existing files to compare
+    // against; otherwise delete existing documents (all or this submission's)
+    let mut already_written: HashSet<(String, String)> = HashSet::new();
+    let mut existing_hashes: HashMap<(String, String), String> = HashMap::new();
     match &submission_filter {
+        _ if !matches!(sink, FileSink::Mongo { .. }) => {}
+        _ if resume_from.is_some() => {
+            for doc in output
+                .find(file_query.clone())
+                .projection(doc! { "id_namespace": 1, "local_id": 1 })
+                .run()?
+            {
+                let doc = doc?;
+                if let (Ok(ns), Ok(id)) = (doc.get_str("id_namespace"), doc.get_str("local_id")) {
+                    already_written.insert((ns.to_string(), id.to_string()));
+                }
+            }
+            println!(
+                "  Resuming: {} files already written, skipping them",
+                already_written.len()
+            );
+        }
+        _ if opts.skip_unchanged => {
+            existing_hashes = change::existing_hashes(&output, &file_query)?;
+            println!(
+                "  Comparing against {} existing files",
+                existing_hashes.len()
+            );
+        }
         Some(sub) => {
-            let delete_result = output.delete_many(doc! { "submission": sub }).run()?;
-            println!("  Deleted {} existing {} documents", delete_result.deleted_count, sub);
+            let deleted = sink.delete_submission(sub)?;
+            println!("  Deleted {} existing {} documents", deleted, sub);
+            if let Some(ref public) = public_output {
+                public.delete_many(doc! { "submission": sub }).run()?;
+            }
         }
         None => {
             output.drop().run()?;
+            relations.drop().run()?;
+            if let Some(ref public) = public_output {
+                public.drop().run()?;
+            }
             println!("  Dropped existing collection");
         }
     }
 
-    let pb = ProgressBar::new(enriched.len() as u64);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len}")
-            .unwrap()
-            .progress_chars("#>-"),
-    );
+    // Shard the output before the first insert, or point out that it isn't.
+    // Indexes from an older build are dropped first, so the ones built after
+    // the write fit under MongoDB's limit
+    if matches!(sink, FileSink::Mongo { .. }) {
+        drop_stale_indexes(
+            &conns.target_client,
+            &output,
+            &opts.config,
+            opts.hoist_biosamples,
+        )?;
+        if let Some(ref public) = public_output {
+            drop_stale_indexes(
+                &conns.target_client,
+                public,
+                &opts.config,
+                opts.hoist_biosamples,
+            )?;
+        }
+        match opts.shard_key {
+            Some(ref key) => {
+                sharding::prepare(&conns.target_client, source, &file_query, &output, key)?;
+                if let Some(ref public) = public_output {
+                    sharding::prepare(&conns.target_client, source, &file_query, public, key)?;
+                }
+            }
+            None => sharding::advise(&conns.target_client, &output)?,
+        }
+    }
+
+    // The size budget only applies to documents written to MongoDB, where
+    // one document over the 16MB limit would fail its whole batch
+    let size_policy =
+        matches!(sink, FileSink::Mongo { .. }).then(|| SizePolicy::new(opts.max_doc_size));
 
-    for chunk in enriched.chunks(BATCH_SIZE) {
-        output.insert_many(chunk).run()?;
-        pb.inc(chunk.len() as u64);
+    let batch_size = if sink.in_transaction() {
+        opts.batch_size.min(transactions::TRANSACTION_BATCH_SIZE)
+    } else {
+        opts.batch_size
+    };
+    let pb = dashboard.phase("write", enriched_count.map_or(file_count, |c| c as u64));
+
+    let mut project_stats = ProjectAggregator::default();
+    let mut field_stats = FieldStats::default();
+    let mut next = WriteBatch::with_capacity(batch_size);
+    let mut unchanged: u64 = 0;
+    let mut written: u64 = 0;
+    let mut oversized: u64 = 0;
+    let mut interrupted = false;
+    let mut latency = WriteLatency::new(opts.slow_batch_ms);
+    let mut rejected: Vec<Document> = Vec::new();
+    let write_limit = opts.max_write_ops_per_sec.map(RateLimit::new);
+    let mut flush = |next: WriteBatch| -> Result<()> {
+        change::remove_files(&output, &relations, &next.replaced)?;
+        let (batch, public, edges) = (&next.files, &next.public, &next.edges);
+        // Sorted and deduped runs are fully enriched by now, so they fail
+        // before anything is written
+        if opts.on_miss == OnMiss::Fail && ctx.misses.terms() > 0 {
+            return Err(MaterializeError::MissingLookup(format!(
+                "{} vocabulary ids did not resolve with --on-miss fail",
+                ctx.misses.terms()
+            ))
+            .into());
+        }
+        if let Some(ref limit) = write_limit {
+            limit.acquire(batch.len() + public.len() + edges.len());
+        }
+        let started = Instant::now();
+        let failed = sink.write(batch)?;
+        if !edges.is_empty() {
+            sink.write_relations(edges)?;
+        }
+        if let Some(ref mut public_sink) = public_sink {
+            let failed = public_sink.write(public)?;
+            rejected.extend(failed);
+        }
+        latency.observe(started.elapsed(), batch.len(), &pb);
+        pb.inc(batch.len() as u64);
+        pb.set_message(dashboard::status(&ctx.misses));
+        written += (batch.len() - failed.len()) as u64;
+        rejected.extend(failed);
+        Ok(())
+    };
+    // Batches are written on their own thread, fed through a bounded
+    // channel, so the next batch is enriched and sized while the last one
+    // is in flight. A failed write closes the channel, which stops the loop
+    let (batches, queued) = mpsc::sync_channel::<WriteBatch>(WRITE_DEPTH);
+    let (produced, flushed) = thread::scope(|scope| {
+        let flush = &mut flush;
+        let writer = scope.spawn(move || -> Result<()> {
+            for next in queued {
+                flush(next)?;
+            }
+            Ok(())
+        });
+        let produced = (|| -> Result<()> {
+            for doc in enriched {
+                if checkpoint::requested() {
+                    interrupted = true;
+                    break;
+                }
+                let mut doc = doc?;
+                project_stats.observe(&doc);
+                field_stats.observe(&doc);
+                if !already_written.is_empty() {
+                    let key = (
+                        doc.get_str("id_namespace").unwrap_or_default().to_string(),
+                        doc.get_str("local_id").unwrap_or_default().to_string(),
+                    );
+                    if already_written.contains(&key) {
+                        pb.inc(1);
+                        continue;
+                    }
+                }
+                if opts.skip_unchanged {
+                    let key = change::file_key(&doc);
+                    match existing_hashes.remove(&key) {
+                        Some(hash) if doc.get_str(change::HASH_FIELD) == Ok(hash.as_str()) => {
+                            unchanged += 1;
+                            pb.inc(1);
+                            continue;
+                        }
+                        Some(_) => next.replaced.push(key),
+                        None => {}
+                    }
+                }
+                if opts.tiers {
+                    next.public.push(public_copy(
+                        &doc,
+                        &opts.config.redact,
+                        &opts.config.enrichment,
+                        &ctx.redactions,
+                    ));
+                }
+                if let Some(ref policy) = size_policy {
+                    let doc_edges = policy.apply(&mut doc)?;
+                    if !doc_edges.is_empty() {
+                        next.edges.extend(doc_edges);
+                        oversized += 1;
+                    }
+                }
+                next.files.push(doc);
+                if next.files.len() >= batch_size {
+                    let full = std::mem::replace(&mut next, WriteBatch::with_capacity(batch_size));
+                    if batches.send(full).is_err() {
+                        break;
+                    }
+                }
+            }
+            if !next.files.is_empty() {
+                let _ = batches.send(std::mem::take(&mut next));
+            }
+            Ok(())
+        })();
+        drop(batches);
+        (produced, writer.join().expect("the writer thread panicked"))
+    });
+    flushed?;
+    produced?;
+    // Files the run didn't produce have left the source
+    let mut removed = 0;
+    if opts.skip_unchanged && !interrupted {
+        let gone: Vec<(String, String)> = existing_hashes.into_keys().collect();
+        removed = change::remove_files(&output, &relations, &gone)?;
+        println!(
+            "  {} files unchanged, {} no longer in the source removed",
+            unchanged, removed
+        );
     }
 
     pb.finish_with_message("Write complete");
+    latency.print_summary();
+    #[cfg(feature = "profiling")]
+    if let Some(profiler) = profiler {
+        profiler.finish()?;
+    }
+    let to_mongo = matches!(sink, FileSink::Mongo { .. });
+    if interrupted && sink.in_transaction() {
+        sink.abort()?;
+        return Err(MaterializeError::Interrupted(
+            "Interrupted; the transaction was rolled back and the submission is unchanged"
+                .to_string(),
+        )
+        .into());
+    }
+    sink.finish()?;
+    timings.record("write", started, Some(written));
+
+    if interrupted {
+        if !to_mongo {
+            return Err(MaterializeError::Interrupted(format!(
+                "Interrupted after writing {} files",
+                written
+            ))
+            .into());
+        }
+        let total = resume_from.unwrap_or(0) as u64 + written;
+        checkpoint::save(db, submission_filter, total)?;
+        return Err(MaterializeError::Interrupted(format!(
+            "Interrupted after writing {} files; checkpoint saved. \
+             Rerun with the same options plus --resume to finish",
+            total
+        ))
+        .into());
+    }
+    ctx.unparseable.print();
+    ctx.redactions.print();
+    if !rejected.is_empty() {
+        println!(
+            "  Warning: {} files could not be written; see `rejected` in the run record",
+            rejected.len()
+        );
+    }
+    if oversized > 0 {
+        println!(
+            "  {} documents exceeded the size budget; relations moved to {}",
+            oversized,
+            relations.name()
+        );
+    }
 
     // Create indexes (always, in case they don't exist)
-    println!("\nCreating indexes...");
-    create_indexes(&output)?;
+    if to_mongo {
+        dashboard.stage("indexes");
+        println!("\nCreating indexes...");
+        let started = Instant::now();
+        create_indexes(&output, &opts.config, opts.hoist_biosamples)?;
+        if let Some(ref public) = public_output {
+            create_indexes(public, &opts.config, opts.hoist_biosamples)?;
+        }
+        create_relation_indexes(&relations)?;
+        timings.record("indexes", started, None);
+    }
+    let mut counts_after = Default::default();
+    if to_mongo {
+        counts_after = manifest::submission_counts(&output, &submission_query)?;
+    }
 
-    println!("Done!");
-    Ok(())
-}
+    // Projects, field statistics, and smoke queries describe `files`; a
+    // sample leaves them alone
+    let mut project_count = 0;
+    if opts.sample.is_none() {
+        dashboard.stage("projects");
+        println!("\nMaterializing projects...");
+        let started = Instant::now();
+        project_count = project_stats.write(source, db, &ctx.dccs, submission_filter)?;
+        println!("  Wrote {} project documents", project_count);
+        timings.record("projects", started, Some(project_count as u64));
 
-fn load_collection(coll: &Collection<Document>) -> Vec<Document> {
-    coll.find(doc! {})
-        .run()
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect()
-}
+        println!("\nField statistics:");
+        let started = Instant::now();
+        field_stats.write(db, to_mongo.then_some(&output), submission_filter)?;
+        timings.record("field_stats", started, None);
+    }
 
-fn load_collection_filtered(coll: &Collection<Document>, submission: &Option<String>) -> Vec<Document> {
-    let query = match submission {
-        Some(sub) => doc! { "submission": sub },
-        None => doc! {},
-    };
-    coll.find(query)
-        .run()
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect()
-}
+    // Files embedding a DCC reference find the rest of it in `dccs`
+    if to_mongo && opts.embed_dcc == EmbedDcc::Ref {
+        let count = dccs::write(db, &ctx.dccs, submission_filter)?;
+        println!(
+            "  Wrote {} DCC documents to {}",
+            count,
+            dccs::DCCS_COLLECTION
+        );
+    }
 
-fn load_lookup_table(coll: &Collection<Document>, submission: &Option<String>) -> LookupMap {
-    load_collection_filtered(coll, submission)
-        .into_iter()
-        .filter_map(|d| {
-            let sub = d.get_str("submission").ok()?.to_string();
-            let id = d.get_str("id").ok()?.to_string();
-            Some(((sub, id), d))
-        })
-        .collect()
+    // Smoke queries run against `files`, which a tiered run doesn't write
+    let mut smoke_results = Vec::new();
+    if to_mongo && opts.sample.is_none() && !opts.tiers {
+        dashboard.stage("smoke");
+        println!("\nSmoke queries:");
+        let started = Instant::now();
+        smoke_results = smoke::verify(db, &opts.config.smoke, submission_filter)?;
+        timings.record("smoke", started, None);
+    }
+    timings.insert("lookup_tables_ms", ctx.load_ms.clone());
+    if resume_from.is_some() {
+        checkpoint::clear(db, submission_filter)?;
+    }
+
+    let outputs: Vec<&str> = std::iter::once(&output)
+        .chain(&public_output)
+        .map(|coll| coll.name())
+        .collect();
+    Ok(doc! {
+        "counts": {
+            "files_read": file_count as i64,
+            "files_written": written as i64,
+            "oversized": oversized as i64,
+            "unchanged": unchanged as i64,
+            "removed": removed as i64,
+            "rejected": rejected.len() as i64,
+            "projects": project_count as i64,
+            "views": view_counts,
+            "duplicate_keys": ctx.duplicates.clone(),
+        },
+        "write_latency": latency.report(),
+        "timings": timings.report(),
+        "smoke": smoke_results,
+        "rejected": rejected.iter().take(REJECTED_RECORDED).cloned().collect::<Vec<_>>(),
+        "validation": {
+            "missing_dcc": &missing_dcc,
+            "unparseable": ctx.unparseable.report(),
+        },
+        "redacted": ctx.redactions.report(),
+        "outputs": outputs,
+        "submission_counts": manifest::deltas(&counts_before, &counts_after),
+    })
 }
 
-fn load_entity_table(coll: &Collection<Document>, submission: &Option<String>) -> HashMap<(String, String), Document> {
-    load_collection_filtered(coll, submission)
-        .into_iter()
-        .filter_map(|d| {
-            let ns = d.get_str("id_namespace").ok()?.to_string();
-            let id = d.get_str("local_id").ok()?.to_string();
-            Some(((ns, id), d))
-        })
-        .collect()
+/// Submissions among the selected files that have no `dcc` document.
+fn missing_dcc_submissions(
+    db: &Database,
+    ctx: &LookupContext,
+    file_query: &Document,
+) -> Result<Vec<String>> {
+    let submissions = db
+        .collection::<Document>("file")
+        .distinct("submission", file_query.clone())
+        .run()?;
+    Ok(submissions
+        .iter()
+        .filter_map(|sub| sub.as_str())
+        .filter(|sub| !ctx.dccs.contains_key(*sub))
+        .map(str::to_string)
+        .collect())
 }
 
-fn load_file_in_collection(coll: &Collection<Document>, submission: &Option<String>) -> MultiMap {
-    let mut map: MultiMap = HashMap::new();
-    for doc in load_collection_filtered(coll, submission) {
-        if let (Ok(ns), Ok(id)) = (
-            doc.get_str("file_id_namespace"),
-            doc.get_str("file_local_id"),
-        ) {
-            map.entry((ns.to_string(), id.to_string()))
-                .or_default()
-                .push(doc);
-        }
+/// Run the enrichment for the file(s) whose `local_id` or `persistent_id`
+/// matches `key` and print every lookup made along the way. Nothing is written.
+fn explain(
+    db: &Database,
+    backend: &LookupBackend,
+    key: &str,
+    submissions: &[Option<String>],
+    opts: &Options,
+) -> Result<()> {
+    let mut query = doc! { "$or": [{ "local_id": key }, { "persistent_id": key }] };
+    let submissions: Vec<&String> = submissions.iter().flatten().collect();
+    if !submissions.is_empty() {
+        query.insert("submission", doc! { "$in": submissions });
+    }
+    let files: Vec<Document> = db
+        .collection::<Document>("file")
+        .find(query)
+        .run()?
+        .filter_map(|r| r.ok())
+        .collect();
+    if files.is_empty() {
+        anyhow::bail!("No file matches {}", key);
     }
-    map
-}
 
-fn load_biosample_in_collection(coll: &Collection<Document>, submission: &Option<String>) -> MultiMap {
-    let mut map: MultiMap = HashMap::new();
-    for doc in load_collection_filtered(coll, submission) {
-        if let (Ok(ns), Ok(id)) = (
-            doc.get_str("collection_id_namespace"),
-            doc.get_str("collection_local_id"),
-        ) {
-            map.entry((ns.to_string(), id.to_string()))
-                .or_default()
-                .push(doc);
+    for file in files {
+        // Only load the tables for the file's own submission
+        let submission = file.get_str("submission").ok().map(str::to_string);
+        let ctx = LookupContext::load(db, backend, &submission, opts)?;
+
+        let mut trace = Trace::enabled();
+        let enriched = enrich_file(file, &ctx, &mut trace);
+        println!("\nEnrichment trace:");
+        for line in trace.lines() {
+            println!("  {}", line);
         }
+        println!("\nEnriched document:");
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&bson::Bson::Document(enriched).into_relaxed_extjson())?
+        );
     }
-    map
+    Ok(())
 }
 
-fn create_indexes(coll: &Collection<Document>) -> Result<()> {
-    use mongodb::IndexModel;
-
-    let indexes = vec![
-        doc! { "id_namespace": 1 },
-        doc! { "local_id": 1 },
-        doc! { "id_namespace": 1, "local_id": 1 },
-        doc! { "persistent_id": 1 },
-        doc! { "filename": 1 },
-        doc! { "size_in_bytes": 1 },
-        doc! { "sha256": 1 },
-        doc! { "md5": 1 },
-        doc! { "mime_type": 1 },
-        doc! { "dcc.id": 1 },
-        doc! { "dcc.dcc_name": 1 },
-        doc! { "dcc.dcc_abbreviation": 1 },
-        doc! { "file_format.id": 1 },
-        doc! { "file_format.name": 1 },
-        doc! { "data_type.id": 1 },
-        doc! { "data_type.name": 1 },
-        doc! { "assay_type.id": 1 },
-        doc! { "assay_type.name": 1 },
-        doc! { "collections.id_namespace": 1 },
-        doc! { "collections.local_id": 1 },
-        doc! { "collections.name": 1 },
-        doc! { "collections.biosamples.id_namespace": 1 },
-        doc! { "collections.biosamples.local_id": 1 },
-        doc! { "collections.biosamples.anatomy.id": 1 },
-        doc! { "collections.biosamples.anatomy.name": 1 },
-        doc! { "data_access_level": 1 },
-        doc! { "submission": 1 },
-    ];
-
-    let models: Vec<IndexModel> = indexes
-        .into_iter()
-        .map(|keys| IndexModel::builder().keys(keys).build())
-        .collect();
+/// Append the run's report as one JSON line to `path` (`-` for stdout).
+fn write_report(path: &str, submission: &Option<String>, report: &Document) -> Result<()> {
+    use std::io::Write;
 
-    coll.create_indexes(models).run()?;
-    println!("  Created {} indexes", 27);
+    let mut report = report.clone();
+    report.insert("submission", submission.as_deref());
+    report.insert("tool_version", env!("CARGO_PKG_VERSION"));
+    let line = serde_json::to_string(&bson::Bson::Document(report).into_relaxed_extjson())?;
+    if path == "-" {
+        println!("{}", line);
+    } else {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        writeln!(file, "{}", line)?;
+    }
     Ok(())
 }
@@ -0,0 +1,138 @@
+use anyhow::Result;
+use bson::oid::ObjectId;
+use bson::{doc, Bson, DateTime, Document};
+use mongodb::options::IndexOptions;
+use mongodb::sync::{Collection, Database};
+use mongodb::IndexModel;
+use std::collections::{BTreeMap, BTreeSet};
+
+pub const MANIFEST_COLLECTION: &str = "materialization_manifest";
+
+/// Documents per submission in `coll` among those matching `query`.
+pub fn submission_counts(
+    coll: &Collection<Document>,
+    query: &Document,
+) -> Result<BTreeMap<String, i64>> {
+    let pipeline = vec![
+        doc! { "$match": query.clone() },
+        doc! { "$group": { "_id": "$submission", "count": { "$sum": 1 } } },
+    ];
+    let mut counts = BTreeMap::new();
+    for group in coll.aggregate(pipeline).run()? {
+        let group = group?;
+        if let Ok(submission) = group.get_str("_id") {
+            let count = match group.get("count") {
+                Some(Bson::Int32(n)) => *n as i64,
+                Some(Bson::Int64(n)) => *n,
+                _ => 0,
+            };
+            counts.insert(submission.to_string(), count);
+        }
+    }
+    Ok(counts)
+}
+
+/// `{ submission: { before, after, delta } }` for every submission with
+/// documents before or after a run.
+pub fn deltas(before: &BTreeMap<String, i64>, after: &BTreeMap<String, i64>) -> Document {
+    let submissions: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    submissions
+        .into_iter()
+        .map(|submission| {
+            let before = before.get(submission).copied().unwrap_or(0);
+            let after = after.get(submission).copied().unwrap_or(0);
+            (
+                submission.clone(),
+                Bson::Document(doc! { "before": before, "after": after, "delta": after - before }),
+            )
+        })
+        .collect()
+}
+
+/// What one invocation changed, gathered from its runs' reports and written
+/// to `materialization_manifest` at the end, so API caches and CDNs can
+/// invalidate exactly the submissions and collections it touched.
+#[derive(Default)]
+pub struct Manifest {
+    runs: Vec<ObjectId>,
+    collections: BTreeSet<String>,
+    submissions: BTreeMap<String, Document>,
+}
+
+impl Manifest {
+    /// Take the output collections and per-submission counts from a
+    /// successful run's report.
+    pub fn observe(&mut self, run: ObjectId, report: &Document) {
+        self.runs.push(run);
+        if let Ok(outputs) = report.get_array("outputs") {
+            let names = outputs.iter().filter_map(Bson::as_str).map(str::to_string);
+            self.collections.extend(names);
+        }
+        if let Ok(views) = report
+            .get_document("counts")
+            .and_then(|c| c.get_document("views"))
+        {
+            self.collections.extend(views.keys().cloned());
+        }
+        if let Ok(counts) = report.get_document("submission_counts") {
+            for (submission, count) in counts {
+                if let Bson::Document(count) = count {
+                    self.submissions.insert(submission.clone(), count.clone());
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// Insert the manifest under the next generation number and return it.
+    /// `published` is the generation collection `--publish` created, if any.
+    pub fn write(self, db: &Database, published: Option<&str>) -> Result<i64> {
+        let coll: Collection<Document> = db.collection(MANIFEST_COLLECTION);
+        coll.create_index(
+            IndexModel::builder()
+                .keys(doc! { "generation": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+        )
+        .run()?;
+        let generation = coll
+            .find_one(doc! {})
+            .sort(doc! { "generation": -1 })
+            .run()?
+            .and_then(|last| last.get_i64("generation").ok())
+            .unwrap_or(0)
+            + 1;
+
+        let affected: Vec<&String> = self
+            .submissions
+            .iter()
+            .filter(|(_, count)| {
+                count.get_i64("before") != Ok(0) || count.get_i64("after") != Ok(0)
+            })
+            .map(|(submission, _)| submission)
+            .collect();
+        let submissions: Vec<Document> = self
+            .submissions
+            .iter()
+            .map(|(submission, count)| {
+                let mut entry = doc! { "submission": submission };
+                entry.extend(count.clone());
+                entry
+            })
+            .collect();
+        coll.insert_one(doc! {
+            "generation": generation,
+            "created_at": DateTime::now(),
+            "runs": &self.runs,
+            "collections": self.collections.iter().collect::<Vec<_>>(),
+            "affected_submissions": affected,
+            "submissions": submissions,
+            "published": published.map_or(Bson::Null, Bson::from),
+        })
+        .run()?;
+        Ok(generation)
+    }
+}
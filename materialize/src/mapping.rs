@@ -0,0 +1,186 @@
+use anyhow::{Context, Result};
+use bson::Document;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// How a partner dataset that isn't C2M2, but maps closely, loads into the
+/// C2M2 tables (`ingest --mapping mapping.toml`, or `mapping.yaml`). Each
+/// `[[tables]]` entry turns one payload file into rows of one C2M2 table; a file may feed
+/// several tables, and payload files no entry names load as-is, so a
+/// package can mix partner files with native C2M2 ones such as `dcc.tsv`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mapping {
+    pub tables: Vec<TableMapping>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TableMapping {
+    /// Payload file name, with or without its `.tsv`/`.csv` extension
+    pub source: String,
+    /// C2M2 table the rows load into
+    pub table: String,
+    /// Each column of the C2M2 row and where its value comes from
+    pub columns: BTreeMap<String, ColumnRule>,
+}
+
+/// One C2M2 column: copied from a source `column`, a constant `value`, or
+/// a `template` with `{Column}` placeholders filled from the source row.
+/// `map` then translates values (e.g. partner tissue names to UBERON ids),
+/// passing others through, and `default` stands in for a blank result.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnRule {
+    pub column: Option<String>,
+    pub value: Option<String>,
+    pub template: Option<String>,
+    #[serde(default)]
+    pub map: HashMap<String, String>,
+    pub default: Option<String>,
+}
+
+impl Mapping {
+    /// Read the mapping at `path`: YAML for a `.yaml` or `.yml` file, TOML
+    /// otherwise.
+    pub fn load(path: &str) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("reading {}", path))?;
+        Mapping::parse(path, &text)
+    }
+
+    fn parse(path: &str, text: &str) -> Result<Self> {
+        let yaml = Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"));
+        let mapping: Mapping = if yaml {
+            serde_yaml::from_str(text).with_context(|| format!("parsing {}", path))?
+        } else {
+            toml::from_str(text).with_context(|| format!("parsing {}", path))?
+        };
+        for table in &mapping.tables {
+            for (name, rule) in &table.columns {
+                let sources = [&rule.column, &rule.value, &rule.template]
+                    .iter()
+                    .filter(|s| s.is_some())
+                    .count();
+                if sources != 1 {
+                    anyhow::bail!(
+                        "{}: {}.{} needs exactly one of column, value, and template",
+                        path,
+                        table.table,
+                        name
+                    );
+                }
+            }
+        }
+        Ok(mapping)
+    }
+
+    /// The entries loading the payload file `name`.
+    pub fn for_file(&self, name: &str) -> Vec<&TableMapping> {
+        let file_name = name.rsplit('/').next().unwrap_or(name);
+        let stem = file_name
+            .strip_suffix(".tsv")
+            .or_else(|| file_name.strip_suffix(".csv"))
+            .unwrap_or(file_name);
+        self.tables
+            .iter()
+            .filter(|t| t.source == file_name || t.source == stem)
+            .collect()
+    }
+
+    /// Every C2M2 table the mapping loads into.
+    pub fn tables(&self) -> impl Iterator<Item = &str> {
+        self.tables.iter().map(|t| t.table.as_str())
+    }
+}
+
+impl TableMapping {
+    /// The C2M2 row for one source row.
+    pub fn apply(&self, row: &Document) -> Document {
+        let field = |name: &str| row.get_str(name).unwrap_or_default();
+        let mut mapped = Document::new();
+        for (name, rule) in &self.columns {
+            let value = if let Some(ref column) = rule.column {
+                field(column).to_string()
+            } else if let Some(ref value) = rule.value {
+                value.clone()
+            } else {
+                fill(rule.template.as_deref().unwrap_or_default(), field)
+            };
+            let value = rule.map.get(&value).cloned().unwrap_or(value);
+            let value = match rule.default {
+                Some(ref default) if value.is_empty() => default.clone(),
+                _ => value,
+            };
+            mapped.insert(name, value);
+        }
+        mapped
+    }
+}
+
+/// `template` with each `{Column}` replaced by that column's value.
+fn fill<'a>(template: &str, field: impl Fn(&str) -> &'a str) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}') else {
+            break;
+        };
+        filled.push_str(&rest[..open]);
+        filled.push_str(field(&rest[open + 1..open + close]));
+        rest = &rest[open + close + 1..];
+    }
+    filled.push_str(rest);
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yaml_and_toml_mappings_agree() {
+        let toml = r#"
+            [[tables]]
+            source = "samples"
+            table = "biosample"
+            [tables.columns.local_id]
+            template = "{Donor}-{Sample}"
+            [tables.columns.anatomy]
+            column = "Tissue"
+            map = { liver = "UBERON:0002107" }
+        "#;
+        let yaml = r#"
+            tables:
+              - source: samples
+                table: biosample
+                columns:
+                  local_id:
+                    template: "{Donor}-{Sample}"
+                  anatomy:
+                    column: Tissue
+                    map:
+                      liver: "UBERON:0002107"
+        "#;
+        let row = bson::doc! { "Donor": "D1", "Sample": "S2", "Tissue": "liver" };
+        let from_toml = Mapping::parse("mapping.toml", toml).unwrap();
+        for path in ["mapping.yaml", "mapping.YML"] {
+            let from_yaml = Mapping::parse(path, yaml).unwrap();
+            assert_eq!(from_yaml.tables[0].table, "biosample");
+            assert_eq!(
+                from_yaml.tables[0].apply(&row),
+                from_toml.tables[0].apply(&row)
+            );
+        }
+    }
+
+    #[test]
+    fn yaml_rules_need_one_source() {
+        let yaml = "tables:\n  - source: samples\n    table: biosample\n    columns:\n      local_id: {}\n";
+        let err = Mapping::parse("mapping.yaml", yaml).err().unwrap();
+        assert!(err.to_string().contains("needs exactly one of"));
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::Result;
+use bson::{doc, Bson, DateTime, Document};
+use mongodb::sync::{Collection, Database};
+use std::collections::BTreeMap;
+
+use crate::cli::{flag, values};
+use crate::coerce::{coerce_date, coerce_numeric, Unparseable};
+
+/// Layout version of materialized documents, stamped on each one as
+/// `materialized_schema_version`. Bump it, with a step in [`MIGRATIONS`]
+/// that upgrades documents from the previous version, whenever a change
+/// alters fields the portal reads.
+pub const SCHEMA_VERSION: i32 = 1;
+
+pub const VERSION_FIELD: &str = "materialized_schema_version";
+
+/// Collections whose documents carry the stamp and are migrated by default.
+const MIGRATED_COLLECTIONS: [&str; 4] = ["files", "collections", "biosamples", "subjects"];
+
+/// Stamp a freshly enriched document with the current layout version and
+/// the time its run started.
+pub fn stamp(doc: &mut Document, materialized_at: DateTime) {
+    doc.insert(VERSION_FIELD, SCHEMA_VERSION);
+    doc.insert("materialized_at", materialized_at);
+}
+
+/// An upgrade of documents at version `from` to `from + 1`; `apply` gets
+/// the collection name and the document.
+struct Migration {
+    from: i32,
+    summary: &'static str,
+    apply: fn(&str, &mut Document),
+}
+
+/// Documents written before versions were stamped count as version 0.
+const MIGRATIONS: [Migration; 1] = [Migration {
+    from: 0,
+    summary: "store file sizes as int64 and creation_time as a date",
+    apply: typed_fields,
+}];
+
+/// Version 1 stores file sizes and `creation_time` typed, as enrichment
+/// now does; unparseable values are left as they are.
+fn typed_fields(collection: &str, doc: &mut Document) {
+    if collection != "files" {
+        return;
+    }
+    let unparseable = Unparseable::default();
+    coerce_numeric(doc, &unparseable);
+    coerce_date(doc, &unparseable);
+}
+
+fn version(doc: &Document) -> i32 {
+    match doc.get(VERSION_FIELD) {
+        Some(Bson::Int32(v)) => *v,
+        Some(Bson::Int64(v)) => *v as i32,
+        _ => 0,
+    }
+}
+
+/// Apply every step from the document's version up to [`SCHEMA_VERSION`].
+fn upgrade(collection: &str, doc: &mut Document) {
+    let from = version(doc);
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= from) {
+        (migration.apply)(collection, doc);
+    }
+    doc.insert(VERSION_FIELD, SCHEMA_VERSION);
+    doc.insert("migrated_at", DateTime::now());
+}
+
+/// `migrate [--collection NAME]... [--dry-run]` upgrades documents written
+/// by older versions of the materializer in place, one document at a time,
+/// so the portal sees the current layout without a full rematerialization.
+pub fn command(db: &Database, args: &[String]) -> Result<()> {
+    let mut collections = values(args, "--collection");
+    if collections.is_empty() {
+        collections = MIGRATED_COLLECTIONS.iter().map(|c| c.to_string()).collect();
+    }
+    let dry_run = flag(args, "--dry-run");
+
+    println!("Schema version {}", SCHEMA_VERSION);
+    for migration in &MIGRATIONS {
+        println!(
+            "  {} -> {}: {}",
+            migration.from,
+            migration.from + 1,
+            migration.summary
+        );
+    }
+
+    let outdated = doc! { "$or": [
+        { VERSION_FIELD: { "$exists": false } },
+        { VERSION_FIELD: { "$lt": SCHEMA_VERSION } },
+    ] };
+    for name in &collections {
+        let coll: Collection<Document> = db.collection(name);
+        let newer = coll
+            .count_documents(doc! { VERSION_FIELD: { "$gt": SCHEMA_VERSION } })
+            .run()?;
+        if newer > 0 {
+            println!(
+                "\n  Warning: {} {} documents were written by a newer version; left alone",
+                newer, name
+            );
+        }
+
+        let mut by_version: BTreeMap<i32, u64> = BTreeMap::new();
+        let mut migrated = 0;
+        for doc in coll.find(outdated.clone()).run()? {
+            let mut doc = doc?;
+            *by_version.entry(version(&doc)).or_default() += 1;
+            if dry_run {
+                continue;
+            }
+            let id = doc.get("_id").cloned().unwrap_or(Bson::Null);
+            upgrade(name, &mut doc);
+            coll.replace_one(doc! { "_id": id }, doc).run()?;
+            migrated += 1;
+        }
+
+        let found: u64 = by_version.values().sum();
+        println!("\n{}: {} documents to upgrade", name, found);
+        for (version, count) in &by_version {
+            println!("  version {}: {}", version, count);
+        }
+        if !dry_run {
+            println!("  Upgraded {} documents", migrated);
+        }
+    }
+    Ok(())
+}
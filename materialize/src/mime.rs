@@ -0,0 +1,88 @@
+use bson::{Bson, Document};
+
+/// MIME types for EDAM `file_format` ids, preferred over the extension.
+const FORMAT_MIME_TYPES: [(&str, &str); 20] = [
+    ("format:1929", "text/x-fasta"),
+    ("format:1930", "text/x-fastq"),
+    ("format:1931", "text/x-fastq"),
+    ("format:1932", "text/x-fastq"),
+    ("format:2572", "application/x-bam"),
+    ("format:2573", "text/x-sam"),
+    ("format:3462", "application/x-cram"),
+    ("format:3016", "text/x-vcf"),
+    ("format:3003", "text/x-bed"),
+    ("format:2306", "text/x-gtf"),
+    ("format:1975", "text/x-gff3"),
+    ("format:3475", "text/tab-separated-values"),
+    ("format:3752", "text/csv"),
+    ("format:3464", "application/json"),
+    ("format:2332", "application/xml"),
+    ("format:3508", "application/pdf"),
+    ("format:3579", "image/jpeg"),
+    ("format:3603", "image/png"),
+    ("format:3591", "image/tiff"),
+    ("format:3590", "application/x-hdf5"),
+];
+
+/// MIME types by filename extension (lowercase, without the dot).
+const EXTENSION_MIME_TYPES: [(&str, &str); 30] = [
+    ("fa", "text/x-fasta"),
+    ("fasta", "text/x-fasta"),
+    ("fna", "text/x-fasta"),
+    ("fq", "text/x-fastq"),
+    ("fastq", "text/x-fastq"),
+    ("bam", "application/x-bam"),
+    ("sam", "text/x-sam"),
+    ("cram", "application/x-cram"),
+    ("vcf", "text/x-vcf"),
+    ("bed", "text/x-bed"),
+    ("gtf", "text/x-gtf"),
+    ("gff", "text/x-gff3"),
+    ("gff3", "text/x-gff3"),
+    ("tsv", "text/tab-separated-values"),
+    ("csv", "text/csv"),
+    ("txt", "text/plain"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("html", "text/html"),
+    ("pdf", "application/pdf"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("h5", "application/x-hdf5"),
+    ("h5ad", "application/x-hdf5"),
+    ("hdf5", "application/x-hdf5"),
+    ("zip", "application/zip"),
+    ("tar", "application/x-tar"),
+];
+
+/// Compression suffixes looked through to the extension underneath
+/// (`reads.fastq.gz` is FASTQ).
+const COMPRESSION_EXTENSIONS: [&str; 5] = ["gz", "bgz", "bz2", "xz", "zst"];
+
+/// The MIME type implied by a file's `file_format` (embedded term or raw
+/// id), falling back to its `filename` extension.
+pub fn infer(file: &Document) -> Option<&'static str> {
+    let format = match file.get("file_format") {
+        Some(Bson::Document(term)) => term.get_str("id").ok(),
+        Some(Bson::String(id)) => Some(id.as_str()),
+        _ => None,
+    };
+    if let Some(format) = format {
+        if let Some((_, mime_type)) = FORMAT_MIME_TYPES.iter().find(|(id, _)| *id == format) {
+            return Some(mime_type);
+        }
+    }
+    let filename = file.get_str("filename").ok()?.to_ascii_lowercase();
+    let mut parts = filename.rsplit('.');
+    let mut extension = parts.next()?;
+    if COMPRESSION_EXTENSIONS.contains(&extension) {
+        extension = parts.next()?;
+    }
+    EXTENSION_MIME_TYPES
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, mime_type)| *mime_type)
+}
@@ -0,0 +1,76 @@
+use bson::{Bson, Document};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Maps the field names DCCs actually submit (`SHA256`, `MIME_type`) to the
+/// canonical C2M2 names before anything reads them. Applied to every file
+/// and lookup row as it is loaded. Configured aliases are added to the
+/// built-in ones; with `casing = "lower"` every field name is also
+/// lowercased, which covers most variants without listing them.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NormalizeConfig {
+    pub aliases: HashMap<String, String>,
+    pub casing: Casing,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Casing {
+    /// Keep names as submitted, apart from aliases
+    #[default]
+    Preserve,
+    /// Lowercase every name
+    Lower,
+}
+
+/// Variants seen in submissions, mapped to their C2M2 names.
+const BUILTIN_ALIASES: [(&str, &str); 8] = [
+    ("SHA256", "sha256"),
+    ("sha_256", "sha256"),
+    ("MD5", "md5"),
+    ("MIME_type", "mime_type"),
+    ("mimetype", "mime_type"),
+    ("filesize", "size_in_bytes"),
+    ("file_size", "size_in_bytes"),
+    ("uncompressed_size", "uncompressed_size_in_bytes"),
+];
+
+impl NormalizeConfig {
+    /// The canonical name for `field`, if it differs.
+    fn canonical(&self, field: &str) -> Option<String> {
+        if let Some(name) = self.aliases.get(field) {
+            return Some(name.clone());
+        }
+        if let Some((_, name)) = BUILTIN_ALIASES.iter().find(|(alias, _)| *alias == field) {
+            return Some(name.to_string());
+        }
+        if self.casing == Casing::Lower && field.chars().any(|c| c.is_ascii_uppercase()) {
+            return Some(field.to_ascii_lowercase());
+        }
+        None
+    }
+
+    /// Rename aliased fields in `doc` to their canonical names. When both
+    /// are present, a non-empty canonical value wins and the alias is
+    /// dropped.
+    pub fn apply(&self, doc: &mut Document) {
+        let renames: Vec<(String, String)> = doc
+            .keys()
+            .filter_map(|key| self.canonical(key).map(|name| (key.clone(), name)))
+            .collect();
+        for (alias, name) in renames {
+            let Some(value) = doc.remove(&alias) else {
+                continue;
+            };
+            let keep_existing = match doc.get(&name) {
+                None | Some(Bson::Null) => false,
+                Some(Bson::String(s)) => !s.is_empty(),
+                Some(_) => true,
+            };
+            if !keep_existing {
+                doc.insert(name, value);
+            }
+        }
+    }
+}
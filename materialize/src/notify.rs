@@ -0,0 +1,124 @@
+use anyhow::Result;
+use bson::{Bson, Document};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Payload posted by `--notify-webhook`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum NotifyFormat {
+    /// The JSON run summary as printed on stdout
+    Json,
+    /// A Slack incoming-webhook message (`{"text": ...}`) describing it
+    Slack,
+}
+
+impl NotifyFormat {
+    pub fn parse(format: &str) -> Result<Self> {
+        match format {
+            "json" => Ok(NotifyFormat::Json),
+            "slack" => Ok(NotifyFormat::Slack),
+            other => anyhow::bail!(
+                "Unknown --notify-format {:?}; expected json or slack",
+                other
+            ),
+        }
+    }
+
+    fn payload(self, summary: &Document) -> Value {
+        match self {
+            NotifyFormat::Json => Bson::Document(summary.clone()).into_relaxed_extjson(),
+            NotifyFormat::Slack => json!({ "text": slack_text(summary) }),
+        }
+    }
+}
+
+/// Post the run summary to `url`.
+pub fn post(url: &str, format: NotifyFormat, summary: &Document) -> Result<()> {
+    ureq::post(url)
+        .timeout(Duration::from_secs(30))
+        .set("Content-Type", "application/json")
+        .send_string(&format.payload(summary).to_string())?;
+    Ok(())
+}
+
+/// One line for the run's outcome, then one per submission with its counts
+/// and validation warnings.
+fn slack_text(summary: &Document) -> String {
+    let outcome = summary.get_str("outcome").unwrap_or("unknown");
+    let icon = match outcome {
+        "success" => ":white_check_mark:",
+        "partial" => ":warning:",
+        _ => ":x:",
+    };
+    let mut lines = vec![format!(
+        "{} materialize {} in {:.1}s (exit code {})",
+        icon,
+        outcome,
+        summary.get_i64("duration_ms").unwrap_or_default() as f64 / 1000.0,
+        summary.get_i32("exit_code").unwrap_or_default()
+    )];
+    for submission in summary
+        .get_array("submissions")
+        .into_iter()
+        .flatten()
+        .filter_map(Bson::as_document)
+    {
+        let name = submission
+            .get_str("submission")
+            .unwrap_or("all submissions");
+        if let Ok(kind) = submission.get_str("error") {
+            lines.push(format!("• {}: failed ({})", name, kind));
+            continue;
+        }
+        let counts = submission
+            .get_document("counts")
+            .cloned()
+            .unwrap_or_default();
+        let count = |field: &str| counts.get_i64(field).unwrap_or_default();
+        let mut line = format!(
+            "• {}: {} files written, {} rejected",
+            name,
+            count("files_written"),
+            count("rejected")
+        );
+        for warning in warnings(submission) {
+            line.push_str("; ");
+            line.push_str(&warning);
+        }
+        lines.push(line);
+    }
+    if let Ok(message) = summary
+        .get_document("error")
+        .and_then(|error| error.get_str("message"))
+    {
+        lines.push(format!("Error: {}", message));
+    }
+    lines.join("\n")
+}
+
+/// The validation warnings recorded for a finished submission.
+fn warnings(submission: &Document) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let Ok(validation) = submission.get_document("validation") else {
+        return warnings;
+    };
+    let missing_dcc: Vec<&str> = validation
+        .get_array("missing_dcc")
+        .into_iter()
+        .flatten()
+        .filter_map(Bson::as_str)
+        .collect();
+    if !missing_dcc.is_empty() {
+        warnings.push(format!("no dcc for {}", missing_dcc.join(", ")));
+    }
+    if let Ok(unparseable) = validation.get_document("unparseable") {
+        for (field, report) in unparseable {
+            let count = report
+                .as_document()
+                .and_then(|report| report.get_i64("count").ok())
+                .unwrap_or_default();
+            warnings.push(format!("{} unparseable {} values", count, field));
+        }
+    }
+    warnings
+}
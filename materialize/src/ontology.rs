@@ -0,0 +1,184 @@
+use anyhow::{Context, Result};
+use bson::{doc, Bson};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::sync::{Arc, RwLock};
+
+use crate::intern::Interner;
+
+/// OBO Graphs predicate for `part_of`. It is followed in addition to `is_a` so
+/// e.g. a brain subregion rolls up to "brain".
+const PART_OF: &str = "BFO:0000050";
+
+/// A term hierarchy (UBERON, OBI, ...) loaded from an OBO or OBO Graphs JSON
+/// file.
+pub struct Ontology {
+    names: HashMap<Arc<str>, String>,
+    /// Term ids are interned: a broad term is the parent of thousands
+    parents: HashMap<Arc<str>, Vec<Arc<str>>>,
+    cache: RwLock<HashMap<String, Bson>>,
+}
+
+impl Ontology {
+    /// Load an ontology, picking the parser from the file extension
+    /// (`.json` for OBO Graphs JSON, anything else for OBO).
+    pub fn load(path: &str) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("reading {}", path))?;
+        let ontology = if path.ends_with(".json") {
+            Self::from_json(&text).with_context(|| format!("parsing {}", path))?
+        } else {
+            Self::from_obo(&text)
+        };
+        Ok(ontology)
+    }
+
+    /// Build the ontology from `(id, name)` and `(child, parent)` pairs.
+    fn new(names: Vec<(String, String)>, edges: Vec<(String, String)>) -> Self {
+        let mut ids = Interner::default();
+        let names = names
+            .into_iter()
+            .map(|(id, name)| (ids.intern(&id), name))
+            .collect();
+        let mut parents: HashMap<Arc<str>, Vec<Arc<str>>> = HashMap::new();
+        for (child, parent) in edges {
+            let parent = ids.intern(&parent);
+            parents.entry(ids.intern(&child)).or_default().push(parent);
+        }
+        Ontology {
+            names,
+            parents,
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn from_obo(text: &str) -> Self {
+        let mut names = Vec::new();
+        let mut parents = Vec::new();
+        let mut current: Option<String> = None;
+        let mut in_term = false;
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.starts_with('[') {
+                in_term = line == "[Term]";
+                current = None;
+                continue;
+            }
+            if !in_term {
+                continue;
+            }
+            let Some((tag, value)) = line.split_once(": ") else {
+                continue;
+            };
+            // Drop trailing "! label" comments and {qualifiers}
+            let value = value.split(" ! ").next().unwrap_or(value);
+            let value = value.split(" {").next().unwrap_or(value).trim();
+            match (tag, current.as_ref()) {
+                ("id", _) => current = Some(value.to_string()),
+                ("name", Some(id)) => names.push((id.clone(), value.to_string())),
+                ("is_a", Some(id)) => parents.push((id.clone(), value.to_string())),
+                ("relationship", Some(id)) => {
+                    if let Some(("part_of", parent)) = value.split_once(' ') {
+                        parents.push((id.clone(), parent.to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Self::new(names, parents)
+    }
+
+    fn from_json(text: &str) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct GraphDocument {
+            graphs: Vec<Graph>,
+        }
+        #[derive(Deserialize)]
+        struct Graph {
+            #[serde(default)]
+            nodes: Vec<Node>,
+            #[serde(default)]
+            edges: Vec<Edge>,
+        }
+        #[derive(Deserialize)]
+        struct Node {
+            id: String,
+            lbl: Option<String>,
+        }
+        #[derive(Deserialize)]
+        struct Edge {
+            sub: String,
+            pred: String,
+            obj: String,
+        }
+
+        let graphs: GraphDocument = serde_json::from_str(text)?;
+        let mut names = Vec::new();
+        let mut parents = Vec::new();
+        for graph in graphs.graphs {
+            for node in graph.nodes {
+                if let Some(lbl) = node.lbl {
+                    names.push((compact_id(&node.id), lbl));
+                }
+            }
+            for edge in graph.edges {
+                let pred = compact_id(&edge.pred);
+                if pred == "is_a" || pred == PART_OF || pred == "part_of" {
+                    parents.push((compact_id(&edge.sub), compact_id(&edge.obj)));
+                }
+            }
+        }
+        Ok(Self::new(names, parents))
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// All transitive ancestors of `id` as `[{id, name}]`, nearest first.
+    /// The term itself is not included.
+    pub fn ancestors(&self, id: &str) -> Bson {
+        if let Some(cached) = self.cache.read().unwrap().get(id) {
+            return cached.clone();
+        }
+
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut queue: VecDeque<&str> = VecDeque::new();
+        let mut ancestors = Vec::new();
+        queue.push_back(id);
+        while let Some(term) = queue.pop_front() {
+            for parent in self.parents.get(term).into_iter().flatten() {
+                let parent: &str = parent;
+                if parent != id && seen.insert(parent) {
+                    let mut entry = doc! { "id": parent };
+                    if let Some(name) = self.names.get(parent) {
+                        entry.insert("name", name);
+                    }
+                    ancestors.push(Bson::Document(entry));
+                    queue.push_back(parent);
+                }
+            }
+        }
+
+        let ancestors = Bson::Array(ancestors);
+        self.cache
+            .write()
+            .unwrap()
+            .insert(id.to_string(), ancestors.clone());
+        ancestors
+    }
+}
+
+/// Turn an OBO PURL (`http://purl.obolibrary.org/obo/UBERON_0000955`) into
+/// the CURIE form used by C2M2 (`UBERON:0000955`).
+fn compact_id(id: &str) -> String {
+    match id.strip_prefix("http://purl.obolibrary.org/obo/") {
+        Some(rest) => rest.replacen('_', ":", 1),
+        None => id.to_string(),
+    }
+}
@@ -0,0 +1,248 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Map, Value};
+use std::fs;
+
+use crate::access::LEVELS;
+use crate::change::HASH_FIELD;
+use crate::cli::{flag, value};
+use crate::enrich::{AGE_FIELD, MAX_AGE_FIELD, MIN_AGE_FIELD, PROVENANCE_FIELD};
+use crate::enrichers::CATEGORY_FIELD;
+use crate::flatten::{INTEGER_COLUMNS, STRING_COLUMNS};
+use crate::migrate::{SCHEMA_VERSION, VERSION_FIELD};
+use crate::spec::{EnrichmentSpec, Entity};
+
+/// Identifier of the schema dialect OpenAPI 3.1 shares with JSON Schema.
+const DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Raw C2M2 columns embedded as-is on each nested entity, besides its terms.
+const COLLECTION_COLUMNS: [&str; 8] = [
+    "id_namespace",
+    "local_id",
+    "persistent_id",
+    "creation_time",
+    "abbreviation",
+    "name",
+    "description",
+    "has_time_series_data",
+];
+const PROJECT_COLUMNS: [&str; 7] = [
+    "id_namespace",
+    "local_id",
+    "persistent_id",
+    "creation_time",
+    "abbreviation",
+    "name",
+    "description",
+];
+const BIOSAMPLE_COLUMNS: [&str; 6] = [
+    "id_namespace",
+    "local_id",
+    "project_id_namespace",
+    "project_local_id",
+    "persistent_id",
+    "creation_time",
+];
+const SUBJECT_COLUMNS: [&str; 7] = [
+    "id_namespace",
+    "local_id",
+    "project_id_namespace",
+    "project_local_id",
+    "persistent_id",
+    "creation_time",
+    "age_at_enrollment",
+];
+
+/// `schema [--openapi] [--out FILE]` prints the JSON Schema of a
+/// materialized `files` document, or with `--openapi` the same definitions
+/// as an OpenAPI 3.1 `components.schemas` block, for generating API
+/// clients. Terms follow the configured `[[enrichment.terms]]`.
+pub fn command(spec: &EnrichmentSpec, args: &[String]) -> Result<()> {
+    let schema = if flag(args, "--openapi") {
+        openapi_components(spec)
+    } else {
+        json_schema(spec)
+    };
+    let text = serde_json::to_string_pretty(&schema)?;
+    match value(args, "--out") {
+        Some(path) => {
+            fs::write(&path, text + "\n").with_context(|| format!("writing {}", path))?;
+            println!("Wrote the files document schema to {}", path);
+        }
+        None => println!("{}", text),
+    }
+    Ok(())
+}
+
+/// The `File` schema with every nested definition under `$defs`.
+pub fn json_schema(spec: &EnrichmentSpec) -> Value {
+    json!({
+        "$schema": DIALECT,
+        "$id": format!("urn:materialize:files:v{}", SCHEMA_VERSION),
+        "$ref": "#/$defs/File",
+        "$defs": definitions(spec, "#/$defs/"),
+    })
+}
+
+/// The definitions as an OpenAPI 3.1 document with no paths, to merge into
+/// an API's own description.
+pub fn openapi_components(spec: &EnrichmentSpec) -> Value {
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "Materialized files",
+            "version": format!("{}", SCHEMA_VERSION),
+        },
+        "jsonSchemaDialect": DIALECT,
+        "paths": {},
+        "components": { "schemas": definitions(spec, "#/components/schemas/") },
+    })
+}
+
+/// Each document shape found in an enriched file, keyed by name, with
+/// references resolved under `prefix`. Objects allow properties beyond the
+/// listed ones: raw C2M2 columns a submission adds and enrichers' fields
+/// pass through unchanged.
+fn definitions(spec: &EnrichmentSpec, prefix: &str) -> Map<String, Value> {
+    let reference = |name: &str| json!({ "$ref": format!("{}{}", prefix, name) });
+    let string = json!({ "type": "string" });
+    let date = json!({ "type": "string", "format": "date-time" });
+    let age = json!({ "type": "number", "description": "Years" });
+    let array = |items: Value| json!({ "type": "array", "items": items });
+    // A term whose lookup missed keeps its raw id under `--on-miss keep-id`
+    let term = |ontology: bool| {
+        json!({
+            "anyOf": [
+                reference(if ontology { "OntologyTerm" } else { "Term" }),
+                { "type": "string", "description": "Unresolved term id" },
+            ],
+        })
+    };
+    let columns = |names: &[&str]| -> Map<String, Value> {
+        names
+            .iter()
+            .map(|name| (name.to_string(), string.clone()))
+            .collect()
+    };
+    let terms = |properties: &mut Map<String, Value>, entity: Entity| {
+        for t in spec.terms_for(entity) {
+            properties.insert(t.field.clone(), term(t.ontology.is_some()));
+        }
+    };
+    let object = |properties: Map<String, Value>, required: &[&str]| {
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+            "additionalProperties": true,
+        })
+    };
+    let key: &[&str] = &["id_namespace", "local_id"];
+    // With `--provenance`, on every embedded row
+    let provenance = reference("Provenance");
+
+    let mut subject = columns(&SUBJECT_COLUMNS);
+    terms(&mut subject, Entity::Subject);
+    subject.insert("race".to_string(), array(reference("Term")));
+    subject.insert(AGE_FIELD.to_string(), age.clone());
+    subject.insert(PROVENANCE_FIELD.to_string(), provenance.clone());
+
+    let mut biosample = columns(&BIOSAMPLE_COLUMNS);
+    terms(&mut biosample, Entity::Biosample);
+    biosample.insert("substances".to_string(), array(reference("Term")));
+    biosample.insert("genes".to_string(), array(reference("Term")));
+    biosample.insert("subjects".to_string(), array(reference("Subject")));
+    biosample.insert(AGE_FIELD.to_string(), age.clone());
+    biosample.insert(PROVENANCE_FIELD.to_string(), provenance.clone());
+
+    let mut collection = columns(&COLLECTION_COLUMNS);
+    terms(&mut collection, Entity::Collection);
+    collection.insert("anatomies".to_string(), array(reference("OntologyTerm")));
+    collection.insert("diseases".to_string(), array(reference("Term")));
+    collection.insert(
+        "defined_by_project".to_string(),
+        array(reference("Project")),
+    );
+    collection.insert("biosamples".to_string(), array(reference("Biosample")));
+    // With `--hoist-biosamples`, in place of `biosamples`
+    collection.insert(
+        "biosample_refs".to_string(),
+        array(object(columns(key), key)),
+    );
+    collection.insert(PROVENANCE_FIELD.to_string(), provenance.clone());
+
+    let mut project = columns(&PROJECT_COLUMNS);
+    project.insert(PROVENANCE_FIELD.to_string(), provenance.clone());
+
+    let mut file = columns(&STRING_COLUMNS);
+    for name in INTEGER_COLUMNS {
+        file.insert(
+            name.to_string(),
+            json!({ "type": "integer", "format": "int64" }),
+        );
+    }
+    file.insert("creation_time".to_string(), date.clone());
+    terms(&mut file, Entity::File);
+    file.insert("dcc".to_string(), reference("Dcc"));
+    file.insert("collections".to_string(), array(reference("Collection")));
+    file.insert("biosamples".to_string(), array(reference("Biosample")));
+    file.insert("mime_type_inferred".to_string(), string.clone());
+    file.insert(CATEGORY_FIELD.to_string(), array(string.clone()));
+    file.insert(
+        "derived_from".to_string(),
+        array(object(
+            columns(&["id_namespace", "local_id", "filename"]),
+            key,
+        )),
+    );
+    file.insert(MIN_AGE_FIELD.to_string(), age.clone());
+    file.insert(MAX_AGE_FIELD.to_string(), age);
+    file.insert("access".to_string(), reference("Access"));
+    file.insert("search_text".to_string(), string.clone());
+    file.insert(
+        VERSION_FIELD.to_string(),
+        json!({ "type": "integer", "maximum": SCHEMA_VERSION }),
+    );
+    file.insert("materialized_at".to_string(), date.clone());
+    file.insert(HASH_FIELD.to_string(), string.clone());
+
+    let mut term_properties = columns(&["id", "name", "description", "synonyms"]);
+    term_properties.insert(PROVENANCE_FIELD.to_string(), provenance.clone());
+    let plain_term = object(term_properties.clone(), &["id"]);
+    term_properties.insert(
+        "ancestors".to_string(),
+        array(object(columns(&["id", "name"]), &["id"])),
+    );
+    let ontology_term = object(term_properties, &["id"]);
+
+    let mut dcc = columns(&["id", "dcc_name", "dcc_abbreviation"]);
+    dcc.insert(
+        "placeholder".to_string(),
+        json!({ "type": "boolean", "description": "No DCC row matched the submission" }),
+    );
+    dcc.insert(PROVENANCE_FIELD.to_string(), provenance.clone());
+
+    let mut source = columns(&["table", "submission"]);
+    source.insert("loaded_at".to_string(), date);
+
+    let mut access = columns(&["embargo_until", "dbgap_study_id", "url"]);
+    access.insert(
+        "level".to_string(),
+        json!({ "type": "string", "enum": LEVELS }),
+    );
+
+    let mut defs = Map::new();
+    defs.insert(
+        "File".to_string(),
+        object(file, &["submission", "id_namespace", "local_id"]),
+    );
+    defs.insert("Collection".to_string(), object(collection, key));
+    defs.insert("Project".to_string(), object(project, key));
+    defs.insert("Biosample".to_string(), object(biosample, key));
+    defs.insert("Subject".to_string(), object(subject, key));
+    defs.insert("Term".to_string(), plain_term);
+    defs.insert("OntologyTerm".to_string(), ontology_term);
+    defs.insert("Dcc".to_string(), object(dcc, &[]));
+    defs.insert("Access".to_string(), object(access, &["level"]));
+    defs.insert("Provenance".to_string(), object(source, &["table"]));
+    defs
+}
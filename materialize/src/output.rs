@@ -0,0 +1,252 @@
+use anyhow::Result;
+use bson::{doc, Document};
+use mongodb::error::ErrorKind;
+use mongodb::sync::{ClientSession, Collection};
+#[cfg(any(feature = "parquet", feature = "arrow"))]
+use std::path::PathBuf;
+
+use crate::error::MaterializeError;
+
+/// Where enriched files are written, from `--output` (or its alias `--sink`).
+pub enum Output {
+    /// The `files` collection (default)
+    Mongo,
+    /// Parquet files partitioned by submission under a directory
+    #[cfg(feature = "parquet")]
+    Parquet(PathBuf),
+    /// One Arrow IPC stream written to a file or named pipe
+    #[cfg(feature = "arrow")]
+    ArrowIpc(PathBuf),
+    /// A Postgres `files` table of JSONB documents, by connection URI
+    #[cfg(feature = "postgres")]
+    Postgres(String),
+}
+
+impl Output {
+    /// `uri` is the `--uri` given alongside `postgres`.
+    pub fn parse(spec: Option<&str>, uri: Option<&str>) -> Result<Self> {
+        let Some(spec) = spec else {
+            return Ok(Output::Mongo);
+        };
+        match spec.split_once(':') {
+            None if spec == "mongo" => Ok(Output::Mongo),
+            #[cfg(feature = "postgres")]
+            None if spec == "postgres" => match uri {
+                Some(uri) => Ok(Output::Postgres(uri.to_string())),
+                None => anyhow::bail!("--output postgres requires --uri <connection-uri>"),
+            },
+            #[cfg(feature = "postgres")]
+            Some(("postgres" | "postgresql", _)) => Ok(Output::Postgres(spec.to_string())),
+            #[cfg(not(feature = "postgres"))]
+            _ if spec.starts_with("postgres") => {
+                let _ = uri;
+                anyhow::bail!("--output postgres requires building with `--features postgres`")
+            }
+            #[cfg(feature = "parquet")]
+            Some(("parquet", dir)) if !dir.is_empty() => Ok(Output::Parquet(PathBuf::from(dir))),
+            #[cfg(not(feature = "parquet"))]
+            Some(("parquet", _)) => {
+                anyhow::bail!("--output parquet requires building with `--features parquet`")
+            }
+            #[cfg(feature = "arrow")]
+            Some(("arrow-ipc", path)) if !path.is_empty() => {
+                Ok(Output::ArrowIpc(PathBuf::from(path)))
+            }
+            #[cfg(not(feature = "arrow"))]
+            Some(("arrow-ipc", _)) => {
+                anyhow::bail!("--output arrow-ipc requires building with `--features arrow`")
+            }
+            _ => anyhow::bail!(
+                "Unknown --output {:?}; expected mongo, parquet:<dir>, arrow-ipc:<path>, or postgres",
+                spec
+            ),
+        }
+    }
+}
+
+/// Destination for batches of enriched files.
+pub enum FileSink<'a> {
+    /// `files` and `file_relations`, written inside `session`'s transaction
+    /// when one was started
+    Mongo {
+        files: &'a Collection<Document>,
+        relations: &'a Collection<Document>,
+        session: Option<Box<ClientSession>>,
+    },
+    #[cfg(feature = "parquet")]
+    Parquet(crate::parquet::ParquetExport),
+    #[cfg(feature = "arrow")]
+    ArrowIpc(crate::ipc::ArrowIpcExport),
+    #[cfg(feature = "postgres")]
+    Postgres(Box<crate::postgres::PostgresSink>),
+}
+
+impl FileSink<'_> {
+    /// Delete a submission's existing files and relations.
+    pub fn delete_submission(&mut self, submission: &str) -> Result<u64, MaterializeError> {
+        self.delete(submission)
+            .map_err(MaterializeError::WriteFailure)
+    }
+
+    fn delete(&mut self, submission: &str) -> Result<u64> {
+        let filter = doc! { "submission": submission };
+        match self {
+            FileSink::Mongo {
+                files,
+                relations,
+                session: Some(session),
+            } => {
+                relations
+                    .delete_many(filter.clone())
+                    .session(session.as_mut())
+                    .run()?;
+                Ok(files
+                    .delete_many(filter)
+                    .session(session.as_mut())
+                    .run()?
+                    .deleted_count)
+            }
+            FileSink::Mongo {
+                files, relations, ..
+            } => {
+                relations.delete_many(filter.clone()).run()?;
+                Ok(files.delete_many(filter).run()?.deleted_count)
+            }
+            #[cfg(any(feature = "parquet", feature = "arrow", feature = "postgres"))]
+            _ => Ok(0),
+        }
+    }
+
+    /// Write a batch, returning the files that could not be written (their
+    /// key and error).
+    ///
+    /// Outside a transaction, MongoDB inserts are unordered so one bad
+    /// document doesn't stop the rest; each document the server rejected is
+    /// retried on its own before it is given up on. In a transaction any
+    /// error aborts the transaction, so the batch fails as a whole.
+    pub fn write(&mut self, batch: &[Document]) -> Result<Vec<Document>, MaterializeError> {
+        self.insert(batch).map_err(MaterializeError::WriteFailure)
+    }
+
+    fn insert(&mut self, batch: &[Document]) -> Result<Vec<Document>> {
+        match self {
+            FileSink::Mongo { files, session, .. } => match session {
+                Some(session) => {
+                    files.insert_many(batch).session(session.as_mut()).run()?;
+                }
+                None => return insert_unordered(files, batch),
+            },
+            #[cfg(feature = "parquet")]
+            FileSink::Parquet(export) => export.write(batch)?,
+            #[cfg(feature = "arrow")]
+            FileSink::ArrowIpc(export) => export.write(batch)?,
+            #[cfg(feature = "postgres")]
+            FileSink::Postgres(sink) => sink.write(batch)?,
+        }
+        Ok(Vec::new())
+    }
+
+    /// Write the edges of documents that exceeded the size budget.
+    pub fn write_relations(&mut self, edges: &[Document]) -> Result<(), MaterializeError> {
+        self.insert_relations(edges)
+            .map_err(MaterializeError::WriteFailure)
+    }
+
+    fn insert_relations(&mut self, edges: &[Document]) -> Result<()> {
+        match self {
+            FileSink::Mongo {
+                relations,
+                session: Some(session),
+                ..
+            } => {
+                relations
+                    .insert_many(edges)
+                    .session(session.as_mut())
+                    .run()?;
+            }
+            FileSink::Mongo { relations, .. } => {
+                relations.insert_many(edges).run()?;
+            }
+            #[cfg(any(feature = "parquet", feature = "arrow", feature = "postgres"))]
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        matches!(
+            self,
+            FileSink::Mongo {
+                session: Some(_),
+                ..
+            }
+        )
+    }
+
+    /// Roll back everything written in the transaction, if there is one.
+    pub fn abort(self) -> Result<(), MaterializeError> {
+        if let FileSink::Mongo {
+            session: Some(mut session),
+            ..
+        } = self
+        {
+            session.abort_transaction().run()?;
+        }
+        Ok(())
+    }
+
+    /// Flush buffered output; commits the transaction, if there is one.
+    pub fn finish(self) -> Result<(), MaterializeError> {
+        self.close().map_err(MaterializeError::WriteFailure)
+    }
+
+    fn close(self) -> Result<()> {
+        match self {
+            FileSink::Mongo { session, .. } => {
+                if let Some(mut session) = session {
+                    session.commit_transaction().run()?;
+                }
+                Ok(())
+            }
+            #[cfg(feature = "parquet")]
+            FileSink::Parquet(export) => export.finish(),
+            #[cfg(feature = "arrow")]
+            FileSink::ArrowIpc(export) => export.finish(),
+            #[cfg(feature = "postgres")]
+            FileSink::Postgres(sink) => sink.finish(),
+        }
+    }
+}
+
+fn insert_unordered(files: &Collection<Document>, batch: &[Document]) -> Result<Vec<Document>> {
+    let error = match files.insert_many(batch).ordered(false).run() {
+        Ok(_) => return Ok(Vec::new()),
+        Err(error) => error,
+    };
+    let failed: Vec<usize> = match *error.kind {
+        ErrorKind::InsertMany(ref failure) if failure.write_concern_error.is_none() => failure
+            .write_errors
+            .iter()
+            .flatten()
+            .map(|e| e.index)
+            .collect(),
+        _ => return Err(error.into()),
+    };
+    let mut rejected = Vec::new();
+    for file in failed.into_iter().filter_map(|i| batch.get(i)) {
+        if let Err(e) = files.insert_one(file).run() {
+            let id_namespace = file.get_str("id_namespace").unwrap_or_default();
+            let local_id = file.get_str("local_id").unwrap_or_default();
+            println!(
+                "  Warning: could not write file ({}, {}): {}",
+                id_namespace, local_id, e
+            );
+            rejected.push(doc! {
+                "id_namespace": id_namespace,
+                "local_id": local_id,
+                "error": e.to_string(),
+            });
+        }
+    }
+    Ok(rejected)
+}
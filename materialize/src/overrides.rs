@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use bson::Document;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+
+use crate::ingest::hex;
+
+/// Vocabulary tables mapped to files of corrected terms (`[overrides]`),
+/// e.g. `anatomy = "overrides/anatomy.tsv"`. A term in the file replaces
+/// the fields it sets on every submission's row with the same id, so a
+/// known-bad label is fixed once, centrally, without editing submissions.
+#[derive(Default, Deserialize)]
+#[serde(transparent)]
+pub struct OverrideConfig {
+    pub files: HashMap<String, String>,
+}
+
+impl OverrideConfig {
+    /// The overrides configured for `table`, if any.
+    pub fn load(&self, table: &str) -> Result<Option<TermOverrides>> {
+        self.files
+            .get(table)
+            .map(|path| TermOverrides::load(path))
+            .transpose()
+    }
+}
+
+/// Corrected fields by term id, read from a TSV with an `id` column and a
+/// column per field (blank cells leave the field alone), or from an OBO
+/// file, whose `name`, `def`, and `synonym` tags override `name`,
+/// `description`, and `synonyms`.
+pub struct TermOverrides {
+    pub path: String,
+    terms: HashMap<String, Document>,
+    digest: String,
+}
+
+impl TermOverrides {
+    pub fn load(path: &str) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("reading {}", path))?;
+        let terms = if path.ends_with(".obo") {
+            from_obo(&text)
+        } else {
+            from_tsv(&text).with_context(|| format!("parsing {}", path))?
+        };
+        Ok(TermOverrides {
+            path: path.to_string(),
+            terms,
+            digest: hex(&Sha256::digest(text.as_bytes())),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Overwrite `term`'s fields with the overrides for `id`. Returns
+    /// whether there were any.
+    pub fn apply(&self, id: &str, term: &mut Document) -> bool {
+        let Some(fields) = self.terms.get(id) else {
+            return false;
+        };
+        term.extend(fields.clone());
+        true
+    }
+
+    /// `fingerprint` extended with the file's content, so a cached lookup
+    /// map is rebuilt when the overrides change.
+    pub fn fingerprint(&self, fingerprint: Option<String>) -> Option<String> {
+        fingerprint.map(|f| format!("{}|{}", f, self.digest))
+    }
+}
+
+fn from_tsv(text: &str) -> Result<HashMap<String, Document>> {
+    let mut tsv = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let headers = tsv.headers()?.clone();
+    if !headers.iter().any(|h| h == "id") {
+        anyhow::bail!("no id column");
+    }
+    let mut terms = HashMap::new();
+    for record in tsv.records() {
+        let record = record?;
+        let mut id = None;
+        let mut fields = Document::new();
+        for (field, value) in headers.iter().zip(record.iter()) {
+            match field {
+                "id" => id = Some(value.to_string()),
+                _ if value.is_empty() => {}
+                _ => {
+                    fields.insert(field, value);
+                }
+            }
+        }
+        if let Some(id) = id.filter(|id| !id.is_empty()) {
+            terms.insert(id, fields);
+        }
+    }
+    Ok(terms)
+}
+
+/// Synonyms are joined with `|`, as in a C2M2 `synonyms` column.
+fn from_obo(text: &str) -> HashMap<String, Document> {
+    let mut terms = HashMap::new();
+    let mut current: Option<(String, Document, Vec<String>)> = None;
+    let mut finish = |current: Option<(String, Document, Vec<String>)>| {
+        if let Some((id, mut fields, synonyms)) = current {
+            if !synonyms.is_empty() {
+                fields.insert("synonyms", synonyms.join("|"));
+            }
+            terms.insert(id, fields);
+        }
+    };
+
+    let mut in_term = false;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            finish(current.take());
+            in_term = line == "[Term]";
+            continue;
+        }
+        if !in_term {
+            continue;
+        }
+        let Some((tag, value)) = line.split_once(": ") else {
+            continue;
+        };
+        match (tag, current.as_mut()) {
+            ("id", _) => {
+                finish(current.take());
+                current = Some((value.trim().to_string(), Document::new(), Vec::new()));
+            }
+            ("name", Some((_, fields, _))) => {
+                fields.insert("name", value.trim());
+            }
+            ("def", Some((_, fields, _))) => {
+                if let Some(def) = quoted(value) {
+                    fields.insert("description", def);
+                }
+            }
+            ("synonym", Some((_, _, synonyms))) => {
+                synonyms.extend(quoted(value).map(str::to_string));
+            }
+            _ => {}
+        }
+    }
+    finish(current);
+    terms
+}
+
+/// The leading quoted string of an OBO tag value, e.g. the text of
+/// `"A part of the brain." [UBERON:cjm]`.
+fn quoted(value: &str) -> Option<&str> {
+    let rest = value.trim().strip_prefix('"')?;
+    let mut escaped = false;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return Some(&rest[..i]),
+            _ => escaped = false,
+        }
+    }
+    None
+}
@@ -0,0 +1,77 @@
+use anyhow::{Context, Result};
+use arrow_schema::SchemaRef;
+use bson::Document;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::record_batch::{record_batch, schema};
+
+/// Writes enriched files as Parquet, one `submission=<id>/files.parquet`
+/// partition per submission, with embedded terms flattened into columns and
+/// collection/biosample values as list columns.
+pub struct ParquetExport {
+    dir: PathBuf,
+    schema: SchemaRef,
+    writers: HashMap<String, ArrowWriter<File>>,
+}
+
+impl ParquetExport {
+    pub fn create(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        Ok(ParquetExport {
+            dir: dir.to_path_buf(),
+            schema: Arc::new(schema()),
+            writers: HashMap::new(),
+        })
+    }
+
+    pub fn write(&mut self, batch: &[Document]) -> Result<()> {
+        let mut by_submission: HashMap<&str, Vec<&Document>> = HashMap::new();
+        for file in batch {
+            let submission = file.get_str("submission").unwrap_or_default();
+            by_submission.entry(submission).or_default().push(file);
+        }
+        for (submission, files) in by_submission {
+            let records = record_batch(&self.schema, &files)?;
+            if !self.writers.contains_key(submission) {
+                let writer = self.open_partition(submission)?;
+                self.writers.insert(submission.to_string(), writer);
+            }
+            self.writers.get_mut(submission).unwrap().write(&records)?;
+        }
+        Ok(())
+    }
+
+    fn open_partition(&self, submission: &str) -> Result<ArrowWriter<File>> {
+        let dir = self.dir.join(format!("submission={}", submission));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("files.parquet");
+        let file = File::create(&path).with_context(|| format!("creating {}", path.display()))?;
+        let props = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        Ok(ArrowWriter::try_new(
+            file,
+            self.schema.clone(),
+            Some(props),
+        )?)
+    }
+
+    pub fn finish(self) -> Result<()> {
+        let partitions = self.writers.len();
+        for (_, writer) in self.writers {
+            writer.close()?;
+        }
+        println!(
+            "  Wrote {} Parquet partitions to {}",
+            partitions,
+            self.dir.display()
+        );
+        Ok(())
+    }
+}
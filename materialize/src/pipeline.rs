@@ -0,0 +1,169 @@
+use anyhow::Result;
+use bson::{doc, Bson, Document};
+use indicatif::ProgressBar;
+use mongodb::options::ClientOptions;
+use rayon::prelude::*;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use tokio::sync::mpsc as async_mpsc;
+
+use crate::checkpoint;
+use crate::connection::DATABASE;
+use crate::cursor::{is_cursor_gone, report_reopen, resume_query, MAX_REOPENS};
+use crate::dashboard;
+use crate::enrich::{enrich_file, Trace};
+use crate::lookup::LookupContext;
+use crate::throttle::RateLimit;
+
+/// Chunks buffered between stages; bounds memory to a few chunks in flight.
+const DEPTH: usize = 4;
+
+/// Files each rayon task enriches between progress bar updates, so workers
+/// don't contend on the bar for every file.
+const PROGRESS_CHUNK: usize = 256;
+
+/// Enrich `files` in place on the rayon pool, advancing the progress bar
+/// once per chunk.
+///
+/// Once shutdown is requested, the remaining files are passed through
+/// untouched; they are discarded before anything is written.
+pub fn enrich_all(
+    mut files: Vec<Document>,
+    ctx: &LookupContext,
+    pb: &ProgressBar,
+) -> Vec<Document> {
+    files.par_chunks_mut(PROGRESS_CHUNK).for_each(|chunk| {
+        let mut enriched = 0;
+        for file in chunk.iter_mut() {
+            if checkpoint::requested() {
+                break;
+            }
+            *file = enrich_file(std::mem::take(file), ctx, &mut Trace::disabled());
+            enriched += 1;
+        }
+        dashboard::advance(pb, enriched, &ctx.misses);
+    });
+    files
+}
+
+/// Stream the files matching `queries`, one after another, through
+/// enrichment while the caller writes, so reading, enriching, and writing
+/// overlap.
+///
+/// A tokio task reads `file` from `source` with the async driver in chunks of
+/// `chunk_size`; an enrichment thread fans each chunk out over the rayon
+/// pool; the returned iterator yields the enriched files in chunk order.
+/// Stages are joined by bounded channels, so a slow writer stalls the reader
+/// instead of buffering the whole submission. Dropping the iterator stops
+/// both stages. With a `read_limit`, each chunk waits for its slot before
+/// it is handed on.
+pub fn stream(
+    source: ClientOptions,
+    queries: Vec<Document>,
+    find_batch_size: u32,
+    chunk_size: usize,
+    read_limit: Option<Arc<RateLimit>>,
+    ctx: Arc<LookupContext<'static>>,
+    pb: ProgressBar,
+) -> Result<Box<dyn Iterator<Item = Result<Document>>>> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let client = runtime.block_on(async { mongodb::Client::with_options(source) })?;
+
+    let (raw_tx, mut raw_rx) = async_mpsc::channel::<Result<Vec<Document>>>(DEPTH);
+    thread::spawn(move || {
+        runtime.block_on(async move {
+            let limit = read_limit.as_deref();
+            if let Err(e) = fetch(
+                &client,
+                queries,
+                find_batch_size,
+                chunk_size,
+                limit,
+                &raw_tx,
+            )
+            .await
+            {
+                let _ = raw_tx.send(Err(e)).await;
+            }
+        })
+    });
+
+    let (tx, rx) = mpsc::sync_channel::<Result<Vec<Document>>>(DEPTH);
+    thread::spawn(move || {
+        while let Some(chunk) = raw_rx.blocking_recv() {
+            let enriched = chunk.map(|files| enrich_all(files, &ctx, &pb));
+            if tx.send(enriched).is_err() {
+                break;
+            }
+        }
+        pb.finish_with_message("Processing complete");
+    });
+
+    Ok(Box::new(rx.into_iter().flat_map(
+        |chunk| -> Box<dyn Iterator<Item = Result<Document>>> {
+            match chunk {
+                Ok(files) => Box::new(files.into_iter().map(Ok)),
+                Err(e) => Box::new(std::iter::once(Err(e))),
+            }
+        },
+    )))
+}
+
+async fn fetch(
+    client: &mongodb::Client,
+    queries: Vec<Document>,
+    find_batch_size: u32,
+    chunk_size: usize,
+    read_limit: Option<&RateLimit>,
+    tx: &async_mpsc::Sender<Result<Vec<Document>>>,
+) -> Result<()> {
+    // Read in `_id` order so a cursor that times out while the writer
+    // holds it idle can be reopened where it stopped
+    let coll = client.database(DATABASE).collection::<Document>("file");
+    let mut chunk = Vec::with_capacity(chunk_size);
+    for query in queries {
+        let open = |last_id: Option<&Bson>| {
+            coll.find(resume_query(&query, last_id))
+                .sort(doc! { "_id": 1 })
+                .batch_size(find_batch_size)
+        };
+        let mut cursor = open(None).await?;
+        let mut last_id: Option<Bson> = None;
+        let mut reopens = 0;
+        loop {
+            match cursor.advance().await {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) if is_cursor_gone(&e) && reopens < MAX_REOPENS => {
+                    reopens += 1;
+                    report_reopen(coll.name(), reopens);
+                    cursor = open(last_id.as_ref()).await?;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            }
+            let file: Document = cursor.deserialize_current()?;
+            last_id = file.get("_id").cloned();
+            chunk.push(file);
+            if chunk.len() >= chunk_size {
+                let full = std::mem::replace(&mut chunk, Vec::with_capacity(chunk_size));
+                if let Some(limit) = read_limit {
+                    tokio::time::sleep(limit.delay(full.len())).await;
+                }
+                if tx.send(Ok(full)).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    if !chunk.is_empty() {
+        if let Some(limit) = read_limit {
+            tokio::time::sleep(limit.delay(chunk.len())).await;
+        }
+        let _ = tx.send(Ok(chunk)).await;
+    }
+    Ok(())
+}
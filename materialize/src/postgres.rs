@@ -0,0 +1,72 @@
+use anyhow::{Context, Result};
+use bson::{Bson, Document};
+use postgres::types::Json;
+use postgres::{Client, NoTls};
+use serde_json::Value;
+
+/// Writes enriched files to a Postgres `files` table, one JSONB document per
+/// row alongside its submission and key, for deployments that serve from
+/// Postgres instead of MongoDB.
+pub struct PostgresSink {
+    client: Client,
+}
+
+impl PostgresSink {
+    /// Connect, create the table if needed, and remove the rows being
+    /// replaced (the submission's, or all of them).
+    pub fn create(uri: &str, submission: &Option<String>) -> Result<Self> {
+        let mut client = Client::connect(uri, NoTls).context("connecting to Postgres")?;
+        client.batch_execute(
+            "CREATE TABLE IF NOT EXISTS files (
+                 submission TEXT NOT NULL,
+                 id_namespace TEXT,
+                 local_id TEXT,
+                 doc JSONB NOT NULL
+             )",
+        )?;
+        match submission {
+            Some(sub) => {
+                let deleted = client.execute("DELETE FROM files WHERE submission = $1", &[sub])?;
+                println!("  Deleted {} existing {} rows", deleted, sub);
+            }
+            None => {
+                client.batch_execute("TRUNCATE files")?;
+                println!("  Truncated existing table");
+            }
+        }
+        Ok(PostgresSink { client })
+    }
+
+    pub fn write(&mut self, batch: &[Document]) -> Result<()> {
+        let mut submissions: Vec<&str> = Vec::with_capacity(batch.len());
+        let mut namespaces: Vec<Option<&str>> = Vec::with_capacity(batch.len());
+        let mut local_ids: Vec<Option<&str>> = Vec::with_capacity(batch.len());
+        let mut docs: Vec<Json<Value>> = Vec::with_capacity(batch.len());
+        for file in batch {
+            submissions.push(file.get_str("submission").unwrap_or_default());
+            namespaces.push(file.get_str("id_namespace").ok());
+            local_ids.push(file.get_str("local_id").ok());
+            let mut file = file.clone();
+            file.remove("_id");
+            docs.push(Json(Bson::Document(file).into_relaxed_extjson()));
+        }
+        self.client.execute(
+            "INSERT INTO files (submission, id_namespace, local_id, doc)
+             SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::jsonb[])",
+            &[&submissions, &namespaces, &local_ids, &docs],
+        )?;
+        Ok(())
+    }
+
+    /// Create the indexes once the rows are loaded.
+    pub fn finish(mut self) -> Result<()> {
+        self.client.batch_execute(
+            "CREATE INDEX IF NOT EXISTS files_submission ON files (submission);
+             CREATE INDEX IF NOT EXISTS files_key ON files (id_namespace, local_id);
+             CREATE INDEX IF NOT EXISTS files_doc ON files USING GIN (doc jsonb_path_ops);
+             ANALYZE files;",
+        )?;
+        println!("  Created Postgres indexes");
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use bson::{Bson, Document};
+use serde::Deserialize;
+
+/// Maps a file's `mime_type`/`file_format` to the kind of inline previewer
+/// the portal should offer. Rules are tried in order; the first match wins.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PreviewConfig {
+    /// Value used when no rule matches
+    pub default: String,
+    pub rules: Vec<PreviewRule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PreviewRule {
+    /// Preview kind assigned on match (e.g. `image`, `table`, `sequence`)
+    pub preview: String,
+    /// MIME types to match; `type/*` matches a whole top-level type
+    #[serde(default)]
+    pub mime_types: Vec<String>,
+    /// EDAM `file_format` ids to match
+    #[serde(default)]
+    pub file_formats: Vec<String>,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        let rule = |preview: &str, mime_types: &[&str], file_formats: &[&str]| PreviewRule {
+            preview: preview.to_string(),
+            mime_types: mime_types.iter().map(|s| s.to_string()).collect(),
+            file_formats: file_formats.iter().map(|s| s.to_string()).collect(),
+        };
+        PreviewConfig {
+            default: "none".to_string(),
+            rules: vec![
+                rule(
+                    "image",
+                    &["image/*"],
+                    &["format:3547", "format:3603", "format:3579", "format:3591"],
+                ),
+                rule(
+                    "table",
+                    &["text/csv", "text/tab-separated-values"],
+                    &["format:3475", "format:3752", "format:3620"],
+                ),
+                rule(
+                    "sequence",
+                    &["text/x-fasta", "text/x-fastq"],
+                    &["format:1929", "format:1930", "format:1931", "format:1932"],
+                ),
+            ],
+        }
+    }
+}
+
+impl PreviewConfig {
+    /// Classify an enriched file. `file_format` may be an embedded term or a
+    /// raw id.
+    pub fn classify(&self, file: &Document) -> &str {
+        let mime_type = file
+            .get_str("mime_type")
+            .ok()
+            .map(|m| m.split(';').next().unwrap_or(m).trim().to_ascii_lowercase());
+        let format_id = match file.get("file_format") {
+            Some(Bson::Document(term)) => term.get_str("id").ok(),
+            Some(Bson::String(id)) => Some(id.as_str()),
+            _ => None,
+        };
+
+        self.rules
+            .iter()
+            .find(|rule| {
+                let mime_match = mime_type.as_deref().is_some_and(|mime| {
+                    rule.mime_types
+                        .iter()
+                        .any(|pattern| match pattern.strip_suffix("/*") {
+                            Some(top) => mime.split('/').next() == Some(top),
+                            None => pattern.eq_ignore_ascii_case(mime),
+                        })
+                });
+                let format_match =
+                    format_id.is_some_and(|id| rule.file_formats.iter().any(|f| f == id));
+                mime_match || format_match
+            })
+            .map_or(self.default.as_str(), |rule| rule.preview.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bson::doc;
+
+    #[test]
+    fn matches_mime_types_and_top_level_wildcards() {
+        let config = PreviewConfig::default();
+        assert_eq!(config.classify(&doc! { "mime_type": "image/png" }), "image");
+        assert_eq!(
+            config.classify(&doc! { "mime_type": "Text/CSV; charset=utf-8" }),
+            "table"
+        );
+        assert_eq!(
+            config.classify(&doc! { "mime_type": "application/pdf" }),
+            "none"
+        );
+        assert_eq!(config.classify(&doc! {}), "none");
+    }
+
+    #[test]
+    fn matches_embedded_terms_and_raw_format_ids() {
+        let config = PreviewConfig::default();
+        let fastq = doc! { "file_format": { "id": "format:1930", "name": "FASTQ" } };
+        assert_eq!(config.classify(&fastq), "sequence");
+        assert_eq!(
+            config.classify(&doc! { "file_format": "format:3475" }),
+            "table"
+        );
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let config = PreviewConfig::default();
+        let file = doc! { "mime_type": "image/tiff", "file_format": "format:3475" };
+        assert_eq!(config.classify(&file), "image");
+    }
+}
@@ -0,0 +1,49 @@
+use anyhow::{Context, Result};
+use bson::DateTime;
+use pprof::protos::Message;
+use std::fs::{self, File};
+use std::path::PathBuf;
+
+/// Sampling rate for `--profile-cpu`, in Hz.
+const FREQUENCY: i32 = 99;
+
+/// CPU profile of one run's enrichment phase, written on [`finish`] as a
+/// flamegraph SVG and a pprof protobuf under the profile directory.
+///
+/// [`finish`]: CpuProfiler::finish
+pub struct CpuProfiler {
+    guard: pprof::ProfilerGuard<'static>,
+    dir: PathBuf,
+}
+
+impl CpuProfiler {
+    pub fn start(dir: &str) -> Result<Self> {
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(FREQUENCY)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()?;
+        Ok(CpuProfiler { guard, dir })
+    }
+
+    pub fn finish(self) -> Result<()> {
+        let report = self.guard.report().build()?;
+        let stem = format!("materialize-{}", DateTime::now().timestamp_millis());
+
+        let svg = self.dir.join(format!("{}.svg", stem));
+        report.flamegraph(File::create(&svg)?)?;
+
+        let proto = self.dir.join(format!("{}.pb", stem));
+        let mut bytes = Vec::new();
+        report.pprof()?.write_to_vec(&mut bytes)?;
+        fs::write(&proto, bytes)?;
+
+        println!(
+            "  Wrote CPU profile to {} and {}",
+            svg.display(),
+            proto.display()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use bson::{doc, Document};
+use serde::Deserialize;
+
+/// Per lookup table whitelist of the fields embedded into enriched files.
+/// Tables without an entry are embedded whole.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectionConfig {
+    pub dcc: Option<Vec<String>>,
+    pub file_format: Option<Vec<String>>,
+    pub data_type: Option<Vec<String>>,
+    pub assay_type: Option<Vec<String>>,
+    pub analysis_type: Option<Vec<String>>,
+    pub anatomy: Option<Vec<String>>,
+    pub collection: Option<Vec<String>>,
+    pub biosample: Option<Vec<String>>,
+    pub subject: Option<Vec<String>>,
+}
+
+impl ProjectionConfig {
+    pub fn for_table(&self, table: &str) -> Option<&[String]> {
+        let fields = match table {
+            "dcc" => &self.dcc,
+            "file_format" => &self.file_format,
+            "data_type" => &self.data_type,
+            "assay_type" => &self.assay_type,
+            "analysis_type" => &self.analysis_type,
+            "anatomy" => &self.anatomy,
+            "collection" => &self.collection,
+            "biosample" => &self.biosample,
+            "subject" => &self.subject,
+            _ => &None,
+        };
+        fields.as_deref()
+    }
+}
+
+/// Server-side projection returning the whitelisted `fields` plus the `keys`
+/// the table is joined on.
+pub fn find_projection(fields: &[String], keys: &[&str]) -> Document {
+    let mut projection = doc! {};
+    for field in fields
+        .iter()
+        .map(String::as_str)
+        .chain(keys.iter().copied())
+    {
+        projection.insert(field, 1);
+    }
+    projection
+}
+
+/// Drop the join keys fetched only for keying that aren't whitelisted.
+pub fn strip_keys(doc: &mut Document, fields: &[String], keys: &[&str]) {
+    for key in keys {
+        if !fields.iter().any(|f| f == key) {
+            doc.remove(*key);
+        }
+    }
+}
@@ -0,0 +1,389 @@
+use anyhow::Result;
+use bson::{doc, Bson, Document};
+use mongodb::sync::{Collection, Database};
+use mongodb::IndexModel;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::dccs::DCC_REF_FIELDS;
+use crate::lookup::KeyPair;
+
+type ProjectKey = (String, String); // (id_namespace, local_id)
+
+/// One document per project summarizing its files for landing pages.
+pub const FACETS_COLLECTION: &str = "project_facets";
+
+/// Per-project tallies gathered while enriching files.
+#[derive(Default)]
+struct ProjectCounts {
+    files: i64,
+    bytes: i64,
+    collections: HashSet<ProjectKey>,
+}
+
+/// Files of one project, both its own and those in the collections it
+/// defines, for `project_facets`.
+#[derive(Default)]
+struct ProjectFacets {
+    files: i64,
+    direct_files: i64,
+    bytes: i64,
+    /// (files, bytes) by `file_format` id, with the format's name
+    formats: BTreeMap<String, (String, i64, i64)>,
+}
+
+/// Accumulates file, byte, and collection counts per project during the file
+/// pass, then materializes the `projects` and `project_facets` collections
+/// from them.
+#[derive(Default)]
+pub struct ProjectAggregator {
+    counts: HashMap<ProjectKey, ProjectCounts>,
+    facets: HashMap<ProjectKey, ProjectFacets>,
+}
+
+impl ProjectAggregator {
+    pub fn observe(&mut self, file: &Document) {
+        self.observe_facets(file);
+        let (Ok(ns), Ok(id)) = (
+            file.get_str("project_id_namespace"),
+            file.get_str("project_local_id"),
+        ) else {
+            return;
+        };
+        let key = &(ns, id) as &dyn KeyPair;
+        if !self.counts.contains_key(key) {
+            self.counts
+                .insert((ns.to_string(), id.to_string()), ProjectCounts::default());
+        }
+        let counts = self.counts.get_mut(key).unwrap();
+        counts.files += 1;
+        counts.bytes += integer_field(file, "size_in_bytes").unwrap_or(0);
+        if let Ok(collections) = file.get_array("collections") {
+            for coll in collections.iter().filter_map(Bson::as_document) {
+                if let (Ok(ns), Ok(id)) = (coll.get_str("id_namespace"), coll.get_str("local_id")) {
+                    if !counts.collections.contains(&(ns, id) as &dyn KeyPair) {
+                        counts.collections.insert((ns.to_string(), id.to_string()));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Count `file` toward its own project and every project defining one of
+    /// its collections, once each.
+    fn observe_facets(&mut self, file: &Document) {
+        let own = match (
+            file.get_str("project_id_namespace"),
+            file.get_str("project_local_id"),
+        ) {
+            (Ok(ns), Ok(id)) => Some((ns.to_string(), id.to_string())),
+            _ => None,
+        };
+        let mut projects: HashSet<ProjectKey> = own.iter().cloned().collect();
+        let defining = file
+            .get_array("collections")
+            .into_iter()
+            .flatten()
+            .filter_map(Bson::as_document)
+            .filter_map(|coll| coll.get_array("defined_by_project").ok())
+            .flatten()
+            .filter_map(Bson::as_document);
+        for project in defining {
+            if let (Ok(ns), Ok(id)) = (project.get_str("id_namespace"), project.get_str("local_id"))
+            {
+                projects.insert((ns.to_string(), id.to_string()));
+            }
+        }
+        if projects.is_empty() {
+            return;
+        }
+
+        let bytes = integer_field(file, "size_in_bytes").unwrap_or(0);
+        let format = match file.get("file_format") {
+            Some(Bson::Document(format)) => format.get_str("id").ok().map(|id| {
+                let name = format.get_str("name").unwrap_or(id);
+                (id.to_string(), name.to_string())
+            }),
+            Some(Bson::String(id)) if !id.is_empty() => Some((id.clone(), id.clone())),
+            _ => None,
+        };
+        for project in projects {
+            let direct = own.as_ref() == Some(&project);
+            let facets = self.facets.entry(project).or_default();
+            facets.files += 1;
+            facets.direct_files += direct as i64;
+            facets.bytes += bytes;
+            if let Some((id, name)) = &format {
+                let entry = facets
+                    .formats
+                    .entry(id.clone())
+                    .or_insert_with(|| (name.clone(), 0, 0));
+                entry.1 += 1;
+                entry.2 += bytes;
+            }
+        }
+    }
+
+    /// Build one document per project (with its DCC, parent/child stubs, and
+    /// direct and subtree counts) from the `source` project tables and
+    /// replace the matching `projects` documents in `target`, along with
+    /// each project's `project_facets` document. Returns the number of
+    /// project documents written.
+    pub fn write(
+        self,
+        source: &Database,
+        target: &Database,
+        dccs: &HashMap<String, Document>,
+        submission: &Option<String>,
+    ) -> Result<usize> {
+        let query = match submission {
+            Some(sub) => doc! { "submission": sub },
+            None => doc! {},
+        };
+
+        let projects: HashMap<ProjectKey, Document> = source
+            .collection::<Document>("project")
+            .find(query.clone())
+            .run()?
+            .filter_map(|r| r.ok())
+            .filter_map(|d| {
+                let key = (
+                    d.get_str("id_namespace").ok()?.to_string(),
+                    d.get_str("local_id").ok()?.to_string(),
+                );
+                Some((key, d))
+            })
+            .collect();
+
+        let mut children: HashMap<ProjectKey, Vec<ProjectKey>> = HashMap::new();
+        let mut parents: HashMap<ProjectKey, Vec<ProjectKey>> = HashMap::new();
+        for link in source
+            .collection::<Document>("project_in_project")
+            .find(query.clone())
+            .run()?
+            .filter_map(|r| r.ok())
+        {
+            let (Ok(pns), Ok(pid), Ok(cns), Ok(cid)) = (
+                link.get_str("parent_project_id_namespace"),
+                link.get_str("parent_project_local_id"),
+                link.get_str("child_project_id_namespace"),
+                link.get_str("child_project_local_id"),
+            ) else {
+                continue;
+            };
+            let parent = (pns.to_string(), pid.to_string());
+            let child = (cns.to_string(), cid.to_string());
+            children
+                .entry(parent.clone())
+                .or_default()
+                .push(child.clone());
+            parents.entry(child).or_default().push(parent);
+        }
+
+        // Subjects aren't part of the file pass, so count them server-side
+        let mut pipeline = Vec::new();
+        if submission.is_some() {
+            pipeline.push(doc! { "$match": query });
+        }
+        pipeline.push(doc! { "$group": {
+            "_id": { "ns": "$project_id_namespace", "id": "$project_local_id" },
+            "count": { "$sum": 1 },
+        } });
+        let subjects: HashMap<ProjectKey, i64> = source
+            .collection::<Document>("subject")
+            .aggregate(pipeline)
+            .run()?
+            .filter_map(|r| r.ok())
+            .filter_map(|d| {
+                let key = d.get_document("_id").ok()?;
+                let key = (
+                    key.get_str("ns").ok()?.to_string(),
+                    key.get_str("id").ok()?.to_string(),
+                );
+                Some((key, integer_field(&d, "count")?))
+            })
+            .collect();
+
+        let stub = |key: &ProjectKey| {
+            let mut stub = doc! { "id_namespace": &key.0, "local_id": &key.1 };
+            if let Some(project) = projects.get(key) {
+                for field in ["name", "abbreviation"] {
+                    if let Ok(value) = project.get_str(field) {
+                        stub.insert(field, value);
+                    }
+                }
+            }
+            stub
+        };
+
+        let empty = ProjectCounts::default();
+        let mut output: Vec<Document> = Vec::with_capacity(projects.len());
+        for (key, project) in &projects {
+            let mut project_copy = project.clone();
+            project_copy.remove("_id");
+
+            if let Some(dcc) = project.get_str("submission").ok().and_then(|s| dccs.get(s)) {
+                let mut dcc_copy = dcc.clone();
+                dcc_copy.remove("_id");
+                project_copy.insert("dcc", dcc_copy);
+            }
+
+            let stubs = |keys: Option<&Vec<ProjectKey>>| -> Vec<Document> {
+                keys.into_iter().flatten().map(stub).collect()
+            };
+            project_copy.insert("parents", stubs(parents.get(key)));
+            project_copy.insert("children", stubs(children.get(key)));
+
+            let direct = self.counts.get(key).unwrap_or(&empty);
+            project_copy.insert(
+                "counts",
+                doc! {
+                    "files": direct.files,
+                    "bytes": direct.bytes,
+                    "collections": direct.collections.len() as i64,
+                    "subjects": subjects.get(key).copied().unwrap_or(0),
+                },
+            );
+
+            // Roll up over the project and all of its descendants
+            let mut total_files = 0;
+            let mut total_bytes = 0;
+            let mut total_subjects = 0;
+            let mut total_collections: HashSet<&ProjectKey> = HashSet::new();
+            let mut seen: HashSet<&ProjectKey> = HashSet::new();
+            let mut stack = vec![key];
+            while let Some(current) = stack.pop() {
+                if !seen.insert(current) {
+                    continue;
+                }
+                if let Some(counts) = self.counts.get(current) {
+                    total_files += counts.files;
+                    total_bytes += counts.bytes;
+                    total_collections.extend(counts.collections.iter());
+                }
+                total_subjects += subjects.get(current).copied().unwrap_or(0);
+                stack.extend(children.get(current).into_iter().flatten());
+            }
+            project_copy.insert(
+                "total_counts",
+                doc! {
+                    "files": total_files,
+                    "bytes": total_bytes,
+                    "collections": total_collections.len() as i64,
+                    "subjects": total_subjects,
+                },
+            );
+
+            output.push(project_copy);
+        }
+
+        let coll: Collection<Document> = target.collection("projects");
+        match submission {
+            Some(sub) => {
+                coll.delete_many(doc! { "submission": sub }).run()?;
+            }
+            None => coll.drop().run()?,
+        }
+        if !output.is_empty() {
+            coll.insert_many(&output).run()?;
+        }
+
+        let indexes = vec![
+            doc! { "id_namespace": 1, "local_id": 1 },
+            doc! { "name": 1 },
+            doc! { "dcc.id": 1 },
+            doc! { "dcc.dcc_abbreviation": 1 },
+            doc! { "parents.local_id": 1 },
+            doc! { "children.local_id": 1 },
+            doc! { "submission": 1 },
+        ];
+        coll.create_indexes(
+            indexes
+                .into_iter()
+                .map(|keys| IndexModel::builder().keys(keys).build()),
+        )
+        .run()?;
+
+        write_facets(target, &projects, &self.facets, dccs, submission)?;
+        Ok(output.len())
+    }
+}
+
+/// Replace the `project_facets` documents of the loaded projects: file
+/// count (own files plus those in collections the project defines), own
+/// file count, total bytes, and files and bytes per format, most files
+/// first. Projects without files get zero counts, so a landing page can
+/// list every project from this collection alone.
+fn write_facets(
+    target: &Database,
+    projects: &HashMap<ProjectKey, Document>,
+    facets: &HashMap<ProjectKey, ProjectFacets>,
+    dccs: &HashMap<String, Document>,
+    submission: &Option<String>,
+) -> Result<()> {
+    let empty = ProjectFacets::default();
+    let mut output = Vec::with_capacity(projects.len());
+    for (key, project) in projects {
+        let facets = facets.get(key).unwrap_or(&empty);
+        let mut formats: Vec<(&String, &(String, i64, i64))> = facets.formats.iter().collect();
+        formats.sort_by_key(|f| Reverse(f.1 .1));
+        let formats: Vec<Document> = formats
+            .into_iter()
+            .map(|(id, (name, files, bytes))| {
+                doc! { "id": id, "name": name, "files": files, "bytes": bytes }
+            })
+            .collect();
+
+        let mut doc = doc! { "id_namespace": &key.0, "local_id": &key.1 };
+        for field in ["name", "abbreviation", "submission"] {
+            if let Ok(value) = project.get_str(field) {
+                doc.insert(field, value);
+            }
+        }
+        if let Some(dcc) = project.get_str("submission").ok().and_then(|s| dccs.get(s)) {
+            let dcc: Document = DCC_REF_FIELDS
+                .iter()
+                .filter_map(|field| Some((field.to_string(), dcc.get(field)?.clone())))
+                .collect();
+            doc.insert("dcc", dcc);
+        }
+        doc.insert("files", facets.files);
+        doc.insert("direct_files", facets.direct_files);
+        doc.insert("bytes", facets.bytes);
+        doc.insert("formats", formats);
+        output.push(doc);
+    }
+
+    let coll: Collection<Document> = target.collection(FACETS_COLLECTION);
+    match submission {
+        Some(sub) => {
+            coll.delete_many(doc! { "submission": sub }).run()?;
+        }
+        None => coll.drop().run()?,
+    }
+    if !output.is_empty() {
+        coll.insert_many(&output).run()?;
+    }
+    coll.create_indexes(
+        [
+            doc! { "id_namespace": 1, "local_id": 1 },
+            doc! { "dcc.id": 1 },
+            doc! { "submission": 1 },
+        ]
+        .into_iter()
+        .map(|keys| IndexModel::builder().keys(keys).build()),
+    )
+    .run()?;
+    Ok(())
+}
+
+/// Read a numeric field that may have been ingested as a string.
+pub fn integer_field(doc: &Document, field: &str) -> Option<i64> {
+    match doc.get(field)? {
+        Bson::Int32(v) => Some(*v as i64),
+        Bson::Int64(v) => Some(*v),
+        Bson::Double(v) => Some(*v as i64),
+        Bson::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
@@ -0,0 +1,138 @@
+use anyhow::Result;
+use bson::{doc, DateTime, Document};
+use mongodb::sync::{Collection, Database};
+
+use crate::cli::number;
+use crate::config::Config;
+
+/// The view the portal reads; always points at the newest published
+/// generation.
+pub const CURRENT_VIEW: &str = "files_current";
+
+/// One document per generation: `{ _id: N, collection, files, published_at,
+/// superseded_at? }`.
+pub const GENERATIONS_COLLECTION: &str = "files_generations";
+
+const GENERATION_PREFIX: &str = "files_gen_";
+
+/// Hours a superseded generation is kept before it is dropped, so readers
+/// still on it can finish and a bad publish can be rolled back by hand.
+pub const DEFAULT_RETAIN_HOURS: u64 = 24;
+
+/// `publish [--retain-hours H]` snapshots `files` into a new `files_gen_N`
+/// collection, indexes it, and points the `files_current` view at it. The
+/// view is redefined with a single `collMod`, so readers see either the old
+/// generation or the new one and never a partial build. Generations
+/// superseded more than H hours ago (default 24) are dropped.
+pub fn command(db: &Database, args: &[String], config: &Config) -> Result<()> {
+    let retain_hours = number(args, "--retain-hours")?.unwrap_or(DEFAULT_RETAIN_HOURS);
+    publish(db, config, retain_hours)?;
+    Ok(())
+}
+
+/// The `files_gen_N` collections in the database.
+pub fn generation_collections(db: &Database) -> Result<Vec<String>> {
+    Ok(db
+        .list_collection_names()
+        .run()?
+        .into_iter()
+        .filter(|name| generation_number(name).is_some())
+        .collect())
+}
+
+fn generation_number(name: &str) -> Option<i64> {
+    name.strip_prefix(GENERATION_PREFIX)?.parse().ok()
+}
+
+/// Publish a new generation and return its collection's name.
+pub fn publish(db: &Database, config: &Config, retain_hours: u64) -> Result<String> {
+    let generation = generation_collections(db)?
+        .iter()
+        .filter_map(|name| generation_number(name))
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let name = format!("{}{}", GENERATION_PREFIX, generation);
+    println!("\nPublishing generation {}...", generation);
+
+    db.collection::<Document>("files")
+        .aggregate(vec![doc! { "$out": &name }])
+        .run()?;
+    let coll: Collection<Document> = db.collection(&name);
+    let files = coll.estimated_document_count().run()?;
+    if files == 0 {
+        db.collection::<Document>(&name).drop().run()?;
+        anyhow::bail!("files is empty; nothing to publish");
+    }
+    println!("  Copied {} files into {}", files, name);
+    let hoisted = coll
+        .find_one(doc! { "biosamples": { "$exists": true } })
+        .run()?
+        .is_some();
+    crate::create_indexes(&coll, config, hoisted)?;
+
+    let exists = !db
+        .list_collection_names()
+        .filter(doc! { "name": CURRENT_VIEW })
+        .run()?
+        .is_empty();
+    let verb = if exists { "collMod" } else { "create" };
+    db.run_command(doc! { verb: CURRENT_VIEW, "viewOn": &name, "pipeline": [] })
+        .run()?;
+    println!("  {} now points at {}", CURRENT_VIEW, name);
+
+    let now = DateTime::now();
+    let generations: Collection<Document> = db.collection(GENERATIONS_COLLECTION);
+    generations
+        .update_many(
+            doc! { "superseded_at": null },
+            doc! { "$set": { "superseded_at": now } },
+        )
+        .run()?;
+    generations
+        .insert_one(doc! {
+            "_id": generation,
+            "collection": &name,
+            "files": files as i64,
+            "published_at": now,
+        })
+        .run()?;
+
+    collect_garbage(db, &generations, retain_hours)?;
+    Ok(name)
+}
+
+/// Drop generations superseded more than `retain_hours` ago, and any
+/// `files_gen_N` left behind by a publish that never finished.
+fn collect_garbage(
+    db: &Database,
+    generations: &Collection<Document>,
+    retain_hours: u64,
+) -> Result<()> {
+    let cutoff =
+        DateTime::from_millis(DateTime::now().timestamp_millis() - retain_hours as i64 * 3_600_000);
+    let mut known = Vec::new();
+    for record in generations.find(doc! {}).run()? {
+        let record = record?;
+        let name = record.get_str("collection").unwrap_or_default().to_string();
+        let expired = record
+            .get_datetime("superseded_at")
+            .is_ok_and(|at| *at < cutoff);
+        if expired {
+            db.collection::<Document>(&name).drop().run()?;
+            generations
+                .delete_one(doc! { "_id": record.get("_id").cloned() })
+                .run()?;
+            println!("  Dropped expired generation {}", name);
+        } else {
+            known.push(name);
+        }
+    }
+    for name in generation_collections(db)? {
+        if !known.contains(&name) {
+            db.collection::<Document>(&name).drop().run()?;
+            println!("  Dropped unpublished generation {}", name);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use anyhow::Result;
+use arrow_array::builder::{ListBuilder, StringBuilder};
+use arrow_array::{ArrayRef, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use bson::Document;
+use std::sync::Arc;
+
+use crate::flatten::{
+    list_values, string_value, term_value, INTEGER_COLUMNS, LIST_COLUMNS, STRING_COLUMNS,
+    TERM_COLUMNS,
+};
+use crate::projects::integer_field;
+
+// Arrow form of the flattened file shared by the Parquet and Arrow IPC
+// outputs.
+
+/// The flattened schema: [`STRING_COLUMNS`], [`INTEGER_COLUMNS`],
+/// [`TERM_COLUMNS`], then [`LIST_COLUMNS`] as lists of strings.
+pub fn schema() -> Schema {
+    let item = Arc::new(Field::new("item", DataType::Utf8, true));
+    let mut fields: Vec<Field> = Vec::new();
+    fields.extend(
+        STRING_COLUMNS
+            .iter()
+            .map(|name| Field::new(*name, DataType::Utf8, true)),
+    );
+    fields.extend(
+        INTEGER_COLUMNS
+            .iter()
+            .map(|name| Field::new(*name, DataType::Int64, true)),
+    );
+    fields.extend(
+        TERM_COLUMNS
+            .iter()
+            .map(|(name, _, _)| Field::new(*name, DataType::Utf8, true)),
+    );
+    fields.extend(
+        LIST_COLUMNS
+            .iter()
+            .map(|name| Field::new(*name, DataType::List(item.clone()), true)),
+    );
+    Schema::new(fields)
+}
+
+/// Flatten `files` into a record batch of [`schema`].
+pub fn record_batch(schema: &SchemaRef, files: &[&Document]) -> Result<RecordBatch> {
+    let mut columns: Vec<ArrayRef> = Vec::with_capacity(schema.fields().len());
+    for name in STRING_COLUMNS {
+        let values: StringArray = files.iter().map(|f| string_value(f, name)).collect();
+        columns.push(Arc::new(values));
+    }
+    for name in INTEGER_COLUMNS {
+        let values: Int64Array = files.iter().map(|f| integer_field(f, name)).collect();
+        columns.push(Arc::new(values));
+    }
+    for (_, field, key) in TERM_COLUMNS {
+        let values: StringArray = files.iter().map(|f| term_value(f, field, key)).collect();
+        columns.push(Arc::new(values));
+    }
+    for name in LIST_COLUMNS {
+        let mut builder = ListBuilder::new(StringBuilder::new());
+        for file in files {
+            for value in list_values(file, name) {
+                builder.values().append_value(value);
+            }
+            builder.append(true);
+        }
+        columns.push(Arc::new(builder.finish()));
+    }
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
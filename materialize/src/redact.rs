@@ -0,0 +1,321 @@
+use anyhow::{bail, Result};
+use bson::{doc, Bson, Document};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::Mutex;
+
+use crate::change::{content_hash, HASH_FIELD};
+use crate::ingest::hex;
+use crate::search;
+use crate::spec::EnrichmentSpec;
+
+/// Environment variable holding the salt mixed into hashed values; without
+/// it, a hashed low-cardinality field (sex, race) is trivially reversed.
+pub const REDACT_SALT_ENV: &str = "MATERIALIZE_REDACT_SALT";
+
+/// Collections a `--tiers` run writes instead of `files`: everything for
+/// the controlled-access portal, and the redacted copy for the public one.
+pub const CONTROLLED_COLLECTION: &str = "files_controlled";
+pub const PUBLIC_COLLECTION: &str = "files_public";
+
+/// Field paths stripped or hashed from every enriched file before it is
+/// written (`[[redact.fields]]`), for deployments that must not expose
+/// protected fields such as subject demographics.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedactConfig {
+    pub fields: Vec<RedactRule>,
+}
+
+/// One protected path. Segments are field names separated by dots; arrays
+/// along the way are walked element by element, as in a MongoDB query, and
+/// `*` matches every field at its level, so
+/// `collections.biosamples.subjects.*` covers each embedded subject.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedactRule {
+    pub path: String,
+    #[serde(default)]
+    pub action: RedactAction,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedactAction {
+    /// Drop the field
+    #[default]
+    Remove,
+    /// Replace the value with its salted SHA-256, so equal values still
+    /// group together
+    Hash,
+}
+
+impl RedactConfig {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Hashing without a salt is refused outright rather than producing
+    /// digests anyone can reverse from a table of the field's few values.
+    pub fn validate(&self) -> Result<()> {
+        let hashed = self.fields.iter().find(|r| r.action == RedactAction::Hash);
+        if let Some(rule) = hashed {
+            if env::var(REDACT_SALT_ENV).unwrap_or_default().is_empty() {
+                bail!(
+                    "redact.fields hashes {} but {} is not set",
+                    rule.path,
+                    REDACT_SALT_ENV
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Redact every configured path in `file`, recording what was touched
+    /// in `audit`. Returns the number of fields redacted.
+    pub fn apply(&self, file: &mut Document, audit: &Redactions) -> u64 {
+        let mut redacted = 0;
+        for rule in &self.fields {
+            let segments: Vec<&str> = rule.path.split('.').collect();
+            let count = redact_path(file, &segments, rule.action, &audit.salt);
+            if count > 0 {
+                audit.record(rule, count);
+                redacted += count;
+            }
+        }
+        redacted
+    }
+}
+
+/// The public tier's copy of an enriched file: redacted, with `search_text`
+/// rebuilt so the redacted values aren't searchable, and rehashed.
+pub fn public_copy(
+    file: &Document,
+    config: &RedactConfig,
+    spec: &EnrichmentSpec,
+    audit: &Redactions,
+) -> Document {
+    let mut public = file.clone();
+    config.apply(&mut public, audit);
+    public.insert("search_text", search::search_text(&public, spec));
+    public.insert(HASH_FIELD, content_hash(&public));
+    public
+}
+
+fn redact_path(doc: &mut Document, segments: &[&str], action: RedactAction, salt: &str) -> u64 {
+    let Some((first, rest)) = segments.split_first() else {
+        return 0;
+    };
+    let keys: Vec<String> = if *first == "*" {
+        doc.keys().cloned().collect()
+    } else if doc.contains_key(*first) {
+        vec![first.to_string()]
+    } else {
+        Vec::new()
+    };
+    let mut count = 0;
+    for key in keys {
+        if !rest.is_empty() {
+            if let Some(value) = doc.get_mut(&key) {
+                count += redact_value(value, rest, action, salt);
+            }
+            continue;
+        }
+        match action {
+            RedactAction::Remove => {
+                doc.remove(&key);
+                count += 1;
+            }
+            RedactAction::Hash => {
+                if let Some(value) = doc.get_mut(&key) {
+                    hash_value(value, salt);
+                    count += 1;
+                }
+            }
+        }
+    }
+    count
+}
+
+fn redact_value(value: &mut Bson, rest: &[&str], action: RedactAction, salt: &str) -> u64 {
+    match value {
+        Bson::Document(doc) => redact_path(doc, rest, action, salt),
+        Bson::Array(items) => items
+            .iter_mut()
+            .map(|item| redact_value(item, rest, action, salt))
+            .sum(),
+        _ => 0,
+    }
+}
+
+/// Hash a value in place; arrays are hashed element by element and nulls
+/// left as they are.
+fn hash_value(value: &mut Bson, salt: &str) {
+    match value {
+        Bson::Null => {}
+        Bson::Array(items) => items.iter_mut().for_each(|item| hash_value(item, salt)),
+        other => {
+            let text = match &*other {
+                Bson::String(s) => s.clone(),
+                v => v.to_string(),
+            };
+            let digest = Sha256::digest(format!("{}{}", salt, text).as_bytes());
+            *other = Bson::String(hex(&digest));
+        }
+    }
+}
+
+/// Audit of the fields a run redacted, per configured path.
+pub struct Redactions {
+    salt: String,
+    paths: Mutex<BTreeMap<String, (RedactAction, u64)>>,
+}
+
+impl Default for Redactions {
+    fn default() -> Self {
+        Redactions {
+            salt: env::var(REDACT_SALT_ENV).unwrap_or_default(),
+            paths: Mutex::default(),
+        }
+    }
+}
+
+impl Redactions {
+    fn record(&self, rule: &RedactRule, count: u64) {
+        let mut paths = self.paths.lock().unwrap();
+        paths.entry(rule.path.clone()).or_insert((rule.action, 0)).1 += count;
+    }
+
+    /// `{ path: { action, count } }` for every path that matched anything.
+    pub fn report(&self) -> Document {
+        self.paths
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(path, (action, count))| {
+                let action = match action {
+                    RedactAction::Remove => "remove",
+                    RedactAction::Hash => "hash",
+                };
+                (
+                    path.clone(),
+                    Bson::Document(doc! { "action": action, "count": *count as i64 }),
+                )
+            })
+            .collect()
+    }
+
+    pub fn print(&self) {
+        for (path, (action, count)) in self.paths.lock().unwrap().iter() {
+            let verb = match action {
+                RedactAction::Remove => "removed",
+                RedactAction::Hash => "hashed",
+            };
+            println!("  Redacted: {} {} fields at {}", verb, count, path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redact(file: &mut Document, path: &str, action: RedactAction) -> u64 {
+        let segments: Vec<&str> = path.split('.').collect();
+        redact_path(file, &segments, action, "salt")
+    }
+
+    fn digest(text: &str) -> Bson {
+        Bson::String(hex(&Sha256::digest(format!("salt{}", text).as_bytes())))
+    }
+
+    #[test]
+    fn removes_a_top_level_field() {
+        let mut file = doc! { "filename": "a.bam", "size_in_bytes": 10 };
+        assert_eq!(redact(&mut file, "filename", RedactAction::Remove), 1);
+        assert_eq!(file, doc! { "size_in_bytes": 10 });
+        assert_eq!(redact(&mut file, "filename", RedactAction::Remove), 0);
+    }
+
+    #[test]
+    fn walks_arrays_along_the_path() {
+        let mut file = doc! {
+            "collections": [
+                { "biosamples": [
+                    { "subjects": [{ "sex": "F", "local_id": "s1" }] },
+                    { "subjects": [{ "sex": "M", "local_id": "s2" }] },
+                ] },
+                { "biosamples": [] },
+            ],
+        };
+        let count = redact(
+            &mut file,
+            "collections.biosamples.subjects.sex",
+            RedactAction::Remove,
+        );
+        assert_eq!(count, 2);
+        assert_eq!(
+            file,
+            doc! {
+                "collections": [
+                    { "biosamples": [
+                        { "subjects": [{ "local_id": "s1" }] },
+                        { "subjects": [{ "local_id": "s2" }] },
+                    ] },
+                    { "biosamples": [] },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn wildcard_matches_every_field_at_its_level() {
+        let mut file = doc! {
+            "biosamples": [{ "subjects": [{ "sex": "F", "race": ["a"] }] }],
+            "filename": "a.bam",
+        };
+        let count = redact(&mut file, "biosamples.subjects.*", RedactAction::Remove);
+        assert_eq!(count, 2);
+        assert_eq!(
+            file,
+            doc! { "biosamples": [{ "subjects": [{}] }], "filename": "a.bam" }
+        );
+    }
+
+    #[test]
+    fn ignores_paths_through_scalars() {
+        let mut file = doc! { "filename": "a.bam" };
+        assert_eq!(redact(&mut file, "filename.x", RedactAction::Remove), 0);
+        assert_eq!(redact(&mut file, "missing.x", RedactAction::Remove), 0);
+        assert_eq!(file, doc! { "filename": "a.bam" });
+    }
+
+    #[test]
+    fn hashes_strings_and_other_scalars() {
+        let mut file = doc! { "sex": "F", "age": 42 };
+        assert_eq!(redact(&mut file, "*", RedactAction::Hash), 2);
+        assert_eq!(file.get("sex"), Some(&digest("F")));
+        assert_eq!(file.get("age"), Some(&digest(&Bson::Int32(42).to_string())));
+    }
+
+    #[test]
+    fn hashes_arrays_element_wise_and_keeps_nulls() {
+        let mut value = Bson::Array(vec![Bson::from("a"), Bson::Null, Bson::from("a")]);
+        hash_value(&mut value, "salt");
+        assert_eq!(
+            value,
+            Bson::Array(vec![digest("a"), Bson::Null, digest("a")])
+        );
+    }
+
+    #[test]
+    fn hash_depends_on_the_salt() {
+        let mut salted = Bson::from("F");
+        let mut unsalted = Bson::from("F");
+        hash_value(&mut salted, "salt");
+        hash_value(&mut unsalted, "");
+        assert_ne!(salted, unsalted);
+    }
+}
@@ -0,0 +1,215 @@
+use anyhow::Result;
+use bson::{doc, Bson, Document};
+use mongodb::sync::Database;
+use std::collections::HashMap;
+
+use crate::change::{content_hash, HASH_FIELD};
+use crate::cli::{flag, value, values, Options};
+use crate::cursor::ResumableCursor;
+use crate::enrich::PROVENANCE_FIELD;
+use crate::lookup::{
+    load_vocabulary, LookupBackend, LookupMap, VocabScope, ANATOMY_TABLE, DISEASE_TABLE,
+    GENE_TABLE, SUBJECT_RACE_TABLE, SUBSTANCE_TABLE,
+};
+use crate::scrub::ScrubConfig;
+use crate::search;
+use crate::spec::{EnrichmentSpec, Entity};
+
+/// Re-resolves the vocabulary terms embedded in one enriched file against
+/// freshly loaded tables, collecting a `$set` for each term that changed.
+struct Refresh<'a> {
+    tables: &'a HashMap<String, LookupMap>,
+    spec: &'a EnrichmentSpec,
+    scrub: &'a ScrubConfig,
+    scope: VocabScope,
+    submission: String,
+    sets: Document,
+}
+
+impl Refresh<'_> {
+    /// The term `current` (an embedded term, or the raw id a missed lookup
+    /// left) resolves to now, if that differs from what is embedded.
+    /// Ontology ancestors and `--provenance` records are kept as they are.
+    fn refreshed(&self, current: &Bson, table: &str) -> Option<Document> {
+        let map = self.tables.get(table)?;
+        let id = match current {
+            Bson::Document(term) => term.get_str("id").ok()?,
+            Bson::String(id) => id.as_str(),
+            _ => return None,
+        };
+        let mut term = map.get(self.scope.key(&self.submission), id)?.into_owned();
+        term.remove("_id");
+        for kept in ["ancestors", PROVENANCE_FIELD] {
+            if let Some(value) = current.as_document().and_then(|t| t.get(kept)) {
+                term.insert(kept, value.clone());
+            }
+        }
+        self.scrub.apply(&mut term);
+        (current.as_document() != Some(&term)).then_some(term)
+    }
+
+    /// Refresh the single term under `field`.
+    fn term(&mut self, doc: &mut Document, path: &str, field: &str, table: &str) {
+        let Some(term) = doc.get(field).and_then(|t| self.refreshed(t, table)) else {
+            return;
+        };
+        self.sets.insert(format!("{}{}", path, field), term.clone());
+        doc.insert(field, term);
+    }
+
+    /// Refresh every term in the array under `field`.
+    fn term_list(&mut self, doc: &mut Document, path: &str, field: &str, table: &str) {
+        let Ok(terms) = doc.get_array_mut(field) else {
+            return;
+        };
+        for (i, current) in terms.iter_mut().enumerate() {
+            if let Some(term) = self.refreshed(current, table) {
+                self.sets
+                    .insert(format!("{}{}.{}", path, field, i), term.clone());
+                *current = Bson::Document(term);
+            }
+        }
+    }
+
+    fn spec_terms(&mut self, doc: &mut Document, path: &str, entity: Entity) {
+        let spec = self.spec;
+        for term in spec.terms_for(entity) {
+            self.term(doc, path, &term.field, &term.table);
+        }
+    }
+
+    /// Walk the file's embedded entities the way enrichment nests them.
+    fn file(&mut self, file: &mut Document) {
+        self.spec_terms(file, "", Entity::File);
+        for (i, coll) in documents_mut(file, "collections") {
+            let path = format!("collections.{}.", i);
+            self.spec_terms(coll, &path, Entity::Collection);
+            self.term_list(coll, &path, "anatomies", ANATOMY_TABLE);
+            self.term_list(coll, &path, "diseases", DISEASE_TABLE);
+            for (j, biosample) in documents_mut(coll, "biosamples") {
+                self.biosample(biosample, &format!("{}biosamples.{}.", path, j));
+            }
+        }
+        // Hoisted with `--hoist-biosamples`
+        for (j, biosample) in documents_mut(file, "biosamples") {
+            self.biosample(biosample, &format!("biosamples.{}.", j));
+        }
+    }
+
+    fn biosample(&mut self, biosample: &mut Document, path: &str) {
+        self.spec_terms(biosample, path, Entity::Biosample);
+        self.term_list(biosample, path, "substances", SUBSTANCE_TABLE);
+        self.term_list(biosample, path, "genes", GENE_TABLE);
+        for (k, subject) in documents_mut(biosample, "subjects") {
+            let path = format!("{}subjects.{}.", path, k);
+            self.spec_terms(subject, &path, Entity::Subject);
+            self.term_list(subject, &path, "race", SUBJECT_RACE_TABLE);
+        }
+    }
+}
+
+/// The subdocuments of the array `field` with their positions, if any.
+fn documents_mut<'a>(
+    doc: &'a mut Document,
+    field: &str,
+) -> impl Iterator<Item = (usize, &'a mut Document)> {
+    doc.get_array_mut(field)
+        .map(|items| items.as_mut_slice())
+        .unwrap_or_default()
+        .iter_mut()
+        .enumerate()
+        .filter_map(|(i, item)| match item {
+            Bson::Document(doc) => Some((i, doc)),
+            _ => None,
+        })
+}
+
+/// `refresh-vocab [--table NAME]... [--submission X] [--dry-run]` reloads
+/// the vocabulary tables and updates the terms embedded in the existing
+/// `files` in place, `$set`ting only the subdocuments whose term changed
+/// (and `search_text` when a name did). Much cheaper than a full run when
+/// only CV tables were corrected; the joins themselves are not redone, so
+/// new or removed associations still need a run.
+pub fn command(source: &Database, db: &Database, opts: &Options, args: &[String]) -> Result<()> {
+    let submission = value(args, "--submission");
+    let dry_run = flag(args, "--dry-run");
+    let spec = &opts.config.enrichment;
+    let mut tables = values(args, "--table");
+    if tables.is_empty() {
+        // Every table a run resolves terms against
+        let mut all = spec.tables();
+        all.extend([
+            ANATOMY_TABLE,
+            DISEASE_TABLE,
+            SUBJECT_RACE_TABLE,
+            SUBSTANCE_TABLE,
+            GENE_TABLE,
+        ]);
+        for table in all {
+            if !tables.iter().any(|t| t == table) {
+                tables.push(table.to_string());
+            }
+        }
+    }
+
+    println!("Loading vocabulary tables...");
+    let mut maps = HashMap::new();
+    for table in &tables {
+        let map = load_vocabulary(source, &LookupBackend::Memory, table, &submission, opts)?;
+        println!("  {}: {} entries", table, map.len());
+        maps.insert(table.clone(), map);
+    }
+
+    let query = match submission {
+        Some(ref sub) => doc! { "submission": sub },
+        None => doc! {},
+    };
+    let files = db.collection::<Document>("files");
+    let (mut scanned, mut refreshed, mut terms) = (0u64, 0u64, 0usize);
+    for file in ResumableCursor::new(files.clone(), query, opts.find_batch_size) {
+        let mut file = file?;
+        scanned += 1;
+        let mut refresh = Refresh {
+            tables: &maps,
+            spec,
+            scrub: &opts.config.scrub,
+            scope: opts.vocab_scope,
+            submission: file.get_str("submission").unwrap_or_default().to_string(),
+            sets: Document::new(),
+        };
+        refresh.file(&mut file);
+        if refresh.sets.is_empty() {
+            continue;
+        }
+        let mut sets = refresh.sets;
+        terms += sets.len();
+        refreshed += 1;
+        let search_text = search::search_text(&file, spec);
+        if file.get_str("search_text") != Ok(search_text.as_str()) {
+            file.insert("search_text", search_text.as_str());
+            sets.insert("search_text", search_text);
+        }
+        // Keep the hash current so `--skip-unchanged` compares against
+        // the refreshed content
+        if file.contains_key(HASH_FIELD) {
+            sets.insert(HASH_FIELD, content_hash(&file));
+        }
+        if !dry_run {
+            let id = file.get("_id").cloned().unwrap_or(Bson::Null);
+            files
+                .update_one(doc! { "_id": id }, doc! { "$set": sets })
+                .run()?;
+        }
+    }
+
+    let verb = if dry_run {
+        "Would refresh"
+    } else {
+        "Refreshed"
+    };
+    println!(
+        "{} {} terms in {} of {} files",
+        verb, terms, refreshed, scanned
+    );
+    Ok(())
+}
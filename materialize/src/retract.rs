@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use bson::{doc, Document};
+use mongodb::sync::{Client, Database};
+use std::io::{self, BufRead, Write};
+
+use crate::checkpoint::CHECKPOINTS_COLLECTION;
+use crate::cli::{flag, value};
+use crate::dccs::{self, DCCS_COLLECTION};
+use crate::projects::FACETS_COLLECTION;
+use crate::publish::generation_collections;
+use crate::redact::{CONTROLLED_COLLECTION, PUBLIC_COLLECTION};
+use crate::sample::{SAMPLE_COLLECTION, SAMPLE_RELATIONS_COLLECTION};
+use crate::size_policy::RELATIONS_COLLECTION;
+use crate::stats::FIELD_STATS_COLLECTION;
+use crate::submission_stats::STATS_COLLECTION;
+use crate::submissions::source_collections;
+use crate::transactions;
+
+/// Collections written by the materializer that hold per-submission
+/// documents. Run records in `materialize_runs` are kept as an audit trail.
+const DERIVED_COLLECTIONS: [&str; 12] = [
+    "files",
+    CONTROLLED_COLLECTION,
+    PUBLIC_COLLECTION,
+    RELATIONS_COLLECTION,
+    SAMPLE_COLLECTION,
+    SAMPLE_RELATIONS_COLLECTION,
+    "projects",
+    FACETS_COLLECTION,
+    STATS_COLLECTION,
+    "collections",
+    "biosamples",
+    "subjects",
+];
+
+/// `retract --submission X [--yes]` removes a submission from the raw C2M2
+/// collections and everything materialized from it. On a replica set the
+/// deletes run in one transaction; on a standalone server they run in order,
+/// materialized collections first, so an interrupted retract never leaves
+/// `files` pointing at raw rows that are gone. DCCs in `dccs` lose the
+/// submission from their `submissions`, and are deleted once none is left.
+pub fn command(client: &Client, db: &Database, args: &[String]) -> Result<()> {
+    let submission =
+        value(args, "--submission").context("usage: retract --submission X [--yes]")?;
+
+    let mut targets: Vec<(String, Document)> = DERIVED_COLLECTIONS
+        .iter()
+        .map(|name| (name.to_string(), doc! { "submission": &submission }))
+        .collect();
+    targets.push((
+        CHECKPOINTS_COLLECTION.to_string(),
+        doc! { "_id": &submission },
+    ));
+    // Field statistics summarize whole DCCs, so those the submission
+    // counted toward are removed until the next run recomputes them
+    targets.push((
+        FIELD_STATS_COLLECTION.to_string(),
+        doc! { "submissions": &submission },
+    ));
+    // Published generations are snapshots of `files`; the portal reads
+    // the current one, so the submission is removed from all of them
+    for name in generation_collections(db)? {
+        targets.push((name, doc! { "submission": &submission }));
+    }
+    for name in source_collections(db)? {
+        targets.push((name, doc! { "submission": &submission }));
+    }
+
+    let mut total = 0;
+    println!("Retracting submission {}:", submission);
+    for (name, filter) in &targets {
+        let count = db
+            .collection::<Document>(name)
+            .count_documents(filter.clone())
+            .run()?;
+        if count > 0 {
+            println!("  {}: {} documents", name, count);
+        }
+        total += count;
+    }
+    let dcc_count = db
+        .collection::<Document>(DCCS_COLLECTION)
+        .count_documents(doc! { "submissions": &submission })
+        .run()?;
+    if dcc_count > 0 {
+        println!(
+            "  {}: {} DCCs list the submission",
+            DCCS_COLLECTION, dcc_count
+        );
+    }
+    if total == 0 && dcc_count == 0 {
+        println!("  Nothing to retract");
+        return Ok(());
+    }
+    if !flag(args, "--yes") && !confirm(&submission)? {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    if transactions::supported(db)? {
+        let mut session = client.start_session().run()?;
+        session.start_transaction().run()?;
+        for (name, filter) in &targets {
+            let deleted = db
+                .collection::<Document>(name)
+                .delete_many(filter.clone())
+                .session(&mut session)
+                .run();
+            if let Err(e) = deleted {
+                session.abort_transaction().run()?;
+                return Err(e)
+                    .with_context(|| format!("retracting from {}; nothing was removed", name));
+            }
+        }
+        if let Err(e) = dccs::retract(db, &submission, Some(&mut session)) {
+            session.abort_transaction().run()?;
+            return Err(e).with_context(|| {
+                format!("retracting from {}; nothing was removed", DCCS_COLLECTION)
+            });
+        }
+        session.commit_transaction().run()?;
+    } else {
+        for (name, filter) in &targets {
+            db.collection::<Document>(name)
+                .delete_many(filter.clone())
+                .run()
+                .with_context(|| format!("retracting from {}", name))?;
+        }
+        dccs::retract(db, &submission, None)
+            .with_context(|| format!("retracting from {}", DCCS_COLLECTION))?;
+    }
+    println!("Retracted {} documents", total);
+    Ok(())
+}
+
+fn confirm(submission: &str) -> Result<bool> {
+    print!("Type the submission id to confirm: ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer.trim() == submission)
+}
@@ -0,0 +1,141 @@
+use anyhow::{Context, Result};
+use bson::oid::ObjectId;
+use bson::{doc, Bson, DateTime, Document};
+use mongodb::sync::{Collection, Database};
+
+use crate::cli::value;
+
+pub const RUNS_COLLECTION: &str = "materialize_runs";
+
+/// Audit record for one materialization run in `materialize_runs`.
+///
+/// The record is inserted as `running` when the run starts and updated with
+/// its outcome, counts, write latency, and (on failure) an error summary when
+/// it ends.
+pub struct RunRecord {
+    coll: Collection<Document>,
+    id: ObjectId,
+    started_at: DateTime,
+}
+
+impl RunRecord {
+    pub fn start(db: &Database, submission: &Option<String>) -> Result<Self> {
+        let coll: Collection<Document> = db.collection(RUNS_COLLECTION);
+        let started_at = DateTime::now();
+        let id = ObjectId::new();
+        coll.insert_one(doc! {
+            "_id": id,
+            "submission": submission.as_deref().map_or(Bson::Null, Bson::from),
+            "started_at": started_at,
+            "tool_version": env!("CARGO_PKG_VERSION"),
+            "outcome": "running",
+        })
+        .run()?;
+        println!("Run {}", id);
+        Ok(RunRecord {
+            coll,
+            id,
+            started_at,
+        })
+    }
+
+    pub fn id(&self) -> ObjectId {
+        self.id
+    }
+
+    fn end(self, mut update: Document) -> Result<()> {
+        let ended_at = DateTime::now();
+        update.insert("ended_at", ended_at);
+        update.insert(
+            "duration_ms",
+            ended_at.timestamp_millis() - self.started_at.timestamp_millis(),
+        );
+        self.coll
+            .update_one(doc! { "_id": self.id }, doc! { "$set": update })
+            .run()?;
+        Ok(())
+    }
+
+    /// Record success along with the run's report fields (`counts`, ...).
+    pub fn finish(self, mut report: Document) -> Result<()> {
+        report.insert("outcome", "success");
+        self.end(report)
+    }
+
+    pub fn fail(self, error: &impl std::fmt::Display) -> Result<()> {
+        self.end(doc! { "outcome": "failure", "error": format!("{:#}", error) })
+    }
+}
+
+/// `runs [list] [--limit N] [--submission X]` lists recent runs;
+/// `runs show <run-id>` prints one run in full.
+pub fn command(db: &Database, args: &[String]) -> Result<()> {
+    let coll: Collection<Document> = db.collection(RUNS_COLLECTION);
+    match args.first().map(String::as_str) {
+        None | Some("list") => list(&coll, args),
+        Some("show") => {
+            let id = args.get(1).context("usage: runs show <run-id>")?;
+            let id = ObjectId::parse_str(id).with_context(|| format!("invalid run id {}", id))?;
+            let run = coll
+                .find_one(doc! { "_id": id })
+                .run()?
+                .with_context(|| format!("no run with id {}", id))?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&Bson::Document(run).into_relaxed_extjson())?
+            );
+            Ok(())
+        }
+        Some(other) => anyhow::bail!("Unknown runs command: {}", other),
+    }
+}
+
+fn list(coll: &Collection<Document>, args: &[String]) -> Result<()> {
+    let limit: i64 = match value(args, "--limit") {
+        Some(limit) => limit.parse().context("--limit must be a number")?,
+        None => 20,
+    };
+    let filter = match value(args, "--submission") {
+        Some(sub) => doc! { "submission": sub },
+        None => doc! {},
+    };
+
+    println!(
+        "{:<24}  {:<12}  {:<24}  {:>10}  {:<8}  {:>12}",
+        "RUN", "SUBMISSION", "STARTED", "DURATION", "OUTCOME", "FILES"
+    );
+    for run in coll
+        .find(filter)
+        .sort(doc! { "started_at": -1 })
+        .limit(limit)
+        .run()?
+    {
+        let run = run?;
+        let duration = match run.get_i64("duration_ms") {
+            Ok(ms) => format!("{:.1}s", ms as f64 / 1000.0),
+            Err(_) => "-".to_string(),
+        };
+        let files = run
+            .get_document("counts")
+            .and_then(|c| c.get_i64("files_written"))
+            .map_or("-".to_string(), |n| n.to_string());
+        println!(
+            "{:<24}  {:<12}  {:<24}  {:>10}  {:<8}  {:>12}",
+            run.get_object_id("_id")
+                .map(|id| id.to_hex())
+                .unwrap_or_default(),
+            run.get_str("submission").unwrap_or("(all)"),
+            run.get_datetime("started_at")
+                .ok()
+                .and_then(|t| t.try_to_rfc3339_string().ok())
+                .unwrap_or_default(),
+            duration,
+            run.get_str("outcome").unwrap_or_default(),
+            files,
+        );
+        if let Ok(error) = run.get_str("error") {
+            println!("    error: {}", error);
+        }
+    }
+    Ok(())
+}
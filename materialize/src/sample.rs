@@ -0,0 +1,163 @@
+use anyhow::Result;
+use bson::{doc, Bson, Document};
+use mongodb::sync::Database;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Where sampled files are written instead of `files`.
+pub const SAMPLE_COLLECTION: &str = "files_sample";
+
+/// Sidecar relations for sampled files under the size policy.
+pub const SAMPLE_RELATIONS_COLLECTION: &str = "file_relations_sample";
+
+/// A deterministic subset of files for development runs: `Count(n)` keeps up
+/// to n files per submission, `Fraction(f)` keeps about that share of them.
+/// Membership depends only on a file's (id_namespace, local_id), so reruns
+/// pick the same files and a larger sample contains a smaller one.
+#[derive(Clone, Copy)]
+pub enum Sample {
+    Count(usize),
+    Fraction(f64),
+}
+
+impl Sample {
+    /// From `--sample N` or `--sample-frac F`; at most one may be given.
+    pub fn parse(count: Option<usize>, fraction: Option<f64>) -> Result<Option<Self>> {
+        match (count, fraction) {
+            (Some(_), Some(_)) => anyhow::bail!("--sample and --sample-frac cannot be combined"),
+            (Some(count), None) => Ok(Some(Sample::Count(count))),
+            (None, Some(fraction)) if fraction > 0.0 && fraction <= 1.0 => {
+                Ok(Some(Sample::Fraction(fraction)))
+            }
+            (None, Some(fraction)) => {
+                anyhow::bail!("--sample-frac must be in (0, 1], got {}", fraction)
+            }
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// The `_id`s of the sampled files among those matching `file_query`.
+    /// Reads only the key fields of the matching files to choose them.
+    pub fn select(self, db: &Database, file_query: &Document) -> Result<Vec<Bson>> {
+        let keys = db
+            .collection::<Document>("file")
+            .find(file_query.clone())
+            .projection(doc! { "submission": 1, "id_namespace": 1, "local_id": 1 })
+            .run()?;
+
+        let mut ids: Vec<Bson> = Vec::new();
+        // Per submission, a max-heap of the smallest hashes seen so far
+        let mut lowest: HashMap<String, BinaryHeap<(u64, usize)>> = HashMap::new();
+        let mut total: u64 = 0;
+        for key in keys {
+            let key = key?;
+            total += 1;
+            let hash = fnv1a(&[
+                key.get_str("id_namespace").unwrap_or_default(),
+                key.get_str("local_id").unwrap_or_default(),
+            ]);
+            let Some(id) = key.get("_id").cloned() else {
+                continue;
+            };
+            match self {
+                Sample::Fraction(fraction) => {
+                    if (hash as f64) < fraction * u64::MAX as f64 {
+                        ids.push(id);
+                    }
+                }
+                Sample::Count(count) => {
+                    let heap = lowest
+                        .entry(key.get_str("submission").unwrap_or_default().to_string())
+                        .or_default();
+                    if heap.len() < count {
+                        heap.push((hash, ids.len()));
+                        ids.push(id);
+                    } else if heap.peek().is_some_and(|&(top, _)| hash < top) {
+                        let (_, slot) = heap.pop().unwrap();
+                        heap.push((hash, slot));
+                        ids[slot] = id;
+                    }
+                }
+            }
+        }
+        println!("  Sampled {} of {} files", ids.len(), total);
+        Ok(ids)
+    }
+}
+
+/// `_id`s per query reading sampled files: 10,000 ObjectIds come to about
+/// 200KB of `$in`, well inside MongoDB's 16MB command limit.
+pub const ID_BATCH: usize = 10_000;
+
+/// `file_query` narrowed to `ids`, as one query per [`ID_BATCH`] of them,
+/// so a sample of any size is read without one oversized `$in`.
+pub fn batches(file_query: &Document, ids: &[Bson]) -> Vec<Document> {
+    ids.chunks(ID_BATCH)
+        .map(|chunk| doc! { "$and": [file_query.clone(), { "_id": { "$in": chunk } }] })
+        .collect()
+}
+
+/// 64-bit FNV-1a over `parts` joined by NUL; stable across builds, unlike
+/// the standard library's hasher.
+fn fnv1a(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in parts.join("\0").bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_is_stable_across_builds() {
+        assert_eq!(fnv1a(&[]), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(&["a"]), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(&["ns", "f1"]), fnv1a(&["ns", "f1"]));
+    }
+
+    #[test]
+    fn fnv1a_separates_parts() {
+        assert_ne!(fnv1a(&["ab", "c"]), fnv1a(&["a", "bc"]));
+    }
+
+    #[test]
+    fn parses_at_most_one_sample_option() {
+        assert!(matches!(
+            Sample::parse(Some(5), None),
+            Ok(Some(Sample::Count(5)))
+        ));
+        assert!(matches!(
+            Sample::parse(None, Some(1.0)),
+            Ok(Some(Sample::Fraction(_)))
+        ));
+        assert!(Sample::parse(None, None).unwrap().is_none());
+        assert!(Sample::parse(Some(5), Some(0.5)).is_err());
+        assert!(Sample::parse(None, Some(0.0)).is_err());
+        assert!(Sample::parse(None, Some(1.5)).is_err());
+    }
+
+    #[test]
+    fn batches_split_the_ids() {
+        let ids: Vec<Bson> = (0..(ID_BATCH as i32 * 2 + 1)).map(Bson::Int32).collect();
+        let query = doc! { "submission": "s" };
+        let batches = batches(&query, &ids);
+        assert_eq!(batches.len(), 3);
+        let sizes: Vec<usize> = batches
+            .iter()
+            .map(|batch| {
+                let clauses = batch.get_array("$and").unwrap();
+                assert_eq!(clauses[0].as_document().unwrap(), &query);
+                let id = clauses[1]
+                    .as_document()
+                    .unwrap()
+                    .get_document("_id")
+                    .unwrap();
+                id.get_array("$in").unwrap().len()
+            })
+            .collect();
+        assert_eq!(sizes, vec![ID_BATCH, ID_BATCH, 1]);
+    }
+}
@@ -0,0 +1,69 @@
+use bson::{Bson, Document};
+use serde::Deserialize;
+
+/// What to do with fields whose value is an empty (or all-whitespace)
+/// string. Submissions leave optional TSV columns like `persistent_id`,
+/// `md5`, and `mime_type` blank, which otherwise sort first and fill the
+/// indexes with `""`. Applied to the enriched file and every document
+/// embedded in it.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScrubConfig {
+    pub empty_strings: Scrub,
+    /// Field names left as they are, wherever they appear
+    pub keep: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scrub {
+    /// Drop the field
+    #[default]
+    Remove,
+    /// Set the field to null
+    Null,
+    /// Leave empty strings in place
+    Off,
+}
+
+impl ScrubConfig {
+    /// Scrub empty strings from `doc` and its subdocuments, including
+    /// documents inside arrays. Returns the number of fields scrubbed.
+    pub fn apply(&self, doc: &mut Document) -> usize {
+        if self.empty_strings == Scrub::Off {
+            return 0;
+        }
+        let mut scrubbed = 0;
+        let empty: Vec<String> = doc
+            .iter()
+            .filter(|(key, value)| {
+                matches!(value, Bson::String(s) if s.trim().is_empty())
+                    && !self.keep.iter().any(|k| k == *key)
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in empty {
+            match self.empty_strings {
+                Scrub::Remove => {
+                    doc.remove(&key);
+                }
+                _ => {
+                    doc.insert(key, Bson::Null);
+                }
+            }
+            scrubbed += 1;
+        }
+        for (_, value) in doc.iter_mut() {
+            scrubbed += self.apply_value(value);
+        }
+        scrubbed
+    }
+
+    fn apply_value(&self, value: &mut Bson) -> usize {
+        match value {
+            Bson::Document(doc) => self.apply(doc),
+            Bson::Array(items) => items.iter_mut().map(|item| self.apply_value(item)).sum(),
+            _ => 0,
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use bson::{Bson, Document};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+
+use crate::spec::{EnrichmentSpec, Entity};
+
+/// Text of an enriched file that keyword search should match, gathered
+/// into one `search_text` field so a single text index covers nested
+/// fields: filename, persistent_id, the DCC's name and abbreviation, the
+/// names of every embedded term, and the names of its collections.
+/// Each value appears once, in the order it is found.
+pub fn search_text(file: &Document, spec: &EnrichmentSpec) -> String {
+    let mut text = SearchText::default();
+    text.push(file.get("filename"));
+    text.push(file.get("persistent_id"));
+    if let Ok(dcc) = file.get_document("dcc") {
+        text.push(dcc.get("dcc_name"));
+        text.push(dcc.get("dcc_abbreviation"));
+    }
+    text.terms(file, spec, Entity::File);
+
+    let mut biosamples: Vec<&Document> = Vec::new();
+    for coll in documents(file, "collections") {
+        text.push(coll.get("name"));
+        text.terms(coll, spec, Entity::Collection);
+        for field in ["anatomies", "diseases"] {
+            for term in documents(coll, field) {
+                text.push(term.get("name"));
+            }
+        }
+        biosamples.extend(documents(coll, "biosamples"));
+    }
+    // Hoisted with `--hoist-biosamples`
+    biosamples.extend(documents(file, "biosamples"));
+    for biosample in biosamples {
+        text.terms(biosample, spec, Entity::Biosample);
+        for field in ["substances", "genes"] {
+            for term in documents(biosample, field) {
+                text.push(term.get("name"));
+            }
+        }
+        for subject in documents(biosample, "subjects") {
+            text.terms(subject, spec, Entity::Subject);
+            for race in documents(subject, "race") {
+                text.push(race.get("name"));
+            }
+        }
+    }
+    text.words.join(" ")
+}
+
+#[derive(Default)]
+struct SearchText<'a> {
+    seen: HashSet<&'a str>,
+    words: Vec<&'a str>,
+}
+
+impl<'a> SearchText<'a> {
+    fn push(&mut self, value: Option<&'a Bson>) {
+        if let Some(Bson::String(value)) = value {
+            let value = value.trim();
+            if !value.is_empty() && self.seen.insert(value) {
+                self.words.push(value);
+            }
+        }
+    }
+
+    /// Names of the resolved spec terms embedded in `doc`; missed lookups
+    /// leave a raw id, which isn't searchable text.
+    fn terms(&mut self, doc: &'a Document, spec: &EnrichmentSpec, entity: Entity) {
+        for term in spec.terms_for(entity) {
+            if let Ok(term) = doc.get_document(&term.field) {
+                self.push(term.get("name"));
+            }
+        }
+    }
+}
+
+/// The subdocuments of the array `field`, if any.
+fn documents<'a>(doc: &'a Document, field: &str) -> impl Iterator<Item = &'a Document> {
+    doc.get_array(field)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(Bson::as_document)
+}
+
+/// Where `search-index apply` keeps an Atlas Search index over the
+/// materialized files (`[atlas_search]`). The Admin API keys come from
+/// `ATLAS_PUBLIC_KEY` and `ATLAS_PRIVATE_KEY`, never the config file.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AtlasSearchConfig {
+    /// Atlas project (group) id
+    pub project_id: Option<String>,
+    pub cluster: Option<String>,
+    pub index_name: String,
+    pub collection: String,
+}
+
+impl Default for AtlasSearchConfig {
+    fn default() -> Self {
+        AtlasSearchConfig {
+            project_id: None,
+            cluster: None,
+            index_name: "default".to_string(),
+            collection: "files".to_string(),
+        }
+    }
+}
+
+/// Static Atlas Search mappings for enriched files: `search_text` and the
+/// names of every spec term as full text, ids as exact tokens, autocomplete
+/// on `filename`, and collections, biosamples, and subjects as embedded
+/// documents so a query can match fields of the same nested entity.
+pub fn atlas_definition(spec: &EnrichmentSpec) -> Value {
+    let string = json!({ "type": "string" });
+    let token = json!({ "type": "token" });
+    let term = json!({
+        "type": "document",
+        "fields": { "id": token, "name": string },
+    });
+    let terms = |entity| -> Map<String, Value> {
+        spec.terms_for(entity)
+            .map(|t| (t.field.clone(), term.clone()))
+            .collect()
+    };
+    let embedded = |fields: Map<String, Value>| {
+        json!({
+            "type": "embeddedDocuments",
+            "dynamic": false,
+            "fields": fields,
+        })
+    };
+
+    let mut subject = terms(Entity::Subject);
+    subject.insert("local_id".to_string(), token.clone());
+    subject.insert("race".to_string(), term.clone());
+
+    let mut biosample = terms(Entity::Biosample);
+    biosample.insert("local_id".to_string(), token.clone());
+    biosample.insert("substances".to_string(), term.clone());
+    biosample.insert("genes".to_string(), term.clone());
+    biosample.insert("subjects".to_string(), embedded(subject));
+
+    let mut collection = terms(Entity::Collection);
+    collection.insert("local_id".to_string(), token.clone());
+    collection.insert("name".to_string(), string.clone());
+    collection.insert("anatomies".to_string(), term.clone());
+    collection.insert("diseases".to_string(), term.clone());
+    collection.insert("biosamples".to_string(), embedded(biosample.clone()));
+
+    let mut fields = terms(Entity::File);
+    fields.insert(
+        "filename".to_string(),
+        json!([{ "type": "string" }, { "type": "autocomplete" }]),
+    );
+    fields.insert("search_text".to_string(), string.clone());
+    fields.insert("persistent_id".to_string(), token.clone());
+    fields.insert("submission".to_string(), token.clone());
+    // Set with `[[enrichers.categories]]`
+    fields.insert("category".to_string(), token.clone());
+    fields.insert(
+        "dcc".to_string(),
+        json!({
+            "type": "document",
+            "fields": { "dcc_name": string, "dcc_abbreviation": token },
+        }),
+    );
+    fields.insert("size_in_bytes".to_string(), json!({ "type": "number" }));
+    fields.insert("creation_time".to_string(), json!({ "type": "date" }));
+    fields.insert("collections".to_string(), embedded(collection));
+    // Hoisted with `--hoist-biosamples`
+    fields.insert("biosamples".to_string(), embedded(biosample));
+    json!({ "mappings": { "dynamic": false, "fields": fields } })
+}
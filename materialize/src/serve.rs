@@ -0,0 +1,190 @@
+use anyhow::Result;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use bson::{doc, Bson, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Client, Collection};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::cli::{number, value};
+use crate::connection::DATABASE;
+
+/// Default `--addr`.
+const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+
+/// Files returned per page without `limit`, and the most one page may hold.
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 1000;
+
+/// Search parameters for `/files`. Term filters match the embedded term's
+/// `id` or `name`; `anatomy` also matches ancestors when UBERON was loaded.
+#[derive(Deserialize)]
+struct FileQuery {
+    format: Option<String>,
+    data_type: Option<String>,
+    assay: Option<String>,
+    anatomy: Option<String>,
+    dcc: Option<String>,
+    submission: Option<String>,
+    q: Option<String>,
+    limit: Option<i64>,
+    skip: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct FileKey {
+    id_namespace: String,
+    local_id: String,
+}
+
+/// `serve [--addr HOST:PORT] [--uri MONGODB_URI] [--workers N]` exposes
+/// read-only search endpoints over the materialized `files` collection:
+///
+/// - `GET /files?format=&data_type=&assay=&anatomy=&dcc=&submission=&q=&limit=&skip=`
+/// - `GET /file?id_namespace=&local_id=`
+/// - `GET /health`
+pub fn command(uri: &str, args: &[String]) -> Result<()> {
+    let addr = value(args, "--addr").unwrap_or_else(|| DEFAULT_ADDR.to_string());
+    let uri = value(args, "--uri").unwrap_or_else(|| uri.to_string());
+    let workers: Option<usize> = number(args, "--workers")?;
+
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    if let Some(workers) = workers {
+        runtime.worker_threads(workers);
+    }
+    runtime.enable_all().build()?.block_on(async {
+        let client = Client::with_uri_str(&uri).await?;
+        let files: Collection<Document> = client.database(DATABASE).collection("files");
+        let app = Router::new()
+            .route("/files", get(search))
+            .route("/file", get(file))
+            .route("/health", get(health))
+            .with_state(files);
+        let listener = tokio::net::TcpListener::bind(&addr).await?;
+        println!("Serving files on http://{}", addr);
+        axum::serve(listener, app).await?;
+        Ok(())
+    })
+}
+
+async fn search(
+    State(files): State<Collection<Document>>,
+    Query(query): Query<FileQuery>,
+) -> Response {
+    let filter = filter(&query);
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let skip = query.skip.unwrap_or(0);
+    let result = async {
+        let total = files.count_documents(filter.clone()).await?;
+        let mut cursor = files
+            .find(filter)
+            .with_options(
+                FindOptions::builder()
+                    .sort(doc! { "id_namespace": 1, "local_id": 1 })
+                    .skip(skip)
+                    .limit(limit)
+                    .projection(doc! { "_id": 0 })
+                    .build(),
+            )
+            .await?;
+        let mut page = Vec::new();
+        while cursor.advance().await? {
+            page.push(to_json(cursor.deserialize_current()?));
+        }
+        Ok::<_, mongodb::error::Error>(
+            json!({ "total": total, "skip": skip, "limit": limit, "files": page }),
+        )
+    }
+    .await;
+    match result {
+        Ok(body) => Json(body).into_response(),
+        Err(e) => error(e),
+    }
+}
+
+async fn file(State(files): State<Collection<Document>>, Query(key): Query<FileKey>) -> Response {
+    let found = files
+        .find_one(doc! { "id_namespace": &key.id_namespace, "local_id": &key.local_id })
+        .projection(doc! { "_id": 0 })
+        .await;
+    match found {
+        Ok(Some(file)) => Json(to_json(file)).into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "file not found" })),
+        )
+            .into_response(),
+        Err(e) => error(e),
+    }
+}
+
+async fn health(State(files): State<Collection<Document>>) -> Response {
+    match files.estimated_document_count().await {
+        Ok(count) => Json(json!({ "status": "ok", "files": count })).into_response(),
+        Err(e) => error(e),
+    }
+}
+
+fn error(e: mongodb::error::Error) -> Response {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "error": e.to_string() })),
+    )
+        .into_response()
+}
+
+fn to_json(file: Document) -> Value {
+    Bson::Document(file).into_relaxed_extjson()
+}
+
+/// The `files` filter for a search; every given parameter must match.
+fn filter(query: &FileQuery) -> Document {
+    let mut clauses: Vec<Document> = Vec::new();
+    let mut term = |value: &Option<String>, paths: &[&str]| {
+        if let Some(value) = value {
+            let any: Vec<Document> = paths.iter().map(|path| doc! { *path: value }).collect();
+            clauses.push(doc! { "$or": any });
+        }
+    };
+    term(&query.format, &["file_format.id", "file_format.name"]);
+    term(&query.data_type, &["data_type.id", "data_type.name"]);
+    term(&query.assay, &["assay_type.id", "assay_type.name"]);
+    term(
+        &query.anatomy,
+        &[
+            "collections.biosamples.anatomy.id",
+            "collections.biosamples.anatomy.name",
+            "collections.biosamples.anatomy.ancestors.id",
+            "collections.biosamples.anatomy.ancestors.name",
+            "biosamples.anatomy.id",
+            "biosamples.anatomy.name",
+            "biosamples.anatomy.ancestors.id",
+            "biosamples.anatomy.ancestors.name",
+        ],
+    );
+    term(&query.dcc, &["dcc.dcc_abbreviation", "dcc.dcc_name"]);
+    term(&query.submission, &["submission"]);
+    if let Some(ref q) = query.q {
+        clauses.push(doc! { "filename": { "$regex": escape_regex(q), "$options": "i" } });
+    }
+    if clauses.is_empty() {
+        doc! {}
+    } else {
+        doc! { "$and": clauses }
+    }
+}
+
+fn escape_regex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,198 @@
+use anyhow::Result;
+use bson::{doc, Bson, Document};
+use mongodb::sync::{Client, Collection, Database};
+use mongodb::IndexModel;
+
+/// Shard key for the output collection (`--shard-key`), e.g.
+/// `submission,local_id:hashed`: fields in order, at most one hashed.
+pub struct ShardKey {
+    fields: Vec<(String, bool)>,
+}
+
+impl ShardKey {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let fields: Vec<(String, bool)> = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(|field| match field.strip_suffix(":hashed") {
+                Some(field) => (field.to_string(), true),
+                None => (field.to_string(), false),
+            })
+            .collect();
+        if fields.is_empty() {
+            anyhow::bail!("--shard-key needs at least one field");
+        }
+        if fields.iter().filter(|(_, hashed)| *hashed).count() > 1 {
+            anyhow::bail!("--shard-key may hash only one field");
+        }
+        Ok(ShardKey { fields })
+    }
+
+    fn keys(&self) -> Document {
+        self.fields
+            .iter()
+            .map(|(field, hashed)| {
+                let order = if *hashed {
+                    Bson::String("hashed".to_string())
+                } else {
+                    Bson::Int32(1)
+                };
+                (field.clone(), order)
+            })
+            .collect()
+    }
+
+    /// A hashed leading field spreads inserts by itself and is pre-split by
+    /// the server; a ranged one is split here.
+    fn hashed_prefix(&self) -> bool {
+        self.fields[0].1
+    }
+}
+
+fn is_sharded_cluster(client: &Client) -> Result<bool> {
+    let hello = client
+        .database("admin")
+        .run_command(doc! { "hello": 1 })
+        .run()?;
+    Ok(hello.get_str("msg") == Ok("isdbgrid"))
+}
+
+fn is_sharded(client: &Client, namespace: &str) -> Result<bool> {
+    Ok(client
+        .database("config")
+        .collection::<Document>("collections")
+        .find_one(doc! { "_id": namespace, "dropped": { "$ne": true } })
+        .run()?
+        .is_some())
+}
+
+/// The key `namespace` is sharded on; none off a sharded cluster.
+pub fn shard_key(client: &Client, namespace: &str) -> Result<Option<Document>> {
+    if !is_sharded_cluster(client)? {
+        return Ok(None);
+    }
+    Ok(client
+        .database("config")
+        .collection::<Document>("collections")
+        .find_one(doc! { "_id": namespace, "dropped": { "$ne": true } })
+        .run()?
+        .and_then(|coll| coll.get_document("key").ok().cloned()))
+}
+
+/// Point out an unsharded output collection on a sharded cluster, where
+/// every bulk insert lands on its primary shard.
+pub fn advise(client: &Client, coll: &Collection<Document>) -> Result<()> {
+    let namespace = coll.namespace().to_string();
+    if is_sharded_cluster(client)? && !is_sharded(client, &namespace)? {
+        println!(
+            "  Note: {} is unsharded on a sharded cluster, so its inserts all hit one shard; \
+             consider --shard-key submission,local_id:hashed",
+            namespace
+        );
+    }
+    Ok(())
+}
+
+/// Shard `coll` on `key` before anything is written to it. A hashed
+/// leading field gets two initial chunks per shard from the server; a
+/// ranged leading field is split at the boundaries of equal-sized buckets
+/// of its values among the source `file` rows matching `file_query`, so
+/// the balancer can spread the chunks before the inserts arrive. Does
+/// nothing off a sharded cluster or on an already sharded collection.
+pub fn prepare(
+    client: &Client,
+    source: &Database,
+    file_query: &Document,
+    coll: &Collection<Document>,
+    key: &ShardKey,
+) -> Result<()> {
+    let namespace = coll.namespace().to_string();
+    if !is_sharded_cluster(client)? {
+        println!(
+            "  --shard-key: not a sharded cluster, writing {} unsharded",
+            namespace
+        );
+        return Ok(());
+    }
+    if is_sharded(client, &namespace)? {
+        println!("  {} is already sharded", namespace);
+        return Ok(());
+    }
+
+    let admin = client.database("admin");
+    let shards = admin
+        .run_command(doc! { "listShards": 1 })
+        .run()?
+        .get_array("shards")
+        .map(Vec::len)
+        .unwrap_or(1)
+        .max(1);
+    let chunks = (shards * 2) as i32;
+    let empty = coll.estimated_document_count().run()? == 0;
+
+    // Sharding a collection that already holds documents needs the index
+    coll.create_index(IndexModel::builder().keys(key.keys()).build())
+        .run()?;
+    admin
+        .run_command(doc! { "enableSharding": coll.namespace().db })
+        .run()?;
+    let mut command = doc! { "shardCollection": &namespace, "key": key.keys() };
+    if empty && key.hashed_prefix() {
+        command.insert("numInitialChunks", chunks);
+    }
+    admin.run_command(command).run()?;
+    println!(
+        "  Sharded {} on {} across {} shards",
+        namespace,
+        key.keys(),
+        shards
+    );
+    if !empty || key.hashed_prefix() {
+        return Ok(());
+    }
+
+    let leading = &key.fields[0].0;
+    let pipeline = vec![
+        doc! { "$match": file_query.clone() },
+        doc! { "$bucketAuto": { "groupBy": format!("${}", leading), "buckets": chunks } },
+    ];
+    let mut splits = 0;
+    for (i, bucket) in source
+        .collection::<Document>("file")
+        .aggregate(pipeline)
+        .allow_disk_use(true)
+        .run()?
+        .enumerate()
+    {
+        let bucket = bucket?;
+        let Some(min) = bucket.get_document("_id").ok().and_then(|id| id.get("min")) else {
+            continue;
+        };
+        // The first bucket starts at MinKey already
+        if i == 0 || *min == Bson::Null {
+            continue;
+        }
+        let middle: Document = key
+            .fields
+            .iter()
+            .map(|(field, _)| {
+                let value = if field == leading {
+                    min.clone()
+                } else {
+                    Bson::MinKey
+                };
+                (field.clone(), value)
+            })
+            .collect();
+        admin
+            .run_command(doc! { "split": &namespace, "middle": middle })
+            .run()?;
+        splits += 1;
+    }
+    println!(
+        "  Pre-split {} at {} {} boundaries",
+        namespace, splits, leading
+    );
+    Ok(())
+}
@@ -0,0 +1,245 @@
+use anyhow::{Context, Result};
+use bson::{doc, Bson, Document};
+use std::collections::HashMap;
+
+/// Side collection holding the relations of documents that exceeded the size
+/// budget, one edge document per (file, collection).
+pub const RELATIONS_COLLECTION: &str = "file_relations";
+
+/// MongoDB's hard limit on a BSON document. One document over it fails the
+/// whole insert batch.
+const MONGO_DOCUMENT_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Budget used without `--max-doc-size`, leaving headroom under the limit
+/// for `_id` and the `size_policy` record.
+pub const DEFAULT_BUDGET: usize = MONGO_DOCUMENT_LIMIT - 64 * 1024;
+
+/// Arrays halved, in order, while a document with collection stubs is still
+/// over budget, with the `size_policy` field recording how many were kept.
+const TRUNCATED_ARRAYS: [(&str, &str); 2] = [
+    ("collections", "inline_relations"),
+    ("biosamples", "inline_biosamples"),
+];
+
+/// Fields kept on the inline collection stubs of an oversized document.
+const STUB_FIELDS: [&str; 4] = ["id_namespace", "local_id", "name", "abbreviation"];
+
+/// Keeps enriched documents under a size budget: `--max-doc-size`, or just
+/// under MongoDB's 16MB limit.
+///
+/// Documents within budget are written as-is. Larger ones switch to the edge
+/// strategy: each embedded collection (with its biosamples) moves to an edge
+/// document in [`RELATIONS_COLLECTION`], and the file keeps only collection
+/// stubs. If the stubs (and hoisted biosamples) still don't fit, those
+/// arrays are truncated and the file is marked `truncated: true`; the full
+/// relations are still in the edges. The decision is recorded on the file
+/// under `size_policy`.
+pub struct SizePolicy {
+    budget: usize,
+}
+
+impl SizePolicy {
+    pub fn new(budget: Option<usize>) -> Self {
+        SizePolicy {
+            budget: budget.map_or(DEFAULT_BUDGET, |b| b.min(DEFAULT_BUDGET)),
+        }
+    }
+
+    /// Apply the policy to `file`, returning the edge documents to write to
+    /// [`RELATIONS_COLLECTION`] (empty when the file fits).
+    pub fn apply(&self, file: &mut Document) -> Result<Vec<Document>> {
+        let original = encoded_len(file)?;
+        if original <= self.budget {
+            return Ok(Vec::new());
+        }
+
+        let collections = match file.remove("collections") {
+            Some(Bson::Array(collections)) => collections,
+            _ => Vec::new(),
+        };
+        let file_key = doc! {
+            "file_id_namespace": file.get_str("id_namespace").unwrap_or_default(),
+            "file_local_id": file.get_str("local_id").unwrap_or_default(),
+            "submission": file.get_str("submission").unwrap_or_default(),
+        };
+
+        // Edges carry whole collections, so hoisted biosamples are nested
+        // back under the collections that reference them
+        let hoisted: HashMap<(&str, &str), &Document> = file
+            .get_array("biosamples")
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(Bson::as_document)
+            .filter_map(|bio| Some((key(bio)?, bio)))
+            .collect();
+
+        let mut stubs: Vec<Bson> = Vec::with_capacity(collections.len());
+        let mut edges: Vec<Document> = Vec::with_capacity(collections.len());
+        for collection in collections {
+            let Bson::Document(mut collection) = collection else {
+                continue;
+            };
+            if let Some(Bson::Array(refs)) = collection.remove("biosample_refs") {
+                let biosamples: Vec<Document> = refs
+                    .iter()
+                    .filter_map(Bson::as_document)
+                    .filter_map(|r| hoisted.get(&key(r)?).map(|bio| (*bio).clone()))
+                    .collect();
+                collection.insert("biosamples", biosamples);
+            }
+            let mut stub = Document::new();
+            for field in STUB_FIELDS {
+                if let Some(value) = collection.get(field) {
+                    stub.insert(field, value.clone());
+                }
+            }
+            stubs.push(Bson::Document(stub));
+            let mut edge = file_key.clone();
+            edge.insert("collection", collection);
+            edges.push(edge);
+        }
+
+        let total = stubs.len();
+        let mut decision = doc! {
+            "strategy": "sidecar",
+            "original_bytes": original as i64,
+            "budget_bytes": self.budget as i64,
+            "relations": total as i64,
+        };
+        file.insert("collections", stubs);
+        file.insert("size_policy", decision.clone());
+
+        // Stubs for thousands of collections can still blow the budget, so
+        // halve them (then any hoisted biosamples) until the document fits
+        for (field, kept_field) in TRUNCATED_ARRAYS {
+            while encoded_len(file)? > self.budget {
+                let Some(Bson::Array(items)) = file.get_mut(field) else {
+                    break;
+                };
+                if items.is_empty() {
+                    break;
+                }
+                items.truncate(items.len() / 2);
+                let kept = items.len();
+                decision.insert("truncated", true);
+                decision.insert(kept_field, kept as i64);
+                file.insert("size_policy", decision.clone());
+                file.insert("truncated", true);
+            }
+        }
+
+        Ok(edges)
+    }
+}
+
+fn key(doc: &Document) -> Option<(&str, &str)> {
+    Some((
+        doc.get_str("id_namespace").ok()?,
+        doc.get_str("local_id").ok()?,
+    ))
+}
+
+/// Size of `doc` as BSON.
+pub fn encoded_len(doc: &Document) -> Result<usize> {
+    let mut buf = Vec::new();
+    doc.to_writer(&mut buf)?;
+    Ok(buf.len())
+}
+
+/// Parse a size such as `2097152`, `512KB`, or `2MB` (binary units).
+pub fn parse_size(size: &str) -> Result<usize> {
+    let size = size.trim();
+    let upper = size.to_ascii_uppercase();
+    let (number, multiplier) = if let Some(n) = upper.strip_suffix("MB") {
+        (n, 1024 * 1024)
+    } else if let Some(n) = upper.strip_suffix("KB") {
+        (n, 1024)
+    } else if let Some(n) = upper.strip_suffix('B') {
+        (n, 1)
+    } else {
+        (upper.as_str(), 1)
+    };
+    let number: usize = number
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid size {:?}", size))?;
+    Ok(number * multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file with `count` collections, each padded with `padding` bytes.
+    fn file(count: usize, padding: usize) -> Document {
+        let collections: Vec<Document> = (0..count)
+            .map(|i| {
+                doc! {
+                    "id_namespace": "ns",
+                    "local_id": format!("c{}", i),
+                    "name": format!("collection {}", i),
+                    "description": "x".repeat(padding),
+                }
+            })
+            .collect();
+        doc! {
+            "id_namespace": "ns",
+            "local_id": "f",
+            "submission": "demo",
+            "collections": collections,
+        }
+    }
+
+    #[test]
+    fn parses_binary_units() {
+        assert_eq!(parse_size("2097152").unwrap(), 2_097_152);
+        assert_eq!(parse_size("512KB").unwrap(), 512 * 1024);
+        assert_eq!(parse_size(" 2mb ").unwrap(), 2 * 1024 * 1024);
+        assert_eq!(parse_size("10B").unwrap(), 10);
+        assert!(parse_size("2GB").is_err());
+        assert!(parse_size("lots").is_err());
+    }
+
+    #[test]
+    fn leaves_files_within_budget_alone() {
+        let mut doc = file(2, 10);
+        let before = doc.clone();
+        let edges = SizePolicy::new(None).apply(&mut doc).unwrap();
+        assert!(edges.is_empty());
+        assert_eq!(doc, before);
+    }
+
+    #[test]
+    fn moves_collections_of_oversized_files_to_edges() {
+        let mut doc = file(3, 1000);
+        let edges = SizePolicy::new(Some(2000)).apply(&mut doc).unwrap();
+        assert_eq!(edges.len(), 3);
+        assert_eq!(edges[0].get_str("file_local_id").unwrap(), "f");
+        let collection = edges[0].get_document("collection").unwrap();
+        assert!(collection.contains_key("description"));
+
+        let stubs = doc.get_array("collections").unwrap();
+        assert_eq!(stubs.len(), 3);
+        assert!(!stubs[0].as_document().unwrap().contains_key("description"));
+        let decision = doc.get_document("size_policy").unwrap();
+        assert_eq!(decision.get_str("strategy").unwrap(), "sidecar");
+        assert_eq!(decision.get_i64("relations").unwrap(), 3);
+        assert!(!decision.contains_key("truncated"));
+    }
+
+    #[test]
+    fn halves_stubs_until_the_file_fits() {
+        let mut doc = file(64, 0);
+        let edges = SizePolicy::new(Some(600)).apply(&mut doc).unwrap();
+        assert_eq!(edges.len(), 64);
+        assert!(encoded_len(&doc).unwrap() <= 600);
+
+        let kept = doc.get_array("collections").unwrap().len();
+        assert!(kept > 0 && kept < 64);
+        let decision = doc.get_document("size_policy").unwrap();
+        assert!(decision.get_bool("truncated").unwrap());
+        assert_eq!(decision.get_i64("inline_relations").unwrap(), kept as i64);
+        assert!(doc.get_bool("truncated").unwrap());
+    }
+}
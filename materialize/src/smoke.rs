@@ -0,0 +1,116 @@
+use anyhow::Result;
+use bson::{doc, Bson, Document};
+use mongodb::sync::{Collection, Database};
+use serde::Deserialize;
+use std::time::Instant;
+
+use crate::error::MaterializeError;
+
+/// A check run against the freshly written `files` collection; the run fails
+/// if any check fails. Checks are scoped to the run's submission.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SmokeQuery {
+    pub name: String,
+    /// `files` filter to count; ignored when `pipeline` is set
+    #[serde(default)]
+    pub filter: Document,
+    /// Aggregation pipeline whose result documents are counted
+    #[serde(default)]
+    pub pipeline: Vec<Document>,
+    /// Fewest matching documents (or pipeline results) that pass
+    #[serde(default = "default_min_results")]
+    pub min_results: u64,
+    /// Slowest passing response time
+    pub max_ms: Option<u64>,
+}
+
+fn default_min_results() -> u64 {
+    1
+}
+
+/// Used when the config file has no `[[smoke]]` checks.
+fn default_queries() -> Vec<SmokeQuery> {
+    vec![SmokeQuery {
+        name: "files written".to_string(),
+        filter: doc! {},
+        pipeline: Vec::new(),
+        min_results: 1,
+        max_ms: None,
+    }]
+}
+
+/// Run every check and return the per-check results for the run record.
+/// Fails with the list of failing checks if any did not pass.
+pub fn verify(
+    db: &Database,
+    queries: &[SmokeQuery],
+    submission: &Option<String>,
+) -> Result<Vec<Document>> {
+    let defaults;
+    let queries = if queries.is_empty() {
+        defaults = default_queries();
+        &defaults
+    } else {
+        queries
+    };
+    let coll: Collection<Document> = db.collection("files");
+
+    let mut results = Vec::with_capacity(queries.len());
+    let mut failures = Vec::new();
+    for query in queries {
+        let started = Instant::now();
+        let found = if query.pipeline.is_empty() {
+            let mut filter = query.filter.clone();
+            if let Some(sub) = submission {
+                filter = doc! { "$and": [filter, { "submission": sub }] };
+            }
+            coll.count_documents(filter).run()?
+        } else {
+            let mut pipeline = query.pipeline.clone();
+            if let Some(sub) = submission {
+                pipeline.insert(0, doc! { "$match": { "submission": sub } });
+            }
+            coll.aggregate(pipeline).run()?.count() as u64
+        };
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+
+        let mut problems = Vec::new();
+        if found < query.min_results {
+            problems.push(format!(
+                "{} results, expected at least {}",
+                found, query.min_results
+            ));
+        }
+        if let Some(max_ms) = query.max_ms.filter(|&max| elapsed_ms > max) {
+            problems.push(format!("took {} ms, limit {} ms", elapsed_ms, max_ms));
+        }
+        let passed = problems.is_empty();
+        println!(
+            "  {} {}: {} results in {} ms",
+            if passed { "ok  " } else { "FAIL" },
+            query.name,
+            found,
+            elapsed_ms
+        );
+        if !passed {
+            failures.push(format!("{} ({})", query.name, problems.join("; ")));
+        }
+        results.push(doc! {
+            "name": &query.name,
+            "results": found as i64,
+            "elapsed_ms": elapsed_ms as i64,
+            "passed": passed,
+            "problems": problems.into_iter().map(Bson::from).collect::<Vec<_>>(),
+        });
+    }
+
+    if !failures.is_empty() {
+        return Err(MaterializeError::Validation(format!(
+            "Smoke queries failed: {}",
+            failures.join(", ")
+        ))
+        .into());
+    }
+    Ok(results)
+}
@@ -0,0 +1,123 @@
+use serde::Deserialize;
+
+/// Which vocabulary references are resolved on which entity. Each term names
+/// the field holding the raw id, the lookup table (source collection) it
+/// resolves against, and optionally the ontology whose ancestors it carries.
+/// Adding a C2M2 CV table is a matter of adding a `[[enrichment.terms]]`
+/// entry; the entity nesting itself follows the C2M2 association tables.
+/// A config file that lists terms replaces the built-in list entirely.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnrichmentSpec {
+    pub terms: Vec<TermSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TermSpec {
+    pub entity: Entity,
+    pub field: String,
+    pub table: String,
+    pub ontology: Option<OntologyName>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Entity {
+    File,
+    Collection,
+    Biosample,
+    Subject,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OntologyName {
+    Obi,
+    Uberon,
+}
+
+impl Entity {
+    /// Where this entity's documents sit inside an enriched file.
+    fn path(self) -> &'static str {
+        match self {
+            Entity::File => "",
+            Entity::Collection => "collections.",
+            Entity::Biosample => "collections.biosamples.",
+            Entity::Subject => "collections.biosamples.subjects.",
+        }
+    }
+}
+
+impl Default for EnrichmentSpec {
+    fn default() -> Self {
+        let term = |entity, field: &str, table: &str, ontology| TermSpec {
+            entity,
+            field: field.to_string(),
+            table: table.to_string(),
+            ontology,
+        };
+        EnrichmentSpec {
+            terms: vec![
+                term(Entity::File, "file_format", "file_format", None),
+                // compression_format is an EDAM format term like file_format
+                term(Entity::File, "compression_format", "file_format", None),
+                term(Entity::File, "data_type", "data_type", None),
+                term(
+                    Entity::File,
+                    "assay_type",
+                    "assay_type",
+                    Some(OntologyName::Obi),
+                ),
+                term(
+                    Entity::File,
+                    "analysis_type",
+                    "analysis_type",
+                    Some(OntologyName::Obi),
+                ),
+                term(
+                    Entity::Biosample,
+                    "anatomy",
+                    "anatomy",
+                    Some(OntologyName::Uberon),
+                ),
+                term(Entity::Subject, "granularity", "subject_granularity", None),
+                term(Entity::Subject, "sex", "subject_sex", None),
+                term(Entity::Subject, "ethnicity", "subject_ethnicity", None),
+            ],
+        }
+    }
+}
+
+impl EnrichmentSpec {
+    /// Lookup tables the terms resolve against, each listed once.
+    pub fn tables(&self) -> Vec<&str> {
+        let mut tables: Vec<&str> = Vec::new();
+        for term in &self.terms {
+            if !tables.contains(&term.table.as_str()) {
+                tables.push(&term.table);
+            }
+        }
+        tables
+    }
+
+    pub fn terms_for(&self, entity: Entity) -> impl Iterator<Item = &TermSpec> {
+        self.terms.iter().filter(move |term| term.entity == entity)
+    }
+
+    /// `files` fields indexed for every embedded term: its id and name,
+    /// plus its ancestors' when an ontology is attached.
+    pub fn index_fields(&self) -> Vec<String> {
+        let mut fields = Vec::new();
+        for term in &self.terms {
+            let path = format!("{}{}", term.entity.path(), term.field);
+            fields.push(format!("{}.id", path));
+            fields.push(format!("{}.name", path));
+            if term.ontology.is_some() {
+                fields.push(format!("{}.ancestors.id", path));
+                fields.push(format!("{}.ancestors.name", path));
+            }
+        }
+        fields
+    }
+}
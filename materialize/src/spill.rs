@@ -0,0 +1,158 @@
+use anyhow::{Context, Result};
+use bson::Document;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of enriched documents sorted in memory before being spilled to a run
+/// file.
+pub const SPILL_RUN_SIZE: usize = 100_000;
+
+/// Output order key: (id_namespace, local_id).
+pub fn sort_key(doc: &Document) -> (&str, &str) {
+    (
+        doc.get_str("id_namespace").unwrap_or_default(),
+        doc.get_str("local_id").unwrap_or_default(),
+    )
+}
+
+/// Compare two documents by [`sort_key`].
+pub fn compare(a: &Document, b: &Document) -> Ordering {
+    sort_key(a).cmp(&sort_key(b))
+}
+
+/// External merge sort for enriched documents that don't fit in RAM.
+///
+/// Each pushed run is sorted and written to its own file under a private
+/// directory inside `dir`; [`ExternalSorter::merge`] then streams the runs back
+/// in key order, dropping duplicate keys when `dedupe` is set. The directory is
+/// removed when the sorter (or its merge iterator) is dropped.
+pub struct ExternalSorter {
+    dir: PathBuf,
+    runs: Vec<PathBuf>,
+    dedupe: bool,
+}
+
+impl ExternalSorter {
+    pub fn new(dir: &Path, dedupe: bool) -> Result<Self> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        let dir = dir.join(format!(
+            "materialize-spill-{}-{}",
+            std::process::id(),
+            nanos
+        ));
+        fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+        Ok(ExternalSorter {
+            dir,
+            runs: Vec::new(),
+            dedupe,
+        })
+    }
+
+    /// Sort `docs` and spill them to a new run file.
+    pub fn push_run(&mut self, mut docs: Vec<Document>) -> Result<()> {
+        if docs.is_empty() {
+            return Ok(());
+        }
+        docs.sort_by(compare);
+        let path = self.dir.join(format!("run-{:05}.bson", self.runs.len()));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for doc in &docs {
+            doc.to_writer(&mut writer)?;
+        }
+        writer.into_inner()?.sync_all()?;
+        self.runs.push(path);
+        Ok(())
+    }
+
+    pub fn run_count(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Merge all runs into a single key-ordered stream.
+    pub fn merge(self) -> Result<MergeIter> {
+        let mut readers = Vec::with_capacity(self.runs.len());
+        for path in &self.runs {
+            readers.push(BufReader::new(File::open(path)?));
+        }
+        let mut merge = MergeIter {
+            heads: Vec::with_capacity(readers.len()),
+            readers,
+            heap: BinaryHeap::new(),
+            last_key: None,
+            dedupe: self.dedupe,
+            _sorter: self,
+        };
+        for run in 0..merge.readers.len() {
+            let head = read_next(&mut merge.readers[run])?;
+            merge.heads.push(head);
+            merge.push_head(run);
+        }
+        Ok(merge)
+    }
+}
+
+impl Drop for ExternalSorter {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn read_next(reader: &mut BufReader<File>) -> Result<Option<Document>> {
+    match Document::from_reader(&mut *reader) {
+        Ok(doc) => Ok(Some(doc)),
+        Err(bson::de::Error::Io(e)) if e.kind() == ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Streaming k-way merge over spilled runs.
+pub struct MergeIter {
+    readers: Vec<BufReader<File>>,
+    heads: Vec<Option<Document>>,
+    heap: BinaryHeap<Reverse<(String, String, usize)>>,
+    last_key: Option<(String, String)>,
+    dedupe: bool,
+    // Keeps the spill directory alive until the merge is done
+    _sorter: ExternalSorter,
+}
+
+impl MergeIter {
+    fn push_head(&mut self, run: usize) {
+        if let Some(ref doc) = self.heads[run] {
+            let (ns, id) = sort_key(doc);
+            self.heap
+                .push(Reverse((ns.to_string(), id.to_string(), run)));
+        }
+    }
+}
+
+impl Iterator for MergeIter {
+    type Item = Result<Document>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Reverse((ns, id, run)) = self.heap.pop()?;
+            let doc = self.heads[run].take()?;
+            match read_next(&mut self.readers[run]) {
+                Ok(next) => self.heads[run] = next,
+                Err(e) => return Some(Err(e)),
+            }
+            self.push_head(run);
+
+            // Equal keys come out lowest run first, so the first document
+            // ingested wins
+            if self.dedupe {
+                let key = (ns, id);
+                if self.last_key.as_ref() == Some(&key) {
+                    continue;
+                }
+                self.last_key = Some(key);
+            }
+            return Some(Ok(doc));
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use anyhow::{Context, Result};
+use bson::{doc, Bson, Document};
+use mongodb::sync::Database;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::flatten::{
+    list_values, string_value, term_value, INTEGER_COLUMNS, STRING_COLUMNS, TERM_COLUMNS,
+};
+use crate::projects::integer_field;
+
+/// Write the materialized `files` (optionally one submission) to a standalone
+/// SQLite catalog at `path`, replacing any existing file.
+///
+/// `files` holds one flattened row per file; `collections`, `biosamples`,
+/// and the `file_collections`/`collection_biosamples` junctions keep the
+/// embedded entities normalized; `files_fts` is an FTS5 index over filenames
+/// and term names keyed by `files.rowid`.
+pub fn export(db: &Database, path: &Path, submission: &Option<String>) -> Result<()> {
+    if path.exists() {
+        fs::remove_file(path).with_context(|| format!("removing {}", path.display()))?;
+    }
+    let mut conn = Connection::open(path).with_context(|| format!("opening {}", path.display()))?;
+    conn.execute_batch(&schema())?;
+
+    let query = match submission {
+        Some(sub) => doc! { "submission": sub },
+        None => doc! {},
+    };
+    let columns: Vec<&str> = STRING_COLUMNS
+        .iter()
+        .chain(INTEGER_COLUMNS.iter())
+        .chain(TERM_COLUMNS.iter().map(|(name, _, _)| name))
+        .copied()
+        .collect();
+    let insert_file = format!(
+        "INSERT INTO files ({}) VALUES ({})",
+        columns.join(", "),
+        vec!["?"; columns.len()].join(", ")
+    );
+
+    let tx = conn.transaction()?;
+    let mut files: u64 = 0;
+    {
+        let mut insert_file = tx.prepare(&insert_file)?;
+        let mut insert_fts =
+            tx.prepare("INSERT INTO files_fts (rowid, filename, terms) VALUES (?1, ?2, ?3)")?;
+        let mut insert_collection = tx.prepare(
+            "INSERT OR IGNORE INTO collections (id_namespace, local_id, name, description, submission)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        let mut insert_biosample = tx.prepare(
+            "INSERT OR IGNORE INTO biosamples (id_namespace, local_id, anatomy_id, anatomy_name, submission)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        let mut insert_file_collection =
+            tx.prepare("INSERT OR IGNORE INTO file_collections VALUES (?1, ?2, ?3, ?4)")?;
+        let mut insert_collection_biosample =
+            tx.prepare("INSERT OR IGNORE INTO collection_biosamples VALUES (?1, ?2, ?3, ?4)")?;
+
+        for file in db.collection::<Document>("files").find(query).run()? {
+            let file = file?;
+            let mut values: Vec<Value> = Vec::with_capacity(columns.len());
+            for name in STRING_COLUMNS {
+                values.push(string_value(&file, name).map_or(Value::Null, Value::Text));
+            }
+            for name in INTEGER_COLUMNS {
+                values.push(integer_field(&file, name).map_or(Value::Null, Value::Integer));
+            }
+            for (_, field, key) in TERM_COLUMNS {
+                values.push(text(term_value(&file, field, key)));
+            }
+            insert_file.execute(params_from_iter(values))?;
+            let rowid = tx.last_insert_rowid();
+
+            let mut terms: Vec<&str> = TERM_COLUMNS
+                .iter()
+                .filter(|(name, _, _)| name.ends_with("_name"))
+                .filter_map(|(_, field, key)| term_value(&file, field, key))
+                .collect();
+            terms.extend(list_values(&file, "anatomy_names"));
+            insert_fts.execute(params![
+                rowid,
+                file.get_str("filename").unwrap_or_default(),
+                terms.join(" ")
+            ])?;
+
+            let file_ns = file.get_str("id_namespace").unwrap_or_default();
+            let file_id = file.get_str("local_id").unwrap_or_default();
+            let sub = file.get_str("submission").unwrap_or_default();
+            // With `--hoist-biosamples`, collections reference the file's
+            // top-level biosamples by key
+            let hoisted: HashMap<(&str, &str), &Document> = file
+                .get_array("biosamples")
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(Bson::as_document)
+                .filter_map(|bio| {
+                    Some((
+                        (
+                            bio.get_str("id_namespace").ok()?,
+                            bio.get_str("local_id").ok()?,
+                        ),
+                        bio,
+                    ))
+                })
+                .collect();
+            let collections = file.get_array("collections").map(Vec::as_slice);
+            for coll in collections
+                .unwrap_or_default()
+                .iter()
+                .filter_map(Bson::as_document)
+            {
+                let (Ok(coll_ns), Ok(coll_id)) =
+                    (coll.get_str("id_namespace"), coll.get_str("local_id"))
+                else {
+                    continue;
+                };
+                insert_collection.execute(params![
+                    coll_ns,
+                    coll_id,
+                    coll.get_str("name").ok(),
+                    coll.get_str("description").ok(),
+                    sub
+                ])?;
+                insert_file_collection.execute(params![file_ns, file_id, coll_ns, coll_id])?;
+
+                let biosamples: Vec<&Document> = match coll.get_array("biosample_refs") {
+                    Ok(refs) => refs
+                        .iter()
+                        .filter_map(Bson::as_document)
+                        .filter_map(|r| {
+                            let key =
+                                (r.get_str("id_namespace").ok()?, r.get_str("local_id").ok()?);
+                            hoisted.get(&key).copied()
+                        })
+                        .collect(),
+                    Err(_) => coll
+                        .get_array("biosamples")
+                        .map(Vec::as_slice)
+                        .unwrap_or_default()
+                        .iter()
+                        .filter_map(Bson::as_document)
+                        .collect(),
+                };
+                for bio in biosamples {
+                    let (Ok(bio_ns), Ok(bio_id)) =
+                        (bio.get_str("id_namespace"), bio.get_str("local_id"))
+                    else {
+                        continue;
+                    };
+                    insert_biosample.execute(params![
+                        bio_ns,
+                        bio_id,
+                        term_value(bio, "anatomy", "id"),
+                        term_value(bio, "anatomy", "name"),
+                        sub
+                    ])?;
+                    insert_collection_biosample
+                        .execute(params![coll_ns, coll_id, bio_ns, bio_id])?;
+                }
+            }
+            files += 1;
+        }
+    }
+    tx.commit()?;
+
+    println!("  Exported {} files to {}", files, path.display());
+    Ok(())
+}
+
+fn schema() -> String {
+    let mut file_columns: Vec<String> = STRING_COLUMNS
+        .iter()
+        .map(|name| format!("{} TEXT", name))
+        .collect();
+    file_columns.extend(
+        INTEGER_COLUMNS
+            .iter()
+            .map(|name| format!("{} INTEGER", name)),
+    );
+    file_columns.extend(
+        TERM_COLUMNS
+            .iter()
+            .map(|(name, _, _)| format!("{} TEXT", name)),
+    );
+    format!(
+        "CREATE TABLE files ({});
+         CREATE INDEX files_key ON files (id_namespace, local_id);
+         CREATE INDEX files_submission ON files (submission);
+         CREATE VIRTUAL TABLE files_fts USING fts5 (filename, terms);
+         CREATE TABLE collections (
+             id_namespace TEXT, local_id TEXT, name TEXT, description TEXT, submission TEXT,
+             PRIMARY KEY (id_namespace, local_id)
+         );
+         CREATE TABLE biosamples (
+             id_namespace TEXT, local_id TEXT, anatomy_id TEXT, anatomy_name TEXT, submission TEXT,
+             PRIMARY KEY (id_namespace, local_id)
+         );
+         CREATE TABLE file_collections (
+             file_id_namespace TEXT, file_local_id TEXT,
+             collection_id_namespace TEXT, collection_local_id TEXT,
+             PRIMARY KEY (file_id_namespace, file_local_id, collection_id_namespace, collection_local_id)
+         );
+         CREATE TABLE collection_biosamples (
+             collection_id_namespace TEXT, collection_local_id TEXT,
+             biosample_id_namespace TEXT, biosample_local_id TEXT,
+             PRIMARY KEY (collection_id_namespace, collection_local_id, biosample_id_namespace, biosample_local_id)
+         );",
+        file_columns.join(", ")
+    )
+}
+
+fn text(value: Option<&str>) -> Value {
+    value.map_or(Value::Null, |s| Value::Text(s.to_string()))
+}
@@ -0,0 +1,257 @@
+use anyhow::Result;
+use bson::{doc, Document};
+use mongodb::sync::{Collection, Database};
+use mongodb::IndexModel;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::projects::integer_field;
+
+/// One document of numeric field summaries per DCC.
+pub const FIELD_STATS_COLLECTION: &str = "field_stats";
+
+/// Numeric file fields summarized per DCC for range facets.
+pub const NUMERIC_FIELDS: [&str; 2] = ["size_in_bytes", "uncompressed_size_in_bytes"];
+
+const PERCENTILES: [(&str, f64); 5] = [
+    ("p25", 0.25),
+    ("p50", 0.50),
+    ("p75", 0.75),
+    ("p90", 0.90),
+    ("p99", 0.99),
+];
+
+/// Collects the values of [`NUMERIC_FIELDS`] per DCC during the file pass
+/// and reduces them to min/max/mean/percentiles.
+#[derive(Default)]
+pub struct FieldStats {
+    // dcc id -> field -> values
+    values: BTreeMap<String, BTreeMap<&'static str, Vec<i64>>>,
+    dccs: BTreeMap<String, Document>,
+    submissions: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl FieldStats {
+    /// Add a file's values to its DCC's. A file without a DCC has none to
+    /// count toward.
+    pub fn observe(&mut self, file: &Document) {
+        let Ok(dcc) = file.get_document("dcc") else {
+            return;
+        };
+        let Ok(id) = dcc.get_str("id") else {
+            return;
+        };
+        self.dccs.entry(id.to_string()).or_insert_with(|| {
+            let mut stub = Document::new();
+            for field in ["id", "dcc_abbreviation", "dcc_name"] {
+                if let Ok(value) = dcc.get_str(field) {
+                    stub.insert(field, value);
+                }
+            }
+            stub
+        });
+        if let Ok(submission) = file.get_str("submission") {
+            self.submissions
+                .entry(id.to_string())
+                .or_default()
+                .insert(submission.to_string());
+        }
+        let fields = self.values.entry(id.to_string()).or_default();
+        for field in NUMERIC_FIELDS {
+            if let Some(value) = integer_field(file, field) {
+                fields.entry(field).or_default().push(value);
+            }
+        }
+    }
+
+    /// Read the values of the other submissions of every DCC seen from
+    /// `files`, so a run that rewrote one submission still summarizes the
+    /// whole DCC.
+    fn observe_rest(&mut self, files: &Collection<Document>, submission: &str) -> Result<()> {
+        let mut projection = doc! { "dcc": 1, "submission": 1 };
+        for field in NUMERIC_FIELDS {
+            projection.insert(field, 1);
+        }
+        let ids: Vec<String> = self.dccs.keys().cloned().collect();
+        let rest = files
+            .find(doc! { "dcc.id": { "$in": ids }, "submission": { "$ne": submission } })
+            .projection(projection)
+            .run()?;
+        for file in rest {
+            self.observe(&file?);
+        }
+        Ok(())
+    }
+
+    /// One `{_id: dcc id, dcc, submissions, fields: {field: summary}}`
+    /// document per DCC seen.
+    pub fn summaries(&mut self) -> Vec<Document> {
+        let mut output = Vec::with_capacity(self.values.len());
+        for (id, fields) in &mut self.values {
+            let mut summaries = Document::new();
+            for (field, values) in fields.iter_mut() {
+                if let Some(summary) = summarize(values) {
+                    summaries.insert(*field, summary);
+                }
+            }
+            let submissions: Vec<&String> =
+                self.submissions.get(id).into_iter().flatten().collect();
+            output.push(doc! {
+                "_id": id.as_str(),
+                "dcc": self.dccs.get(id).cloned().unwrap_or_default(),
+                "submissions": submissions,
+                "fields": summaries,
+            });
+        }
+        output
+    }
+
+    /// Print the summaries and replace the matching `field_stats` documents.
+    /// A run limited to `submission` that wrote to MongoDB passes its
+    /// output as `files`, whose other submissions' files complete the DCCs'
+    /// values; without it, those DCCs are summarized from this run alone.
+    pub fn write(
+        mut self,
+        db: &Database,
+        files: Option<&Collection<Document>>,
+        submission: &Option<String>,
+    ) -> Result<()> {
+        if let (Some(files), Some(sub)) = (files, submission) {
+            self.observe_rest(files, sub)?;
+        }
+        let summaries = self.summaries();
+        for stats in &summaries {
+            println!("  {}:", stats.get_str("_id").unwrap_or_default());
+            for (field, summary) in stats.get_document("fields")? {
+                let Some(summary) = summary.as_document() else {
+                    continue;
+                };
+                println!(
+                    "    {}: count={} min={} max={} mean={:.1} p50={} p99={}",
+                    field,
+                    summary.get_i64("count").unwrap_or_default(),
+                    summary.get_i64("min").unwrap_or_default(),
+                    summary.get_i64("max").unwrap_or_default(),
+                    summary.get_f64("mean").unwrap_or_default(),
+                    summary.get_i64("p50").unwrap_or_default(),
+                    summary.get_i64("p99").unwrap_or_default(),
+                );
+            }
+        }
+
+        let coll: Collection<Document> = db.collection(FIELD_STATS_COLLECTION);
+        match submission {
+            Some(_) => {
+                let ids: Vec<&str> = summaries
+                    .iter()
+                    .filter_map(|stats| stats.get_str("_id").ok())
+                    .collect();
+                coll.delete_many(doc! { "_id": { "$in": ids } }).run()?;
+            }
+            None => coll.drop().run()?,
+        }
+        if !summaries.is_empty() {
+            coll.insert_many(&summaries).run()?;
+        }
+        coll.create_index(
+            IndexModel::builder()
+                .keys(doc! { "submissions": 1 })
+                .build(),
+        )
+        .run()?;
+        Ok(())
+    }
+}
+
+/// Reduce `values` to `{count, min, max, mean, p25..p99}` using
+/// nearest-rank percentiles. Sorts `values` in place.
+fn summarize(values: &mut [i64]) -> Option<Document> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    let count = values.len();
+    let sum: i128 = values.iter().map(|&v| v as i128).sum();
+    let mut summary = doc! {
+        "count": count as i64,
+        "min": values[0],
+        "max": values[count - 1],
+        "mean": sum as f64 / count as f64,
+    };
+    for (name, p) in PERCENTILES {
+        let rank = ((p * count as f64).ceil() as usize).clamp(1, count);
+        summary.insert(name, values[rank - 1]);
+    }
+    Some(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bson::Bson;
+
+    #[test]
+    fn summarizes_with_nearest_rank_percentiles() {
+        let mut values: Vec<i64> = (1..=100).rev().collect();
+        let summary = summarize(&mut values).unwrap();
+        assert_eq!(summary.get_i64("count").unwrap(), 100);
+        assert_eq!(summary.get_i64("min").unwrap(), 1);
+        assert_eq!(summary.get_i64("max").unwrap(), 100);
+        assert_eq!(summary.get_f64("mean").unwrap(), 50.5);
+        assert_eq!(summary.get_i64("p25").unwrap(), 25);
+        assert_eq!(summary.get_i64("p50").unwrap(), 50);
+        assert_eq!(summary.get_i64("p90").unwrap(), 90);
+        assert_eq!(summary.get_i64("p99").unwrap(), 99);
+    }
+
+    #[test]
+    fn a_single_value_is_every_percentile() {
+        let summary = summarize(&mut [7]).unwrap();
+        for name in ["min", "max", "p25", "p50", "p75", "p90", "p99"] {
+            assert_eq!(summary.get_i64(name).unwrap(), 7);
+        }
+    }
+
+    #[test]
+    fn nothing_to_summarize() {
+        assert!(summarize(&mut []).is_none());
+    }
+
+    #[test]
+    fn summarizes_per_dcc_across_submissions() {
+        let file = |dcc: &str, submission: &str, size: i64| {
+            doc! {
+                "dcc": { "id": dcc, "dcc_abbreviation": dcc.to_uppercase() },
+                "submission": submission,
+                "size_in_bytes": size,
+            }
+        };
+        let mut stats = FieldStats::default();
+        stats.observe(&file("a", "a_2023", 10));
+        stats.observe(&file("a", "a_2024", 30));
+        stats.observe(&file("b", "b_2024", 5));
+        stats.observe(&doc! { "submission": "orphan", "size_in_bytes": 1 });
+
+        let summaries = stats.summaries();
+        assert_eq!(summaries.len(), 2);
+        let a = &summaries[0];
+        assert_eq!(a.get_str("_id").unwrap(), "a");
+        assert_eq!(
+            a.get_document("dcc")
+                .unwrap()
+                .get_str("dcc_abbreviation")
+                .unwrap(),
+            "A"
+        );
+        assert_eq!(
+            a.get_array("submissions").unwrap(),
+            &vec![Bson::from("a_2023"), Bson::from("a_2024")]
+        );
+        let size = a
+            .get_document("fields")
+            .unwrap()
+            .get_document("size_in_bytes")
+            .unwrap();
+        assert_eq!(size.get_i64("count").unwrap(), 2);
+        assert_eq!(size.get_i64("max").unwrap(), 30);
+    }
+}
@@ -0,0 +1,149 @@
+use bson::oid::ObjectId;
+use bson::{doc, Bson, DateTime, Document};
+use indicatif::ProgressBar;
+use mongodb::sync::{Collection, Database};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// One document per invocation with its live progress, for dashboards and
+/// operators without access to the process's output.
+pub const STATUS_COLLECTION: &str = "materialize_status";
+
+/// Time between upserts of the status document.
+const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What the invocation is doing now.
+struct Progress {
+    submission: Option<String>,
+    done: usize,
+    total: usize,
+    phase: String,
+    /// The phase's bar, when it has one, for position and ETA
+    bar: Option<ProgressBar>,
+    state: &'static str,
+    error: Option<String>,
+}
+
+impl Progress {
+    fn document(&self) -> Document {
+        let mut status = doc! {
+            "state": self.state,
+            "updated_at": DateTime::now(),
+            "submission": self.submission.as_deref().map_or(Bson::Null, Bson::from),
+            "submissions": { "done": self.done as i64, "total": self.total as i64 },
+            "phase": &self.phase,
+        };
+        if let Some(ref bar) = self.bar {
+            let position = bar.position();
+            let length = bar.length().unwrap_or(0);
+            let percent = match length {
+                0 => 100.0,
+                _ => position as f64 * 100.0 / length as f64,
+            };
+            status.insert("processed", position as i64);
+            status.insert("total", length as i64);
+            status.insert("percent", (percent * 10.0).round() / 10.0);
+            status.insert("per_sec", bar.per_sec().round());
+            status.insert("eta_seconds", bar.eta().as_secs() as i64);
+        }
+        if let Some(ref error) = self.error {
+            status.insert("error", error);
+        }
+        status
+    }
+}
+
+/// Upserts the invocation's `materialize_status` document every
+/// `STATUS_INTERVAL` from a background thread, and once more when the
+/// invocation finishes or fails.
+pub struct StatusReporter {
+    progress: Arc<Mutex<Progress>>,
+    stop: Sender<()>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl StatusReporter {
+    pub fn start(db: &Database, submissions: usize) -> Self {
+        let coll: Collection<Document> = db.collection(STATUS_COLLECTION);
+        let id = ObjectId::new();
+        let progress = Arc::new(Mutex::new(Progress {
+            submission: None,
+            done: 0,
+            total: submissions,
+            phase: "starting".to_string(),
+            bar: None,
+            state: "running",
+            error: None,
+        }));
+        let (stop, stopped) = mpsc::channel();
+        let shared = Arc::clone(&progress);
+        let worker = thread::spawn(move || {
+            let started_at = DateTime::now();
+            let mut warned = false;
+            loop {
+                let finished = !matches!(
+                    stopped.recv_timeout(STATUS_INTERVAL),
+                    Err(RecvTimeoutError::Timeout)
+                );
+                let mut status = shared.lock().unwrap().document();
+                status.insert("pid", std::process::id() as i64);
+                status.insert("started_at", started_at);
+                status.insert("tool_version", env!("CARGO_PKG_VERSION"));
+                let result = coll
+                    .update_one(doc! { "_id": id }, doc! { "$set": status })
+                    .upsert(true)
+                    .run();
+                if let Err(e) = result {
+                    if !warned {
+                        eprintln!("Warning: could not update {}: {}", STATUS_COLLECTION, e);
+                        warned = true;
+                    }
+                }
+                if finished {
+                    break;
+                }
+            }
+        });
+        StatusReporter {
+            progress,
+            stop,
+            worker: Mutex::new(Some(worker)),
+        }
+    }
+
+    pub fn submission(&self, submission: &Option<String>) {
+        let mut progress = self.progress.lock().unwrap();
+        progress.submission = submission.clone();
+        progress.phase = "loading".to_string();
+        progress.bar = None;
+    }
+
+    pub fn finish_submission(&self) {
+        self.progress.lock().unwrap().done += 1;
+    }
+
+    pub fn phase(&self, name: &str, bar: Option<ProgressBar>) {
+        let mut progress = self.progress.lock().unwrap();
+        progress.phase = name.to_string();
+        progress.bar = bar;
+    }
+
+    /// Write the final status and stop the background thread.
+    pub fn finish(&self, error: Option<String>) {
+        {
+            let mut progress = self.progress.lock().unwrap();
+            progress.state = if error.is_some() {
+                "failed"
+            } else {
+                "finished"
+            };
+            progress.error = error;
+        }
+        let _ = self.stop.send(());
+        if let Some(worker) = self.worker.lock().unwrap().take() {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use anyhow::Result;
+use bson::{doc, Bson, DateTime, Document};
+use mongodb::sync::{Collection, Database};
+use mongodb::IndexModel;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::cli::value;
+use crate::projects::integer_field;
+
+pub const STATS_COLLECTION: &str = "submission_stats";
+
+/// Tallies for one submission, gathered from the materialized `files`.
+#[derive(Default)]
+struct SubmissionTally {
+    files: i64,
+    bytes: i64,
+    with_checksum: i64,
+    file_formats: HashSet<String>,
+    assay_types: HashSet<String>,
+    anatomies: HashSet<String>,
+    collections: HashSet<(String, String)>,
+}
+
+impl SubmissionTally {
+    fn observe(&mut self, file: &Document) {
+        self.files += 1;
+        self.bytes += integer_field(file, "size_in_bytes").unwrap_or(0);
+        let has_checksum = ["sha256", "md5"]
+            .iter()
+            .any(|field| file.get_str(field).is_ok_and(|sum| !sum.is_empty()));
+        if has_checksum {
+            self.with_checksum += 1;
+        }
+        if let Some(id) = term_id(file.get("file_format")) {
+            self.file_formats.insert(id.to_string());
+        }
+        if let Some(id) = term_id(file.get("assay_type")) {
+            self.assay_types.insert(id.to_string());
+        }
+        let collections = file.get_array("collections").map(Vec::as_slice);
+        let mut biosamples: Vec<&Bson> = Vec::new();
+        for coll in collections
+            .unwrap_or_default()
+            .iter()
+            .filter_map(Bson::as_document)
+        {
+            if let (Ok(ns), Ok(id)) = (coll.get_str("id_namespace"), coll.get_str("local_id")) {
+                self.collections.insert((ns.to_string(), id.to_string()));
+            }
+            if let Ok(nested) = coll.get_array("biosamples") {
+                biosamples.extend(nested);
+            }
+        }
+        // Hoisted with `--hoist-biosamples`
+        if let Ok(hoisted) = file.get_array("biosamples") {
+            biosamples.extend(hoisted);
+        }
+        for biosample in biosamples.into_iter().filter_map(Bson::as_document) {
+            if let Some(id) = term_id(biosample.get("anatomy")) {
+                self.anatomies.insert(id.to_string());
+            }
+        }
+    }
+
+    fn summary(&self, submission: &str) -> Document {
+        let checksum_pct = if self.files > 0 {
+            self.with_checksum as f64 * 100.0 / self.files as f64
+        } else {
+            0.0
+        };
+        doc! {
+            "submission": submission,
+            "files": self.files,
+            "size_in_bytes": self.bytes,
+            "file_formats": self.file_formats.len() as i64,
+            "assay_types": self.assay_types.len() as i64,
+            "anatomies": self.anatomies.len() as i64,
+            "collections": self.collections.len() as i64,
+            "checksum_pct": checksum_pct,
+            "computed_at": DateTime::now(),
+        }
+    }
+}
+
+/// The id of an embedded term, or the raw id if the lookup missed.
+fn term_id(value: Option<&Bson>) -> Option<&str> {
+    match value? {
+        Bson::Document(term) => term.get_str("id").ok(),
+        Bson::String(id) if !id.is_empty() => Some(id),
+        _ => None,
+    }
+}
+
+/// `stats [--submission X]` summarizes the materialized `files` per
+/// submission, prints the summaries, and replaces them in `submission_stats`.
+pub fn command(db: &Database, args: &[String]) -> Result<()> {
+    let submission = value(args, "--submission");
+    let query = match submission {
+        Some(ref sub) => doc! { "submission": sub },
+        None => doc! {},
+    };
+    let projection = doc! {
+        "submission": 1,
+        "size_in_bytes": 1,
+        "sha256": 1,
+        "md5": 1,
+        "file_format": 1,
+        "assay_type": 1,
+        "collections.id_namespace": 1,
+        "collections.local_id": 1,
+        "collections.biosamples.anatomy": 1,
+        "biosamples.anatomy": 1,
+    };
+
+    let mut tallies: BTreeMap<String, SubmissionTally> = BTreeMap::new();
+    for file in db
+        .collection::<Document>("files")
+        .find(query)
+        .projection(projection)
+        .batch_size(50000)
+        .run()?
+    {
+        let file = file?;
+        let sub = file.get_str("submission").unwrap_or_default();
+        tallies.entry(sub.to_string()).or_default().observe(&file);
+    }
+    if tallies.is_empty() {
+        anyhow::bail!("No materialized files found; run the materializer first");
+    }
+
+    let summaries: Vec<Document> = tallies
+        .iter()
+        .map(|(sub, tally)| tally.summary(sub))
+        .collect();
+
+    println!(
+        "{:<12}  {:>10}  {:>16}  {:>8}  {:>8}  {:>10}  {:>11}  {:>9}",
+        "SUBMISSION", "FILES", "BYTES", "FORMATS", "ASSAYS", "ANATOMIES", "COLLECTIONS", "CHECKSUM"
+    );
+    for stats in &summaries {
+        println!(
+            "{:<12}  {:>10}  {:>16}  {:>8}  {:>8}  {:>10}  {:>11}  {:>8.1}%",
+            stats.get_str("submission").unwrap_or_default(),
+            stats.get_i64("files").unwrap_or_default(),
+            stats.get_i64("size_in_bytes").unwrap_or_default(),
+            stats.get_i64("file_formats").unwrap_or_default(),
+            stats.get_i64("assay_types").unwrap_or_default(),
+            stats.get_i64("anatomies").unwrap_or_default(),
+            stats.get_i64("collections").unwrap_or_default(),
+            stats.get_f64("checksum_pct").unwrap_or_default(),
+        );
+    }
+
+    let coll: Collection<Document> = db.collection(STATS_COLLECTION);
+    match submission {
+        Some(ref sub) => {
+            coll.delete_many(doc! { "submission": sub }).run()?;
+        }
+        None => coll.drop().run()?,
+    }
+    coll.insert_many(&summaries).run()?;
+    coll.create_index(IndexModel::builder().keys(doc! { "submission": 1 }).build())
+        .run()?;
+    Ok(())
+}
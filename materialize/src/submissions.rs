@@ -0,0 +1,255 @@
+use anyhow::{Context, Result};
+use bson::{doc, Bson, DateTime, Document};
+use mongodb::sync::{Collection, Database};
+use std::collections::BTreeMap;
+use std::fs;
+
+use crate::projects::integer_field;
+use crate::runs::RUNS_COLLECTION;
+
+/// Expand a `--submission` value into the concrete submissions to process.
+///
+/// Plain ids are returned as-is. `/regex/` and globs (`*`, `?`, `[...]`) are
+/// matched against the distinct submissions in the source `file` collection.
+pub fn expand(db: &Database, spec: &str) -> Result<Vec<String>> {
+    let regex = match spec.strip_prefix('/').and_then(|s| s.strip_suffix('/')) {
+        Some(regex) => regex.to_string(),
+        None if spec.contains(['*', '?', '[']) => glob_to_regex(spec),
+        None => return Ok(vec![spec.to_string()]),
+    };
+    let mut submissions: Vec<String> = db
+        .collection::<Document>("file")
+        .distinct("submission", doc! { "submission": { "$regex": &regex } })
+        .run()?
+        .into_iter()
+        .filter_map(|s| match s {
+            Bson::String(s) => Some(s),
+            _ => None,
+        })
+        .collect();
+    if submissions.is_empty() {
+        anyhow::bail!("No submission matches {}", spec);
+    }
+    submissions.sort();
+    println!(
+        "Submission pattern {} matches {} submissions: {}",
+        spec,
+        submissions.len(),
+        submissions.join(", ")
+    );
+    Ok(submissions)
+}
+
+/// The submissions owned by the DCC with this abbreviation (ignoring case),
+/// from the source `dcc` collection.
+pub fn for_dcc(db: &Database, abbreviation: &str) -> Result<Vec<String>> {
+    let mut submissions: Vec<String> = db
+        .collection::<Document>("dcc")
+        .find(doc! {})
+        .projection(doc! { "submission": 1, "dcc_abbreviation": 1 })
+        .run()?
+        .filter_map(|d| d.ok())
+        .filter(|d| {
+            d.get_str("dcc_abbreviation")
+                .is_ok_and(|a| a.eq_ignore_ascii_case(abbreviation))
+        })
+        .filter_map(|d| d.get_str("submission").ok().map(str::to_string))
+        .collect();
+    if submissions.is_empty() {
+        anyhow::bail!("No submission belongs to DCC {}", abbreviation);
+    }
+    submissions.sort();
+    submissions.dedup();
+    println!(
+        "DCC {} owns {} submissions: {}",
+        abbreviation,
+        submissions.len(),
+        submissions.join(", ")
+    );
+    Ok(submissions)
+}
+
+/// Read a `--submissions-file`: one submission per line; blank lines and
+/// `#` comments are ignored.
+pub fn read_list(path: &str) -> Result<Vec<String>> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading {}", path))?;
+    let submissions: Vec<String> = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+    if submissions.is_empty() {
+        anyhow::bail!("{} lists no submissions", path);
+    }
+    println!("{} lists {} submissions", path, submissions.len());
+    Ok(submissions)
+}
+
+/// Drop the `--exclude-submission` entries from the selected submissions.
+pub fn exclude(mut submissions: Vec<String>, excluded: &[String]) -> Result<Vec<String>> {
+    let before = submissions.len();
+    submissions.retain(|sub| !excluded.contains(sub));
+    if submissions.len() < before {
+        println!("Excluding {} submissions", before - submissions.len());
+    }
+    if submissions.is_empty() {
+        anyhow::bail!("Every selected submission is excluded");
+    }
+    Ok(submissions)
+}
+
+/// What the source and materialized collections hold for one submission.
+#[derive(Default)]
+struct SubmissionInfo {
+    /// Rows per source table
+    tables: BTreeMap<String, i64>,
+    /// Newest row across the source tables, from its `_id`
+    ingested_at: Option<DateTime>,
+    files: i64,
+    materialized_at: Option<DateTime>,
+}
+
+/// `submissions [list]` enumerates the submissions in the source C2M2
+/// collections with their row counts per table, when they were last
+/// ingested, and when they were last materialized.
+pub fn command(db: &Database, args: &[String]) -> Result<()> {
+    match args.first().map(String::as_str) {
+        None | Some("list") => list(db),
+        Some(other) => anyhow::bail!("Unknown submissions command: {}", other),
+    }
+}
+
+/// The raw C2M2 collections loaded by ingestion, which tags every row with
+/// its `table`.
+pub fn source_collections(db: &Database) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for name in db.list_collection_names().run()? {
+        let tagged = db
+            .collection::<Document>(&name)
+            .find_one(doc! { "table": { "$exists": true } })
+            .run()?;
+        if tagged.is_some() {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+fn list(db: &Database) -> Result<()> {
+    let mut submissions: BTreeMap<String, SubmissionInfo> = BTreeMap::new();
+
+    for name in source_collections(db)? {
+        let coll: Collection<Document> = db.collection(&name);
+        let pipeline = vec![doc! { "$group": {
+            "_id": "$submission",
+            "rows": { "$sum": 1 },
+            "last_id": { "$max": "$_id" },
+        } }];
+        for group in coll.aggregate(pipeline).run()? {
+            let group = group?;
+            let Ok(submission) = group.get_str("_id") else {
+                continue;
+            };
+            let info = submissions.entry(submission.to_string()).or_default();
+            info.tables
+                .insert(name.clone(), integer_field(&group, "rows").unwrap_or(0));
+            if let Ok(id) = group.get_object_id("last_id") {
+                let ingested_at = id.timestamp();
+                if info.ingested_at.is_none_or(|at| at < ingested_at) {
+                    info.ingested_at = Some(ingested_at);
+                }
+            }
+        }
+    }
+
+    let files = db
+        .collection::<Document>("files")
+        .aggregate(vec![
+            doc! { "$group": { "_id": "$submission", "files": { "$sum": 1 } } },
+        ])
+        .run()?;
+    for group in files {
+        let group = group?;
+        if let Some(info) = group
+            .get_str("_id")
+            .ok()
+            .and_then(|sub| submissions.get_mut(sub))
+        {
+            info.files = integer_field(&group, "files").unwrap_or(0);
+        }
+    }
+
+    let runs = db
+        .collection::<Document>(RUNS_COLLECTION)
+        .aggregate(vec![
+            doc! { "$match": { "outcome": "success" } },
+            doc! { "$group": { "_id": "$submission", "ended_at": { "$max": "$ended_at" } } },
+        ])
+        .run()?;
+    for group in runs {
+        let group = group?;
+        if let Some(info) = group
+            .get_str("_id")
+            .ok()
+            .and_then(|sub| submissions.get_mut(sub))
+        {
+            info.materialized_at = group.get_datetime("ended_at").ok().copied();
+        }
+    }
+
+    let timestamp = |at: Option<DateTime>| {
+        at.and_then(|t| t.try_to_rfc3339_string().ok())
+            .unwrap_or_else(|| "-".to_string())
+    };
+    println!(
+        "{:<16}  {:<24}  {:>12}  {:>10}  {:<24}",
+        "SUBMISSION", "INGESTED", "ROWS", "FILES", "MATERIALIZED"
+    );
+    for (submission, info) in &submissions {
+        println!(
+            "{:<16}  {:<24}  {:>12}  {:>10}  {:<24}",
+            submission,
+            timestamp(info.ingested_at),
+            info.tables.values().sum::<i64>(),
+            info.files,
+            timestamp(info.materialized_at),
+        );
+        let tables: Vec<String> = info
+            .tables
+            .iter()
+            .map(|(table, rows)| format!("{}: {}", table, rows))
+            .collect();
+        println!("    {}", tables.join(", "));
+    }
+    Ok(())
+}
+
+/// Translate a shell glob into an anchored regex.
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut in_class = false;
+    for c in glob.chars() {
+        match c {
+            '*' if !in_class => regex.push_str(".*"),
+            '?' if !in_class => regex.push('.'),
+            '[' if !in_class => {
+                in_class = true;
+                regex.push('[');
+            }
+            ']' if in_class => {
+                in_class = false;
+                regex.push(']');
+            }
+            '!' if in_class && regex.ends_with('[') => regex.push('^'),
+            c if !in_class && "\\.+()|{}^$".contains(c) => {
+                regex.push('\\');
+                regex.push(c);
+            }
+            c => regex.push(c),
+        }
+    }
+    regex.push('$');
+    regex
+}
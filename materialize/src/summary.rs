@@ -0,0 +1,101 @@
+use bson::{doc, Bson, Document};
+use std::time::Instant;
+
+use crate::error::{MaterializeError, EXIT_PARTIAL};
+#[cfg(feature = "notify")]
+use crate::notify::{self, NotifyFormat};
+
+/// The outcome of every submission in an invocation, printed as one JSON
+/// line at the end of stdout so wrappers can read the result without
+/// parsing the progress messages, and posted to `--notify-webhook`.
+pub struct RunSummary {
+    started: Instant,
+    submissions: Vec<Document>,
+    enabled: bool,
+    #[cfg(feature = "notify")]
+    webhook: Option<(String, NotifyFormat)>,
+}
+
+impl RunSummary {
+    pub fn start() -> Self {
+        RunSummary {
+            started: Instant::now(),
+            submissions: Vec::new(),
+            enabled: true,
+            #[cfg(feature = "notify")]
+            webhook: None,
+        }
+    }
+
+    /// Also post the summary to `url` when the run ends.
+    #[cfg(feature = "notify")]
+    pub fn notify(&mut self, url: &str, format: NotifyFormat) {
+        self.webhook = Some((url.to_string(), format));
+    }
+
+    /// Print nothing: subcommands and `--explain` own their output.
+    pub fn disable(&mut self) {
+        self.enabled = false;
+    }
+
+    /// Record a submission that finished, with its report's counts and
+    /// validation warnings.
+    pub fn succeeded(&mut self, submission: &Option<String>, report: &Document) {
+        let counts = report.get_document("counts").cloned().unwrap_or_default();
+        let rejected = counts.get_i64("rejected").unwrap_or_default();
+        self.submissions.push(doc! {
+            "submission": submission.as_deref().map_or(Bson::Null, Bson::from),
+            "outcome": if rejected > 0 { "partial" } else { "success" },
+            "counts": counts,
+            "validation": report.get_document("validation").cloned().unwrap_or_default(),
+        });
+    }
+
+    pub fn failed(&mut self, submission: &Option<String>, error: &MaterializeError) {
+        self.submissions.push(doc! {
+            "submission": submission.as_deref().map_or(Bson::Null, Bson::from),
+            "outcome": "failure",
+            "error": error.kind(),
+        });
+    }
+
+    /// Whether a finished submission had files the output rejected.
+    pub fn partial(&self) -> bool {
+        self.submissions
+            .iter()
+            .any(|s| s.get_str("outcome") == Ok("partial"))
+    }
+
+    /// Print (and post) the summary for an invocation ending with
+    /// `exit_code` (and `error`, if it failed). A failed notification is
+    /// reported but doesn't change the exit code.
+    pub fn finish(&self, exit_code: u8, error: Option<&MaterializeError>) {
+        if !self.enabled {
+            return;
+        }
+        let outcome = match (error, exit_code) {
+            (Some(_), _) => "failure",
+            (None, EXIT_PARTIAL) => "partial",
+            (None, _) => "success",
+        };
+        let mut summary = doc! {
+            "outcome": outcome,
+            "exit_code": exit_code as i32,
+            "duration_ms": self.started.elapsed().as_millis() as i64,
+            "submissions": self.submissions.clone(),
+        };
+        if let Some(error) = error {
+            summary.insert(
+                "error",
+                doc! { "kind": error.kind(), "message": format!("{:#}", error) },
+            );
+        }
+        println!("{}", Bson::Document(summary.clone()).into_relaxed_extjson());
+        #[cfg(feature = "notify")]
+        if let Some((ref url, format)) = self.webhook {
+            if let Err(e) = notify::post(url, format, &summary) {
+                eprintln!("Failed to post the run summary to {}: {:#}", url, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Paces reads or writes to at most `per_sec` documents a second
+/// (`--max-read-ops-per-sec`, `--max-write-ops-per-sec`), so a rebuild on a
+/// shared cluster leaves capacity for other tenants. Each call books its
+/// documents on a schedule and waits for its slot; the first call never
+/// waits.
+pub struct RateLimit {
+    per_sec: u64,
+    /// When the next batch may start
+    next: Mutex<Option<Instant>>,
+}
+
+impl RateLimit {
+    pub fn new(per_sec: u64) -> Self {
+        RateLimit {
+            per_sec: per_sec.max(1),
+            next: Mutex::new(None),
+        }
+    }
+
+    pub fn per_sec(&self) -> u64 {
+        self.per_sec
+    }
+
+    /// Book `n` documents and return how long to wait before handling them.
+    pub fn delay(&self, n: usize) -> Duration {
+        let now = Instant::now();
+        let mut next = self.next.lock().unwrap();
+        let start = next.map_or(now, |next| next.max(now));
+        *next = Some(start + Duration::from_secs_f64(n as f64 / self.per_sec as f64));
+        start - now
+    }
+
+    /// Book `n` documents and sleep until their slot.
+    pub fn acquire(&self, n: usize) {
+        let delay = self.delay(n);
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+    }
+}
+
+/// A cursor batch size no larger than a second's worth of reads, so one
+/// `getMore` doesn't pull a burst the limit then has to sleep off.
+pub fn read_batch_size(find_batch_size: u32, limit: Option<&RateLimit>) -> u32 {
+    match limit {
+        Some(limit) => find_batch_size.min(limit.per_sec().min(u32::MAX as u64) as u32),
+        None => find_batch_size,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_first_batch_never_waits() {
+        assert_eq!(RateLimit::new(10).delay(5), Duration::ZERO);
+    }
+
+    #[test]
+    fn later_batches_wait_for_their_slot() {
+        let limit = RateLimit::new(10);
+        limit.delay(10);
+        let wait = limit.delay(5);
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+        let wait = limit.delay(1);
+        assert!(wait > Duration::from_millis(1400) && wait <= Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn a_zero_rate_allows_one_a_second() {
+        assert_eq!(RateLimit::new(0).per_sec(), 1);
+    }
+
+    #[test]
+    fn read_batches_hold_at_most_a_second_of_reads() {
+        let limit = RateLimit::new(100);
+        assert_eq!(read_batch_size(1000, Some(&limit)), 100);
+        assert_eq!(read_batch_size(50, Some(&limit)), 50);
+        assert_eq!(read_batch_size(1000, None), 1000);
+    }
+}
@@ -0,0 +1,40 @@
+use bson::{doc, Document};
+use std::time::Instant;
+
+/// Wall-clock time per phase of a run, with throughput for the phases that
+/// process documents.
+#[derive(Default)]
+pub struct PhaseTimings {
+    phases: Document,
+}
+
+impl PhaseTimings {
+    /// Record `phase` as running from `started` until now, over `docs`
+    /// documents if it processes any.
+    pub fn record(&mut self, phase: &str, started: Instant, docs: Option<u64>) {
+        let seconds = started.elapsed().as_secs_f64();
+        let mut timing = doc! { "seconds": seconds };
+        if let Some(docs) = docs {
+            timing.insert("docs", docs as i64);
+            if seconds > 0.0 {
+                timing.insert("docs_per_sec", docs as f64 / seconds);
+            }
+        }
+        self.phases.insert(phase, timing);
+    }
+
+    /// Record a phase whose timing was measured elsewhere.
+    pub fn insert(&mut self, phase: &str, timing: Document) {
+        self.phases.insert(phase, timing);
+    }
+
+    pub fn report(self) -> Document {
+        self.phases
+    }
+}
+
+/// Record the milliseconds since `since` under `name` and restart the clock.
+pub fn lap(times: &mut Document, name: &str, since: &mut Instant) {
+    times.insert(name, since.elapsed().as_millis() as i64);
+    *since = Instant::now();
+}
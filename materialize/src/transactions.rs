@@ -0,0 +1,67 @@
+use anyhow::Result;
+use bson::{doc, Document};
+use mongodb::sync::Database;
+
+use crate::size_policy::encoded_len;
+
+/// Insert batch size inside a transaction, kept small so no single
+/// operation nears the transaction size limits.
+pub const TRANSACTION_BATCH_SIZE: usize = 1000;
+
+/// Most enriched BSON a submission may come to for its replacement to run
+/// in a transaction. The server aborts a transaction still open after
+/// `transactionLifetimeLimitSeconds` (60 by default), and 256MB of inserts
+/// fits in that at the few tens of MB/s a busy replica set sustains.
+pub const TRANSACTION_MAX_BYTES: usize = 256 * 1024 * 1024;
+
+/// Whether the deployment supports multi-document transactions: replica set
+/// members and mongos routers do, standalone servers do not.
+pub fn supported(db: &Database) -> Result<bool> {
+    let hello = db.run_command(doc! { "hello": 1 }).run()?;
+    Ok(hello.contains_key("setName") || hello.get_str("msg") == Ok("isdbgrid"))
+}
+
+/// Pull enriched files from `files` until it runs out or they pass
+/// `max_bytes` of BSON. Returns them and whether they are all of them.
+pub fn buffer(
+    files: &mut dyn Iterator<Item = Result<Document>>,
+    max_bytes: usize,
+) -> Result<(Vec<Document>, bool)> {
+    let mut buffered = Vec::new();
+    let mut bytes = 0;
+    for file in files {
+        let file = file?;
+        bytes += encoded_len(&file)?;
+        buffered.push(file);
+        if bytes > max_bytes {
+            return Ok((buffered, false));
+        }
+    }
+    Ok((buffered, true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(count: usize) -> impl Iterator<Item = Result<Document>> {
+        (0..count).map(|i| Ok(doc! { "local_id": format!("f{:03}", i) }))
+    }
+
+    #[test]
+    fn buffers_everything_under_the_limit() {
+        let (buffered, complete) = buffer(&mut files(10), 1024 * 1024).unwrap();
+        assert_eq!(buffered.len(), 10);
+        assert!(complete);
+    }
+
+    #[test]
+    fn stops_once_past_the_limit() {
+        let size = encoded_len(&doc! { "local_id": "f000" }).unwrap();
+        let mut rest = files(10);
+        let (buffered, complete) = buffer(&mut rest, size * 3).unwrap();
+        assert_eq!(buffered.len(), 4);
+        assert!(!complete);
+        assert_eq!(rest.count(), 6);
+    }
+}
@@ -0,0 +1,202 @@
+use anyhow::{Context, Result};
+use bson::{doc, Bson, Document};
+use mongodb::sync::Database;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+
+use crate::cli::{number, value};
+use crate::coerce::parse_date;
+use crate::submissions::source_collections;
+
+/// Failing rows printed per table before the rest are only counted.
+const DEFAULT_EXAMPLES: usize = 3;
+
+/// Fields added by ingestion rather than read from the datapackage.
+const INGEST_FIELDS: [&str; 3] = ["_id", "submission", "table"];
+
+/// The parts of a frictionless `C2M2_datapackage.json` the check uses.
+#[derive(Deserialize)]
+struct DataPackage {
+    resources: Vec<Resource>,
+}
+
+#[derive(Deserialize)]
+struct Resource {
+    name: String,
+    schema: TableSchema,
+}
+
+#[derive(Deserialize)]
+struct TableSchema {
+    fields: Vec<FieldSchema>,
+}
+
+#[derive(Deserialize)]
+struct FieldSchema {
+    name: String,
+    #[serde(rename = "type", default = "string_type")]
+    kind: String,
+    #[serde(default)]
+    constraints: Constraints,
+}
+
+#[derive(Default, Deserialize)]
+struct Constraints {
+    #[serde(default)]
+    required: bool,
+    #[serde(rename = "enum")]
+    allowed: Option<Vec<String>>,
+}
+
+fn string_type() -> String {
+    "string".to_string()
+}
+
+#[derive(Default)]
+struct TableReport {
+    rows: u64,
+    failing_rows: u64,
+    /// Error count per `field: problem`
+    errors: BTreeMap<String, u64>,
+    examples: Vec<String>,
+}
+
+/// `validate schema --schema <C2M2_datapackage.json> [--submission X]
+/// [--examples N]` checks every row of the source collections against the
+/// C2M2 frictionless table schemas: unknown fields, missing required
+/// columns, values that don't parse as the column's type, and values outside
+/// an enumeration. Prints per-table error counts with example rows and fails
+/// when any row is invalid.
+pub fn command(db: &Database, args: &[String]) -> Result<()> {
+    match args.first().map(String::as_str) {
+        Some("schema") => schema(db, &args[1..]),
+        Some(other) => anyhow::bail!("Unknown validate command: {}", other),
+        None => anyhow::bail!("Usage: validate schema --schema <C2M2_datapackage.json>"),
+    }
+}
+
+fn schema(db: &Database, args: &[String]) -> Result<()> {
+    let Some(path) = value(args, "--schema") else {
+        anyhow::bail!("validate schema needs --schema <C2M2_datapackage.json>");
+    };
+    let submission = value(args, "--submission");
+    let examples: usize = number(args, "--examples")?.unwrap_or(DEFAULT_EXAMPLES);
+
+    let text = fs::read_to_string(&path).with_context(|| format!("reading {}", path))?;
+    let package: DataPackage =
+        serde_json::from_str(&text).with_context(|| format!("parsing {}", path))?;
+    let collections: HashSet<String> = source_collections(db)?.into_iter().collect();
+
+    let query = match submission {
+        Some(ref sub) => doc! { "submission": sub },
+        None => doc! {},
+    };
+    let mut invalid = 0;
+    for resource in &package.resources {
+        if !collections.contains(&resource.name) {
+            continue;
+        }
+        let mut report = TableReport::default();
+        for row in db
+            .collection::<Document>(&resource.name)
+            .find(query.clone())
+            .run()?
+        {
+            let row = row?;
+            report.rows += 1;
+            let problems = check_row(&row, &resource.schema);
+            if problems.is_empty() {
+                continue;
+            }
+            report.failing_rows += 1;
+            for problem in &problems {
+                *report.errors.entry(problem.clone()).or_default() += 1;
+            }
+            if report.examples.len() < examples {
+                report.examples.push(format!(
+                    "{} {}: {}",
+                    row.get_str("submission").unwrap_or_default(),
+                    row.get("_id").map(ToString::to_string).unwrap_or_default(),
+                    problems.join("; ")
+                ));
+            }
+        }
+
+        println!(
+            "  {:<32} {:>10} rows {:>10} invalid",
+            resource.name, report.rows, report.failing_rows
+        );
+        for (error, count) in &report.errors {
+            println!("      {:>10}  {}", count, error);
+        }
+        for example in &report.examples {
+            println!("      e.g. {}", example);
+        }
+        invalid += report.failing_rows;
+    }
+
+    if invalid > 0 {
+        anyhow::bail!("{} rows fail the C2M2 schema", invalid);
+    }
+    println!("All rows match the C2M2 schema");
+    Ok(())
+}
+
+/// Problems with one row, phrased `field: problem` so they group by kind.
+fn check_row(row: &Document, schema: &TableSchema) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (key, _) in row {
+        if !INGEST_FIELDS.contains(&key.as_str()) && !schema.fields.iter().any(|f| f.name == *key) {
+            problems.push(format!("{}: unknown field", key));
+        }
+    }
+    for field in &schema.fields {
+        let value = row.get(&field.name).filter(|v| !is_empty(v));
+        let Some(value) = value else {
+            if field.constraints.required {
+                problems.push(format!("{}: required but missing", field.name));
+            }
+            continue;
+        };
+        if !has_type(value, &field.kind) {
+            problems.push(format!("{}: not a valid {}", field.name, field.kind));
+        } else if let (Some(allowed), Bson::String(s)) = (&field.constraints.allowed, value) {
+            if !allowed.contains(s) {
+                problems.push(format!("{}: {:?} not in enumeration", field.name, s));
+            }
+        }
+    }
+    problems
+}
+
+fn is_empty(value: &Bson) -> bool {
+    match value {
+        Bson::Null => true,
+        Bson::String(s) => s.is_empty(),
+        _ => false,
+    }
+}
+
+/// Whether `value` is, or (for CSV-loaded strings) parses as, a frictionless
+/// `kind`. Types the check doesn't know are accepted.
+fn has_type(value: &Bson, kind: &str) -> bool {
+    match (kind, value) {
+        ("integer", Bson::Int32(_) | Bson::Int64(_)) => true,
+        ("integer", Bson::String(s)) => s.parse::<i64>().is_ok(),
+        ("number", Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_)) => true,
+        ("number", Bson::String(s)) => s.parse::<f64>().is_ok(),
+        ("boolean", Bson::Boolean(_)) => true,
+        ("boolean", Bson::String(s)) => {
+            matches!(
+                s.to_ascii_lowercase().as_str(),
+                "true" | "false" | "1" | "0"
+            )
+        }
+        ("date" | "datetime", Bson::DateTime(_)) => true,
+        ("date" | "datetime", Bson::String(s)) => parse_date(s).is_some(),
+        ("string", Bson::String(_)) => true,
+        ("integer" | "number" | "boolean" | "date" | "datetime" | "string", _) => false,
+        _ => true,
+    }
+}
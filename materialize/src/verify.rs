@@ -0,0 +1,199 @@
+use anyhow::Result;
+use base64::Engine;
+use bson::{doc, Document};
+use mongodb::sync::Database;
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::cli::{number, value};
+use crate::projects::integer_field;
+
+/// Metadata reported by the object store for one object.
+#[derive(Default)]
+struct ObjectMeta {
+    size: Option<i64>,
+    md5: Option<String>,
+    sha256: Option<String>,
+}
+
+enum Outcome {
+    Match,
+    Mismatch(Vec<String>),
+    Unreachable(String),
+}
+
+#[derive(Default)]
+struct DccTally {
+    checked: u64,
+    matched: u64,
+    mismatched: u64,
+    unreachable: u64,
+}
+
+/// `verify [--submission X] [--sample N]` compares the size and checksums
+/// of files whose `persistent_id` points at S3, GCS, or plain HTTP(S)
+/// against the object store's metadata, reporting mismatches per DCC.
+/// Objects are fetched anonymously, so only public buckets can be checked.
+pub fn command(db: &Database, args: &[String]) -> Result<()> {
+    let submission = value(args, "--submission");
+    let sample: Option<i64> = number(args, "--sample")?;
+
+    let mut filter = doc! { "persistent_id": { "$regex": "^(s3|gs|https?)://" } };
+    if let Some(ref sub) = submission {
+        filter.insert("submission", sub);
+    }
+    let mut pipeline = vec![doc! { "$match": filter }];
+    if let Some(size) = sample {
+        pipeline.push(doc! { "$sample": { "size": size } });
+    }
+    pipeline.push(doc! { "$project": {
+        "id_namespace": 1,
+        "local_id": 1,
+        "submission": 1,
+        "persistent_id": 1,
+        "size_in_bytes": 1,
+        "sha256": 1,
+        "md5": 1,
+        "dcc.dcc_abbreviation": 1,
+    } });
+    let files: Vec<Document> = db
+        .collection::<Document>("files")
+        .aggregate(pipeline)
+        .run()?
+        .collect::<Result<_, _>>()?;
+    println!("Verifying {} files against object storage", files.len());
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(30))
+        .build();
+    let outcomes: Vec<Outcome> = files.par_iter().map(|file| check(&agent, file)).collect();
+
+    let mut tallies: BTreeMap<String, DccTally> = BTreeMap::new();
+    for (file, outcome) in files.iter().zip(&outcomes) {
+        let dcc = file
+            .get_document("dcc")
+            .and_then(|dcc| dcc.get_str("dcc_abbreviation"))
+            .or_else(|_| file.get_str("submission"))
+            .unwrap_or("unknown")
+            .to_string();
+        let tally = tallies.entry(dcc.clone()).or_default();
+        tally.checked += 1;
+        let key = format!(
+            "{}/{}",
+            file.get_str("id_namespace").unwrap_or_default(),
+            file.get_str("local_id").unwrap_or_default()
+        );
+        match outcome {
+            Outcome::Match => tally.matched += 1,
+            Outcome::Mismatch(problems) => {
+                tally.mismatched += 1;
+                println!("  MISMATCH {} {}: {}", dcc, key, problems.join("; "));
+            }
+            Outcome::Unreachable(error) => {
+                tally.unreachable += 1;
+                println!("  ERROR    {} {}: {}", dcc, key, error);
+            }
+        }
+    }
+
+    println!();
+    println!(
+        "  {:<16} {:>9} {:>9} {:>11} {:>12}",
+        "dcc", "checked", "ok", "mismatched", "unreachable"
+    );
+    let mut mismatched = 0;
+    for (dcc, tally) in &tallies {
+        println!(
+            "  {:<16} {:>9} {:>9} {:>11} {:>12}",
+            dcc, tally.checked, tally.matched, tally.mismatched, tally.unreachable
+        );
+        mismatched += tally.mismatched;
+    }
+
+    if mismatched > 0 {
+        anyhow::bail!("{} files do not match object storage", mismatched);
+    }
+    Ok(())
+}
+
+fn check(agent: &ureq::Agent, file: &Document) -> Outcome {
+    let pid = file.get_str("persistent_id").unwrap_or_default();
+    let meta = match head(agent, &object_url(pid)) {
+        Ok(meta) => meta,
+        Err(e) => return Outcome::Unreachable(e.to_string()),
+    };
+
+    let mut problems = Vec::new();
+    if let (Some(expected), Some(actual)) = (integer_field(file, "size_in_bytes"), meta.size) {
+        if expected != actual {
+            problems.push(format!("size {} != {}", expected, actual));
+        }
+    }
+    for (field, actual) in [("sha256", &meta.sha256), ("md5", &meta.md5)] {
+        let expected = file.get_str(field).ok().filter(|sum| !sum.is_empty());
+        if let (Some(expected), Some(actual)) = (expected, actual) {
+            if !expected.eq_ignore_ascii_case(actual) {
+                problems.push(format!("{} {} != {}", field, expected, actual));
+            }
+        }
+    }
+    if problems.is_empty() {
+        Outcome::Match
+    } else {
+        Outcome::Mismatch(problems)
+    }
+}
+
+/// The public HTTPS endpoint for an `s3://` or `gs://` URI.
+fn object_url(pid: &str) -> String {
+    if let Some(rest) = pid.strip_prefix("s3://") {
+        let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+        format!("https://{}.s3.amazonaws.com/{}", bucket, key)
+    } else if let Some(rest) = pid.strip_prefix("gs://") {
+        format!("https://storage.googleapis.com/{}", rest)
+    } else {
+        pid.to_string()
+    }
+}
+
+/// HEAD the object and pull size and checksums from the S3 (`ETag`,
+/// `x-amz-checksum-sha256`) or GCS (`x-goog-hash`) headers. Checksums come
+/// back hex-encoded.
+fn head(agent: &ureq::Agent, url: &str) -> Result<ObjectMeta> {
+    let response = agent
+        .head(url)
+        .set("x-amz-checksum-mode", "ENABLED")
+        .call()?;
+    let mut meta = ObjectMeta {
+        size: response
+            .header("x-goog-stored-content-length")
+            .or_else(|| response.header("content-length"))
+            .and_then(|len| len.parse().ok()),
+        ..ObjectMeta::default()
+    };
+
+    // A multipart S3 upload's ETag (`<hex>-<parts>`) is not an MD5
+    let etag = response.header("etag").map(|tag| tag.trim_matches('"'));
+    if let Some(tag) = etag.filter(|tag| tag.len() == 32 && !tag.contains('-')) {
+        meta.md5 = Some(tag.to_ascii_lowercase());
+    }
+    if let Some(sum) = response.header("x-amz-checksum-sha256") {
+        meta.sha256 = base64_to_hex(sum);
+    }
+    if let Some(hashes) = response.header("x-goog-hash") {
+        for hash in hashes.split(',') {
+            if let Some(sum) = hash.trim().strip_prefix("md5=") {
+                meta.md5 = base64_to_hex(sum);
+            }
+        }
+    }
+    Ok(meta)
+}
+
+fn base64_to_hex(value: &str) -> Option<String> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(value.trim())
+        .ok()?;
+    Some(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
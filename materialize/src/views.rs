@@ -0,0 +1,150 @@
+use anyhow::Result;
+use bson::{doc, Document};
+use mongodb::sync::{Collection, Database};
+use mongodb::IndexModel;
+use rayon::prelude::*;
+
+use crate::checkpoint;
+use crate::enrich::{embed_dcc, enrich_biosample, enrich_collection, enrich_subject, Trace};
+use crate::lookup::{LookupContext, LookupMap};
+use crate::migrate;
+
+/// A denormalized collection written from the loaded lookup tables. `files`
+/// is the main pipeline; the others embed the same terms and nested
+/// entities rooted at a different table.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum View {
+    Files,
+    Collections,
+    Biosamples,
+    Subjects,
+}
+
+impl View {
+    /// Parse a `--views` list such as `files,subjects`; defaults to `files`.
+    pub fn parse_list(spec: Option<&str>) -> Result<Vec<View>> {
+        let Some(spec) = spec else {
+            return Ok(vec![View::Files]);
+        };
+        let mut views = Vec::new();
+        for name in spec.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let view = match name {
+                "files" => View::Files,
+                "collections" => View::Collections,
+                "biosamples" => View::Biosamples,
+                "subjects" => View::Subjects,
+                other => anyhow::bail!(
+                    "Unknown view {:?}; expected files, collections, biosamples, or subjects",
+                    other
+                ),
+            };
+            if !views.contains(&view) {
+                views.push(view);
+            }
+        }
+        if views.is_empty() {
+            anyhow::bail!("--views needs at least one view");
+        }
+        Ok(views)
+    }
+
+    /// Collection the view is written to.
+    pub fn collection(self) -> &'static str {
+        match self {
+            View::Files => "files",
+            View::Collections => "collections",
+            View::Biosamples => "biosamples",
+            View::Subjects => "subjects",
+        }
+    }
+
+    fn source<'c>(self, ctx: &'c LookupContext) -> &'c LookupMap {
+        match self {
+            View::Files => unreachable!("files are read from the `file` collection"),
+            View::Collections => &ctx.collections,
+            View::Biosamples => &ctx.biosamples,
+            View::Subjects => &ctx.subjects,
+        }
+    }
+}
+
+/// Replace an entity view (all of it, or one submission's documents) with
+/// every loaded entity enriched like its counterpart nested under `files`.
+/// Returns the number of documents written.
+pub fn write(
+    db: &Database,
+    ctx: &LookupContext,
+    view: View,
+    submission_filter: &Option<String>,
+) -> Result<u64> {
+    let output: Collection<Document> = db.collection(view.collection());
+    match submission_filter {
+        Some(sub) => {
+            output.delete_many(doc! { "submission": sub }).run()?;
+        }
+        None => output.drop().run()?,
+    }
+
+    let entities: Vec<(String, String, Document)> = view
+        .source(ctx)
+        .iter()
+        .map(|(ns, id, doc)| (ns, id, doc.into_owned()))
+        .collect();
+    let enriched: Vec<Document> = entities
+        .into_par_iter()
+        .map(|(ns, id, mut doc)| {
+            let submission = doc
+                .get_str("submission")
+                .ok()
+                .or(submission_filter.as_deref())
+                .unwrap_or_default()
+                .to_string();
+            doc.remove("_id");
+            doc.insert("id_namespace", ns.as_str());
+            doc.insert("local_id", id.as_str());
+            doc.insert("submission", submission.as_str());
+
+            let mut trace = Trace::disabled();
+            embed_dcc(&mut doc, &submission, ctx, &mut trace);
+            match view {
+                View::Collections => {
+                    enrich_collection(&mut doc, &ns, &id, &submission, ctx, &mut trace)
+                }
+                View::Biosamples => {
+                    enrich_biosample(&mut doc, &ns, &id, &submission, ctx, &mut trace)
+                }
+                View::Subjects => enrich_subject(&mut doc, &ns, &id, &submission, ctx, &mut trace),
+                View::Files => unreachable!(),
+            }
+            migrate::stamp(&mut doc, ctx.materialized_at);
+            doc
+        })
+        .collect();
+
+    let mut written = 0;
+    for chunk in enriched.chunks(ctx.opts.batch_size) {
+        if checkpoint::requested() {
+            anyhow::bail!(
+                "Interrupted after writing {} {}",
+                written,
+                view.collection()
+            );
+        }
+        output.insert_many(chunk).run()?;
+        written += chunk.len() as u64;
+    }
+
+    let indexes = vec![
+        doc! { "id_namespace": 1, "local_id": 1 },
+        doc! { "submission": 1 },
+        doc! { "dcc.id": 1 },
+    ];
+    output
+        .create_indexes(
+            indexes
+                .into_iter()
+                .map(|keys| IndexModel::builder().keys(keys).build()),
+        )
+        .run()?;
+    Ok(written)
+}
@@ -0,0 +1,144 @@
+use anyhow::Result;
+use bson::{doc, Bson, Document};
+use mongodb::sync::Database;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::cli::{number, values};
+use crate::config::Config;
+use crate::lookup::{ANATOMY_TABLE, DISEASE_TABLE, GENE_TABLE, SUBSTANCE_TABLE};
+
+/// Term metadata compared across submissions.
+const COMPARED_FIELDS: [&str; 3] = ["name", "description", "synonyms"];
+
+/// Conflicting terms printed per table before the rest are only counted.
+const DEFAULT_SHOWN: usize = 20;
+
+/// Every value one field takes, with the submissions giving it.
+type Variants = BTreeMap<String, BTreeSet<String>>;
+
+/// What one CV term looks like in each submission that defines it.
+#[derive(Default)]
+struct TermVariants {
+    submissions: BTreeSet<String>,
+    fields: BTreeMap<&'static str, Variants>,
+}
+
+impl TermVariants {
+    fn observe(&mut self, submission: &str, row: &Document) {
+        self.submissions.insert(submission.to_string());
+        for field in COMPARED_FIELDS {
+            if let Some(value) = row.get(field).and_then(render) {
+                self.fields
+                    .entry(field)
+                    .or_default()
+                    .entry(value)
+                    .or_default()
+                    .insert(submission.to_string());
+            }
+        }
+    }
+
+    /// Fields given more than one value across submissions.
+    fn conflicts(&self) -> impl Iterator<Item = (&'static str, &Variants)> {
+        self.fields
+            .iter()
+            .filter(|(_, values)| values.len() > 1)
+            .map(|(field, values)| (*field, values))
+    }
+}
+
+/// A comparable form of a metadata value: trimmed strings, and arrays
+/// (`synonyms`) sorted and joined with `|`. Blank values count as absent,
+/// so a submission leaving a description out doesn't conflict.
+fn render(value: &Bson) -> Option<String> {
+    let rendered = match value {
+        Bson::String(s) => s.trim().to_string(),
+        Bson::Array(items) => {
+            let mut items: Vec<String> = items.iter().filter_map(render).collect();
+            items.sort();
+            items.join("|")
+        }
+        Bson::Null => return None,
+        other => other.to_string(),
+    };
+    (!rendered.is_empty()).then_some(rendered)
+}
+
+/// `vocab audit [--table NAME]... [--examples N]` groups the rows of each
+/// CV table by term id across submissions and reports the ids whose name,
+/// description, or synonyms differ between the submissions defining them.
+pub fn command(db: &Database, config: &Config, args: &[String]) -> Result<()> {
+    match args.first().map(String::as_str) {
+        Some("audit") => audit(db, config, &args[1..]),
+        Some(other) => anyhow::bail!("Unknown vocab command: {}", other),
+        None => anyhow::bail!("Usage: vocab audit [--table NAME]..."),
+    }
+}
+
+fn audit(db: &Database, config: &Config, args: &[String]) -> Result<()> {
+    let mut tables = values(args, "--table");
+    if tables.is_empty() {
+        // The tables a run loads: the spec's, plus the rollup vocabularies
+        for table in config.enrichment.tables().into_iter().chain([
+            ANATOMY_TABLE,
+            DISEASE_TABLE,
+            SUBSTANCE_TABLE,
+            GENE_TABLE,
+        ]) {
+            if !tables.iter().any(|t| t == table) {
+                tables.push(table.to_string());
+            }
+        }
+    }
+    let shown = number(args, "--examples")?.unwrap_or(DEFAULT_SHOWN);
+
+    for table in &tables {
+        let mut terms: BTreeMap<String, TermVariants> = BTreeMap::new();
+        for row in db
+            .collection::<Document>(table)
+            .find(doc! {})
+            .projection(doc! { "_id": 0 })
+            .run()?
+        {
+            let mut row = row?;
+            config.normalize.apply(&mut row);
+            if let (Ok(id), Ok(submission)) = (row.get_str("id"), row.get_str("submission")) {
+                terms
+                    .entry(id.to_string())
+                    .or_default()
+                    .observe(submission, &row);
+            }
+        }
+
+        let shared = terms.values().filter(|t| t.submissions.len() > 1).count();
+        let conflicting: Vec<(&String, &TermVariants)> = terms
+            .iter()
+            .filter(|(_, term)| term.conflicts().next().is_some())
+            .collect();
+        println!(
+            "\n{}: {} terms, {} defined by several submissions, {} with conflicting metadata",
+            table,
+            terms.len(),
+            shared,
+            conflicting.len()
+        );
+        for (id, term) in conflicting.iter().take(shown) {
+            let submissions: Vec<&str> = term.submissions.iter().map(String::as_str).collect();
+            println!("  {} ({})", id, submissions.join(", "));
+            for (field, values) in term.conflicts() {
+                let variants: Vec<String> = values
+                    .iter()
+                    .map(|(value, subs)| {
+                        let subs: Vec<&str> = subs.iter().map(String::as_str).collect();
+                        format!("{:?} ({})", value, subs.join(", "))
+                    })
+                    .collect();
+                println!("    {}: {}", field, variants.join("; "));
+            }
+        }
+        if conflicting.len() > shown {
+            println!("  ... and {} more", conflicting.len() - shown);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use anyhow::Result;
+use bson::{doc, Document};
+use mongodb::sync::Database;
+use std::collections::HashMap;
+
+use crate::projects::integer_field;
+
+/// This process's share of a rebuild split across several workers
+/// (`--workers N --worker-id I`, I counting from 0). Every worker computes
+/// the same assignment from the source, so they need no coordination
+/// beyond being started with the same selection.
+#[derive(Clone, Copy)]
+pub struct Workers {
+    pub count: usize,
+    pub id: usize,
+}
+
+impl Workers {
+    pub fn parse(count: Option<usize>, id: Option<usize>) -> Result<Option<Self>> {
+        match (count, id) {
+            (None, None) => Ok(None),
+            (Some(count), Some(id)) if count > 0 && id < count => Ok(Some(Workers { count, id })),
+            (Some(count), Some(id)) => {
+                anyhow::bail!("--worker-id must be below --workers {}, got {}", count, id)
+            }
+            _ => anyhow::bail!("--workers and --worker-id must be given together"),
+        }
+    }
+
+    /// The selected submissions this worker materializes. Submissions are
+    /// dealt largest first, by file count in the source, to whichever worker
+    /// has the fewest files so far, so workers finish at about the same
+    /// time even when a few submissions dominate.
+    pub fn assign(self, db: &Database, submissions: Vec<String>) -> Result<Vec<String>> {
+        let pipeline = vec![
+            doc! { "$match": { "submission": { "$in": &submissions } } },
+            doc! { "$group": { "_id": "$submission", "files": { "$sum": 1 } } },
+        ];
+        let mut files: HashMap<String, i64> = HashMap::new();
+        for group in db
+            .collection::<Document>("file")
+            .aggregate(pipeline)
+            .run()?
+        {
+            let group = group?;
+            if let Ok(submission) = group.get_str("_id") {
+                files.insert(
+                    submission.to_string(),
+                    integer_field(&group, "files").unwrap_or(0),
+                );
+            }
+        }
+
+        let sizes: Vec<(i64, String)> = submissions
+            .into_iter()
+            .map(|sub| (files.get(&sub).copied().unwrap_or(0), sub))
+            .collect();
+        let (assigned, assigned_files) = self.deal(sizes);
+        println!(
+            "Worker {} of {}: {} submissions, {} files: {}",
+            self.id,
+            self.count,
+            assigned.len(),
+            assigned_files,
+            assigned.join(", ")
+        );
+        Ok(assigned)
+    }
+
+    /// This worker's share of `sizes` (file count, submission), sorted, and
+    /// its total file count.
+    fn deal(self, mut sizes: Vec<(i64, String)>) -> (Vec<String>, i64) {
+        sizes.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        let mut loads = vec![0i64; self.count];
+        let mut assigned = Vec::new();
+        let mut assigned_files = 0;
+        for (size, submission) in sizes {
+            let worker = (0..self.count).min_by_key(|&w| (loads[w], w)).unwrap();
+            loads[worker] += size;
+            if worker == self.id {
+                assigned.push(submission);
+                assigned_files += size;
+            }
+        }
+        assigned.sort();
+        (assigned, assigned_files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn share(count: usize, id: usize, sizes: &[(i64, &str)]) -> (Vec<String>, i64) {
+        let sizes = sizes.iter().map(|&(n, s)| (n, s.to_string())).collect();
+        Workers { count, id }.deal(sizes)
+    }
+
+    #[test]
+    fn deals_largest_first_to_the_least_loaded_worker() {
+        let sizes = [(10, "d"), (100, "a"), (50, "c"), (60, "b")];
+        assert_eq!(share(2, 0, &sizes), (vec!["a".into(), "d".into()], 110));
+        assert_eq!(share(2, 1, &sizes), (vec!["b".into(), "c".into()], 110));
+    }
+
+    #[test]
+    fn ties_are_dealt_by_name() {
+        let sizes = [(5, "b"), (5, "a"), (5, "c")];
+        assert_eq!(share(2, 0, &sizes).0, ["a", "c"]);
+        assert_eq!(share(2, 1, &sizes).0, ["b"]);
+    }
+
+    #[test]
+    fn every_submission_goes_to_exactly_one_worker() {
+        let sizes: Vec<(i64, String)> = (0..20)
+            .map(|i| (i * 7 % 13, format!("s{:02}", i)))
+            .collect();
+        let mut dealt: Vec<String> = (0..3)
+            .flat_map(|id| Workers { count: 3, id }.deal(sizes.clone()).0)
+            .collect();
+        dealt.sort();
+        let expected: Vec<String> = sizes.into_iter().map(|(_, s)| s).collect();
+        assert_eq!(dealt, expected);
+    }
+
+    #[test]
+    fn parses_worker_options() {
+        assert!(Workers::parse(None, None).unwrap().is_none());
+        assert!(Workers::parse(Some(3), Some(2)).unwrap().is_some());
+        assert!(Workers::parse(Some(3), Some(3)).is_err());
+        assert!(Workers::parse(Some(0), Some(0)).is_err());
+        assert!(Workers::parse(Some(3), None).is_err());
+    }
+}
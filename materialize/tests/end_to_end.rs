@@ -0,0 +1,133 @@
+//! End-to-end runs of the materializer against a throwaway MongoDB. They
+//! need Docker, so they are ignored by default; run them with
+//! `cargo test -- --ignored`.
+
+mod testutil;
+
+use bson::{doc, Bson, Document};
+use materialize::migrate::SCHEMA_VERSION;
+use testutil::{scratch_dir, TestMongo};
+
+const SIZES: [&str; 6] = ["--files", "40", "--collections", "4", "--biosamples", "12"];
+
+#[test]
+#[ignore = "starts a MongoDB container"]
+fn fixture_submission_is_fully_enriched() {
+    let mongo = TestMongo::start();
+    mongo.load_fixture(&scratch_dir("enriched"), "demo", &SIZES);
+    mongo.materialize(&["--submission", "demo"]);
+
+    let files = mongo.files();
+    assert_eq!(files.count_documents(doc! {}).run().unwrap(), 40);
+    // Every vocabulary reference in the fixture has a CV row, so every
+    // file resolves its format and DCC
+    let resolved = doc! {
+        "dcc.dcc_abbreviation": "DEMO",
+        "file_format.name": { "$exists": true },
+        "data_type.name": { "$exists": true },
+        "assay_type.name": { "$exists": true },
+    };
+    assert_eq!(files.count_documents(resolved).run().unwrap(), 40);
+
+    let file = mongo.file("file-0000000");
+    assert_eq!(file.get_str("submission").unwrap(), "demo");
+    assert!(matches!(file.get("size_in_bytes"), Some(Bson::Int64(_))));
+    assert!(matches!(file.get("creation_time"), Some(Bson::DateTime(_))));
+    assert_eq!(
+        file.get_i32("materialized_schema_version").unwrap(),
+        SCHEMA_VERSION
+    );
+    assert!(file.get_datetime("materialized_at").is_ok());
+
+    let collections = file.get_array("collections").unwrap();
+    assert!(!collections.is_empty());
+    let collection = collections[0].as_document().unwrap();
+    assert_eq!(collection.get_str("local_id").unwrap(), "collection-00000");
+    assert_eq!(collection.get_array("anatomies").unwrap().len(), 1);
+    assert_eq!(collection.get_array("defined_by_project").unwrap().len(), 1);
+
+    // Biosamples i, i + 4, i + 8 belong to collection i
+    let biosamples = collection.get_array("biosamples").unwrap();
+    assert_eq!(biosamples.len(), 3);
+    let biosample = biosamples[0].as_document().unwrap();
+    assert!(biosample
+        .get_document("anatomy")
+        .unwrap()
+        .contains_key("name"));
+    let subjects = biosample.get_array("subjects").unwrap();
+    assert_eq!(subjects.len(), 1);
+    let subject = subjects[0].as_document().unwrap();
+    assert!(subject.get_document("sex").unwrap().contains_key("name"));
+    assert_eq!(subject.get_array("race").unwrap().len(), 1);
+
+    let run = mongo
+        .db()
+        .collection::<Document>("materialize_runs")
+        .find_one(doc! { "submission": "demo" })
+        .run()
+        .unwrap()
+        .expect("a run record");
+    assert_eq!(run.get_str("outcome").unwrap(), "success");
+}
+
+#[test]
+#[ignore = "starts a MongoDB container"]
+fn rematerializing_replaces_only_that_submission() {
+    let mongo = TestMongo::start();
+    let dir = scratch_dir("replace");
+    mongo.load_fixture(&dir, "demo", &SIZES);
+    mongo.load_fixture(&dir, "other", &["--files", "10", "--dcc", "OTHER"]);
+    mongo.materialize(&["--submission", "demo"]);
+    mongo.materialize(&["--submission", "other"]);
+    mongo.materialize(&["--submission", "demo"]);
+
+    let files = mongo.files();
+    let count = |submission: &str| {
+        files
+            .count_documents(doc! { "submission": submission })
+            .run()
+            .unwrap()
+    };
+    assert_eq!(count("demo"), 40);
+    assert_eq!(count("other"), 10);
+}
+
+#[test]
+#[ignore = "starts a MongoDB container"]
+fn hoisted_biosamples_are_embedded_once() {
+    let mongo = TestMongo::start();
+    mongo.load_fixture(&scratch_dir("hoist"), "demo", &SIZES);
+    mongo.materialize(&["--submission", "demo", "--hoist-biosamples"]);
+
+    // Collections never share biosamples in the fixture, so each ref names
+    // a distinct hoisted biosample
+    for file in mongo.files().find(doc! {}).run().unwrap() {
+        let file = file.unwrap();
+        let mut refs = 0;
+        for collection in file.get_array("collections").unwrap() {
+            let collection = collection.as_document().unwrap();
+            assert!(!collection.contains_key("biosamples"));
+            refs += collection.get_array("biosample_refs").unwrap().len();
+        }
+        assert_eq!(file.get_array("biosamples").unwrap().len(), refs);
+        assert!(refs >= 3);
+    }
+}
+
+#[test]
+#[ignore = "starts a MongoDB container"]
+fn retract_removes_source_and_materialized_rows() {
+    let mongo = TestMongo::start();
+    mongo.load_fixture(&scratch_dir("retract"), "demo", &SIZES);
+    mongo.materialize(&["--submission", "demo"]);
+    mongo.materialize(&["retract", "--submission", "demo", "--yes"]);
+
+    assert_eq!(mongo.files().count_documents(doc! {}).run().unwrap(), 0);
+    let raw = mongo
+        .db()
+        .collection::<Document>("file")
+        .count_documents(doc! { "submission": "demo" })
+        .run()
+        .unwrap();
+    assert_eq!(raw, 0);
+}
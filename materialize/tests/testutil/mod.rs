@@ -0,0 +1,107 @@
+//! Shared setup for the integration tests: a throwaway MongoDB container and
+//! helpers for running the `materialize` binary against it.
+
+use bson::{doc, Document};
+use mongodb::sync::{Client, Collection, Database};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use testcontainers::runners::SyncRunner;
+use testcontainers::Container;
+use testcontainers_modules::mongo::Mongo;
+
+/// A MongoDB server that lives as long as the value; the container is
+/// removed when it is dropped.
+pub struct TestMongo {
+    _container: Container<Mongo>,
+    pub uri: String,
+    client: Client,
+}
+
+impl TestMongo {
+    pub fn start() -> Self {
+        let container = Mongo::default()
+            .start()
+            .expect("starting the MongoDB container (is Docker running?)");
+        let host = container.get_host().expect("container host");
+        let port = container.get_host_port_ipv4(27017).expect("container port");
+        let uri = format!("mongodb://{}:{}", host, port);
+        let client = Client::with_uri_str(&uri).expect("connecting to the container");
+        TestMongo {
+            _container: container,
+            uri,
+            client,
+        }
+    }
+
+    /// The database the materializer reads and writes.
+    pub fn db(&self) -> Database {
+        self.client.database("cfdb")
+    }
+
+    pub fn files(&self) -> Collection<Document> {
+        self.db().collection("files")
+    }
+
+    /// Run `materialize` with these arguments against the container and
+    /// panic with its output unless it succeeds.
+    pub fn materialize(&self, args: &[&str]) -> String {
+        let output = Command::new(env!("CARGO_BIN_EXE_materialize"))
+            .args(args)
+            .arg("--quiet")
+            .env("DATABASE_URL", &self.uri)
+            .output()
+            .expect("running materialize");
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        assert!(
+            output.status.success(),
+            "materialize {:?} failed:\n{}\n{}",
+            args,
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        stdout
+    }
+
+    /// Generate a fixture package with `generate-fixtures` and ingest it as
+    /// `submission`.
+    pub fn load_fixture(&self, dir: &Path, submission: &str, sizes: &[&str]) {
+        let package = fixture(dir, submission, sizes);
+        self.materialize(&[
+            "ingest",
+            package.to_str().unwrap(),
+            "--submission",
+            submission,
+        ]);
+    }
+
+    /// The materialized file with this `local_id`.
+    pub fn file(&self, local_id: &str) -> Document {
+        self.files()
+            .find_one(doc! { "local_id": local_id })
+            .run()
+            .expect("querying files")
+            .unwrap_or_else(|| panic!("{} was not materialized", local_id))
+    }
+}
+
+/// Write `<dir>/<name>.zip` with `generate-fixtures`; `sizes` are its
+/// `--files`/`--collections`/`--biosamples` options.
+pub fn fixture(dir: &Path, name: &str, sizes: &[&str]) -> PathBuf {
+    let package = dir.join(format!("{}.zip", name));
+    let status = Command::new(env!("CARGO_BIN_EXE_materialize"))
+        .arg("generate-fixtures")
+        .arg(&package)
+        .args(sizes)
+        .status()
+        .expect("running generate-fixtures");
+    assert!(status.success(), "generate-fixtures failed");
+    package
+}
+
+/// A fresh scratch directory for one test's packages.
+pub fn scratch_dir(test: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("materialize-test-{}-{}", std::process::id(), test));
+    std::fs::create_dir_all(&dir).expect("creating the scratch directory");
+    dir
+}
@@ -1,5 +1,7 @@
 from __future__ import annotations
 
+from datetime import datetime
+
 import strawberry
 
 
@@ -10,6 +12,13 @@ class AnatomyInput:
     description: list[str] | None = None
 
 
+@strawberry.input
+class AnalysisTypeInput:
+    id: list[str] | None = None
+    name: list[str] | None = None
+    description: list[str] | None = None
+
+
 @strawberry.input
 class AssayTypeInput:
     id: list[str] | None = None
@@ -28,6 +37,7 @@ class BiosampleInput:
     sample_prep_method: list[str] | None = None
     anatomy: list[AnatomyInput] | None = None
     biofluid: list[str] | None = None
+    subjects: list[SubjectInput] | None = None
 
 
 @strawberry.input
@@ -49,6 +59,17 @@ class DataTypeInput:
     description: list[str] | None = None
 
 
+@strawberry.input
+class DateTimeRangeInput:
+    """
+    Dates from `gte` (inclusive) up to `lt` (exclusive); either bound may be
+    left open.
+    """
+
+    gte: datetime | None = None
+    lt: datetime | None = None
+
+
 @strawberry.input
 class DCCInput:
     id: list[str] | None = None
@@ -78,16 +99,16 @@ class FileMetadataInput:
     project_id_namespace: list[str] | None = None
     project_local_id: list[str] | None = None
     persistent_id: list[str] | None = None
-    creation_time: list[str] | None = None
+    creation_time: list[DateTimeRangeInput] | None = None
     size_in_bytes: list[int] | None = None
     sha256: list[str] | None = None
     md5: list[str] | None = None
     filename: list[str] | None = None
     file_format: list[FileFormatInput] | None = None
-    compression_format: list[str] | None = None
+    compression_format: list[FileFormatInput] | None = None
     data_type: list[DataTypeInput] | None = None
     assay_type: list[AssayTypeInput] | None = None
-    analysis_type: list[str] | None = None
+    analysis_type: list[AnalysisTypeInput] | None = None
     mime_type: list[str] | None = None
     bundle_collection_id_namespace: list[str] | None = None
     bundle_collection_local_id: list[str] | None = None
@@ -95,12 +116,34 @@ class FileMetadataInput:
     access_url: list[str] | None = None
 
 
+@strawberry.input
+class SubjectTermInput:
+    id: list[str] | None = None
+    name: list[str] | None = None
+
+
+@strawberry.input
+class SubjectInput:
+    id_namespace: list[str] | None = None
+    local_id: list[str] | None = None
+    persistent_id: list[str] | None = None
+    creation_time: list[str] | None = None
+    granularity: list[SubjectTermInput] | None = None
+    sex: list[SubjectTermInput] | None = None
+    ethnicity: list[SubjectTermInput] | None = None
+    race: list[SubjectTermInput] | None = None
+
+
 def to_dict(obj):
     """
     Convert a nested strawberry input object into a dict.
     """
     if isinstance(obj, list):
         return [to_dict(item) for item in obj]
+    if isinstance(obj, DateTimeRangeInput):
+        # A query operator document, matched against the BSON date as a whole
+        bounds = {"$gte": obj.gte, "$lt": obj.lt}
+        return {op: bound for op, bound in bounds.items() if bound is not None}
     if not hasattr(obj, "__strawberry_definition__"):
         return obj
     result = {}
@@ -115,6 +158,8 @@ def to_query(obj, prefix=""):
     Convert a nested dict/list structure into a flattened MongoDB query.
     """
     if isinstance(obj, dict):
+        if obj and all(k.startswith("$") for k in obj):
+            return {prefix: obj}
         and_clause = []
         for k, v in obj.items():
             key = f"{prefix}.{k}" if prefix else k
@@ -1,8 +1,9 @@
 from __future__ import annotations
 
+from datetime import datetime
 from typing import List, Optional
 
-from pydantic import BaseModel
+from pydantic import BaseModel, field_validator
 
 
 class FileMetadataModel(BaseModel):
@@ -60,8 +61,9 @@ class FileMetadataModel(BaseModel):
             (e.g., TSV or FASTQ). If compressed, this is the uncompressed format.
         
         compression_format:
-            An EDAM CV term ID identifying the compression format (e.g., gzip or
-            bzip2). None if file is not compressed.
+            An EDAM CV term identifying the compression format (e.g., gzip or
+            bzip2), resolved from the file_format table. None if file is not
+            compressed.
         
         data_type:
             An EDAM CV term ID identifying the type of information stored in this
@@ -72,7 +74,7 @@ class FileMetadataModel(BaseModel):
             results summarized by this file.
         
         analysis_type:
-            An OBI CV term ID describing the type of analytic operation that
+            An OBI CV term describing the type of analytic operation that
             generated this file.
         
         mime_type:
@@ -118,10 +120,10 @@ class FileMetadataModel(BaseModel):
     md5: Optional[str] = None
     filename: str = str()
     file_format: Optional[FileFormat] = None
-    compression_format: Optional[str] = None
+    compression_format: Optional[FileFormat] = None
     data_type: Optional[DataType] = None
     assay_type: Optional[AssayType] = None
-    analysis_type: Optional[str] = None
+    analysis_type: Optional[AnalysisType] = None
     mime_type: Optional[str] = None
     bundle_collection_id_namespace: Optional[str] = None
     bundle_collection_local_id: Optional[str] = None
@@ -130,6 +132,22 @@ class FileMetadataModel(BaseModel):
     status: Optional[str] = None
     data_access_level: Optional[str] = None
 
+    @field_validator("creation_time", mode="before")
+    @classmethod
+    def _creation_time_isoformat(cls, value):
+        # The materializer stores creation_time as a BSON date
+        if isinstance(value, datetime):
+            return value.isoformat()
+        return value
+
+    @field_validator("compression_format", "analysis_type", mode="before")
+    @classmethod
+    def _cv_term(cls, value):
+        # An id the materializer couldn't resolve is left as a bare string
+        if isinstance(value, str):
+            return {"id": value}
+        return value
+
 
 class DCC(BaseModel):
     """
@@ -200,6 +218,26 @@ class AssayType(BaseModel):
     description: Optional[str] = None
 
 
+class AnalysisType(BaseModel):
+    """
+    An Ontology for Biomedical Investigations (OBI) CV term.
+
+    Describes types of analytic operations that generate C2M2 files.
+
+    Attributes:
+        id:
+            An OBI CV term identifier.
+        name:
+            A short, human-readable, machine-read-friendly label for this OBI term.
+        description:
+            A human-readable description of this OBI term.
+    """
+
+    id: str = str()
+    name: str = str()
+    description: Optional[str] = None
+
+
 class FileFormat(BaseModel):
     """
     An EDAM CV 'format:' term.
@@ -328,6 +366,10 @@ class Biosample(BaseModel):
         biofluid:
             An UBERON CV term or InterLex term used to locate the origin of this
             biosample within the fluid compartment of its source or host organism.
+        
+        subjects:
+            The subjects this biosample was taken from, with their CV terms
+            resolved.
     """
 
     id_namespace: str = str()
@@ -339,6 +381,7 @@ class Biosample(BaseModel):
     sample_prep_method: Optional[str] = None
     anatomy: Optional[Anatomy] = None
     biofluid: Optional[str] = None
+    subjects: List[Subject] = []
 
 
 class Anatomy(BaseModel):
@@ -363,3 +406,135 @@ class Anatomy(BaseModel):
     id: str = str()
     name: str = str()
     description: Optional[str] = None
+
+
+class Subject(BaseModel):
+    """
+    A biological entity from which a C2M2 biosample can be generated.
+
+    Attributes:
+        id_namespace:
+            A CFDE-cleared identifier representing the top-level data space
+            containing this subject. Part 1 of 2-component composite primary key.
+        
+        local_id:
+            An identifier representing this subject, unique within this
+            id_namespace. Part 2 of 2-component composite primary key.
+        
+        persistent_id:
+            A persistent, resolvable (not necessarily retrievable) URI or compact
+            ID permanently attached to this subject.
+        
+        creation_time:
+            An ISO 8601/RFC 3339 compliant timestamp documenting this subject's
+            creation time (YYYY-MM-DDTHH:MM:SS±NN:NN).
+        
+        granularity:
+            A CFDE CV term categorizing this subject by multiplicity.
+        
+        sex:
+            A CFDE CV term specifying the biological sex of this subject.
+        
+        ethnicity:
+            A CFDE CV term specifying the self-reported ethnicity of this subject.
+        
+        race:
+            CFDE CV terms specifying the self-reported race(s) of this subject.
+    """
+
+    id_namespace: str = str()
+    local_id: str = str()
+    persistent_id: Optional[str] = None
+    creation_time: Optional[str] = None
+    granularity: Optional[SubjectGranularity] = None
+    sex: Optional[SubjectSex] = None
+    ethnicity: Optional[SubjectEthnicity] = None
+    race: List[SubjectRace] = []
+
+    @field_validator("granularity", "sex", "ethnicity", mode="before")
+    @classmethod
+    def _cv_term(cls, value):
+        # An id the materializer couldn't resolve is left as a bare string
+        if isinstance(value, str):
+            return {"id": value}
+        return value
+
+
+class SubjectGranularity(BaseModel):
+    """
+    A CFDE subject granularity CV term (e.g. single organism, cell line).
+
+    Attributes:
+        id:
+            A CFDE subject granularity CV term identifier.
+        
+        name:
+            A short, human-readable label for this granularity.
+        
+        description:
+            A human-readable description of this granularity.
+    """
+
+    id: str = str()
+    name: str = str()
+    description: Optional[str] = None
+
+
+class SubjectSex(BaseModel):
+    """
+    A CFDE subject sex CV term.
+
+    Attributes:
+        id:
+            A CFDE subject sex CV term identifier.
+        
+        name:
+            A short, human-readable label for this sex.
+        
+        description:
+            A human-readable description of this sex.
+    """
+
+    id: str = str()
+    name: str = str()
+    description: Optional[str] = None
+
+
+class SubjectEthnicity(BaseModel):
+    """
+    A CFDE subject ethnicity CV term.
+
+    Attributes:
+        id:
+            A CFDE subject ethnicity CV term identifier.
+        
+        name:
+            A short, human-readable label for this ethnicity.
+        
+        description:
+            A human-readable description of this ethnicity.
+    """
+
+    id: str = str()
+    name: str = str()
+    description: Optional[str] = None
+
+
+class SubjectRace(BaseModel):
+    """
+    A CFDE subject race CV term.
+
+    Attributes:
+        id:
+            A CFDE subject race CV term identifier.
+        
+        name:
+            A short, human-readable label for this race.
+        
+        description:
+            A human-readable description of this race.
+    """
+
+    id: str = str()
+    name: str = str()
+    description: Optional[str] = None